@@ -1,12 +1,13 @@
 .section .boot, "awx"
 .code16
 
-# This stage sets the target operating mode, loads the kernel from disk,
-# creates an e820 memory map, enters protected mode, and jumps to the
-# third stage.
+# This stage sets the target operating mode, loads the kernel and the
+# initrd from disk, creates an e820 memory map, enters protected mode, and
+# jumps to the third stage.
 
 second_stage_start_str: .asciz "Booting (second stage)..."
 kernel_load_failed_str: .asciz "Failed to load kernel from disk"
+initrd_load_failed_str: .asciz "Failed to load initrd from disk"
 
 kernel_load_failed:
     mov si, offset kernel_load_failed_str
@@ -14,6 +15,12 @@ kernel_load_failed:
 kernel_load_failed_spin:
     jmp kernel_load_failed_spin
 
+initrd_load_failed:
+    mov si, offset initrd_load_failed_str
+    call real_mode_println
+initrd_load_failed_spin:
+    jmp initrd_load_failed_spin
+
 stage_2:
     mov si, offset second_stage_start_str
     call real_mode_println
@@ -53,33 +60,43 @@ load_kernel_from_disk:
     add ecx, 511 # align up
     shr ecx, 9
 
-load_next_kernel_block_from_disk:
-    # load block from disk
+    call load_blocks_from_disk
+    jc kernel_load_failed
+
+load_initrd_from_disk:
+    # The initrd is optional, it follows the kernel on the disk and starts with a header
+    # block holding the "kinitrd" magic and the size of the archive in bytes. It's
+    # loaded at the first page after the kernel.
+    add edi, 0xfff
+    and edi, 0xfffff000
+    mov [_initrd_start], edi
+
+    # reading past the end of the disk means there is no initrd
     mov si, offset dap
     mov ah, 0x42
     int 0x13
-    jc kernel_load_failed
+    jc load_initrd_done
 
-    # copy block to 2MiB
-    push ecx
-    push esi
-    mov ecx, 512 / 4
-    # move with zero extension
-    # because we are moving a word ptr
-    # to esi, a 32-bit register.
     movzx esi, word ptr [dap_buffer_addr]
-    # move from esi to edi ecx times.
-    rep movsd [edi], [esi]
-    pop esi
-    pop ecx
+    cmp dword ptr [esi], 0x696e696b # "kini"
+    jne load_initrd_done
+    cmp dword ptr [esi + 4], 0x00647274 # "trd\0"
+    jne load_initrd_done
 
-    # next block
+    # block count
+    mov ecx, [esi + 8]
+    mov [_initrd_size], ecx
+    add ecx, 511 # align up
+    shr ecx, 9
+
+    # skip the header block
     mov eax, [dap_start_lba]
     add eax, 1
     mov [dap_start_lba], eax
 
-    sub ecx, 1
-    jnz load_next_kernel_block_from_disk
+    call load_blocks_from_disk
+    jc initrd_load_failed
+load_initrd_done:
 
 create_memory_map:
     lea di, es:[_memory_map]
@@ -104,6 +121,51 @@ spin32:
     jmp spin32
 
 
+# copy blocks from the disk to memory, one block at a time through the dap buffer
+# IN
+#   ecx: number of blocks
+#   edi: destination address
+#   dap_start_lba: first block
+# OUT
+#   CF set if a block couldn't be read
+#   edi: address after the last block
+#   dap_start_lba: block after the last block
+load_blocks_from_disk:
+    test ecx, ecx
+    jz load_blocks_done
+
+load_next_block_from_disk:
+    # load block from disk
+    mov si, offset dap
+    mov ah, 0x42
+    int 0x13
+    jc load_blocks_done
+
+    # copy block to the destination
+    push ecx
+    push esi
+    mov ecx, 512 / 4
+    # move with zero extension
+    # because we are moving a word ptr
+    # to esi, a 32-bit register.
+    movzx esi, word ptr [dap_buffer_addr]
+    # move from esi to edi ecx times.
+    rep movsd [edi], [esi]
+    pop esi
+    pop ecx
+
+    # next block
+    mov eax, [dap_start_lba]
+    add eax, 1
+    mov [dap_start_lba], eax
+
+    sub ecx, 1
+    jnz load_next_block_from_disk
+    clc
+load_blocks_done:
+    ret
+
+
 
 # print a string and a newline
 # IN
@@ -169,3 +231,11 @@ vga_print_char:
 
 vga_position:
     .double 0
+
+# physical range of the initrd read by the fourth stage, the size is zero without an initrd
+.global _initrd_start
+.global _initrd_size
+_initrd_start:
+    .double 0
+_initrd_size:
+    .double 0
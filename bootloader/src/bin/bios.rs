@@ -16,12 +16,15 @@ use bootloader::{
         memory::{BiosFrameAllocator, E820MemoryMap},
         CONFIG,
     },
-    boot_info::{FrameBufferInfo, PixelFormat},
+    boot_info::{FrameBufferInfo, MemoryRegion, MemoryRegionKind, PixelFormat},
 };
 
 use page_mapper::{instrumented::TracingMapper, OffsetMapper};
 
-use libx64::address::{PhysicalAddr, VirtualAddr};
+use libx64::{
+    address::{PhysicalAddr, VirtualAddr},
+    paging::frame::PhysicalFrame,
+};
 
 global_asm!(include_str!("../asm/stage_1.s"));
 global_asm!(include_str!("../asm/stage_2.s"));
@@ -42,6 +45,12 @@ extern "C" {
     static VBEModeInfo_bluefieldposition: u8;
 }
 
+// values defined in `stage_2.s`
+extern "C" {
+    static _initrd_start: u32;
+    static _initrd_size: u32;
+}
+
 // Symbols defined in `linker.ld`
 extern "C" {
     static mmap_ent: usize;
@@ -105,6 +114,15 @@ fn make_framebuffer() -> (PhysicalAddr, FrameBufferInfo) {
     (addr, info)
 }
 
+fn make_initrd() -> Option<MemoryRegion> {
+    let (start, size) = unsafe { (u64::from(_initrd_start), u64::from(_initrd_size)) };
+    (size != 0).then(|| MemoryRegion {
+        start,
+        end: start + size,
+        kind: MemoryRegionKind::Initrd,
+    })
+}
+
 fn bootloader_main(kernel: Result<Kernel, KernelError>) -> Result<!, BootloaderError> {
     let span = info_span!(
         "bootloader",
@@ -122,13 +140,21 @@ fn bootloader_main(kernel: Result<Kernel, KernelError>) -> Result<!, BootloaderE
     );
 
     let kernel = kernel.expect("invalid kernel no booting will be attempted");
+    let initrd = make_initrd();
+
+    // the initrd is loaded after the kernel
+    let last_frame = initrd.map_or_else(
+        || kernel.frames().last().unwrap(),
+        |initrd| PhysicalFrame::containing(PhysicalAddr::new(initrd.end - 1)),
+    );
 
     // Extract lower 8 bits
     let memory_map = unsafe {
         E820MemoryMap::from_memory(
             VirtualAddr::from_ptr(&_memory_map),
             usize::try_from((mmap_ent & 0xff) as u64).unwrap(),
-            core::iter::Step::forward(kernel.frames().last().unwrap(), 1),
+            core::iter::Step::forward(last_frame, 1),
+            initrd,
         )
     };
 
@@ -148,6 +174,7 @@ fn bootloader_main(kernel: Result<Kernel, KernelError>) -> Result<!, BootloaderE
     )?;
 
     bootloader.detect_rsdp();
+    bootloader.record_initrd(initrd);
 
     if CONFIG.map_framebuffer {
         let (start, info) = make_framebuffer();
@@ -169,7 +196,8 @@ fn bootloader_main(kernel: Result<Kernel, KernelError>) -> Result<!, BootloaderE
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    error!("[PANIC]: {}", info);
+    error!(panic = true, "[PANIC]: {}", info);
+    qemu_logger::SERIAL.flush();
     libx64::diverging_hlt()
 }
 
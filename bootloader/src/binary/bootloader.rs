@@ -127,7 +127,7 @@ pub struct Bootloader<KM, BM, A, K = Kernel, S = NoStack> {
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum BootloaderError {
     AllocatorError(FrameError),
-    ElfLoader(&'static str),
+    ElfLoader(kelf::Error),
 }
 
 impl From<FrameError> for BootloaderError {
@@ -243,9 +243,7 @@ where
         self.entries
             .set_elf_loaded(&self.kernel.elf_file(), self.kernel.offset);
 
-        let tls = loader
-            .load_segments()
-            .map_err(|err| BootloaderError::ElfLoader(err))?;
+        let tls = loader.load_segments().map_err(BootloaderError::ElfLoader)?;
 
         unsafe {
             addr_of_mut!((*self.bootinfo.as_mut_ptr()).tls_template).write(tls.into());
@@ -410,6 +408,20 @@ where
         }
     }
 
+    #[cold]
+    pub fn record_initrd(&mut self, initrd: Option<MemoryRegion>) {
+        if let Some(initrd) = initrd {
+            info!(
+                "Initrd loaded at {:?}..{:?}",
+                PhysicalAddr::new(initrd.start),
+                PhysicalAddr::new(initrd.end)
+            );
+        }
+        unsafe {
+            addr_of_mut!((*self.bootinfo.as_mut_ptr()).initrd).write(initrd.into());
+        }
+    }
+
     #[cold]
     pub fn boot(mut self) -> Result<!, BootloaderError> {
         // identity-map context switch function, so that we don't get an immediate pagefault
@@ -450,6 +462,9 @@ where
             addresses.entry_point
         );
 
+        // the kernel resets the serial port, the logs still buffered would be lost
+        qemu_logger::SERIAL.flush();
+
         // yolo. (at least we have a kernel and a stack :^)
         unsafe { context_switch(addresses) }
     }
@@ -33,6 +33,8 @@ pub struct E820MemoryMap<'a> {
     pub memory_map: &'a [E820MemoryRegion],
     /// next available frame
     pub next_frame: PhysicalFrame<Page4Kb>,
+    /// initrd loaded before the next available frame
+    pub initrd: Option<MemoryRegion>,
 }
 
 #[derive(Debug)]
@@ -70,6 +72,7 @@ impl<'a> E820MemoryMap<'a> {
         addr: VirtualAddr,
         len: usize,
         next_frame: PhysicalFrame<Page4Kb>,
+        initrd: Option<MemoryRegion>,
     ) -> Self {
         let memory_map = unsafe {
             let ptr = addr.ptr::<E820MemoryRegion>().unwrap().as_ptr();
@@ -78,6 +81,7 @@ impl<'a> E820MemoryMap<'a> {
         Self {
             memory_map,
             next_frame,
+            initrd,
         }
     }
 
@@ -232,17 +236,13 @@ pub fn construct_memory_map<'a>(
         let kind = match descriptor.kind() {
             MemoryRegionKind::Usable => {
                 if end.as_u64() <= next_free.as_u64() {
-                    MemoryRegionKind::Bootloader
+                    add_used_region(start, end, mem.initrd, regions, &mut next_index)?;
+                    continue;
                 } else if descriptor.start().as_u64() >= next_free.as_u64() {
                     MemoryRegionKind::Usable
                 } else {
                     // part of the region is used -> add it separately
-                    let used_region = MemoryRegion {
-                        start: descriptor.start().as_u64(),
-                        end: next_free.as_u64(),
-                        kind: MemoryRegionKind::Bootloader,
-                    };
-                    add_region(used_region, regions, &mut next_index)?;
+                    add_used_region(start, next_free, mem.initrd, regions, &mut next_index)?;
 
                     // add unused part normally
                     start = next_free;
@@ -263,6 +263,37 @@ pub fn construct_memory_map<'a>(
     let initialized = &mut regions[..next_index];
     Ok(unsafe { MaybeUninit::slice_assume_init_mut(initialized) })
 }
+
+/// Add the memory used by the bootloader, the part of it holding the initrd is added as a
+/// separate region.
+fn add_used_region(
+    start: PhysicalAddr,
+    end: PhysicalAddr,
+    initrd: Option<MemoryRegion>,
+    regions: &mut [MaybeUninit<MemoryRegion>],
+    next_index: &mut usize,
+) -> Result<(), FrameError> {
+    let (start, end) = (start.as_u64(), end.as_u64());
+    let (initrd_start, initrd_end) = match initrd {
+        Some(initrd) if initrd.start < end && start < initrd.end => {
+            (initrd.start.max(start), initrd.end.min(end))
+        }
+        _ => (end, end),
+    };
+
+    let parts = [
+        (start, initrd_start, MemoryRegionKind::Bootloader),
+        (initrd_start, initrd_end, MemoryRegionKind::Initrd),
+        (initrd_end, end, MemoryRegionKind::Bootloader),
+    ];
+    for (start, end, kind) in parts {
+        if start < end {
+            add_region(MemoryRegion { start, end, kind }, regions, next_index)?;
+        }
+    }
+    Ok(())
+}
+
 fn add_region(
     region: MemoryRegion,
     regions: &mut [MaybeUninit<MemoryRegion>],
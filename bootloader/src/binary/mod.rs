@@ -81,7 +81,7 @@ where
     let memory_map_regions_addr =
         boot_info_end.align_up(u64::try_from(mem::align_of::<MemoryRegion>()).unwrap());
 
-    let regions = frame_allocator.len() + 1; // one region might be split into used/unused
+    let regions = frame_allocator.len() + 3; // one region might be split into used/initrd/used/unused
     let memory_map_regions_end = memory_map_regions_addr + regions * mem::size_of::<MemoryRegion>();
 
     let start_page = Page::<Page4Kb>::containing(boot_info_addr);
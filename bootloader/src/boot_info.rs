@@ -45,6 +45,11 @@ pub struct BootInfo {
     pub rsdp_addr: Optional<u64>,
     /// The thread local storage (TLS) template of the kernel executable, if present.
     pub tls_template: Optional<TlsTemplate>,
+    /// Physical range of the initial RAM disk if one follows the kernel on the disk.
+    ///
+    /// The region is also part of [`memory_regions`][Self::memory_regions] with the
+    /// [`Initrd`][MemoryRegionKind::Initrd] kind.
+    pub initrd: Optional<MemoryRegion>,
 }
 
 /// FFI-safe slice of [`MemoryRegion`] structs, semantically equivalent to
@@ -127,6 +132,10 @@ pub enum MemoryRegionKind {
     ///
     /// This memory should _not_ be used by the kernel.
     Bootloader,
+    /// Archive loaded by the bootloader after the kernel.
+    ///
+    /// This memory can be reused by the kernel once it is done with the initrd.
+    Initrd,
     /// An unknown memory region reported by the UEFI firmware.
     ///
     /// This should only be used if the UEFI memory type is known as usable.
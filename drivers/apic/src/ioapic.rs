@@ -0,0 +1,164 @@
+use bitfield::bitfield;
+
+use libx64::address::{PhysicalAddr, VirtualAddr};
+
+use crate::Error;
+
+bitfield! {
+    /// # Redirection table entry
+    ///
+    /// ## Format
+    ///
+    /// Bits  | Field            | Description
+    /// ------|------------------|-------------------------------------------------------
+    /// 0-7   | Vector           | Interrupt vector raised on the destination
+    /// 8-10  | Delivery mode    | 0: fixed, 1: lowest priority, 2: SMI, 4: NMI, 5: INIT
+    /// 11    | Destination mode | 0: physical (APIC id), 1: logical
+    /// 12    | Delivery status  | 1: the interrupt is pending (read only)
+    /// 13    | Pin polarity     | 0: active high, 1: active low
+    /// 14    | Remote IRR       | Level triggered interrupt accepted (read only)
+    /// 15    | Trigger mode     | 0: edge, 1: level
+    /// 16    | Mask             | 1: the interrupt is not delivered
+    /// 56-63 | Destination      | APIC id of the target in physical mode
+    #[derive(Clone, Copy)]
+    #[repr(transparent)]
+    pub unsafe struct RedirectionEntry: u64 {
+        pub vector: 0..8,
+        pub delivery_mode: 8..11,
+        pub destination_mode: 11..12,
+        pub delivery_status: 12..13,
+        pub polarity: 13..14,
+        pub remote_irr: 14..15,
+        pub trigger_mode: 15..16,
+        pub mask: 16..17,
+        pub destination: 56..64,
+    }
+}
+
+impl RedirectionEntry {
+    /// Edge triggered, active high, fixed delivery to the given APIC
+    #[must_use]
+    pub const fn fixed(vector: u8, apic_id: u8) -> Self {
+        Self::zero()
+            .set_vector(vector as u64)
+            .set_destination(apic_id as u64)
+    }
+
+    #[must_use]
+    pub const fn masked() -> Self {
+        Self::zero().set_mask(1)
+    }
+}
+
+/// # I/O APIC
+///
+/// Registers are accessed indirectly, the index is written to `IOREGSEL` (base + 0x00) and the
+/// value is read or written through `IOWIN` (base + 0x10).
+///
+/// Register  | Description
+/// ----------|---------------------------------------------------
+/// 0x00      | I/O APIC id
+/// 0x01      | Version, bits 16-23 hold the last redirection entry
+/// 0x10-0x3F | Redirection table, two 32 bits registers per entry
+pub struct IoApic {
+    base: VirtualAddr,
+    /// First global system interrupt handled by this I/O APIC
+    gsi_base: u32,
+}
+
+impl IoApic {
+    /// Default physical base of the MMIO window
+    pub const DEFAULT_BASE: PhysicalAddr = PhysicalAddr::new(0xFEC0_0000);
+
+    const IOREGSEL: usize = 0x00;
+    const IOWIN: usize = 0x10;
+
+    const ID: u32 = 0x00;
+    const VERSION: u32 = 0x01;
+    const REDIRECTION_TABLE: u32 = 0x10;
+
+    /// # Safety
+    ///
+    /// `base` must be an uncached mapping of the I/O APIC MMIO window
+    #[must_use]
+    pub const unsafe fn new(base: VirtualAddr, gsi_base: u32) -> Self {
+        Self { base, gsi_base }
+    }
+
+    fn read(&mut self, reg: u32) -> u32 {
+        let select = (self.base + Self::IOREGSEL).ptr::<u32>().unwrap();
+        let window = (self.base + Self::IOWIN).ptr::<u32>().unwrap();
+        unsafe {
+            core::ptr::write_volatile(select.as_ptr(), reg);
+            core::ptr::read_volatile(window.as_ptr())
+        }
+    }
+
+    fn write(&mut self, reg: u32, value: u32) {
+        let select = (self.base + Self::IOREGSEL).ptr::<u32>().unwrap();
+        let window = (self.base + Self::IOWIN).ptr::<u32>().unwrap();
+        unsafe {
+            core::ptr::write_volatile(select.as_ptr(), reg);
+            core::ptr::write_volatile(window.as_ptr(), value);
+        }
+    }
+
+    #[must_use]
+    pub fn id(&mut self) -> u8 {
+        ((self.read(Self::ID) >> 24) & 0xF) as u8
+    }
+
+    /// Number of redirection entries.
+    #[must_use]
+    pub fn entries(&mut self) -> u32 {
+        ((self.read(Self::VERSION) >> 16) & 0xFF) + 1
+    }
+
+    #[must_use]
+    pub const fn gsi_base(&self) -> u32 {
+        self.gsi_base
+    }
+
+    /// Whether the global system interrupt is one of this I/O APIC pins.
+    pub fn handles(&mut self, gsi: u32) -> bool {
+        (self.gsi_base..self.gsi_base + self.entries()).contains(&gsi)
+    }
+
+    /// # Errors
+    ///
+    /// Errors if the global system interrupt isn't handled by this I/O APIC
+    pub fn set_entry(&mut self, gsi: u32, entry: RedirectionEntry) -> Result<(), Error> {
+        if !self.handles(gsi) {
+            return Err(Error::InvalidGsi);
+        }
+        let reg = Self::REDIRECTION_TABLE + 2 * (gsi - self.gsi_base);
+        let value = entry.as_u64();
+
+        // mask the pin while the entry is only half written
+        self.write(reg, RedirectionEntry::masked().as_u64() as u32);
+        self.write(reg + 1, (value >> 32) as u32);
+        self.write(reg, value as u32);
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Errors if the global system interrupt isn't handled by this I/O APIC
+    pub fn entry(&mut self, gsi: u32) -> Result<RedirectionEntry, Error> {
+        if !self.handles(gsi) {
+            return Err(Error::InvalidGsi);
+        }
+        let reg = Self::REDIRECTION_TABLE + 2 * (gsi - self.gsi_base);
+        let value = u64::from(self.read(reg)) | (u64::from(self.read(reg + 1)) << 32);
+        Ok(unsafe { RedirectionEntry::raw(value) })
+    }
+
+    /// Mask every pin.
+    pub fn mask_all(&mut self) {
+        let base = self.gsi_base;
+        for gsi in base..base + self.entries() {
+            // the gsi is in range
+            let _ = self.set_entry(gsi, RedirectionEntry::masked());
+        }
+    }
+}
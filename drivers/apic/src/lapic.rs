@@ -0,0 +1,169 @@
+use core::time::Duration;
+
+use libx64::{
+    address::{PhysicalAddr, VirtualAddr},
+    msr::IA32_APIC_BASE,
+};
+
+use crate::Error;
+
+/// Local APIC registers, offsets from the MMIO base
+#[derive(Debug, Clone, Copy)]
+#[repr(usize)]
+enum Register {
+    Id = 0x20,
+    Version = 0x30,
+    TaskPriority = 0x80,
+    Eoi = 0xB0,
+    SpuriousVector = 0xF0,
+    LvtTimer = 0x320,
+    LvtLint0 = 0x350,
+    LvtLint1 = 0x360,
+    LvtError = 0x370,
+    TimerInitialCount = 0x380,
+    TimerCurrentCount = 0x390,
+    TimerDivide = 0x3E0,
+}
+
+/// # Local APIC
+///
+/// Source: Intel SDM Vol. 3A, Chapter 10
+///
+/// ## IA32_APIC_BASE MSR
+///
+/// Bits  | Description
+/// ------|------------------------------------------
+/// 8     | BSP flag, the processor is the bootstrap
+/// 11    | APIC global enable
+/// 12-35 | Physical base of the 4Kb MMIO window
+pub struct LocalApic {
+    base: VirtualAddr,
+    /// Timer ticks per second with the divider set to 16, known after calibration
+    timer_frequency: Option<u32>,
+}
+
+impl LocalApic {
+    /// Default physical base of the MMIO window
+    pub const DEFAULT_BASE: PhysicalAddr = PhysicalAddr::new(0xFEE0_0000);
+
+    const GLOBAL_ENABLE: u64 = 1 << 11;
+    const BASE_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+    const SOFTWARE_ENABLE: u32 = 1 << 8;
+    const MASKED: u32 = 1 << 16;
+    const PERIODIC: u32 = 1 << 17;
+    const DIVIDE_BY_16: u32 = 0b0011;
+
+    /// # Safety
+    ///
+    /// `base` must be an uncached mapping of the local APIC MMIO window
+    #[must_use]
+    pub const unsafe fn new(base: VirtualAddr) -> Self {
+        Self {
+            base,
+            timer_frequency: None,
+        }
+    }
+
+    /// Physical base of the MMIO window as reported by the MSR.
+    #[must_use]
+    pub fn physical_base() -> PhysicalAddr {
+        PhysicalAddr::new(unsafe { IA32_APIC_BASE.read() } & Self::BASE_MASK)
+    }
+
+    fn read(&self, reg: Register) -> u32 {
+        let ptr = (self.base + reg as usize).ptr::<u32>().unwrap();
+        unsafe { core::ptr::read_volatile(ptr.as_ptr()) }
+    }
+
+    fn write(&mut self, reg: Register, value: u32) {
+        let ptr = (self.base + reg as usize).ptr::<u32>().unwrap();
+        unsafe { core::ptr::write_volatile(ptr.as_ptr(), value) }
+    }
+
+    /// Globally enable the APIC and accept every interrupt priority.
+    ///
+    /// Local interrupt pins are masked, external interrupts come from the I/O APIC.
+    pub fn enable(&mut self, spurious_vector: u8) {
+        let mut msr = IA32_APIC_BASE;
+        unsafe { msr.write(msr.read() | Self::GLOBAL_ENABLE) };
+
+        self.write(Register::TaskPriority, 0);
+        self.write(Register::LvtLint0, Self::MASKED);
+        self.write(Register::LvtLint1, Self::MASKED);
+        self.write(Register::LvtError, Self::MASKED);
+        self.write(Register::LvtTimer, Self::MASKED);
+        self.write(
+            Register::SpuriousVector,
+            Self::SOFTWARE_ENABLE | u32::from(spurious_vector),
+        );
+    }
+
+    #[must_use]
+    pub fn id(&self) -> u8 {
+        (self.read(Register::Id) >> 24) as u8
+    }
+
+    #[must_use]
+    pub fn version(&self) -> u8 {
+        self.read(Register::Version) as u8
+    }
+
+    /// Signal the end of the interrupt being serviced.
+    pub fn eoi(&mut self) {
+        self.write(Register::Eoi, 0);
+    }
+
+    /// Measure the timer frequency by counting down during a PIT busy wait.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the PIT can't wait for the calibration window
+    pub fn calibrate_timer(&mut self, pit: &mut pit::Pit) -> Result<u32, Error> {
+        const WINDOW: Duration = Duration::from_millis(10);
+
+        self.write(Register::LvtTimer, Self::MASKED);
+        self.write(Register::TimerDivide, Self::DIVIDE_BY_16);
+
+        let elapsed = libx64::without_interrupts(|| {
+            self.write(Register::TimerInitialCount, u32::MAX);
+            let waited = pit.busy_wait(WINDOW);
+            let elapsed = u32::MAX - self.read(Register::TimerCurrentCount);
+            self.write(Register::TimerInitialCount, 0);
+            waited.map(|()| elapsed)
+        })
+        .map_err(|_| Error::Calibration)?;
+
+        let frequency = elapsed * (Duration::from_secs(1).as_millis() / WINDOW.as_millis()) as u32;
+        self.timer_frequency = Some(frequency);
+        Ok(frequency)
+    }
+
+    #[must_use]
+    pub const fn timer_frequency(&self) -> Option<u32> {
+        self.timer_frequency
+    }
+
+    /// Fire `vector` at `hz` from the local timer.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the timer wasn't calibrated or can't reach the frequency
+    pub fn start_periodic_timer(&mut self, vector: u8, hz: u32) -> Result<(), Error> {
+        let frequency = self.timer_frequency.ok_or(Error::UncalibratedTimer)?;
+        let count = frequency
+            .checked_div(hz)
+            .filter(|&count| count != 0)
+            .ok_or(Error::UnsupportedFrequency)?;
+
+        self.write(Register::TimerDivide, Self::DIVIDE_BY_16);
+        self.write(Register::LvtTimer, Self::PERIODIC | u32::from(vector));
+        self.write(Register::TimerInitialCount, count);
+        Ok(())
+    }
+
+    pub fn stop_timer(&mut self) {
+        self.write(Register::LvtTimer, Self::MASKED);
+        self.write(Register::TimerInitialCount, 0);
+    }
+}
@@ -0,0 +1,103 @@
+#![no_std]
+
+pub mod ioapic;
+pub mod lapic;
+
+use ioapic::{IoApic, RedirectionEntry};
+use lapic::LocalApic;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    UnhandledInterrupt,
+    /// No I/O APIC pin for this global system interrupt
+    InvalidGsi,
+    /// The PIT could not time the calibration window
+    Calibration,
+    UncalibratedTimer,
+    UnsupportedFrequency,
+}
+
+/// ISA IRQ wired to a different I/O APIC pin, as reported by the MADT.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct InterruptOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    pub active_low: bool,
+    pub level_triggered: bool,
+}
+
+/// Local APIC of the bootstrap processor and the I/O APIC routing the ISA interrupts.
+///
+/// ISA IRQ `n` raises vector `OFFSET + n` like the remapped 8259 pair, so both controllers
+/// serve the same interrupt indices.
+pub struct Apic<const OFFSET: u8> {
+    local: LocalApic,
+    io: IoApic,
+}
+
+impl<const OFFSET: u8> Apic<OFFSET> {
+    /// Vector of spurious interrupts, they must not be acknowledged
+    pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+    #[must_use]
+    pub const fn new(local: LocalApic, io: IoApic) -> Self {
+        Self { local, io }
+    }
+
+    /// Enable the local APIC, every I/O APIC pin starts masked.
+    ///
+    /// The 8259 pair must be masked beforehand, see `pic::chained::Chained::disable`.
+    pub fn init(&mut self) {
+        self.local.enable(Self::SPURIOUS_VECTOR);
+        self.io.mask_all();
+    }
+
+    /// Route an ISA IRQ to the bootstrap processor.
+    ///
+    /// # Errors
+    ///
+    /// Errors if no I/O APIC pin handles the IRQ
+    pub fn route_isa(&mut self, irq: u8, overrides: &[InterruptOverride]) -> Result<(), Error> {
+        let vector = OFFSET + irq;
+        let mut entry = RedirectionEntry::fixed(vector, self.local.id());
+
+        let gsi = match overrides.iter().find(|o| o.irq == irq) {
+            Some(o) => {
+                entry = entry
+                    .set_polarity(u64::from(o.active_low))
+                    .set_trigger_mode(u64::from(o.level_triggered));
+                o.gsi
+            }
+            // ISA interrupts are identity mapped unless overridden
+            None => u32::from(irq),
+        };
+
+        self.io.set_entry(gsi, entry)
+    }
+
+    /// # Errors
+    ///
+    /// This function errors if the interrupt wasn't raised through the APIC
+    pub fn interupt_fn<T>(&mut self, int_code: T) -> Result<(), Error>
+    where
+        T: libx64::idt::TrustedUserInterruptIndex,
+    {
+        let int_code = int_code.into();
+        if int_code == usize::from(Self::SPURIOUS_VECTOR) {
+            return Ok(());
+        }
+        if int_code < usize::from(OFFSET) {
+            return Err(Error::UnhandledInterrupt);
+        }
+        self.local.eoi();
+        Ok(())
+    }
+
+    pub fn local(&mut self) -> &mut LocalApic {
+        &mut self.local
+    }
+
+    pub fn io(&mut self) -> &mut IoApic {
+        &mut self.io
+    }
+}
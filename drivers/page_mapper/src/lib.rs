@@ -47,6 +47,19 @@ impl OffsetMapper {
         self.walker.translator()
     }
 
+    /// Mapper of the level 4 table in `frame`, the tables are reached at `offset` + their
+    /// physical address.
+    ///
+    /// # Safety
+    ///
+    /// The frame must hold a level 4 table that isn't mapped by another mapper
+    #[must_use]
+    pub unsafe fn from_frame(frame: PhysicalFrame<Page4Kb>, offset: VirtualAddr) -> Self {
+        let walker = OffsetWalker::<Page4Kb>::new(offset);
+        let level4 = FrameTranslator::<(), Page4Kb>::translate_frame(&walker, frame);
+        Self::from_p4(level4, offset)
+    }
+
     #[must_use]
     unsafe fn from_p4(level4: PinTableMut<'_, Level4>, offset: VirtualAddr) -> Self {
         Self {
@@ -133,7 +146,7 @@ impl PageMapper<Page4Kb> for OffsetMapper {
             let mut entry = level_1
                 .try_into_table()?
                 .index_pin_mut(addr.page_table_index(Level1));
-            entry.as_mut().set_flags(flags);
+            entry.as_mut().replace_flags(flags);
         }
 
         Ok(TlbFlush::new(page))
@@ -233,7 +246,7 @@ impl PageMapper<Page2Mb> for OffsetMapper {
             let mut entry = level_2
                 .try_into_table()?
                 .index_pin_mut(addr.page_table_index(Level2));
-            entry.as_mut().set_flags(flags | Flags::HUGE);
+            entry.as_mut().replace_flags(flags | Flags::HUGE);
         }
 
         Ok(TlbFlush::new(page))
@@ -316,7 +329,7 @@ impl PageMapper<Page1Gb> for OffsetMapper {
         // SAFETY: we are the sole owner of this page and the entry will be valid
         unsafe {
             let mut entry = level_3.index_pin_mut(addr.page_table_index(Level3));
-            entry.as_mut().set_flags(flags | Flags::HUGE);
+            entry.as_mut().replace_flags(flags | Flags::HUGE);
         }
         Ok(TlbFlush::new(page))
     }
@@ -1,5 +1,5 @@
 use crate::words::{ICW1, ICW3, ICW4};
-use crate::{Pic, Raw, RemapInit, RemapUninit};
+use crate::{Pic, PicFinalState, Raw, RemapInit, RemapUninit};
 
 use libx64::port::WPort;
 
@@ -52,6 +52,49 @@ impl<const A: u8, const B: u8> Chained<A, B> {
         }
     }
 
+    /// Mask every IRQ line, used when interrupts are routed through another controller.
+    ///
+    /// The pair should be initialized first so spurious interrupts don't land on exception
+    /// vectors.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the pic isn't initialized
+    pub fn disable(&mut self) -> Result<(), Error> {
+        match self.state {
+            State::Init((ref mut master, ref mut slave)) => {
+                master.set_mask(0xFF);
+                slave.set_mask(0xFF);
+                Ok(())
+            }
+            State::Raw((ref mut master, ref mut slave)) => {
+                master.set_mask(0xFF);
+                slave.set_mask(0xFF);
+                Ok(())
+            }
+            State::Uninit(_) => Err(Error::UnexpectedUnitialized),
+        }
+    }
+
+    /// Enable the ISA `irq` line, the line of the slave is enabled along with its IRQs.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the pic isn't initialized
+    pub fn unmask(&mut self, irq: u8) -> Result<(), Error> {
+        match self.state {
+            State::Init((ref mut master, ref mut slave)) => {
+                unmask(master, slave, irq);
+                Ok(())
+            }
+            State::Raw((ref mut master, ref mut slave)) => {
+                unmask(master, slave, irq);
+                Ok(())
+            }
+            State::Uninit(_) => Err(Error::UnexpectedUnitialized),
+        }
+    }
+
     /// # Errors
     ///
     /// This function errors if the chained pic doesn't handle this interrupt, or isn't intialized
@@ -80,6 +123,21 @@ impl<const A: u8, const B: u8> Chained<A, B> {
     }
 }
 
+fn unmask<S, const A: u8, const B: u8>(master: &mut Pic<S, A>, slave: &mut Pic<S, B>, irq: u8)
+where
+    S: PicFinalState,
+{
+    const SLAVE_LINE: u8 = 2;
+
+    let master_irq = if irq < 8 {
+        irq
+    } else {
+        slave.set_mask(slave.read_mask() & !(1 << (irq - 8)));
+        SLAVE_LINE
+    };
+    master.set_mask(master.read_mask() & !(1 << master_irq));
+}
+
 #[must_use]
 pub fn remap_init<const A: u8, const B: u8>(
     master: Pic<RemapUninit, A>,
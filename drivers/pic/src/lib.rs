@@ -81,11 +81,6 @@ where
     pub fn slave() -> Self {
         unsafe { Self::new(0xA0, 0xA1) }
     }
-
-    #[must_use]
-    pub fn read_mask(&self) -> u8 {
-        unsafe { self.data.read() }
-    }
 }
 
 impl<const OFFSET: u8> Pic<RemapUninit, OFFSET> {
@@ -178,6 +173,13 @@ where
             self.command.write(Self::EOI);
         }
     }
+
+    /// Set the interrupt mask register, a set bit disables the IRQ line
+    pub fn set_mask(&mut self, mask: u8) {
+        unsafe {
+            self.data.write(mask);
+        }
+    }
 }
 
 impl<S, const OFFSET: u8> Pic<S, OFFSET> {
@@ -188,4 +190,10 @@ impl<S, const OFFSET: u8> Pic<S, OFFSET> {
     pub const fn handles_interrupt(&self, id: u8) -> bool {
         OFFSET <= id && id < OFFSET + 8
     }
+
+    /// Read the interrupt mask register, a set bit disables the IRQ line
+    #[must_use]
+    pub fn read_mask(&self) -> u8 {
+        unsafe { self.data.read() }
+    }
 }
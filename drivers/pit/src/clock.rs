@@ -0,0 +1,123 @@
+use core::{
+    ops::{Add, Sub},
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::{BASE_FREQUENCY, MAX_DIVISOR};
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+/// The BIOS leaves the PIT at its slowest rate
+static DIVISOR: AtomicU32 = AtomicU32::new(MAX_DIVISOR);
+
+pub(crate) fn advance() -> u64 {
+    TICKS.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+pub(crate) fn set_divisor(divisor: u32) {
+    DIVISOR.store(divisor, Ordering::Relaxed);
+}
+
+/// Number of timer interrupts since boot.
+#[inline]
+#[must_use]
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Time between two timer interrupts.
+#[must_use]
+pub fn tick_period() -> Duration {
+    let nanos = u128::from(DIVISOR.load(Ordering::Relaxed)) * NANOS_PER_SEC;
+    Duration::from_nanos((nanos / u128::from(BASE_FREQUENCY)) as u64)
+}
+
+/// Number of ticks covering `duration`, rounded up so a timer never fires early.
+#[must_use]
+pub fn ticks_for(duration: Duration) -> u64 {
+    let divisor = u128::from(DIVISOR.load(Ordering::Relaxed)) * NANOS_PER_SEC;
+    let ticks = (duration.as_nanos() * u128::from(BASE_FREQUENCY) + divisor - 1) / divisor;
+    u64::try_from(ticks).unwrap_or(u64::MAX)
+}
+
+/// A point of the monotonic clock, with the resolution of a timer tick.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    #[inline]
+    #[must_use]
+    pub fn now() -> Self {
+        Self(ticks())
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Self(ticks)
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn ticks(self) -> u64 {
+        self.0
+    }
+
+    /// Time elapsed since `earlier`, zero if `earlier` is later than `self`.
+    #[must_use]
+    pub fn duration_since(self, earlier: Self) -> Duration {
+        let ticks = u128::from(self.0.saturating_sub(earlier.0));
+        let nanos = ticks * u128::from(DIVISOR.load(Ordering::Relaxed)) * NANOS_PER_SEC
+            / u128::from(BASE_FREQUENCY);
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+
+    #[must_use]
+    pub fn elapsed(self) -> Duration {
+        Self::now().duration_since(self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        Self(self.0.saturating_add(ticks_for(rhs)))
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.duration_since(rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the tests keep the divisor left by the BIOS, it is shared by the whole process
+
+    #[test]
+    fn ticks_round_up() {
+        assert_eq!(ticks_for(Duration::ZERO), 0);
+        assert_eq!(ticks_for(tick_period()), 1);
+        assert_eq!(ticks_for(tick_period() + Duration::from_nanos(1)), 2);
+        assert_eq!(ticks_for(Duration::from_secs(1)), 19);
+    }
+
+    #[test]
+    fn instants_saturate() {
+        let start = Instant::from_ticks(10);
+        assert_eq!((start + tick_period()).ticks(), 11);
+        assert_eq!(Instant::from_ticks(11) - start, tick_period());
+        assert_eq!(start - Instant::from_ticks(11), Duration::ZERO);
+
+        let last = Instant::from_ticks(u64::MAX - 1);
+        assert_eq!(last + Duration::from_secs(1), Instant::from_ticks(u64::MAX));
+    }
+}
@@ -0,0 +1,194 @@
+#![no_std]
+
+extern crate alloc;
+
+use core::time::Duration;
+
+use bitfield::bitfield;
+use libx64::port::{RWPort, WPort};
+
+pub mod clock;
+pub mod time;
+mod wheel;
+
+pub use clock::Instant;
+pub use time::{interval, sleep, Interval, Sleep};
+
+/// Frequency of the PIT oscillator in Hz
+pub const BASE_FREQUENCY: u32 = 1_193_182;
+
+/// Largest reload value, written as 0 to the channel
+pub const MAX_DIVISOR: u32 = 0x1_0000;
+
+bitfield! {
+    /// # Mode/Command register
+    ///
+    /// Source: <https://wiki.osdev.org/Programmable_Interval_Timer>
+    ///
+    /// ## Format
+    ///
+    /// Bits | Usage          | Description
+    /// -----|----------------|-------------------------------------------------------------
+    /// 0    | BCD            | 0: 16-bit binary; 1: four-digit BCD
+    /// 1-3  | Operating mode | 0: interrupt on terminal count, 2: rate generator, 3: square wave
+    /// 4-5  | Access mode    | 0: latch count, 1: lobyte, 2: hibyte, 3: lobyte/hibyte
+    /// 6-7  | Channel        | 0-2: channel, 3: read-back command
+    #[derive(Clone, Copy)]
+    #[repr(transparent)]
+    pub unsafe struct Command: u8 {
+        pub bcd: 0..1,
+        pub mode: 1..4,
+        pub access: 4..6,
+        pub channel: 6..8,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    /// The frequency can't be reached with a 16 bits divisor
+    UnsupportedFrequency,
+    /// The duration doesn't fit in a single channel 2 countdown (~54ms)
+    UnsupportedDuration,
+}
+
+/// # Programmable Interval Timer (8254)
+///
+/// Channel 0 is wired to IRQ0 and drives the clock until the local APIC timer takes over,
+/// channel 2 is only used for busy waits as it doesn't raise interrupts.
+///
+/// ## Port Map
+///
+/// Port Address | Description
+/// -------------|--------------------------------------
+/// 0x40         | Channel 0 data port (read/write)
+/// 0x41         | Channel 1 data port (read/write)
+/// 0x42         | Channel 2 data port (read/write)
+/// 0x43         | Mode/Command register (write only)
+/// 0x61         | Channel 2 gate (bit 0) and output (bit 5)
+pub struct Pit {
+    channel0: WPort<u8>,
+    channel2: WPort<u8>,
+    command: WPort<u8>,
+    gate: RWPort<u8>,
+}
+
+impl Pit {
+    const TERMINAL_COUNT: u8 = 0;
+    const RATE_GENERATOR: u8 = 2;
+    const LOBYTE_HIBYTE: u8 = 3;
+
+    const GATE: u8 = 1 << 0;
+    const SPEAKER: u8 = 1 << 1;
+    const OUTPUT: u8 = 1 << 5;
+
+    /// # Safety
+    ///
+    /// The caller must be the only one driving the PIT
+    #[must_use]
+    pub const unsafe fn new() -> Self {
+        Self {
+            channel0: WPort::new(0x40),
+            channel2: WPort::new(0x42),
+            command: WPort::new(0x43),
+            gate: RWPort::new(0x61),
+        }
+    }
+
+    /// Program channel 0 to fire at `hz` and return the frequency actually reached.
+    ///
+    /// The monotonic clock is updated with the new tick period.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the frequency is out of the reachable range (19Hz to 597kHz)
+    pub fn set_frequency(&mut self, hz: u32) -> Result<u32, Error> {
+        let divisor = Self::divisor(hz)?;
+        self.set_divisor(divisor);
+        Ok(BASE_FREQUENCY / divisor)
+    }
+
+    /// Reload value of channel 0 for `hz`, the rate generator doesn't support a divisor of 1.
+    fn divisor(hz: u32) -> Result<u32, Error> {
+        BASE_FREQUENCY
+            .checked_div(hz)
+            .filter(|divisor| (2..=MAX_DIVISOR).contains(divisor))
+            .ok_or(Error::UnsupportedFrequency)
+    }
+
+    fn set_divisor(&mut self, divisor: u32) {
+        let command = Command::zero()
+            .set_mode(Self::RATE_GENERATOR)
+            .set_access(Self::LOBYTE_HIBYTE)
+            .set_channel(0);
+
+        // 0x1_0000 truncates to 0 which is the 65536 reload value
+        let reload = divisor as u16;
+
+        libx64::without_interrupts(|| unsafe {
+            self.command.write(command.as_u8());
+            self.channel0.write(reload as u8);
+            self.channel0.write((reload >> 8) as u8);
+        });
+        clock::set_divisor(divisor);
+    }
+
+    /// Spin for `duration` counting down channel 2, usable with interrupts disabled.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the duration is too long for a single countdown
+    pub fn busy_wait(&mut self, duration: Duration) -> Result<(), Error> {
+        let count = duration.as_nanos() * u128::from(BASE_FREQUENCY) / 1_000_000_000;
+        let count = u16::try_from(count)
+            .ok()
+            .filter(|&count| count != 0)
+            .ok_or(Error::UnsupportedDuration)?;
+
+        let command = Command::zero()
+            .set_mode(Self::TERMINAL_COUNT)
+            .set_access(Self::LOBYTE_HIBYTE)
+            .set_channel(2);
+
+        unsafe {
+            // speaker off, gate low while the counter is loaded
+            let control = self.gate.read() & !(Self::SPEAKER | Self::GATE);
+            self.gate.write(control);
+
+            self.command.write(command.as_u8());
+            self.channel2.write(count as u8);
+            self.channel2.write((count >> 8) as u8);
+
+            // the rising edge of the gate starts the countdown
+            self.gate.write(control | Self::GATE);
+            while self.gate.read() & Self::OUTPUT == 0 {
+                core::hint::spin_loop();
+            }
+            self.gate.write(control);
+        }
+        Ok(())
+    }
+}
+
+/// Advance the clock and wake the expired timers, must be called from the timer interrupt handler.
+pub fn tick() {
+    let now = clock::advance();
+    wheel::WHEEL.lock().expire(now);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divisor_range() {
+        assert_eq!(Pit::divisor(BASE_FREQUENCY / 2), Ok(2));
+        assert_eq!(Pit::divisor(1000), Ok(1193));
+        assert_eq!(Pit::divisor(19), Ok(62799));
+        assert_eq!(
+            Pit::divisor(BASE_FREQUENCY),
+            Err(Error::UnsupportedFrequency)
+        );
+        assert_eq!(Pit::divisor(18), Err(Error::UnsupportedFrequency));
+        assert_eq!(Pit::divisor(0), Err(Error::UnsupportedFrequency));
+    }
+}
@@ -0,0 +1,124 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    clock::{self, Instant},
+    wheel::{TimerId, WHEEL},
+};
+
+/// Future completing once the deadline is reached.
+#[must_use = "futures do nothing unless polled"]
+pub struct Sleep {
+    deadline: Instant,
+    timer: Option<TimerId>,
+}
+
+/// Sleep for at least `duration`.
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep::until(Instant::now() + duration)
+}
+
+impl Sleep {
+    pub const fn until(deadline: Instant) -> Self {
+        Self {
+            deadline,
+            timer: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Move the deadline, the sleep can be polled again even if it already completed.
+    pub fn reset(&mut self, deadline: Instant) {
+        self.cancel();
+        self.deadline = deadline;
+    }
+
+    fn cancel(&mut self) {
+        if let Some(id) = self.timer.take() {
+            let deadline = self.deadline.ticks();
+            libx64::without_interrupts(|| WHEEL.lock().remove(id, deadline));
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let deadline = self.deadline.ticks();
+
+        // the timer interrupt can't advance the clock between the check and the registration
+        libx64::without_interrupts(|| {
+            if clock::ticks() >= deadline {
+                self.timer = None;
+                return Poll::Ready(());
+            }
+
+            let mut wheel = WHEEL.lock();
+            match self.timer {
+                Some(id) if wheel.update(id, deadline, cx.waker()) => {}
+                _ => self.timer = Some(wheel.insert(deadline, cx.waker().clone())),
+            }
+            Poll::Pending
+        })
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Stream yielding every `period`, the first tick completes after one period.
+///
+/// Missed ticks are skipped rather than completed in a burst.
+#[must_use = "streams do nothing unless polled"]
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+
+pub fn interval(period: Duration) -> Interval {
+    Interval {
+        period,
+        sleep: sleep(period),
+    }
+}
+
+impl Interval {
+    #[must_use]
+    pub const fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Poll the next tick, returns the instant it was scheduled for.
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        match Pin::new(&mut self.sleep).poll(cx) {
+            Poll::Ready(()) => {
+                let deadline = self.sleep.deadline();
+                // a period shorter than a tick still waits for the next one
+                let next = (deadline + self.period).max(Instant::from_ticks(deadline.ticks() + 1));
+                self.sleep.reset(next.max(Instant::now()));
+                Poll::Ready(deadline)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl kcore::futures::stream::Stream for Interval {
+    type Item = Instant;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_tick(cx).map(Some)
+    }
+}
@@ -0,0 +1,176 @@
+use alloc::vec::Vec;
+use core::task::Waker;
+
+use kcore::sync::SpinMutex;
+
+/// Number of slots, timers further than a full rotation stay in their slot until their turn.
+const SLOTS: usize = 64;
+
+pub(crate) static WHEEL: SpinMutex<TimerWheel> = SpinMutex::new(TimerWheel::new());
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) struct TimerId(u64);
+
+struct Timer {
+    id: TimerId,
+    deadline: u64,
+    waker: Waker,
+}
+
+/// Hashed timer wheel, timers are stored in the slot of their deadline tick.
+///
+/// The wheel is accessed from the timer interrupt, it must only be locked with interrupts
+/// disabled.
+pub(crate) struct TimerWheel {
+    slots: [Vec<Timer>; SLOTS],
+    next_id: u64,
+}
+
+impl TimerWheel {
+    const fn new() -> Self {
+        const EMPTY: Vec<Timer> = Vec::new();
+        Self {
+            slots: [EMPTY; SLOTS],
+            next_id: 0,
+        }
+    }
+
+    const fn slot(deadline: u64) -> usize {
+        (deadline % SLOTS as u64) as usize
+    }
+
+    pub(crate) fn insert(&mut self, deadline: u64, waker: Waker) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+
+        self.slots[Self::slot(deadline)].push(Timer {
+            id,
+            deadline,
+            waker,
+        });
+        id
+    }
+
+    /// Replace the waker of a pending timer, returns false if the timer already expired.
+    pub(crate) fn update(&mut self, id: TimerId, deadline: u64, waker: &Waker) -> bool {
+        match self.slots[Self::slot(deadline)]
+            .iter_mut()
+            .find(|timer| timer.id == id)
+        {
+            Some(timer) => {
+                if !timer.waker.will_wake(waker) {
+                    timer.waker = waker.clone();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: TimerId, deadline: u64) {
+        let slot = &mut self.slots[Self::slot(deadline)];
+        if let Some(idx) = slot.iter().position(|timer| timer.id == id) {
+            slot.swap_remove(idx);
+        }
+    }
+
+    /// Wake the timers of the current slot whose deadline passed.
+    pub(crate) fn expire(&mut self, now: u64) {
+        let slot = &mut self.slots[Self::slot(now)];
+
+        let mut idx = 0;
+        while idx < slot.len() {
+            if slot[idx].deadline <= now {
+                slot.swap_remove(idx).waker.wake();
+            } else {
+                idx += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::{sync::Arc, task::Wake};
+
+    /// Waker pushing its deadline on a shared log.
+    struct Probe {
+        deadline: u64,
+        woken: Arc<SpinMutex<Vec<u64>>>,
+    }
+
+    impl Wake for Probe {
+        fn wake(self: Arc<Self>) {
+            self.woken.lock().push(self.deadline);
+        }
+    }
+
+    fn waker(woken: &Arc<SpinMutex<Vec<u64>>>, deadline: u64) -> Waker {
+        let probe = Probe {
+            deadline,
+            woken: Arc::clone(woken),
+        };
+        Arc::new(probe).into()
+    }
+
+    fn insert(wheel: &mut TimerWheel, woken: &Arc<SpinMutex<Vec<u64>>>, deadline: u64) -> TimerId {
+        wheel.insert(deadline, waker(woken, deadline))
+    }
+
+    fn expire(wheel: &mut TimerWheel, woken: &Arc<SpinMutex<Vec<u64>>>, now: u64) -> Vec<u64> {
+        wheel.expire(now);
+        core::mem::take(&mut *woken.lock())
+    }
+
+    #[test]
+    fn expires_in_deadline_order() {
+        let mut wheel = TimerWheel::new();
+        let woken = Arc::new(SpinMutex::new(Vec::new()));
+        for deadline in [3, 1, 2, 1 + SLOTS as u64] {
+            insert(&mut wheel, &woken, deadline);
+        }
+
+        assert_eq!(expire(&mut wheel, &woken, 1), [1]);
+        assert_eq!(expire(&mut wheel, &woken, 2), [2]);
+        assert_eq!(expire(&mut wheel, &woken, 3), [3]);
+        assert!(expire(&mut wheel, &woken, 4).is_empty());
+        assert_eq!(
+            expire(&mut wheel, &woken, 1 + SLOTS as u64),
+            [1 + SLOTS as u64]
+        );
+    }
+
+    #[test]
+    fn cancelled_timers_dont_fire() {
+        let mut wheel = TimerWheel::new();
+        let woken = Arc::new(SpinMutex::new(Vec::new()));
+        let cancelled = insert(&mut wheel, &woken, 5);
+        let updated = insert(&mut wheel, &woken, 5);
+
+        wheel.remove(cancelled, 5);
+        assert!(wheel.update(updated, 5, &waker(&woken, 6)));
+        assert!(!wheel.update(cancelled, 5, &waker(&woken, 5)));
+
+        // the updated timer wakes its new waker
+        assert_eq!(expire(&mut wheel, &woken, 5), [6]);
+        assert!(!wheel.update(updated, 5, &waker(&woken, 5)));
+    }
+
+    #[test]
+    fn slots_roll_over() {
+        let mut wheel = TimerWheel::new();
+        let woken = Arc::new(SpinMutex::new(Vec::new()));
+        let rotation = SLOTS as u64;
+        for deadline in [rotation - 1, rotation, 2 * rotation] {
+            insert(&mut wheel, &woken, deadline);
+        }
+
+        assert_eq!(expire(&mut wheel, &woken, rotation - 1), [rotation - 1]);
+        assert_eq!(expire(&mut wheel, &woken, rotation), [rotation]);
+        assert!(expire(&mut wheel, &woken, 2 * rotation - 1).is_empty());
+        // a tick skipped by a late interrupt is caught up on the next rotation
+        assert_eq!(expire(&mut wheel, &woken, 3 * rotation), [2 * rotation]);
+    }
+}
@@ -0,0 +1,162 @@
+use alloc::{alloc::Allocator, sync::Arc};
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+
+use kcore::{futures::task::AtomicWaker, sync::SpinMutex};
+
+use kalloc::shared::SharedAllocator;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum JoinError {
+    /// The task was cancelled through [`JoinHandle::abort`]
+    Aborted,
+    /// The task was dropped before it completed, (ie. the scheduler was dropped)
+    Dropped,
+}
+
+enum Slot<T> {
+    Pending,
+    Ready(T),
+    Failed(JoinError),
+    Taken,
+}
+
+/// State shared between a spawned task and its [`JoinHandle`]
+struct JoinState<T> {
+    slot: SpinMutex<Slot<T>>,
+    aborted: AtomicBool,
+    /// waker of the task awaiting the handle
+    join: AtomicWaker,
+    /// waker of the spawned task itself, used to run it one last time on abort
+    task: AtomicWaker,
+}
+
+impl<T> JoinState<T> {
+    fn complete(&self, slot: Slot<T>) {
+        {
+            let mut current = self.slot.lock();
+            if matches!(*current, Slot::Pending) {
+                *current = slot;
+            }
+        }
+        self.join.wake();
+    }
+}
+
+/// Future driven by the scheduler, stores the output of the spawned future for its handle.
+pub(crate) struct Harness<F: Future, A: Allocator> {
+    future: F,
+    state: Arc<JoinState<F::Output>, SharedAllocator<A>>,
+}
+
+impl<F, A> Harness<F, A>
+where
+    F: Future,
+    A: Allocator,
+{
+    pub(crate) fn new(future: F, alloc: SharedAllocator<A>) -> (Self, JoinHandle<F::Output, A>) {
+        let state = Arc::new_in(
+            JoinState {
+                slot: SpinMutex::new(Slot::Pending),
+                aborted: AtomicBool::new(false),
+                join: AtomicWaker::new(),
+                task: AtomicWaker::new(),
+            },
+            alloc,
+        );
+        let handle = JoinHandle {
+            state: Arc::clone(&state),
+        };
+        (Self { future, state }, handle)
+    }
+}
+
+impl<F, A> Future for Harness<F, A>
+where
+    F: Future,
+    A: Allocator,
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: the future is structurally pinned, the state is never moved out
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if this.state.aborted.load(Ordering::Acquire) {
+            this.state.complete(Slot::Failed(JoinError::Aborted));
+            return Poll::Ready(());
+        }
+        this.state.task.register(cx.waker());
+
+        match unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx) {
+            Poll::Ready(output) => {
+                this.state.complete(Slot::Ready(output));
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<F, A> Drop for Harness<F, A>
+where
+    F: Future,
+    A: Allocator,
+{
+    fn drop(&mut self) {
+        // noop if the task already completed
+        self.state.complete(Slot::Failed(JoinError::Dropped));
+    }
+}
+
+/// Handle to a spawned task, awaiting it yields the task output.
+///
+/// Dropping the handle detaches the task, it keeps running until completion.
+pub struct JoinHandle<T, A: Allocator> {
+    state: Arc<JoinState<T>, SharedAllocator<A>>,
+}
+
+impl<T, A> JoinHandle<T, A>
+where
+    A: Allocator,
+{
+    /// Cancel the task, it is dropped the next time the scheduler runs it and the handle
+    /// resolves to [`JoinError::Aborted`] unless it already completed.
+    pub fn abort(&self) {
+        self.state.aborted.store(true, Ordering::Release);
+        self.state.task.wake();
+    }
+
+    /// Whether the task completed, was aborted or dropped.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        !matches!(*self.state.slot.lock(), Slot::Pending)
+    }
+}
+
+impl<T, A> Future for JoinHandle<T, A>
+where
+    A: Allocator,
+{
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.state.join.register(cx.waker());
+
+        let mut slot = self.state.slot.lock();
+        match core::mem::replace(&mut *slot, Slot::Taken) {
+            Slot::Pending => {
+                *slot = Slot::Pending;
+                Poll::Pending
+            }
+            Slot::Ready(output) => Poll::Ready(Ok(output)),
+            Slot::Failed(err) => Poll::Ready(Err(err)),
+            Slot::Taken => panic!("JoinHandle polled after completion"),
+        }
+    }
+}
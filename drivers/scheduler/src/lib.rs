@@ -3,39 +3,113 @@
 
 extern crate alloc;
 
-use alloc::{alloc::Allocator, boxed::Box, collections::VecDeque};
+mod join;
+
+pub use join::{JoinError, JoinHandle};
+
+use alloc::{alloc::Allocator, boxed::Box, collections::BTreeMap, sync::Arc};
 
 use core::{
     future::Future,
     pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
     task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
 };
 
 use kalloc::shared::SharedAllocator;
+use kcore::queue::ArrayQueue;
+
+/// Number of tasks a [`Scheduler`] created with [`Scheduler::new`] can hold.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    /// The scheduler already holds as many tasks as its ready queue can track
+    Full,
+}
 
-struct SchedulerWaker;
+/// Ids of the woken tasks, in wake order.
+struct ReadyQueue {
+    ids: ArrayQueue<TaskId>,
+    /// A wake was dropped on a full queue, the woken tasks are looked up in the task list
+    overflowed: AtomicBool,
+}
+
+impl ReadyQueue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            ids: ArrayQueue::new(capacity),
+            overflowed: AtomicBool::new(false),
+        }
+    }
+
+    fn push(&self, id: TaskId) {
+        if self.ids.push(id).is_err() {
+            self.overflowed.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// Per task waker, waking pushes the task id on the scheduler ready queue.
+struct SchedulerWaker {
+    id: TaskId,
+    queued: AtomicBool,
+    ready: Arc<ReadyQueue>,
+}
 
 impl SchedulerWaker {
     const VTABLE: &'static RawWakerVTable =
         &RawWakerVTable::new(Self::clone, Self::wake, Self::wake_by_ref, Self::drop);
 
-    pub fn waker() -> Waker {
-        unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), Self::VTABLE)) }
+    fn new(id: TaskId, ready: Arc<ReadyQueue>) -> Arc<Self> {
+        Arc::new(Self {
+            id,
+            // the task is scheduled as soon as it is spawned
+            queued: AtomicBool::new(true),
+            ready,
+        })
+    }
+
+    pub fn waker(this: &Arc<Self>) -> Waker {
+        let ptr = Arc::into_raw(Arc::clone(this)).cast::<()>();
+        unsafe { Waker::from_raw(RawWaker::new(ptr, Self::VTABLE)) }
+    }
+
+    fn schedule(&self) {
+        // a task is pushed at most once until it is polled again, wakers run in interrupt
+        // handlers so a full queue of stale ids only flags the scheduler instead of panicking
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            self.ready.push(self.id);
+        }
     }
 
-    unsafe fn clone(_ptr: *const ()) -> RawWaker {
-        RawWaker::new(core::ptr::null(), Self::VTABLE)
+    unsafe fn clone(ptr: *const ()) -> RawWaker {
+        Arc::increment_strong_count(ptr.cast::<Self>());
+        RawWaker::new(ptr, Self::VTABLE)
     }
 
-    unsafe fn wake(_ptr: *const ()) {
-        todo!()
+    unsafe fn wake(ptr: *const ()) {
+        let this = Arc::from_raw(ptr.cast::<Self>());
+        this.schedule();
     }
 
-    unsafe fn wake_by_ref(_ptr: *const ()) {
-        todo!()
+    unsafe fn wake_by_ref(ptr: *const ()) {
+        (*ptr.cast::<Self>()).schedule();
     }
 
-    unsafe fn drop(_ptr: *const ()) {}
+    unsafe fn drop(ptr: *const ()) {
+        drop(Arc::from_raw(ptr.cast::<Self>()));
+    }
 }
 
 type TaskFuture = dyn Future<Output = ()>;
@@ -72,8 +146,15 @@ where
     }
 }
 
+struct Entry<A: Allocator> {
+    task: Task<A>,
+    waker: Waker,
+    state: Arc<SchedulerWaker>,
+}
+
 pub struct Scheduler<A: Allocator> {
-    tasks: VecDeque<Task<A>>,
+    tasks: BTreeMap<TaskId, Entry<A>>,
+    ready: Arc<ReadyQueue>,
     alloc: SharedAllocator<A>,
 }
 
@@ -82,27 +163,277 @@ where
     A: Allocator + 'static,
 {
     pub fn new(alloc: A) -> Self {
+        Self::with_capacity(alloc, DEFAULT_CAPACITY)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the capacity is zero
+    pub fn with_capacity(alloc: A, capacity: usize) -> Self {
         Self {
-            tasks: VecDeque::new(),
+            tasks: BTreeMap::new(),
+            ready: Arc::new(ReadyQueue::new(capacity)),
             alloc: SharedAllocator::new(alloc),
         }
     }
 
-    pub fn spawn<F>(&mut self, task: F)
+    /// Spawn a task, its output can be retrieved by awaiting the returned handle.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the scheduler is full
+    pub fn spawn<F>(&mut self, task: F) -> Result<JoinHandle<F::Output, A>, Error>
     where
-        F: Future<Output = ()> + 'static,
+        F: Future + 'static,
+        F::Output: 'static,
     {
-        self.tasks.push_back(Task::new(task, self.alloc.clone()));
+        if self.tasks.len() >= self.ready.ids.capacity() {
+            return Err(Error::Full);
+        }
+
+        let (harness, handle) = join::Harness::new(task, self.alloc.clone());
+
+        let id = TaskId::next();
+        let state = SchedulerWaker::new(id, Arc::clone(&self.ready));
+        let entry = Entry {
+            task: Task::new(harness, self.alloc.clone()),
+            waker: SchedulerWaker::waker(&state),
+            state,
+        };
+
+        if self.ready.ids.is_full() {
+            self.drop_stale();
+        }
+        self.ready.ids.push(id).map_err(|_| Error::Full)?;
+        self.tasks.insert(id, entry);
+        Ok(handle)
+    }
+
+    /// Remove the ids of completed tasks from the ready queue.
+    ///
+    /// A task woken while it is polled for the last time leaves its id in the queue, without this
+    /// a full queue of stale ids would refuse spawns while the task list has room.
+    fn drop_stale(&mut self) {
+        for _ in 0..self.ready.ids.len() {
+            let Some(id) = self.ready.ids.pop() else {
+                break;
+            };
+            if self.tasks.contains_key(&id) {
+                // live tasks are queued at most once so the id fits back in
+                let _ = self.ready.ids.push(id);
+            }
+        }
     }
 
+    /// Queue again every task that was woken, after a wake was dropped on a full queue.
+    fn requeue(&mut self) {
+        while self.ready.ids.pop().is_some() {}
+        for (id, entry) in &self.tasks {
+            if entry.state.queued.load(Ordering::Acquire) {
+                self.ready.push(*id);
+            }
+        }
+    }
+
+    /// Poll every task that was woken since its last poll, returns the number of polled tasks.
+    pub fn run_ready(&mut self) -> usize {
+        let mut polled = 0;
+
+        if self.ready.overflowed.swap(false, Ordering::AcqRel) {
+            self.requeue();
+        }
+        while let Some(id) = self.ready.ids.pop() {
+            // tasks that completed may still have wakers lying around
+            let Some(entry) = self.tasks.get_mut(&id) else {
+                continue;
+            };
+
+            // clear before polling so a wake during the poll schedules the task again
+            entry.state.queued.store(false, Ordering::Release);
+
+            let mut context = Context::from_waker(&entry.waker);
+            polled += 1;
+            if Pin::new(&mut entry.task).poll(&mut context).is_ready() {
+                // leftover wakers must not push the id again
+                entry.state.queued.store(true, Ordering::Release);
+                self.tasks.remove(&id);
+            }
+        }
+        polled
+    }
+
+    /// Run the tasks until all of them complete, halting the cpu while none is ready.
     pub fn run(&mut self) {
-        while let Some(mut task) = self.tasks.pop_front() {
-            let waker = SchedulerWaker::waker();
-            let mut context = Context::from_waker(&waker);
-            match Pin::new(&mut task).poll(&mut context) {
-                Poll::Ready(_a) => (),
-                Poll::Pending => self.tasks.push_back(task),
+        while !self.tasks.is_empty() {
+            self.run_ready();
+
+            // wakers are mostly called from interrupt handlers, interrupts are disabled while
+            // checking the queue so a wake can't slip in between the check and the halt
+            libx64::cli();
+            if self.ready.ids.is_empty() && !self.tasks.is_empty() {
+                libx64::sti_hlt();
+            } else {
+                libx64::sti();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::{alloc::Global, rc::Rc};
+    use core::cell::{Cell, RefCell};
+
+    /// Task pending until `done` is set, keeps the waker of its last poll.
+    #[derive(Default)]
+    struct Probe {
+        polls: Cell<usize>,
+        done: Cell<bool>,
+        waker: RefCell<Option<Waker>>,
+    }
+
+    impl Probe {
+        fn task(self: &Rc<Self>) -> impl Future<Output = ()> {
+            let probe = Rc::clone(self);
+            core::future::poll_fn(move |cx| {
+                probe.polls.set(probe.polls.get() + 1);
+                if probe.done.get() {
+                    return Poll::Ready(());
+                }
+                *probe.waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            })
+        }
+
+        fn wake(&self) {
+            self.waker.borrow().as_ref().unwrap().wake_by_ref();
+        }
+    }
+
+    fn noop_waker() -> Waker {
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(
+            |_| RawWaker::new(core::ptr::null(), &VTABLE),
+            |_| {},
+            |_| {},
+            |_| {},
+        );
+        unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+    }
+
+    fn join<T, A: Allocator>(handle: &mut JoinHandle<T, A>) -> Poll<Result<T, JoinError>> {
+        Pin::new(handle).poll(&mut Context::from_waker(&noop_waker()))
+    }
+
+    #[test]
+    fn repeated_wakes_poll_once() {
+        let mut scheduler = Scheduler::new(Global);
+        let probe = Rc::new(Probe::default());
+        scheduler.spawn(probe.task()).unwrap();
+        assert_eq!(scheduler.run_ready(), 1);
+        assert_eq!(scheduler.run_ready(), 0);
+
+        for _ in 0..3 {
+            probe.wake();
+        }
+        assert_eq!(scheduler.run_ready(), 1);
+        assert_eq!(probe.polls.get(), 2);
+    }
+
+    #[test]
+    fn finished_task_isnt_polled() {
+        let mut scheduler = Scheduler::new(Global);
+        let probe = Rc::new(Probe::default());
+        scheduler.spawn(probe.task()).unwrap();
+        scheduler.run_ready();
+
+        probe.done.set(true);
+        probe.wake();
+        assert_eq!(scheduler.run_ready(), 1);
+        assert!(scheduler.tasks.is_empty());
+
+        // the waker outlives the task
+        probe.wake();
+        assert!(scheduler.ready.ids.is_empty());
+        assert_eq!(scheduler.run_ready(), 0);
+        assert_eq!(probe.polls.get(), 2);
+    }
+
+    #[test]
+    fn full_queue_drops_the_wake() {
+        let mut scheduler = Scheduler::with_capacity(Global, 2);
+        let probes = [Rc::new(Probe::default()), Rc::new(Probe::default())];
+        for probe in &probes {
+            scheduler.spawn(probe.task()).unwrap();
+        }
+        assert_eq!(scheduler.run_ready(), 2);
+        assert!(matches!(scheduler.spawn(async {}), Err(Error::Full)));
+
+        // ids of tasks that are gone fill the queue
+        scheduler.ready.ids.push(TaskId(u64::MAX)).unwrap();
+        scheduler.ready.ids.push(TaskId(u64::MAX - 1)).unwrap();
+        probes[1].wake();
+        assert!(scheduler.ready.overflowed.load(Ordering::Acquire));
+
+        assert_eq!(scheduler.run_ready(), 1);
+        assert_eq!(probes[0].polls.get(), 1);
+        assert_eq!(probes[1].polls.get(), 2);
+        assert!(!scheduler.ready.overflowed.load(Ordering::Acquire));
+    }
+
+    #[test]
+    fn join_yields_the_output() {
+        let mut scheduler = Scheduler::new(Global);
+        let mut handle = scheduler.spawn(async { 7 }).unwrap();
+        assert!(!handle.is_finished());
+        assert!(join(&mut handle).is_pending());
+
+        scheduler.run_ready();
+        assert!(handle.is_finished());
+        assert_eq!(join(&mut handle), Poll::Ready(Ok(7)));
+    }
+
+    #[test]
+    fn join_wakes_the_awaiting_task() {
+        let mut scheduler = Scheduler::new(Global);
+        let probe = Rc::new(Probe::default());
+        let handle = scheduler.spawn(probe.task()).unwrap();
+        let output = Rc::new(Cell::new(None));
+        let joined = Rc::clone(&output);
+        scheduler
+            .spawn(async move { joined.set(Some(handle.await)) })
+            .unwrap();
+        assert_eq!(scheduler.run_ready(), 2);
+
+        probe.done.set(true);
+        probe.wake();
+        // the probe completes and wakes the task awaiting it
+        assert_eq!(scheduler.run_ready(), 2);
+        assert_eq!(output.get(), Some(Ok(())));
+        assert!(scheduler.tasks.is_empty());
+    }
+
+    #[test]
+    fn abort_cancels_the_task() {
+        let mut scheduler = Scheduler::new(Global);
+        let probe = Rc::new(Probe::default());
+        let mut handle = scheduler.spawn(probe.task()).unwrap();
+        scheduler.run_ready();
+
+        handle.abort();
+        assert_eq!(scheduler.run_ready(), 1);
+        assert_eq!(probe.polls.get(), 1);
+        assert_eq!(join(&mut handle), Poll::Ready(Err(JoinError::Aborted)));
+        assert!(scheduler.tasks.is_empty());
+    }
+
+    #[test]
+    fn dropped_scheduler_fails_the_handle() {
+        let mut scheduler = Scheduler::new(Global);
+        let mut handle = scheduler.spawn(core::future::pending::<()>()).unwrap();
+        drop(scheduler);
+        assert!(handle.is_finished());
+        assert_eq!(join(&mut handle), Poll::Ready(Err(JoinError::Dropped)));
+    }
+}
@@ -0,0 +1,330 @@
+//! Interrupt driven serial port
+//!
+//! The bytes received and the bytes waiting to be sent are kept in rings of `N` bytes, the
+//! handler of the IRQ of the port moves them between the rings and the FIFOs of the UART. The
+//! ring state is locked with interrupts disabled so it is never held by a preempted writer.
+
+use core::{
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use kcore::{
+    futures::{sink::Sink, stream::Stream},
+    sync::SpinMutex,
+};
+
+use crate::{ring::Ring, Config, Error, IntEnFlags, LineError, LineStsFlags, SerialPort};
+
+/// Bytes written to the transmit FIFO at once
+const FIFO_SIZE: usize = 16;
+
+/// Line errors since the port was initialized.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct ErrorCounts {
+    pub overrun: u64,
+    pub parity: u64,
+    pub framing: u64,
+    pub breaks: u64,
+    /// Bytes lost because the receive ring was full
+    pub dropped: u64,
+}
+
+struct Inner<const N: usize> {
+    port: SerialPort,
+    rx: Ring<N>,
+    tx: Ring<N>,
+    /// Reported before the bytes received after it
+    error: Option<LineError>,
+    errors: ErrorCounts,
+    rx_waker: Option<Waker>,
+    tx_waker: Option<Waker>,
+    /// The transmitter empty interrupt is enabled while `tx` isn't empty
+    transmitting: bool,
+}
+
+impl<const N: usize> Inner<N> {
+    fn line_sts(&mut self) -> LineStsFlags {
+        let status = self.port.line_sts();
+        if let Some(error) = status.error() {
+            self.error = Some(error);
+            match error {
+                LineError::Overrun => self.errors.overrun += 1,
+                LineError::Parity => self.errors.parity += 1,
+                LineError::Framing => self.errors.framing += 1,
+                LineError::Break => self.errors.breaks += 1,
+            }
+        }
+        status
+    }
+
+    fn receive(&mut self) {
+        while self.line_sts().contains(LineStsFlags::INPUT_FULL) {
+            let byte = unsafe { self.port.data.read() };
+            if !self.rx.push(byte) {
+                self.errors.dropped += 1;
+            }
+        }
+    }
+
+    /// Fill the transmit FIFO if it is empty, the interrupt is raised again once it is.
+    fn transmit(&mut self) {
+        if self.line_sts().contains(LineStsFlags::OUTPUT_EMPTY) {
+            for _ in 0..FIFO_SIZE {
+                let Some(byte) = self.tx.pop() else {
+                    break;
+                };
+                unsafe { self.port.data.write(byte) };
+            }
+        }
+
+        let transmitting = !self.tx.is_empty();
+        if transmitting != self.transmitting {
+            self.transmitting = transmitting;
+            let mut flags = IntEnFlags::RECEIVED | IntEnFlags::ERRORED;
+            flags.set(IntEnFlags::SENT, transmitting);
+            self.port.set_interrupts(flags);
+        }
+    }
+
+    /// Wakers of the tasks that can make progress.
+    fn wakers(&mut self) -> (Option<Waker>, Option<Waker>) {
+        let rx = if self.rx.is_empty() && self.error.is_none() {
+            None
+        } else {
+            self.rx_waker.take()
+        };
+        let tx = if self.tx.is_full() {
+            None
+        } else {
+            self.tx_waker.take()
+        };
+        (rx, tx)
+    }
+}
+
+/// Serial port driven by its interrupts.
+pub struct Uart<const N: usize> {
+    inner: SpinMutex<Inner<N>>,
+}
+
+impl<const N: usize> Uart<N> {
+    #[must_use]
+    pub const fn new(port: SerialPort) -> Self {
+        Self {
+            inner: SpinMutex::new(Inner {
+                port,
+                rx: Ring::new(),
+                tx: Ring::new(),
+                error: None,
+                errors: ErrorCounts {
+                    overrun: 0,
+                    parity: 0,
+                    framing: 0,
+                    breaks: 0,
+                    dropped: 0,
+                },
+                rx_waker: None,
+                tx_waker: None,
+                transmitting: false,
+            }),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut Inner<N>) -> R) -> R {
+        libx64::without_interrupts(|| f(&mut self.inner.lock()))
+    }
+
+    /// Configure the line and enable the receive and line status interrupts, the IRQ of the
+    /// port must then be routed to [`Uart::handle_interrupt`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if the configuration isn't supported
+    pub fn init(&self, config: Config) -> Result<(), Error> {
+        self.with(|inner| {
+            inner.port.configure(config)?;
+            inner
+                .port
+                .set_interrupts(IntEnFlags::RECEIVED | IntEnFlags::ERRORED);
+            inner.transmitting = false;
+            inner.transmit();
+            Ok(())
+        })
+    }
+
+    /// Move the bytes between the rings and the UART, called by the handler of the IRQ.
+    pub fn handle_interrupt(&self) {
+        let (rx, tx) = self.with(|inner| {
+            while inner.port.pending() {
+                inner.receive();
+                inner.transmit();
+            }
+            inner.wakers()
+        });
+        rx.into_iter().chain(tx).for_each(Waker::wake);
+    }
+
+    /// Read the received bytes without blocking.
+    ///
+    /// # Errors
+    ///
+    /// Returns the line error detected before the bytes still in the ring
+    pub fn read(&self, buffer: &mut [u8]) -> Result<usize, LineError> {
+        self.with(|inner| {
+            if let Some(error) = inner.error.take() {
+                return Err(error);
+            }
+            let mut n = 0;
+            for slot in buffer.iter_mut() {
+                let Some(byte) = inner.rx.pop() else {
+                    break;
+                };
+                *slot = byte;
+                n += 1;
+            }
+            Ok(n)
+        })
+    }
+
+    /// Queue the bytes that fit in the transmit ring, returns how many were queued.
+    pub fn write(&self, bytes: &[u8]) -> usize {
+        self.with(|inner| {
+            let n = inner.tx.extend(bytes);
+            inner.transmit();
+            n
+        })
+    }
+
+    /// Queue all the bytes, the transmit FIFO is fed by polling while the ring is full so this
+    /// doesn't depend on interrupts being enabled.
+    pub fn write_all(&self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let n = self.with(|inner| {
+                let n = inner.tx.extend(bytes);
+                if n == 0 {
+                    wait_for!(inner.line_sts().contains(LineStsFlags::OUTPUT_EMPTY));
+                }
+                inner.transmit();
+                n
+            });
+            bytes = &bytes[n..];
+        }
+    }
+
+    /// Send the queued bytes by polling, used when the interrupts won't be enabled again.
+    pub fn flush(&self) {
+        self.with(|inner| {
+            while !inner.tx.is_empty() {
+                wait_for!(inner.line_sts().contains(LineStsFlags::OUTPUT_EMPTY));
+                inner.transmit();
+            }
+        });
+    }
+
+    pub fn errors(&self) -> ErrorCounts {
+        self.with(|inner| inner.errors)
+    }
+
+    #[must_use]
+    pub const fn reader(&self) -> Reader<'_, N> {
+        Reader(self)
+    }
+
+    #[must_use]
+    pub const fn writer(&self) -> Writer<'_, N> {
+        Writer(self)
+    }
+}
+
+/// Received bytes of a [`Uart`], the line errors are yielded in order with the bytes.
+pub struct Reader<'a, const N: usize>(&'a Uart<N>);
+
+impl<const N: usize> Stream for Reader<'_, N> {
+    type Item = Result<u8, LineError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.with(|inner| {
+            if let Some(error) = inner.error.take() {
+                return Poll::Ready(Some(Err(error)));
+            }
+            if let Some(byte) = inner.rx.pop() {
+                return Poll::Ready(Some(Ok(byte)));
+            }
+            inner.rx_waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+    }
+}
+
+impl<const N: usize> kio::read::AsyncRead for Reader<'_, N> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut [u8],
+    ) -> Poll<kio::Result<usize>> {
+        match self.0.read(buffer) {
+            Ok(0) if !buffer.is_empty() => {
+                self.0.with(|inner| {
+                    // a byte may have been received since the read
+                    if inner.rx.is_empty() && inner.error.is_none() {
+                        inner.rx_waker = Some(cx.waker().clone());
+                    } else {
+                        cx.waker().wake_by_ref();
+                    }
+                });
+                Poll::Pending
+            }
+            Ok(n) => Poll::Ready(Ok(n)),
+            Err(error) => Poll::Ready(Err(error.into())),
+        }
+    }
+}
+
+/// Transmit half of a [`Uart`].
+pub struct Writer<'a, const N: usize>(&'a Uart<N>);
+
+impl<const N: usize> Sink<u8> for Writer<'_, N> {
+    type Error = kio::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.with(|inner| {
+            if inner.tx.is_full() {
+                inner.tx_waker = Some(cx.waker().clone());
+                Poll::Pending
+            } else {
+                Poll::Ready(Ok(()))
+            }
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: u8) -> Result<(), Self::Error> {
+        match self.0.write(&[item]) {
+            0 => Err(kio::Error::new(kio::ErrorKind::StorageFull)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The bytes are flushed once they are all in the transmit FIFO.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.with(|inner| {
+            if inner.tx.is_empty() {
+                Poll::Ready(Ok(()))
+            } else {
+                inner.tx_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl<const N: usize> kio::write::Write for Writer<'_, N> {
+    fn write(&mut self, buffer: &[u8]) -> kio::Result<usize> {
+        self.0.write_all(buffer);
+        Ok(buffer.len())
+    }
+}
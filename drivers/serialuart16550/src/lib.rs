@@ -4,6 +4,8 @@ use bitflags::bitflags;
 
 use libx64::port::{Port, RPort, RWPort, WPort};
 
+pub use buffered::{ErrorCounts, Reader, Uart, Writer};
+
 bitflags! {
     /// Interrupt enable flags
     struct IntEnFlags: u8 {
@@ -18,12 +20,32 @@ bitflags! {
     /// Line status flags
     struct LineStsFlags: u8 {
         const INPUT_FULL = 1;
-        // 1 to 4 unknown
+        const OVERRUN_ERROR = 1 << 1;
+        const PARITY_ERROR = 1 << 2;
+        const FRAMING_ERROR = 1 << 3;
+        const BREAK = 1 << 4;
         const OUTPUT_EMPTY = 1 << 5;
         // 6 and 7 unknown
     }
 }
 
+impl LineStsFlags {
+    /// A break also sets the framing error, it is reported first.
+    fn error(self) -> Option<LineError> {
+        if self.contains(Self::OVERRUN_ERROR) {
+            Some(LineError::Overrun)
+        } else if self.contains(Self::BREAK) {
+            Some(LineError::Break)
+        } else if self.contains(Self::FRAMING_ERROR) {
+            Some(LineError::Framing)
+        } else if self.contains(Self::PARITY_ERROR) {
+            Some(LineError::Parity)
+        } else {
+            None
+        }
+    }
+}
+
 macro_rules! wait_for {
     ($cond:expr) => {
         #[allow(clippy::semicolon_if_nothing_returned)]
@@ -33,10 +55,137 @@ macro_rules! wait_for {
     };
 }
 
+mod buffered;
+mod ring;
+
+/// Clock of the divisor latch, a divisor of 1 is the fastest rate
+const MAX_BAUD_RATE: u32 = 115_200;
+
+/// Standard I/O ports of the PC serial ports.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Com {
+    Com1,
+    Com2,
+    Com3,
+    Com4,
+}
+
+impl Com {
+    #[must_use]
+    pub const fn base(self) -> u16 {
+        match self {
+            Self::Com1 => 0x3F8,
+            Self::Com2 => 0x2F8,
+            Self::Com3 => 0x3E8,
+            Self::Com4 => 0x2E8,
+        }
+    }
+
+    /// ISA IRQ of the port, COM3 and COM4 share the lines of COM1 and COM2.
+    #[must_use]
+    pub const fn irq(self) -> u8 {
+        match self {
+            Self::Com1 | Self::Com3 => 4,
+            Self::Com2 | Self::Com4 => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(u8)]
+pub enum DataBits {
+    Five = 0,
+    Six = 1,
+    Seven = 2,
+    Eight = 3,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Parity {
+    None = 0b000,
+    Odd = 0b001,
+    Even = 0b011,
+    /// The parity bit is always set
+    Mark = 0b101,
+    /// The parity bit is always cleared
+    Space = 0b111,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(u8)]
+pub enum StopBits {
+    One = 0,
+    /// One and a half with five data bits
+    Two = 1,
+}
+
+/// Line settings of a serial port.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Config {
+    /// Must divide 115200
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Config {
+    /// [38400/8-N-1](https://en.wikipedia.org/wiki/8-N-1)
+    pub const DEFAULT: Self = Self {
+        baud_rate: 38400,
+        data_bits: DataBits::Eight,
+        parity: Parity::None,
+        stop_bits: StopBits::One,
+    };
+
+    fn divisor(self) -> Option<u16> {
+        if self.baud_rate == 0 || MAX_BAUD_RATE % self.baud_rate != 0 {
+            return None;
+        }
+        u16::try_from(MAX_BAUD_RATE / self.baud_rate).ok()
+    }
+
+    const fn line_ctrl(self) -> u8 {
+        self.data_bits as u8 | (self.stop_bits as u8) << 2 | (self.parity as u8) << 3
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    /// The baud rate doesn't divide 115200
+    InvalidBaudRate(u32),
+}
+
+/// Errors detected by the UART on the received bytes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LineError {
+    /// A byte was received before the previous one was read and is lost
+    Overrun,
+    Parity,
+    /// The stop bit of a byte was missing
+    Framing,
+    /// The line was held low for longer than a byte
+    Break,
+}
+
+impl From<LineError> for kio::Error {
+    fn from(_: LineError) -> Self {
+        kio::Error::new(kio::ErrorKind::InvalidData)
+    }
+}
+
 #[derive(Debug)]
 pub struct SerialPort {
     data: RWPort<u8>,
     int_en: WPort<u8>,
+    int_id: RPort<u8>,
     fifo_ctrl: WPort<u8>,
     line_ctrl: WPort<u8>,
     modem_ctrl: WPort<u8>,
@@ -54,6 +203,7 @@ impl SerialPort {
         Self {
             data: Port::new(base),
             int_en: WPort::new(base + 1),
+            int_id: RPort::new(base + 2),
             fifo_ctrl: WPort::new(base + 2),
             line_ctrl: WPort::new(base + 3),
             modem_ctrl: WPort::new(base + 4),
@@ -61,10 +211,34 @@ impl SerialPort {
         }
     }
 
+    /// Creates the interface of a standard serial port.
+    ///
+    /// # Safety
+    /// The caller must ensure the port exists and isn't driven elsewhere.
+    #[must_use]
+    pub const unsafe fn com(port: Com) -> Self {
+        Self::new(port.base())
+    }
+
     /// Initializes the serial port.
     ///
     /// The default configuration of [38400/8-N-1](https://en.wikipedia.org/wiki/8-N-1) is used.
     pub fn init(&mut self) {
+        self.configure(Config::DEFAULT)
+            .expect("default configuration is valid");
+    }
+
+    /// Initializes the serial port with the line settings of `config`, received bytes raise an
+    /// interrupt.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the baud rate isn't supported
+    pub fn configure(&mut self, config: Config) -> Result<(), Error> {
+        let [dll, dlm] = config
+            .divisor()
+            .ok_or(Error::InvalidBaudRate(config.baud_rate))?
+            .to_le_bytes();
         unsafe {
             // Disable interrupts
             self.int_en.write(0x00);
@@ -72,12 +246,12 @@ impl SerialPort {
             // Enable DLAB
             self.line_ctrl.write(0x80);
 
-            // Set maximum speed to 38400 bps by configuring DLL and DLM
-            self.data.write(0x03);
-            self.int_en.write(0x00);
+            // Set the speed by configuring DLL and DLM
+            self.data.write(dll);
+            self.int_en.write(dlm);
 
-            // Disable DLAB and set data word length to 8 bits
-            self.line_ctrl.write(0x03);
+            // Disable DLAB and set the data word length, parity and stop bits
+            self.line_ctrl.write(config.line_ctrl());
 
             // Enable FIFO, clear TX/RX queues and
             // set interrupt watermark at 14 bytes
@@ -88,8 +262,19 @@ impl SerialPort {
             self.modem_ctrl.write(0x0B);
 
             // Enable interrupts
-            self.int_en.write(0x01);
+            self.int_en.write(IntEnFlags::RECEIVED.bits());
         }
+        Ok(())
+    }
+
+    fn set_interrupts(&mut self, flags: IntEnFlags) {
+        unsafe { self.int_en.write(flags.bits()) };
+    }
+
+    /// Whether an interrupt is pending, reading the identification acknowledges the transmitter
+    /// empty interrupt.
+    fn pending(&mut self) -> bool {
+        unsafe { self.int_id.read() & 1 == 0 }
     }
 
     fn line_sts(&mut self) -> LineStsFlags {
@@ -131,6 +316,13 @@ impl SerialPort {
             self.data.read()
         }
     }
+
+    /// Receives a byte if one is waiting in the FIFO.
+    pub fn try_receive(&mut self) -> Option<u8> {
+        self.line_sts()
+            .contains(LineStsFlags::INPUT_FULL)
+            .then(|| unsafe { self.data.read() })
+    }
 }
 
 impl kio::write::Write for SerialPort {
@@ -145,9 +337,71 @@ impl kio::write::Write for SerialPort {
     }
 }
 
+impl kio::read::Read for SerialPort {
+    /// Blocks until a byte is received then reads the bytes already waiting in the FIFO.
+    fn read(&mut self, buffer: &mut [u8]) -> kio::Result<usize> {
+        let Some((first, rest)) = buffer.split_first_mut() else {
+            return Ok(0);
+        };
+        *first = self.receive();
+        let mut n = 1;
+        for byte in rest {
+            match self.try_receive() {
+                Some(received) => *byte = received,
+                None => break,
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
 impl core::fmt::Write for SerialPort {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
         s.chars().into_iter().for_each(|b| self.send_char(b));
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(baud_rate: u32) -> Config {
+        Config {
+            baud_rate,
+            ..Config::DEFAULT
+        }
+    }
+
+    #[test]
+    fn divisor() {
+        assert_eq!(config(115_200).divisor(), Some(1));
+        assert_eq!(Config::DEFAULT.divisor(), Some(3));
+        assert_eq!(config(50).divisor(), Some(2304));
+
+        assert_eq!(config(0).divisor(), None);
+        assert_eq!(config(100_000).divisor(), None);
+        assert_eq!(config(230_400).divisor(), None);
+    }
+
+    #[test]
+    fn line_ctrl() {
+        assert_eq!(Config::DEFAULT.line_ctrl(), 0b0000_0011);
+
+        let config = Config {
+            data_bits: DataBits::Seven,
+            parity: Parity::Even,
+            stop_bits: StopBits::Two,
+            ..Config::DEFAULT
+        };
+        assert_eq!(config.line_ctrl(), 0b0001_1110);
+
+        let config = Config {
+            data_bits: DataBits::Five,
+            parity: Parity::Space,
+            ..Config::DEFAULT
+        };
+        assert_eq!(config.line_ctrl(), 0b0011_1000);
+    }
+}
@@ -0,0 +1,108 @@
+/// Fixed size FIFO of bytes.
+pub struct Ring<const N: usize> {
+    bytes: [u8; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Ring<N> {
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Returns `false` if the ring is full.
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.bytes[(self.head + self.len) % N] = byte;
+        self.len += 1;
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+        let byte = self.bytes[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+
+    /// Push the bytes that fit, returns how many were pushed.
+    pub fn extend(&mut self, bytes: &[u8]) -> usize {
+        let n = bytes.len().min(N - self.len);
+        for &byte in &bytes[..n] {
+            self.push(byte);
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ring;
+
+    #[test]
+    fn full_and_empty() {
+        let mut ring = Ring::<2>::new();
+        assert!(ring.is_empty());
+        assert_eq!(ring.pop(), None);
+
+        assert!(ring.push(1));
+        assert!(ring.push(2));
+        assert!(ring.is_full());
+        assert!(!ring.push(3));
+
+        assert_eq!(ring.pop(), Some(1));
+        assert_eq!(ring.pop(), Some(2));
+        assert!(ring.is_empty());
+        assert_eq!(ring.pop(), None);
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut ring = Ring::<4>::new();
+        assert_eq!(ring.extend(&[1, 2, 3]), 3);
+        assert_eq!(ring.pop(), Some(1));
+        assert_eq!(ring.pop(), Some(2));
+
+        // the head is at 2, the last pushes wrap to the start of the buffer
+        assert!(ring.push(4));
+        assert!(ring.push(5));
+        assert!(ring.push(6));
+        assert!(ring.is_full());
+        for byte in 3..=6 {
+            assert_eq!(ring.pop(), Some(byte));
+        }
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn extend_stops_when_full() {
+        let mut ring = Ring::<4>::new();
+        assert_eq!(ring.extend(&[]), 0);
+        assert_eq!(ring.extend(&[1, 2, 3]), 3);
+        assert_eq!(ring.pop(), Some(1));
+
+        assert_eq!(ring.extend(&[4, 5, 6]), 2);
+        assert!(ring.is_full());
+        assert_eq!(ring.extend(&[7]), 0);
+        for byte in [2, 3, 4, 5] {
+            assert_eq!(ring.pop(), Some(byte));
+        }
+    }
+}
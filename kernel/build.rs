@@ -0,0 +1,40 @@
+//! Assemble the user programs embedded in the kernel image until there is a filesystem.
+use std::{
+    env,
+    path::PathBuf,
+    process::{self, Command},
+};
+
+/// Sources in `user/`, each one is linked as a position independent executable
+const PROGRAMS: &[&str] = &["hello"];
+
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR not set"));
+
+    for program in PROGRAMS {
+        let source = format!("user/{}.S", program);
+        println!("cargo:rerun-if-changed={}", source);
+
+        let object = out_dir.join(format!("{}.o", program));
+        let mut cmd = Command::new("as");
+        cmd.args(["--64", "-o"]).arg(&object).arg(&source);
+        run(cmd, "as");
+
+        let mut cmd = Command::new("ld");
+        cmd.args(["-pie", "--no-dynamic-linker"]);
+        cmd.args(["-z", "max-page-size=4096", "-z", "noexecstack"]);
+        cmd.arg("-o").arg(out_dir.join(program)).arg(&object);
+        run(cmd, "ld");
+    }
+}
+
+fn run(mut cmd: Command, name: &str) {
+    let exit_status = cmd
+        .status()
+        .unwrap_or_else(|err| panic!("failed to run {}: {}", name, err));
+    if !exit_status.success() {
+        eprintln!("Error: Running {} failed", name);
+        process::exit(1);
+    }
+}
+
@@ -0,0 +1,253 @@
+use libx64::address::PhysicalAddr;
+
+use super::{Error, Fields, PhysicalWindow, Register, SdtHeader};
+
+pub const SIGNATURE: &[u8; 4] = b"FACP";
+
+/// # ACPI Power Management Timer
+///
+/// Free running counter at 3.579545MHz, 24 or 32 bits wide.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PmTimer {
+    pub register: Register,
+    /// The counter is 32 bits wide instead of 24
+    pub extended: bool,
+}
+
+impl PmTimer {
+    pub const FREQUENCY: u32 = 3_579_545;
+
+    #[must_use]
+    pub fn read(&self) -> u32 {
+        // SAFETY: reading the counter has no side effect
+        let value = unsafe { self.register.read() };
+        if self.extended {
+            value
+        } else {
+            value & 0x00FF_FFFF
+        }
+    }
+}
+
+/// Register and value written to reset the machine.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ResetRegister {
+    pub register: Register,
+    pub value: u8,
+}
+
+/// `SLP_TYP` values of the soft off sleep state, found in the `\_S5` package of the DSDT.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SoftOff {
+    pub pm1a: u16,
+    pub pm1b: u16,
+}
+
+/// # Fixed ACPI Description Table
+///
+/// Fields past the end of an older revision of the table are left out.
+///
+/// ## Format
+///
+/// Offset | Size | Field
+/// -------|------|-------------------------------------------
+/// 40     | 4    | DSDT physical address
+/// 46     | 2    | SCI interrupt
+/// 48     | 4    | SMI command port
+/// 52     | 1    | Value written to the SMI port to enable ACPI
+/// 64     | 4    | PM1a control block port
+/// 68     | 4    | PM1b control block port
+/// 76     | 4    | PM timer block port
+/// 89     | 1    | PM1 control block length
+/// 109    | 2    | IA-PC boot architecture flags
+/// 112    | 4    | Feature flags
+/// 116    | 12   | Reset register
+/// 128    | 1    | Reset value
+/// 140    | 8    | 64 bits DSDT physical address
+/// 172    | 12   | Extended PM1a control block
+/// 184    | 12   | Extended PM1b control block
+/// 208    | 12   | Extended PM timer block
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub dsdt: PhysicalAddr,
+    pub sci_interrupt: u16,
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub boot_flags: u16,
+    pub pm1a_control: Option<Register>,
+    pub pm1b_control: Option<Register>,
+    pub pm_timer: Option<PmTimer>,
+    pub reset: Option<ResetRegister>,
+    pub soft_off: Option<SoftOff>,
+}
+
+impl Fadt {
+    const TMR_VAL_EXT: u32 = 1 << 8;
+    const RESET_REG_SUP: u32 = 1 << 10;
+
+    /// `SCI_EN` bit of PM1 control, set once the firmware handed ACPI over
+    const SCI_EN: u16 = 1 << 0;
+    const SLP_TYP_SHIFT: u16 = 10;
+    const SLP_EN: u16 = 1 << 13;
+
+    /// Parse the table and look up the soft off state in the DSDT.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the table is too short or a register isn't in memory or I/O space
+    pub(super) fn parse(window: &PhysicalWindow, table: &[u8]) -> Result<Self, Error> {
+        let truncated = Error::Truncated(*SIGNATURE);
+        let fields = Fields::new(table);
+        let extended = |offset| match fields.slice(offset, 12) {
+            Some(gas) => Register::parse(window, gas),
+            None => Ok(None),
+        };
+
+        let flags = fields.u32(112).unwrap_or(0);
+        let pm1_width = fields.u8(89).ok_or(truncated)?.saturating_mul(8);
+
+        let dsdt = fields
+            .u64(140)
+            .filter(|&dsdt| dsdt != 0)
+            .or_else(|| fields.u32(40).map(u64::from))
+            .map(PhysicalAddr::new)
+            .ok_or(truncated)?;
+
+        let pm_timer = match extended(208)? {
+            Some(register) => Some(register),
+            None => Register::port(fields.u32(76).ok_or(truncated)?, 32),
+        }
+        .map(|register| PmTimer {
+            register,
+            extended: flags & Self::TMR_VAL_EXT != 0,
+        });
+
+        let reset = match fields.slice(116, 12) {
+            Some(gas) if flags & Self::RESET_REG_SUP != 0 => Register::parse(window, gas)?
+                .map(|register| ResetRegister {
+                    register,
+                    value: fields.u8(128).unwrap_or(0),
+                }),
+            _ => None,
+        };
+
+        let mut fadt = Self {
+            dsdt,
+            sci_interrupt: fields.u16(46).ok_or(truncated)?,
+            smi_command: fields.u32(48).ok_or(truncated)?,
+            acpi_enable: fields.u8(52).ok_or(truncated)?,
+            boot_flags: fields.u16(109).unwrap_or(0),
+            pm1a_control: match extended(172)? {
+                Some(register) => Some(register),
+                None => Register::port(fields.u32(64).ok_or(truncated)?, pm1_width),
+            },
+            pm1b_control: match extended(184)? {
+                Some(register) => Some(register),
+                None => Register::port(fields.u32(68).ok_or(truncated)?, pm1_width),
+            },
+            pm_timer,
+            reset,
+            soft_off: None,
+        };
+
+        // SAFETY: the DSDT address comes from a checksummed FADT
+        match unsafe { window.table(fadt.dsdt) } {
+            Ok((_, dsdt)) => fadt.soft_off = find_soft_off(&dsdt[SdtHeader::SIZE..]),
+            Err(e) => warn!("invalid DSDT: {:?}", e),
+        }
+
+        Ok(fadt)
+    }
+
+    /// Hand power management over from the firmware by writing `acpi_enable` to the SMI port.
+    pub fn enable(&self) {
+        let Some(pm1a) = self.pm1a_control else {
+            return;
+        };
+        // SAFETY: reading PM1 control has no side effect
+        if self.smi_command == 0 || unsafe { pm1a.read() } as u16 & Self::SCI_EN != 0 {
+            return;
+        }
+
+        unsafe {
+            libx64::port::WPort::<u8>::new(self.smi_command as u16).write(self.acpi_enable);
+            while pm1a.read() as u16 & Self::SCI_EN == 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Enter the soft off state, only returns if the machine is still running.
+    ///
+    /// The returned error is [`Error::MissingSleepState`] when the DSDT doesn't define `\_S5`,
+    /// when there is no PM1 control block or when the write didn't power off the machine.
+    pub fn shutdown(&self) -> Error {
+        let (Some(soft_off), Some(pm1a)) = (self.soft_off, self.pm1a_control) else {
+            return Error::MissingSleepState;
+        };
+
+        self.enable();
+        let sleep = |register: Register, slp_typ: u16| unsafe {
+            let value = register.read() as u16 & !(0b111 << Self::SLP_TYP_SHIFT);
+            register.write(u32::from(
+                value | (slp_typ << Self::SLP_TYP_SHIFT) | Self::SLP_EN,
+            ));
+        };
+
+        libx64::without_interrupts(|| {
+            sleep(pm1a, soft_off.pm1a);
+            if let Some(pm1b) = self.pm1b_control {
+                sleep(pm1b, soft_off.pm1b);
+            }
+        });
+        Error::MissingSleepState
+    }
+
+    /// Write the reset value to the reset register, returns if the machine didn't reset.
+    pub fn reset(&self) {
+        if let Some(reset) = self.reset {
+            libx64::without_interrupts(|| unsafe { reset.register.write(u32::from(reset.value)) });
+        }
+    }
+}
+
+/// Find the `SLP_TYPa`/`SLP_TYPb` values in the AML `Name(\_S5, Package() { a, b, ... })`.
+///
+/// The full AML interpreter isn't needed, the object is found by its name and the package is
+/// decoded by hand.
+fn find_soft_off(aml: &[u8]) -> Option<SoftOff> {
+    const PACKAGE_OP: u8 = 0x12;
+
+    let name = aml.windows(4).position(|w| w == b"_S5_")?;
+    let mut aml = aml.get(name + 4..)?;
+
+    if *aml.first()? != PACKAGE_OP {
+        return None;
+    }
+    // the two high bits of the lead byte are the number of following length bytes
+    let pkg_length_bytes = usize::from(aml.get(1)? >> 6);
+    // skip the opcode, the package length and the element count
+    aml = aml.get(2 + pkg_length_bytes + 1..)?;
+
+    let pm1a = aml_integer(&mut aml)?;
+    let pm1b = aml_integer(&mut aml)?;
+    Some(SoftOff { pm1a, pm1b })
+}
+
+/// Decode a small AML integer and advance past it.
+fn aml_integer(aml: &mut &[u8]) -> Option<u16> {
+    const ZERO_OP: u8 = 0x00;
+    const ONE_OP: u8 = 0x01;
+    const BYTE_PREFIX: u8 = 0x0A;
+    const WORD_PREFIX: u8 = 0x0B;
+
+    let (value, len) = match *aml.first()? {
+        ZERO_OP => (0, 1),
+        ONE_OP => (1, 1),
+        BYTE_PREFIX => (u16::from(*aml.get(1)?), 2),
+        WORD_PREFIX => (u16::from_le_bytes([*aml.get(1)?, *aml.get(2)?]), 3),
+        value => (u16::from(value), 1),
+    };
+    *aml = aml.get(len..)?;
+    Some(value)
+}
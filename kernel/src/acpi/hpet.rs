@@ -0,0 +1,63 @@
+use super::{Error, Fields, PhysicalWindow, Register};
+
+pub const SIGNATURE: &[u8; 4] = b"HPET";
+
+/// # High Precision Event Timer Description Table
+///
+/// ## Format
+///
+/// Offset | Size | Field
+/// -------|------|----------------------------------------------------------
+/// 36     | 4    | Event timer block id
+/// 40     | 12   | Base address of the registers
+/// 52     | 1    | HPET sequence number
+/// 53     | 2    | Minimum clock tick in periodic mode
+/// 55     | 1    | Page protection
+///
+/// ## Event timer block id
+///
+/// Bits  | Description
+/// ------|-------------------------------------
+/// 0-7   | Hardware revision
+/// 8-12  | Number of comparators minus one
+/// 13    | The main counter is 64 bits wide
+/// 15    | Legacy replacement IRQ routing capable
+/// 16-31 | PCI vendor id
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    pub base: Register,
+    pub number: u8,
+    pub min_tick: u16,
+    pub revision: u8,
+    pub comparators: u8,
+    pub counter_64: bool,
+    pub legacy_replacement: bool,
+    pub vendor: u16,
+}
+
+impl Hpet {
+    /// # Errors
+    ///
+    /// Errors if the table is too short or the registers aren't memory mapped
+    pub(super) fn parse(window: &PhysicalWindow, table: &[u8]) -> Result<Self, Error> {
+        let truncated = Error::Truncated(*SIGNATURE);
+        let fields = Fields::new(table);
+
+        let id = fields.u32(36).ok_or(truncated)?;
+        let base = match Register::parse(window, fields.slice(40, 12).ok_or(truncated)?)? {
+            Some(base @ Register::Memory { .. }) => base,
+            _ => return Err(Error::UnsupportedAddressSpace(table[40])),
+        };
+
+        Ok(Self {
+            base,
+            number: fields.u8(52).ok_or(truncated)?,
+            min_tick: fields.u16(53).ok_or(truncated)?,
+            revision: id as u8,
+            comparators: ((id >> 8) & 0x1F) as u8 + 1,
+            counter_64: id & (1 << 13) != 0,
+            legacy_replacement: id & (1 << 15) != 0,
+            vendor: (id >> 16) as u16,
+        })
+    }
+}
@@ -0,0 +1,176 @@
+use alloc::vec::Vec;
+
+use apic::InterruptOverride;
+use libx64::address::PhysicalAddr;
+
+use super::{Error, Fields, SdtHeader};
+
+pub const SIGNATURE: &[u8; 4] = b"APIC";
+
+/// Processor usable by the OS, from a local APIC or x2APIC entry.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Processor {
+    pub acpi_id: u32,
+    pub apic_id: u32,
+    /// The processor is running (or is the bootstrap processor)
+    pub enabled: bool,
+    /// The processor is disabled but can be brought online
+    pub online_capable: bool,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct IoApicEntry {
+    pub id: u8,
+    pub address: PhysicalAddr,
+    /// First global system interrupt handled by this I/O APIC
+    pub gsi_base: u32,
+}
+
+/// Local APIC pin wired to the NMI.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LocalNmi {
+    /// ACPI processor id, 0xFF for every processor
+    pub processor: u8,
+    pub lint: u8,
+    pub active_low: bool,
+    pub level_triggered: bool,
+}
+
+/// # Multiple APIC Description Table
+///
+/// ## Entries
+///
+/// Type | Description
+/// -----|-------------------------------------
+/// 0    | Processor local APIC
+/// 1    | I/O APIC
+/// 2    | Interrupt source override
+/// 4    | Local APIC NMI
+/// 5    | Local APIC address override
+/// 9    | Processor local x2APIC
+#[derive(Debug, Clone)]
+pub struct Madt {
+    pub local_apic: PhysicalAddr,
+    /// The dual 8259 pair is installed and must be masked before using the APIC
+    pub pc_at_compatible: bool,
+    pub processors: Vec<Processor>,
+    pub io_apics: Vec<IoApicEntry>,
+    pub overrides: Vec<InterruptOverride>,
+    pub nmis: Vec<LocalNmi>,
+}
+
+impl Madt {
+    const ENTRIES: usize = SdtHeader::SIZE + 8;
+
+    const PROCESSOR_LOCAL_APIC: u8 = 0;
+    const IO_APIC: u8 = 1;
+    const INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
+    const LOCAL_APIC_NMI: u8 = 4;
+    const LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+    const PROCESSOR_LOCAL_X2APIC: u8 = 9;
+
+    const ENABLED: u32 = 1 << 0;
+    const ONLINE_CAPABLE: u32 = 1 << 1;
+
+    /// # Errors
+    ///
+    /// Errors if an entry goes past the end of the table
+    pub fn parse(table: &[u8]) -> Result<Self, Error> {
+        let truncated = Error::Truncated(*SIGNATURE);
+        let fields = Fields::new(table);
+
+        let mut madt = Self {
+            local_apic: PhysicalAddr::new(u64::from(
+                fields.u32(SdtHeader::SIZE).ok_or(truncated)?,
+            )),
+            pc_at_compatible: fields.u32(SdtHeader::SIZE + 4).ok_or(truncated)? & 1 != 0,
+            processors: Vec::new(),
+            io_apics: Vec::new(),
+            overrides: Vec::new(),
+            nmis: Vec::new(),
+        };
+
+        let mut offset = Self::ENTRIES;
+        while offset + 2 <= table.len() {
+            let kind = table[offset];
+            let len = usize::from(table[offset + 1]);
+            let entry = Fields::new(fields.slice(offset, len).ok_or(truncated)?);
+            if len < 2 {
+                return Err(truncated);
+            }
+            madt.parse_entry(kind, &entry).ok_or(truncated)?;
+            offset += len;
+        }
+
+        Ok(madt)
+    }
+
+    fn parse_entry(&mut self, kind: u8, entry: &Fields<'_>) -> Option<()> {
+        match kind {
+            Self::PROCESSOR_LOCAL_APIC => {
+                let flags = entry.u32(4)?;
+                self.processors.push(Processor {
+                    acpi_id: u32::from(entry.u8(2)?),
+                    apic_id: u32::from(entry.u8(3)?),
+                    enabled: flags & Self::ENABLED != 0,
+                    online_capable: flags & Self::ONLINE_CAPABLE != 0,
+                });
+            }
+            Self::IO_APIC => self.io_apics.push(IoApicEntry {
+                id: entry.u8(2)?,
+                address: PhysicalAddr::new(u64::from(entry.u32(4)?)),
+                gsi_base: entry.u32(8)?,
+            }),
+            Self::INTERRUPT_SOURCE_OVERRIDE => {
+                let (active_low, level_triggered) = decode_mps_flags(entry.u16(8)?);
+                self.overrides.push(InterruptOverride {
+                    irq: entry.u8(3)?,
+                    gsi: entry.u32(4)?,
+                    active_low,
+                    level_triggered,
+                });
+            }
+            Self::LOCAL_APIC_NMI => {
+                let (active_low, level_triggered) = decode_mps_flags(entry.u16(3)?);
+                self.nmis.push(LocalNmi {
+                    processor: entry.u8(2)?,
+                    lint: entry.u8(5)?,
+                    active_low,
+                    level_triggered,
+                });
+            }
+            Self::LOCAL_APIC_ADDRESS_OVERRIDE => {
+                self.local_apic = PhysicalAddr::new(entry.u64(4)?);
+            }
+            Self::PROCESSOR_LOCAL_X2APIC => {
+                let flags = entry.u32(8)?;
+                self.processors.push(Processor {
+                    acpi_id: entry.u32(12)?,
+                    apic_id: entry.u32(4)?,
+                    enabled: flags & Self::ENABLED != 0,
+                    online_capable: flags & Self::ONLINE_CAPABLE != 0,
+                });
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    /// I/O APIC handling the global system interrupt.
+    #[must_use]
+    pub fn io_apic_for(&self, gsi: u32) -> Option<&IoApicEntry> {
+        self.io_apics
+            .iter()
+            .filter(|io| io.gsi_base <= gsi)
+            .max_by_key(|io| io.gsi_base)
+    }
+}
+
+/// Decode the polarity (bits 0-1) and trigger mode (bits 2-3) of MPS INTI flags.
+///
+/// `0b00` conforms to the bus, ISA interrupts are active high and edge triggered.
+const fn decode_mps_flags(flags: u16) -> (bool, bool) {
+    let active_low = flags & 0b11 == 0b11;
+    let level_triggered = (flags >> 2) & 0b11 == 0b11;
+    (active_low, level_triggered)
+}
@@ -0,0 +1,255 @@
+//! # Advanced Configuration and Power Interface
+//!
+//! Source: ACPI Specification 6.4, Chapter 5
+//!
+//! The bootloader maps the whole physical memory at a fixed offset, tables are read through
+//! that window and never mapped individually.
+
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+mod sdt;
+
+use libx64::{
+    address::{PhysicalAddr, VirtualAddr},
+    port::RWPort,
+};
+
+pub use fadt::Fadt;
+pub use hpet::Hpet;
+pub use madt::Madt;
+pub use sdt::SdtHeader;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    /// The RSDP signature or checksum is invalid
+    InvalidRsdp,
+    /// A table doesn't sum to zero
+    Checksum([u8; 4]),
+    /// A table is shorter than its fixed fields
+    Truncated([u8; 4]),
+    /// The register address space isn't system memory or system I/O
+    UnsupportedAddressSpace(u8),
+    /// The DSDT doesn't define the `\_S5` sleep state
+    MissingSleepState,
+}
+
+/// Tables found through the root system description table.
+#[derive(Debug)]
+pub struct Acpi {
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    pub madt: Option<Madt>,
+    pub fadt: Option<Fadt>,
+    pub hpet: Option<Hpet>,
+}
+
+/// Walk the RSDT (or XSDT on ACPI 2.0+) and parse the tables the kernel knows about.
+///
+/// Tables with an invalid checksum are skipped with a warning, unknown signatures are ignored.
+///
+/// # Safety
+///
+/// `rsdp` must be the address found by the bootloader and the physical memory must be mapped at
+/// `pmo`
+///
+/// # Errors
+///
+/// Errors if the RSDP or the root table is invalid
+pub unsafe fn init(rsdp: PhysicalAddr, pmo: VirtualAddr) -> Result<Acpi, Error> {
+    let window = PhysicalWindow(pmo);
+    let rsdp = sdt::Rsdp::read(&window, rsdp)?;
+
+    let root = rsdp.root_table();
+    let (header, entries) = window.table(root)?;
+    trace!(
+        "ACPI {} root table {:?} at {:?}",
+        rsdp.revision(),
+        header.signature(),
+        root
+    );
+
+    let mut acpi = Acpi {
+        revision: rsdp.revision(),
+        oem_id: rsdp.oem_id(),
+        madt: None,
+        fadt: None,
+        hpet: None,
+    };
+
+    for addr in rsdp.entries(entries) {
+        let (header, bytes) = match window.table(addr) {
+            Ok(table) => table,
+            Err(e) => {
+                warn!("skipping ACPI table at {:?}: {:?}", addr, e);
+                continue;
+            }
+        };
+
+        let parsed = match &header.signature() {
+            madt::SIGNATURE => Madt::parse(bytes).map(|madt| acpi.madt = Some(madt)),
+            fadt::SIGNATURE => Fadt::parse(&window, bytes).map(|fadt| acpi.fadt = Some(fadt)),
+            hpet::SIGNATURE => Hpet::parse(&window, bytes).map(|hpet| acpi.hpet = Some(hpet)),
+            signature => {
+                trace!("ignoring ACPI table {:?}", core::str::from_utf8(signature));
+                Ok(())
+            }
+        };
+        if let Err(e) = parsed {
+            warn!("invalid ACPI table {:?}: {:?}", header.signature(), e);
+        }
+    }
+
+    Ok(acpi)
+}
+
+/// Physical memory seen through the bootloader offset mapping
+pub(crate) struct PhysicalWindow(VirtualAddr);
+
+impl PhysicalWindow {
+    pub(crate) fn translate(&self, addr: PhysicalAddr) -> VirtualAddr {
+        self.0 + addr.as_u64()
+    }
+
+    /// # Safety
+    ///
+    /// The range must be mapped physical memory
+    unsafe fn bytes(&self, addr: PhysicalAddr, len: usize) -> &'static [u8] {
+        let ptr = self.translate(addr).ptr::<u8>().unwrap();
+        core::slice::from_raw_parts(ptr.as_ptr(), len)
+    }
+
+    /// Read a table header and return the whole checksummed table.
+    ///
+    /// # Safety
+    ///
+    /// `addr` must point to a system description table
+    unsafe fn table(&self, addr: PhysicalAddr) -> Result<(SdtHeader, &'static [u8]), Error> {
+        let header = SdtHeader::read(self.bytes(addr, SdtHeader::SIZE));
+        if header.length() < SdtHeader::SIZE {
+            return Err(Error::Truncated(header.signature()));
+        }
+        let bytes = self.bytes(addr, header.length());
+        if !sdt::checksum(bytes) {
+            return Err(Error::Checksum(header.signature()));
+        }
+        Ok((header, bytes))
+    }
+}
+
+/// # Generic Address Structure
+///
+/// Register location used by the fixed tables, only the system memory and system I/O address
+/// spaces are supported.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Register {
+    Memory { addr: VirtualAddr, width: u8 },
+    Io { port: u16, width: u8 },
+}
+
+impl Register {
+    const SYSTEM_MEMORY: u8 = 0;
+    const SYSTEM_IO: u8 = 1;
+
+    /// Decode a 12 bytes generic address structure, `None` if the address is null.
+    fn parse(window: &PhysicalWindow, gas: &[u8]) -> Result<Option<Self>, Error> {
+        let space = gas[0];
+        let width = gas[1];
+        let addr = u64::from_le_bytes(gas[4..12].try_into().unwrap());
+        if addr == 0 {
+            return Ok(None);
+        }
+        match space {
+            Self::SYSTEM_MEMORY => Ok(Some(Self::Memory {
+                addr: window.translate(PhysicalAddr::new(addr)),
+                width,
+            })),
+            Self::SYSTEM_IO => Ok(Some(Self::Io {
+                port: addr as u16,
+                width,
+            })),
+            space => Err(Error::UnsupportedAddressSpace(space)),
+        }
+    }
+
+    /// Legacy 32 bits port block fields, `None` if unused.
+    fn port(port: u32, width: u8) -> Option<Self> {
+        (port != 0).then(|| Self::Io {
+            port: port as u16,
+            width,
+        })
+    }
+
+    /// # Safety
+    ///
+    /// Reading the register must not have side effects the caller isn't aware of
+    pub unsafe fn read(&self) -> u32 {
+        match *self {
+            Self::Memory { addr, width: 8 } => {
+                u32::from(core::ptr::read_volatile(addr.ptr::<u8>().unwrap().as_ptr()))
+            }
+            Self::Memory { addr, width: 16 } => {
+                u32::from(core::ptr::read_volatile(addr.ptr::<u16>().unwrap().as_ptr()))
+            }
+            Self::Memory { addr, .. } => {
+                core::ptr::read_volatile(addr.ptr::<u32>().unwrap().as_ptr())
+            }
+            Self::Io { port, width: 8 } => u32::from(RWPort::<u8>::new(port).read()),
+            Self::Io { port, width: 16 } => u32::from(RWPort::<u16>::new(port).read()),
+            Self::Io { port, .. } => RWPort::<u32>::new(port).read(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Writing the register changes the hardware state
+    pub unsafe fn write(&self, value: u32) {
+        match *self {
+            Self::Memory { addr, width: 8 } => {
+                core::ptr::write_volatile(addr.ptr::<u8>().unwrap().as_ptr(), value as u8);
+            }
+            Self::Memory { addr, width: 16 } => {
+                core::ptr::write_volatile(addr.ptr::<u16>().unwrap().as_ptr(), value as u16);
+            }
+            Self::Memory { addr, .. } => {
+                core::ptr::write_volatile(addr.ptr::<u32>().unwrap().as_ptr(), value);
+            }
+            Self::Io { port, width: 8 } => RWPort::<u8>::new(port).write(value as u8),
+            Self::Io { port, width: 16 } => RWPort::<u16>::new(port).write(value as u16),
+            Self::Io { port, .. } => RWPort::<u32>::new(port).write(value),
+        }
+    }
+}
+
+/// Little endian reads at fixed offsets, tables grew across revisions so fields past the end
+/// of a table are `None`.
+pub(crate) struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    pub(crate) const fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+
+    pub(crate) fn slice(&self, offset: usize, len: usize) -> Option<&'a [u8]> {
+        self.0.get(offset..offset + len)
+    }
+
+    pub(crate) fn u8(&self, offset: usize) -> Option<u8> {
+        self.0.get(offset).copied()
+    }
+
+    pub(crate) fn u16(&self, offset: usize) -> Option<u16> {
+        self.slice(offset, 2)
+            .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    pub(crate) fn u32(&self, offset: usize) -> Option<u32> {
+        self.slice(offset, 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&self, offset: usize) -> Option<u64> {
+        self.slice(offset, 8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+}
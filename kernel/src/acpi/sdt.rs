@@ -0,0 +1,145 @@
+use libx64::address::PhysicalAddr;
+
+use super::{Error, Fields, PhysicalWindow};
+
+/// Bytes of a table sum to zero modulo 256.
+pub(super) fn checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+/// # Root System Description Pointer
+///
+/// ## Format
+///
+/// Offset | Size | Field
+/// -------|------|-------------------------------------------------
+/// 0      | 8    | Signature, `"RSD PTR "`
+/// 8      | 1    | Checksum of the first 20 bytes
+/// 9      | 6    | OEM id
+/// 15     | 1    | Revision, 0 for ACPI 1.0 and 2 for ACPI 2.0+
+/// 16     | 4    | RSDT physical address
+/// 20     | 4    | Length of the structure (ACPI 2.0+)
+/// 24     | 8    | XSDT physical address (ACPI 2.0+)
+/// 32     | 1    | Checksum of the whole structure (ACPI 2.0+)
+pub(super) struct Rsdp {
+    revision: u8,
+    oem_id: [u8; 6],
+    rsdt: u32,
+    xsdt: Option<u64>,
+}
+
+impl Rsdp {
+    const SIGNATURE: &'static [u8; 8] = b"RSD PTR ";
+    const V1_SIZE: usize = 20;
+    const V2_SIZE: usize = 36;
+
+    /// # Safety
+    ///
+    /// `addr` must be mapped through the window
+    pub(super) unsafe fn read(window: &PhysicalWindow, addr: PhysicalAddr) -> Result<Self, Error> {
+        let v1 = window.bytes(addr, Self::V1_SIZE);
+        if &v1[..8] != Self::SIGNATURE || !checksum(v1) {
+            return Err(Error::InvalidRsdp);
+        }
+
+        let fields = Fields::new(v1);
+        let revision = v1[15];
+        let mut rsdp = Self {
+            revision,
+            oem_id: v1[9..15].try_into().unwrap(),
+            rsdt: fields.u32(16).unwrap(),
+            xsdt: None,
+        };
+
+        if revision >= 2 {
+            let v2 = window.bytes(addr, Self::V2_SIZE);
+            if !checksum(v2) {
+                return Err(Error::InvalidRsdp);
+            }
+            rsdp.xsdt = Fields::new(v2).u64(24).filter(|&xsdt| xsdt != 0);
+        }
+        Ok(rsdp)
+    }
+
+    pub(super) const fn revision(&self) -> u8 {
+        self.revision
+    }
+
+    pub(super) const fn oem_id(&self) -> [u8; 6] {
+        self.oem_id
+    }
+
+    /// The XSDT if present, the RSDT otherwise.
+    pub(super) fn root_table(&self) -> PhysicalAddr {
+        PhysicalAddr::new(self.xsdt.unwrap_or_else(|| u64::from(self.rsdt)))
+    }
+
+    /// Table addresses of the root table, 64 bits wide in the XSDT and 32 bits in the RSDT.
+    pub(super) fn entries(&self, table: &'static [u8]) -> impl Iterator<Item = PhysicalAddr> {
+        let width = if self.xsdt.is_some() { 8 } else { 4 };
+        table[SdtHeader::SIZE..]
+            .chunks_exact(width)
+            .map(move |entry| {
+                let mut addr = [0; 8];
+                addr[..width].copy_from_slice(entry);
+                PhysicalAddr::new(u64::from_le_bytes(addr))
+            })
+    }
+}
+
+/// # System Description Table Header
+///
+/// ## Format
+///
+/// Offset | Size | Field
+/// -------|------|---------------------------------------
+/// 0      | 4    | Signature
+/// 4      | 4    | Length of the table, header included
+/// 8      | 1    | Revision
+/// 9      | 1    | Checksum of the whole table
+/// 10     | 6    | OEM id
+/// 16     | 8    | OEM table id
+/// 24     | 4    | OEM revision
+/// 28     | 4    | Creator id
+/// 32     | 4    | Creator revision
+#[derive(Debug, Clone, Copy)]
+pub struct SdtHeader {
+    signature: [u8; 4],
+    length: u32,
+    revision: u8,
+    oem_table_id: [u8; 8],
+}
+
+impl SdtHeader {
+    pub const SIZE: usize = 36;
+
+    pub(super) fn read(bytes: &[u8]) -> Self {
+        let fields = Fields::new(bytes);
+        Self {
+            signature: bytes[0..4].try_into().unwrap(),
+            length: fields.u32(4).unwrap(),
+            revision: bytes[8],
+            oem_table_id: bytes[16..24].try_into().unwrap(),
+        }
+    }
+
+    #[must_use]
+    pub const fn signature(&self) -> [u8; 4] {
+        self.signature
+    }
+
+    #[must_use]
+    pub const fn length(&self) -> usize {
+        self.length as usize
+    }
+
+    #[must_use]
+    pub const fn revision(&self) -> u8 {
+        self.revision
+    }
+
+    #[must_use]
+    pub const fn oem_table_id(&self) -> [u8; 8] {
+        self.oem_table_id
+    }
+}
@@ -0,0 +1,185 @@
+//! Commands sent by `konsole` over the serial line
+//!
+//! The serial interrupt buffers the received bytes, a kernel thread decodes them into requests
+//! and answers through the logger, see [`protocols::command`].
+
+use alloc::vec::Vec;
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bootloader::boot_info::{MemoryRegion, MemoryRegionKind};
+use kio::read::{AsyncRead, FramedRead, Read};
+use libx64::{address::VirtualAddr, port::WPort};
+use mais::CobsDecoder;
+use protocols::{
+    command::{self, ArchivedRequest, Region, RegionKind, Response, Task, TaskState},
+    log::Level,
+};
+use rkyv::AlignedBytes;
+
+use crate::{
+    acpi::Fadt,
+    mem::{context::with_kernel, vma},
+    process::ProcessId,
+    thread::{self, ThreadId},
+};
+
+/// Largest request frame
+const FRAME_SIZE: usize = 64;
+
+/// Bytes received by the serial port, reads park the thread until the serial interrupt wakes it.
+struct Rx;
+
+impl Read for Rx {
+    fn read(&mut self, buffer: &mut [u8]) -> kio::Result<usize> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let waker = thread::waker(thread::current());
+        let mut cx = Context::from_waker(&waker);
+        let mut reader = qemu_logger::SERIAL.reader();
+        loop {
+            match Pin::new(&mut reader).poll_read(&mut cx, buffer) {
+                Poll::Ready(Ok(n)) => return Ok(n),
+                // the decoder drops the corrupted frame at the next delimiter
+                Poll::Ready(Err(err)) => warn!("serial line error: {:?}", err),
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+}
+
+/// Spawn the thread serving the commands, the reset register of `fadt` reboots the machine.
+///
+/// # Errors
+///
+/// Errors if the thread stack could not be mapped
+pub fn init(pmo: VirtualAddr, fadt: Option<Fadt>) -> Result<ThreadId, vma::Error> {
+    with_kernel(|ctx| thread::spawn_thread(ctx, move || serve(pmo, fadt)))
+}
+
+fn serve(pmo: VirtualAddr, fadt: Option<Fadt>) {
+    let mut request = AlignedBytes([0u8; FRAME_SIZE]);
+    for frame in FramedRead::new([0u8; FRAME_SIZE], Rx, CobsDecoder::<FRAME_SIZE>) {
+        let handled = match frame {
+            Ok(frame) => {
+                let request = &mut request[..frame.len()];
+                request.copy_from_slice(&frame);
+                command::archived_request(request)
+                    .ok_or(command::Error::InvalidRequest)
+                    .and_then(|request| handle(request, pmo, fadt))
+            }
+            Err(err) => {
+                warn!("invalid command frame: {:?}", err);
+                Err(command::Error::InvalidRequest)
+            }
+        };
+        if let Err(err) = handled {
+            send(&Response::Error(err));
+        }
+    }
+}
+
+fn handle(
+    request: &ArchivedRequest,
+    pmo: VirtualAddr,
+    fadt: Option<Fadt>,
+) -> Result<(), command::Error> {
+    debug!("command {:?}", request);
+    match request {
+        ArchivedRequest::MemoryMap => {
+            let regions: Vec<Region> = memory_map().iter().map(region).collect();
+            for regions in regions.chunks(command::MAX_ITEMS) {
+                send(&Response::MemoryMap(regions));
+            }
+        }
+        ArchivedRequest::ReadPhysical { address, len } => {
+            let bytes = physical(pmo, *address, *len).ok_or(command::Error::InvalidRange)?;
+            send(&Response::Memory {
+                address: *address,
+                bytes,
+            });
+        }
+        ArchivedRequest::Tasks => {
+            let tasks: Vec<Task> = thread::list().into_iter().map(task).collect();
+            for tasks in tasks.chunks(command::MAX_ITEMS) {
+                send(&Response::Tasks(tasks));
+            }
+        }
+        ArchivedRequest::SetLogLevel(level) => {
+            let level = Level::from(*level);
+            qemu_logger::set_max_level(level);
+            send(&Response::LogLevel(level));
+        }
+        ArchivedRequest::Reboot => {
+            send(&Response::Rebooting);
+            reboot(fadt);
+        }
+    }
+    Ok(())
+}
+
+fn send(response: &Response<'_>) {
+    if let Err(err) = qemu_logger::respond(response) {
+        warn!("unable to send the response: {:?}", err);
+    }
+}
+
+fn memory_map() -> &'static [MemoryRegion] {
+    with_kernel(|ctx| ctx.layout().regions())
+}
+
+/// Bytes of a range inside a region of the memory map.
+fn physical(pmo: VirtualAddr, address: u64, len: u32) -> Option<&'static [u8]> {
+    let end = address.checked_add(u64::from(len))?;
+    let mapped = memory_map()
+        .iter()
+        .any(|region| region.start <= address && end <= region.end);
+    if len > command::MAX_READ || !mapped {
+        return None;
+    }
+    let start = pmo + address;
+    // SAFETY: the physical memory is mapped at `pmo` and the range is in the memory map
+    Some(unsafe { core::slice::from_raw_parts(start.as_u64() as *const u8, len as usize) })
+}
+
+fn region(region: &MemoryRegion) -> Region {
+    let kind = match region.kind {
+        MemoryRegionKind::Usable => RegionKind::Usable,
+        MemoryRegionKind::Bootloader => RegionKind::Bootloader,
+        MemoryRegionKind::Initrd => RegionKind::Initrd,
+        _ => RegionKind::Reserved,
+    };
+    Region {
+        start: region.start,
+        end: region.end,
+        kind,
+    }
+}
+
+fn task(info: thread::Info) -> Task {
+    let state = match info.state {
+        thread::State::Running => TaskState::Running,
+        thread::State::Ready => TaskState::Ready,
+        thread::State::Sleeping(_) => TaskState::Sleeping,
+        thread::State::Blocked | thread::State::Parked => TaskState::Blocked,
+        thread::State::Finished => TaskState::Finished,
+    };
+    Task {
+        id: info.id.as_u64(),
+        process: info.process.map_or(0, ProcessId::as_u64),
+        state,
+    }
+}
+
+/// Reset through the ACPI reset register, or pulse the reset line of the 8042 controller.
+fn reboot(fadt: Option<Fadt>) -> ! {
+    if let Some(fadt) = fadt {
+        fadt.reset();
+    }
+    // SAFETY: the machine is reset
+    libx64::without_interrupts(|| unsafe { WPort::<u8>::new(0x64).write(0xFE) });
+    libx64::diverging_hlt();
+}
@@ -0,0 +1,45 @@
+//! Filesystem tree of the kernel
+//!
+//! A [`TmpFs`] is mounted on `/` and the initrd, if the bootloader found one, on
+//! [`INITRD_MOUNT`].
+
+use alloc::sync::Arc;
+
+use kcore::sync::SpinMutex;
+use vfs::{ArchiveFs, Kind, TmpFs, Vfs};
+
+/// Directory the initrd is mounted on
+pub const INITRD_MOUNT: &str = "/initrd";
+
+static VFS: SpinMutex<Option<Vfs>> = SpinMutex::new(None);
+
+/// Mount the root filesystem and the initrd.
+///
+/// # Errors
+///
+/// Errors if the initrd isn't a valid archive
+///
+/// # Panics
+///
+/// Panics if called twice
+pub fn init() -> Result<(), vfs::Error> {
+    let mut vfs = Vfs::new();
+    vfs.mount("/", Arc::new(TmpFs::new()))?;
+    if let Some(archive) = crate::initrd::archive() {
+        vfs.create(INITRD_MOUNT, Kind::Directory)?;
+        vfs.mount(INITRD_MOUNT, Arc::new(ArchiveFs::new(archive)?))?;
+        info!("initrd mounted on {}", INITRD_MOUNT);
+    }
+
+    let previous = libx64::without_interrupts(|| VFS.lock().replace(vfs));
+    assert!(previous.is_none(), "filesystem initialized twice");
+    Ok(())
+}
+
+/// Run `f` on the filesystem tree, interrupts are disabled while the lock is held.
+pub fn with_vfs<R>(f: impl FnOnce(&mut Vfs) -> R) -> R {
+    libx64::without_interrupts(|| {
+        let mut vfs = VFS.lock();
+        f(vfs.as_mut().expect("filesystem not initialized"))
+    })
+}
@@ -0,0 +1,169 @@
+use apic::{
+    ioapic::IoApic,
+    lapic::LocalApic,
+    Apic, InterruptOverride,
+};
+use libx64::{
+    address::{PhysicalAddr, VirtualAddr},
+    idt::TrustedUserInterruptIndex,
+    paging::{
+        entry::Flags,
+        frame::{FrameAllocator, FrameDeallocator},
+        page::{PageMapper, PageTranslator},
+        Page4Kb,
+    },
+};
+use pic::chained::Chained;
+
+use crate::mem::{
+    context::MemoryContext,
+    vma::{self, Backing, Purpose},
+};
+
+/// Both controllers raise ISA IRQ `n` on vector `OFFSET + n`
+pub const OFFSET: u8 = 0x20;
+
+/// IRQ of the first serial port
+pub const SERIAL_IRQ: u8 = 4;
+
+/// ISA IRQs routed through the I/O APIC, the local APIC timer replaces the PIT on IRQ 0
+const ISA_IRQS: [u8; 2] = [1, SERIAL_IRQ];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    Pic(pic::chained::Error),
+    Apic(apic::Error),
+    Map(vma::Error),
+}
+
+pub enum InterruptController {
+    Pic(Chained<0x20, 0x28>),
+    Apic(Apic<OFFSET>),
+}
+
+impl InterruptController {
+    #[must_use]
+    pub fn uninit() -> Self {
+        Self::Pic(Chained::uninit())
+    }
+
+    /// Remap the 8259 pair, it stays the controller until [`InterruptController::enable_apic`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if the controller was already initialized
+    pub fn init(&mut self) -> Result<(), Error> {
+        match self {
+            Self::Pic(pics) => pics.init().map_err(Error::Pic),
+            Self::Apic(_) => Err(Error::Pic(pic::chained::Error::AlreadyInit)),
+        }
+    }
+
+    /// Enable an ISA IRQ line the firmware left masked on the 8259 pair, the I/O APIC routes
+    /// its own.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the pic wasn't initialized
+    pub fn unmask(&mut self, irq: u8) -> Result<(), Error> {
+        match self {
+            Self::Pic(pics) => pics.unmask(irq).map_err(Error::Pic),
+            Self::Apic(_) => Ok(()),
+        }
+    }
+
+    /// Mask the 8259 pair and route the ISA IRQs through the I/O APIC instead.
+    ///
+    /// The local APIC timer is calibrated against the PIT and takes over the timer interrupt at
+    /// `timer_hz`, it must be the frequency of the PIT so the clock keeps the same tick period.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the pic wasn't initialized, the MMIO windows can't be mapped, an IRQ can't be
+    /// routed or the timer can't be started
+    pub fn enable_apic<M, A>(
+        &mut self,
+        ctx: &mut MemoryContext<M, A>,
+        io_base: PhysicalAddr,
+        gsi_base: u32,
+        overrides: &[InterruptOverride],
+        timer_hz: u32,
+    ) -> Result<(), Error>
+    where
+        M: PageMapper<Page4Kb> + PageTranslator,
+        A: FrameAllocator<Page4Kb> + FrameDeallocator<Page4Kb>,
+    {
+        if let Self::Apic(_) = self {
+            return Ok(());
+        }
+
+        let lapic_virt = map_mmio(ctx, "local apic", LocalApic::physical_base())?;
+        let io_virt = map_mmio(ctx, "io apic", io_base)?;
+
+        let mut apic = Apic::<OFFSET>::new(unsafe { LocalApic::new(lapic_virt) }, unsafe {
+            IoApic::new(io_virt, gsi_base)
+        });
+
+        libx64::without_interrupts(|| {
+            if let Self::Pic(pics) = self {
+                pics.disable().map_err(Error::Pic)?;
+            }
+            apic.init();
+
+            for irq in ISA_IRQS {
+                apic.route_isa(irq, overrides).map_err(Error::Apic)?;
+            }
+
+            // SAFETY: the PIT channel 2 isn't used anywhere else
+            let frequency = apic
+                .local()
+                .calibrate_timer(&mut unsafe { pit::Pit::new() })
+                .map_err(Error::Apic)?;
+            trace!("LAPIC timer running at {}Hz", frequency);
+
+            // the PIT pin stays masked, the clock advances on the local timer from now on
+            apic.local()
+                .start_periodic_timer(OFFSET, timer_hz)
+                .map_err(Error::Apic)?;
+            trace!("LAPIC timer drives the clock at {}Hz", timer_hz);
+
+            *self = Self::Apic(apic);
+            Ok(())
+        })
+    }
+
+    /// Acknowledge the interrupt.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the interrupt wasn't raised by the active controller
+    pub fn interupt_fn<T>(&mut self, int_code: T) -> Result<(), Error>
+    where
+        T: TrustedUserInterruptIndex,
+    {
+        match self {
+            Self::Pic(pics) => pics.interupt_fn(int_code).map_err(Error::Pic),
+            Self::Apic(apic) => apic.interupt_fn(int_code).map_err(Error::Apic),
+        }
+    }
+}
+
+fn map_mmio<M, A>(
+    ctx: &mut MemoryContext<M, A>,
+    name: &'static str,
+    phys: PhysicalAddr,
+) -> Result<VirtualAddr, Error>
+where
+    M: PageMapper<Page4Kb> + PageTranslator,
+    A: FrameAllocator<Page4Kb> + FrameDeallocator<Page4Kb>,
+{
+    ctx.mmap(
+        name,
+        1,
+        Flags::RW | Flags::PCD,
+        Purpose::Mmio,
+        Backing::Physical(phys),
+    )
+    .map(|pages| pages.start())
+    .map_err(Error::Map)
+}
@@ -1,8 +1,13 @@
+use core::cell::UnsafeCell;
+
 use libx64::{
     address::VirtualAddr,
-    descriptors::{CodeSegmentDescriptor, GdtNull, SystemSegmentDescriptor},
+    descriptors::{
+        CodeSegmentDescriptor, DataSegmentDescriptor, GdtNull, SystemSegmentDescriptor,
+    },
     gdt::GlobalDescriptorTable,
     segments::TaskStateSegment,
+    Privilege,
 };
 
 use kcore::tables::{gdt::Selectors, idt::IstEntry};
@@ -12,18 +17,37 @@ klazy! {
         let mut gdt = GlobalDescriptorTable::new();
 
         gdt.add_entry(GdtNull);
-        let task_state = gdt.add_entry(SystemSegmentDescriptor::from(&*TSS));
         let code_segment = gdt.add_entry(CodeSegmentDescriptor::kernel_x64());
+        let data_segment = gdt.add_entry(DataSegmentDescriptor::kernel_x64());
+        let user_data = gdt
+            .add_entry(DataSegmentDescriptor::user_x64())
+            .set_rpl(u16::from(Privilege::Ring3));
+        let user_code = gdt
+            .add_entry(CodeSegmentDescriptor::user_x64())
+            .set_rpl(u16::from(Privilege::Ring3));
+        let task_state = gdt.add_entry(SystemSegmentDescriptor::from(TSS.get()));
 
         (gdt, Selectors {
                 code_segment,
+                data_segment,
+                user_data,
+                user_code,
                 task_state
         })
     };
 }
 
+/// Task state segment, the cpu reads the ring 0 stack from it on every privilege change.
+pub struct Tss(UnsafeCell<TaskStateSegment>);
+
+impl Tss {
+    fn get(&self) -> &TaskStateSegment {
+        unsafe { &*self.0.get() }
+    }
+}
+
 klazy! {
-    pub ref static TSS: TaskStateSegment = {
+    pub ref static TSS: Tss = {
         let mut tss = TaskStateSegment::zero();
 
         tss.ist[IstEntry::DoubleFault] = {
@@ -37,6 +61,13 @@ klazy! {
             VirtualAddr::from_ptr(unsafe { STACK.0.as_ptr().add(STACK_SIZE) })
         };
 
-        tss
+        Tss(UnsafeCell::new(tss))
     };
 }
+
+/// Set the stack loaded when an interrupt or an exception is taken in ring 3.
+pub fn set_kernel_stack(top: VirtualAddr) {
+    // SAFETY: the cpu only reads the segment when switching privilege, which can't happen while
+    // interrupts are disabled
+    libx64::without_interrupts(|| unsafe { (*TSS.0.get()).rsp[0] = top });
+}
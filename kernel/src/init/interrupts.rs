@@ -19,6 +19,8 @@ klazy! {
         // User Interrupts
         idt.user[user::IntIdx::Timer].register(user::timer);
         idt.user[user::IntIdx::Keyboard].register(user::keyboard);
+        idt.user[user::IntIdx::Serial].register(user::serial);
+        idt.user[user::IntIdx::Spurious].register(user::spurious);
 
         idt
     };
@@ -43,25 +45,39 @@ pub extern "x86-interrupt" fn general_protection(f: InterruptFrame, code: u64) {
 
 pub extern "x86-interrupt" fn page_fault(f: InterruptFrame, code: u64) {
     let code = PageFaultErrorCode::from_bits_truncate(code);
-    panic!("#PF (code: {:?}) {:#?}", code, f);
+    if let Err(fault) = crate::mem::fault::handle(libx64::control::cr2(), code) {
+        if code.contains(PageFaultErrorCode::USER_MODE) {
+            if let Some(process) = crate::thread::current_process() {
+                error!("{:?} killed by #PF {}", process, fault);
+                crate::thread::exit();
+            }
+        }
+        panic!("#PF {}\n{:#?}", fault, f);
+    }
 }
 
 #[interrupt_list::interrupt_list(IntIdx)]
 pub mod user {
-    use super::{super::KEYBOARD, InterruptFrame};
+    use super::{
+        super::{controller::InterruptController, KEYBOARD},
+        InterruptFrame,
+    };
     use kcore::{klazy, sync::SpinMutex};
-    use pic::chained::Chained;
 
     klazy! {
-        pub ref static PICS: SpinMutex<Chained<0x20, 0x28>> = {
-            SpinMutex::new(Chained::<0x20, 0x28>::uninit())
+        pub ref static CONTROLLER: SpinMutex<InterruptController> = {
+            SpinMutex::new(InterruptController::uninit())
         };
     }
 
     #[interrupt_list::user_interrupt(32)]
     pub extern "x86-interrupt" fn timer(f: InterruptFrame) {
         drop(f);
-        PICS.lock().interupt_fn(IntIdx::Timer).expect("timer");
+        CONTROLLER.lock().interupt_fn(IntIdx::Timer).expect("timer");
+
+        pit::tick();
+        // may switch to another thread, the interrupt must be acknowledged first
+        crate::thread::tick();
     }
 
     #[interrupt_list::user_interrupt(33)]
@@ -72,6 +88,24 @@ pub mod user {
 
         unsafe { KEYBOARD.lock().add_value(KB.read()) };
 
-        PICS.lock().interupt_fn(IntIdx::Keyboard).expect("keyboard");
+        CONTROLLER
+            .lock()
+            .interupt_fn(IntIdx::Keyboard)
+            .expect("keyboard");
     }
+
+    /// Raised when the first serial port received bytes or can send more
+    #[interrupt_list::user_interrupt(36)]
+    pub extern "x86-interrupt" fn serial(_f: InterruptFrame) {
+        qemu_logger::SERIAL.handle_interrupt();
+
+        CONTROLLER
+            .lock()
+            .interupt_fn(IntIdx::Serial)
+            .expect("serial");
+    }
+
+    /// Raised by the local APIC when an interrupt vanished before being delivered, no EOI
+    #[interrupt_list::user_interrupt(255)]
+    pub extern "x86-interrupt" fn spurious(_f: InterruptFrame) {}
 }
@@ -1,14 +1,36 @@
+pub mod controller;
 mod gdt;
 mod interrupts;
 
-use kcore::sync::SpinMutex;
+use core::time::Duration;
+
+use apic::ioapic::IoApic;
+use kcore::{sync::SpinMutex, tables::gdt::Selectors};
 use keyboard::Keyboard;
 use libx64::{
     gdt::lgdt,
     idt::lidt,
-    segments::{ltr, set_cs, set_ss, SegmentSelector},
+    paging::{
+        frame::{FrameAllocator, FrameDeallocator},
+        page::{PageMapper, PageTranslator},
+        Page4Kb,
+    },
+    segments::{ltr, set_cs, set_ds, set_es, set_ss, SegmentSelector},
 };
 
+use crate::{acpi::Madt, mem::context::MemoryContext};
+
+pub use gdt::set_kernel_stack;
+
+/// Frequency of the timer interrupt
+pub const TIMER_FREQUENCY: u32 = 100;
+
+/// Time a thread runs before being preempted
+const TIME_SLICE: Duration = Duration::from_millis(20);
+
+/// Time counted by the TSC to measure its frequency
+const TSC_CALIBRATION: Duration = Duration::from_millis(10);
+
 klazy! {
     pub ref static KEYBOARD: SpinMutex<Keyboard> = SpinMutex::new(Keyboard::new());
 }
@@ -23,6 +45,8 @@ pub fn kinit() {
 
     set_cs(segments.code_segment);
     set_ss(SegmentSelector::zero()); // https://github.com/rust-osdev/bootloader/issues/196
+    set_ds(segments.data_segment);
+    set_es(segments.data_segment);
     ltr(segments.task_state);
 
     trace!("CS: {:?}", segments.code_segment);
@@ -31,10 +55,70 @@ pub fn kinit() {
     lidt(&interrupts::IDT.lidt_ptr());
     trace!("IDT Initialized at {:?}", interrupts::IDT.lidt_ptr());
 
-    interrupts::user::PICS
+    interrupts::user::CONTROLLER
         .lock()
         .init()
         .expect("failed to initialize PIC");
+    interrupts::user::CONTROLLER
+        .lock()
+        .unmask(controller::SERIAL_IRQ)
+        .expect("failed to unmask the serial IRQ");
 
     trace!("PIC Initialized");
+
+    // SAFETY: the PIT is only programmed here
+    let frequency = unsafe { pit::Pit::new() }
+        .set_frequency(TIMER_FREQUENCY)
+        .expect("failed to program the PIT");
+
+    trace!("PIT Initialized at {}Hz", frequency);
+
+    let quantum = pit::clock::ticks_for(TIME_SLICE);
+    crate::thread::set_quantum(quantum);
+    trace!("threads preempted every {} ticks", quantum);
+
+    let calibration = libx64::tsc::calibrate(TSC_CALIBRATION, |window| {
+        // SAFETY: the channel 2 of the PIT is only used for calibrations, one at a time
+        unsafe { pit::Pit::new() }.busy_wait(window)
+    });
+    match calibration {
+        Ok(hz) => {
+            qemu_logger::set_tsc_frequency(hz);
+            trace!("TSC running at {}Hz", hz);
+        }
+        Err(err) => warn!("failed to calibrate the TSC: {:?}", err),
+    }
+}
+
+/// Segment selectors of the GDT loaded by [`kinit`].
+pub fn selectors() -> &'static Selectors {
+    &gdt::GDT.1
+}
+
+/// Switch interrupt delivery from the 8259 pair to the APIC.
+///
+/// The APIC MMIO windows have to be mapped so this runs once the memory context exists. The
+/// I/O APIC and the ISA overrides come from the MADT when the firmware provides one, the timer
+/// interrupt moves from the PIT to the local APIC timer.
+#[tracing::instrument(skip(ctx, madt))]
+pub fn apic<M, A>(ctx: &mut MemoryContext<M, A>, madt: Option<&Madt>)
+where
+    M: PageMapper<Page4Kb> + PageTranslator,
+    A: FrameAllocator<Page4Kb> + FrameDeallocator<Page4Kb>,
+{
+    let routing = madt.and_then(|madt| Some((madt.io_apic_for(0)?, madt.overrides.as_slice())));
+    let (io_base, gsi_base, overrides) = match routing {
+        Some((io, overrides)) => (io.address, io.gsi_base, overrides),
+        None => {
+            warn!("no I/O APIC in the MADT, using the default routing");
+            (IoApic::DEFAULT_BASE, 0, &[][..])
+        }
+    };
+
+    interrupts::user::CONTROLLER
+        .lock()
+        .enable_apic(ctx, io_base, gsi_base, overrides, TIMER_FREQUENCY)
+        .expect("failed to initialize APIC");
+
+    trace!("APIC Initialized");
 }
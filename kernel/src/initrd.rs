@@ -0,0 +1,49 @@
+//! Initial RAM disk loaded by the bootloader after the kernel
+//!
+//! The archive stays in the [`Initrd`](bootloader::boot_info::MemoryRegionKind::Initrd) region
+//! and is read through the physical memory offset, its files are never copied.
+
+use bootloader::boot_info::MemoryRegion;
+use kcore::sync::SpinMutex;
+use ktar::Archive;
+use libx64::address::VirtualAddr;
+
+static INITRD: SpinMutex<Option<Archive<'static>>> = SpinMutex::new(None);
+
+/// Record the archive found by the bootloader, if any, and log its entries.
+///
+/// # Panics
+///
+/// Panics if called twice
+pub fn init(region: Option<&MemoryRegion>, pmo: VirtualAddr) {
+    let Some(region) = region else {
+        info!("no initrd");
+        return;
+    };
+    let start = pmo + region.start;
+    let len = usize::try_from(region.end - region.start).unwrap();
+    // SAFETY: the region is reserved for the initrd and the physical memory is mapped at `pmo`
+    let archive =
+        Archive::new(unsafe { core::slice::from_raw_parts(start.as_u64() as *const u8, len) });
+
+    info!("initrd of {} bytes at {:?}", len, start);
+    for entry in archive.entries() {
+        match entry {
+            Ok(entry) => trace!(
+                "  /{} ({:?}, {} bytes)",
+                entry.path,
+                entry.kind,
+                entry.size()
+            ),
+            Err(err) => error!("invalid initrd: {:?}", err),
+        }
+    }
+
+    let previous = libx64::without_interrupts(|| INITRD.lock().replace(archive));
+    assert!(previous.is_none(), "initrd initialized twice");
+}
+
+/// The archive loaded by the bootloader.
+pub fn archive() -> Option<Archive<'static>> {
+    libx64::without_interrupts(|| *INITRD.lock())
+}
@@ -4,6 +4,7 @@
 #![feature(abi_x86_interrupt)]
 #![feature(step_trait)]
 #![feature(array_chunks)]
+#![feature(asm_sym)]
 #![test_runner(crate::infra::tests::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 #![no_main]
@@ -19,14 +20,27 @@ extern crate alloc;
 
 use core::panic::PanicInfo;
 
-use libx64::{address::VirtualAddr, paging::page::PageTranslator};
+use libx64::{
+    address::{PhysicalAddr, VirtualAddr},
+    paging::page::PageTranslator,
+};
 
-use crate::mem::{context::MemoryLayout, pmm::PhysicalMemoryManager};
+use crate::mem::{
+    context::MemoryLayout,
+    pmm::{Frames, PhysicalMemoryManager},
+};
 
+pub mod acpi;
+pub mod command;
+pub mod fs;
 #[macro_use]
 mod infra;
 mod init;
+pub mod initrd;
 pub mod mem;
+pub mod process;
+pub mod syscall;
+pub mod thread;
 
 bootloader::entry_point!(kmain);
 pub fn kmain(bi: &'static mut bootloader::BootInfo) -> ! {
@@ -45,25 +59,74 @@ pub fn kmain(bi: &'static mut bootloader::BootInfo) -> ! {
     info!("kernel loaded");
 
     init::kinit();
+    syscall::init();
     libx64::sti();
     trace!("interrupts enabled");
 
     let pmo = VirtualAddr::new(bi.physical_memory_offset);
 
-
     let mut context = crate::mem::context::MemoryContext::new(
         MemoryLayout::init(&bi.memory_regions).expect("memory layout"),
         page_mapper::OffsetMapper::new(pmo),
-        PhysicalMemoryManager::init(&bi.memory_regions),
+        Frames::install(PhysicalMemoryManager::init(&bi.memory_regions)),
     );
+    mem::galloc::GLOBAL_ALLOC.init(pmo);
 
     dbg!(context.layout().usable.len());
     dbg!(context.mapper.try_translate(pmo).unwrap());
 
-    mem::galloc::GLOBAL_ALLOC
-        .map(&mut context)
-        .expect("unable to map the global allocator");
+    mem::context::install(context);
+    mem::context::with_kernel(process::init).expect("unable to allocate the kernel page tables");
+    initrd::init(bi.initrd.as_ref(), pmo);
+    fs::init().expect("unable to mount the filesystems");
+
+    // SAFETY: the RSDP was found by the bootloader which maps the physical memory at `pmo`
+    let acpi = bi
+        .rsdp_addr
+        .into_option()
+        .map(|rsdp| unsafe { acpi::init(PhysicalAddr::new(rsdp), pmo) })
+        .transpose()
+        .expect("invalid ACPI tables");
+    if let Some(acpi) = &acpi {
+        info!(
+            "ACPI {} tables: MADT {}, FADT {}, HPET {}",
+            acpi.revision,
+            acpi.madt.is_some(),
+            acpi.fadt.is_some(),
+            acpi.hpet.is_some()
+        );
+    }
 
+    if cfg!(feature = "use-apic") {
+        let madt = acpi.as_ref().and_then(|acpi| acpi.madt.as_ref());
+        mem::context::with_kernel(|ctx| init::apic(ctx, madt));
+    }
+
+    mem::context::with_kernel(thread::init).expect("unable to initialize threads");
+    command::init(pmo, acpi.as_ref().and_then(|acpi| acpi.fadt))
+        .expect("unable to spawn the command thread");
+
+    let worker = mem::context::with_kernel(|ctx| {
+        thread::spawn_thread(ctx, || {
+            for i in 0..3 {
+                trace!("worker thread tick {}", i);
+                thread::sleep(core::time::Duration::from_millis(100));
+            }
+        })
+    })
+    .expect("unable to spawn the worker thread");
+    mem::context::with_kernel(|ctx| ctx.space.dump());
+    thread::join(worker);
+
+    // the initrd copy of the program is preferred over the embedded one
+    let hello = match process::exec_file("/initrd/hello", &["hello"], &[]) {
+        Err(process::Error::Fs(vfs::Error::NotFound)) => {
+            process::exec(process::programs::HELLO, &["hello"], &[])
+        }
+        hello => hello,
+    }
+    .expect("unable to start the first process");
+    thread::join(hello);
 
     let f = bi.framebuffer.as_mut().unwrap();
     let info = f.info();
@@ -74,14 +137,15 @@ pub fn kmain(bi: &'static mut bootloader::BootInfo) -> ! {
 
     {
         /*
-        let sched_alloc = MemoryMappedObject::new(
-            SpinMutex::new(SlabPage::from_page(Page::<Page4Kb>::containing(
-                VirtualAddr::new(0x1_0000_4000),
-            ))),
-            // NOTE: ???
-            PageRangeInclusive::<Page4Kb>::with_size(VirtualAddr::new(0x1_0000_4000), 4 * Kb),
-        );
-        sched_alloc.map(&mut context).expect("scheduler allocator");
+        let arena = mem::context::with_kernel(|ctx| {
+            use libx64::paging::entry::Flags;
+            use mem::vma::{Backing, Purpose};
+            ctx.mmap("scheduler arena", 1, Flags::RW, Purpose::Heap, Backing::Eager)
+        })
+        .expect("scheduler allocator");
+        let sched_alloc = SpinMutex::new(SlabPage::from_page(Page::<Page4Kb>::containing(
+            arena.start(),
+        )));
 
         dbg!(alloc::boxed::Box::new_in(1u8, &sched_alloc));
         */
@@ -90,14 +154,14 @@ pub fn kmain(bi: &'static mut bootloader::BootInfo) -> ! {
     {
         /*
         use scheduler::{Scheduler, Task};
-        let mut scheduler = Scheduler::new(sched_alloc.into_resource());
+        let mut scheduler = Scheduler::new(sched_alloc);
                 scheduler.spawn(async {
                     use kcore::futures::stream::StreamExt;
 
                     while let Some(key) = crate::init::KEYBOARD.lock().next().await {
                         dbg!(key);
                     }
-                });
+                }).expect("keyboard task");
                 scheduler.run();
         */
     }
@@ -110,6 +174,7 @@ pub fn kmain(bi: &'static mut bootloader::BootInfo) -> ! {
 
 #[panic_handler]
 fn ph(info: &PanicInfo) -> ! {
-    error!("PANIC => {}", info);
+    error!(panic = true, "PANIC => {}", info);
+    qemu_logger::SERIAL.flush();
     libx64::diverging_hlt();
 }
@@ -0,0 +1,31 @@
+//! Object caches
+//!
+//! Objects the kernel allocates and frees all the time get a [`SlabCache`] of their own, freed
+//! objects stay in the cache instead of going back to the heap. Caches are locked with
+//! interrupts disabled, objects may be freed by the scheduler.
+
+use alloc::alloc::{AllocError, Allocator, Global, Layout};
+use core::ptr::NonNull;
+
+use kalloc::slab::SlabCache;
+use kcore::sync::SpinMutex;
+
+/// Slab cache backed by the heap.
+pub struct Cache(SpinMutex<SlabCache<Global>>);
+
+impl Cache {
+    #[must_use]
+    pub const fn new(cache: SlabCache<Global>) -> Self {
+        Self(SpinMutex::new(cache))
+    }
+}
+
+unsafe impl Allocator for Cache {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        libx64::without_interrupts(|| self.0.allocate(layout))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        libx64::without_interrupts(|| self.0.deallocate(ptr, layout));
+    }
+}
@@ -1,15 +1,54 @@
-use bootloader::boot_info::{MemoryRegionKind, MemoryRegions};
+use bootloader::boot_info::{MemoryRegion, MemoryRegionKind, MemoryRegions};
+use kcore::sync::SpinMutex;
 use libx64::{
     address::PhysicalAddr,
     paging::{frame::FrameRangeInclusive, Page4Kb},
 };
+use page_mapper::OffsetMapper;
+
+use crate::mem::{pmm::Frames, vma::AddressSpace};
+
+pub type KernelContext = MemoryContext<OffsetMapper, Frames>;
+
+struct SharedContext(KernelContext);
+
+// SAFETY: the page tables are only reached through the lock
+unsafe impl Send for SharedContext {}
+
+/// Memory context of the kernel address space, shared with the page fault handler
+static KERNEL: SpinMutex<Option<SharedContext>> = SpinMutex::new(None);
+
+/// Share the kernel context, it is then only reachable through [`with_kernel`].
+pub fn install(ctx: KernelContext) {
+    let previous = KERNEL.lock().replace(SharedContext(ctx));
+    assert!(previous.is_none(), "kernel memory context installed twice");
+}
+
+/// Run `f` on the kernel context.
+///
+/// Interrupts are disabled so the lock is never held by a preempted thread, the fault handler
+/// can only find it taken if `f` itself faults.
+pub fn with_kernel<R>(f: impl FnOnce(&mut KernelContext) -> R) -> R {
+    libx64::without_interrupts(|| {
+        let mut ctx = KERNEL.lock();
+        f(&mut ctx.as_mut().expect("kernel memory context not installed").0)
+    })
+}
+
+/// Run `f` on the kernel context unless it is already borrowed.
+pub(crate) fn try_with_kernel<R>(f: impl FnOnce(&mut KernelContext) -> R) -> Option<R> {
+    let mut ctx = KERNEL.try_lock()?;
+    Some(f(&mut ctx.as_mut()?.0))
+}
 
 pub struct MemoryContext<M, A> {
     layout: MemoryLayout,
     pub mapper: M,
     pub alloc: A,
+    pub space: AddressSpace,
 }
 
+#[derive(Clone)]
 pub struct MemoryLayout {
     memory_map: &'static MemoryRegions,
     pub usable: FrameRangeInclusive<Page4Kb>,
@@ -17,10 +56,16 @@ pub struct MemoryLayout {
 
 impl<M, A> MemoryContext<M, A> {
     pub fn new(layout: MemoryLayout, mapper: M, alloc: A) -> Self {
+        Self::with_space(layout, mapper, alloc, AddressSpace::kernel())
+    }
+
+    /// Context managing `space` instead of the kernel window.
+    pub fn with_space(layout: MemoryLayout, mapper: M, alloc: A, space: AddressSpace) -> Self {
         Self {
             layout,
             mapper,
             alloc,
+            space,
         }
     }
 
@@ -59,6 +104,11 @@ impl MemoryLayout {
     pub fn memory_map(&self) -> *const MemoryRegions {
         self.memory_map
     }
+
+    /// Memory map given by the bootloader.
+    pub fn regions(&self) -> &'static [MemoryRegion] {
+        self.memory_map
+    }
 }
 
 impl core::fmt::Debug for MemoryLayout {
@@ -0,0 +1,211 @@
+//! Page fault resolution.
+//!
+//! Virtual regions are reserved up front without committing frames, the fault handler backs
+//! them one page at a time on first access. Faults outside of a reserved region, or that can't
+//! be resolved, are reported with what was accessed and how.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use kcore::sync::SpinMutex;
+use libx64::{
+    address::VirtualAddr,
+    paging::{
+        entry::Flags,
+        frame::{FrameAllocator, FrameError},
+        page::{Page, PageMapper, PageRangeInclusive, TlbFlush},
+        Page4Kb, PageFaultErrorCode,
+    },
+};
+
+use crate::mem::context::{try_with_kernel, KernelContext};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RegionKind {
+    /// Frames are allocated and zeroed on first access
+    LazyZero,
+    /// Stack growing down to the start of the region, the lowest page is never mapped and
+    /// catches overflows
+    GrowableStack,
+    /// Address space that must never be accessed
+    Guard,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: &'static str,
+    pub pages: PageRangeInclusive<Page4Kb>,
+    pub kind: RegionKind,
+    /// Flags of the pages mapped on demand
+    pub flags: Flags,
+}
+
+impl Region {
+    fn overlaps(&self, pages: &PageRangeInclusive<Page4Kb>) -> bool {
+        self.pages.start() <= pages.end() && pages.start() <= self.pages.end()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    /// The range overlaps the named region
+    Overlap(&'static str),
+}
+
+static REGIONS: SpinMutex<Vec<Region>> = SpinMutex::new(Vec::new());
+
+/// Reserve a range of virtual memory, nothing is mapped until it is accessed.
+///
+/// # Errors
+///
+/// Errors if the range overlaps an already reserved region
+pub fn reserve(
+    name: &'static str,
+    pages: PageRangeInclusive<Page4Kb>,
+    kind: RegionKind,
+    flags: Flags,
+) -> Result<(), Error> {
+    libx64::without_interrupts(|| {
+        let mut regions = REGIONS.lock();
+        if let Some(region) = regions.iter().find(|r| r.overlaps(&pages)) {
+            return Err(Error::Overlap(region.name));
+        }
+        trace!("reserved {} {:?} ({:?})", name, pages, kind);
+        regions.push(Region {
+            name,
+            pages,
+            kind,
+            flags,
+        });
+        Ok(())
+    })
+}
+
+/// Forget the region starting at `start`, pages that were mapped on demand stay mapped.
+pub fn release(start: VirtualAddr) -> Option<Region> {
+    libx64::without_interrupts(|| {
+        let mut regions = REGIONS.lock();
+        let idx = regions.iter().position(|r| r.pages.start() == start)?;
+        Some(regions.swap_remove(idx))
+    })
+}
+
+/// Change the flags of the pages mapped on demand in the region starting at `start`.
+pub fn protect(start: VirtualAddr, flags: Flags) {
+    libx64::without_interrupts(|| {
+        let mut regions = REGIONS.lock();
+        if let Some(region) = regions.iter_mut().find(|r| r.pages.start() == start) {
+            region.flags = flags;
+        }
+    });
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Cause {
+    /// No region is reserved at this address
+    Unreserved,
+    /// The access isn't allowed by the flags of the page or of the region
+    Protection,
+    /// The region must not be accessed
+    Guard,
+    /// The access hit the guard page at the bottom of a stack
+    StackOverflow,
+    /// Backing the page failed
+    Map(FrameError),
+    /// The fault happened while the kernel context was borrowed
+    ContextBusy,
+}
+
+/// Unresolved page fault.
+#[derive(Debug, Clone)]
+pub struct Fault {
+    pub addr: VirtualAddr,
+    pub code: PageFaultErrorCode,
+    pub region: Option<Region>,
+    pub cause: Cause,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = if self.code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+            "instruction fetch"
+        } else if self.code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+            "write"
+        } else {
+            "read"
+        };
+        let privilege = if self.code.contains(PageFaultErrorCode::USER_MODE) {
+            "user"
+        } else {
+            "kernel"
+        };
+
+        write!(f, "{} of {:?} from {} mode", access, self.addr, privilege)?;
+        match &self.region {
+            Some(region) => write!(f, " in {} ({:?})", region.name, region.kind)?,
+            None => write!(f, " outside of any region")?,
+        }
+        write!(f, ": {:?} [{:?}]", self.cause, self.code)
+    }
+}
+
+/// Back the faulting page if it belongs to a lazily committed region.
+///
+/// # Errors
+///
+/// Errors if the fault can't be resolved, the faulting code must not be resumed
+pub fn handle(addr: VirtualAddr, code: PageFaultErrorCode) -> Result<(), Fault> {
+    let page = Page::<Page4Kb>::containing(addr);
+    let region = libx64::without_interrupts(|| {
+        let regions = REGIONS.lock();
+        regions.iter().find(|r| r.pages.contains(&page)).cloned()
+    });
+
+    let fault = |region, cause| Fault {
+        addr,
+        code,
+        region,
+        cause,
+    };
+
+    let region = match region {
+        Some(region) => region,
+        None => return Err(fault(None, Cause::Unreserved)),
+    };
+    // user code must not get kernel pages committed on its behalf
+    let privileged =
+        code.contains(PageFaultErrorCode::USER_MODE) && !region.flags.contains(Flags::US);
+    if code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) || privileged {
+        return Err(fault(Some(region), Cause::Protection));
+    }
+
+    let cause = match region.kind {
+        RegionKind::Guard => Some(Cause::Guard),
+        RegionKind::GrowableStack if page.ptr() == region.pages.start() => {
+            Some(Cause::StackOverflow)
+        }
+        RegionKind::LazyZero | RegionKind::GrowableStack => None,
+    };
+    if let Some(cause) = cause {
+        return Err(fault(Some(region), cause));
+    }
+
+    match try_with_kernel(|ctx| commit(ctx, page, region.flags)) {
+        Some(Ok(())) => Ok(()),
+        Some(Err(err)) => Err(fault(Some(region), Cause::Map(err))),
+        None => Err(fault(Some(region), Cause::ContextBusy)),
+    }
+}
+
+/// Map a zeroed frame at `page`.
+fn commit(ctx: &mut KernelContext, page: Page<Page4Kb>, flags: Flags) -> Result<(), FrameError> {
+    let frame = FrameAllocator::<Page4Kb>::alloc(&mut ctx.alloc)?;
+
+    // zero through the physical memory mapping, the page may not be writable
+    let zero = ctx.mapper.offset() + frame.ptr().as_u64();
+    unsafe { core::ptr::write_bytes(zero.ptr::<u8>().unwrap().as_ptr(), 0, Page4Kb) };
+
+    ctx.mapper
+        .map(page, frame, flags | Flags::PRESENT, &mut ctx.alloc)
+        .map(TlbFlush::flush)
+}
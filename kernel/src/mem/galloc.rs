@@ -1,23 +1,207 @@
+//! Kernel heap
+//!
+//! Small allocations are served by slab pages, one list per size class, that are allocated from
+//! the physical memory manager when every slab of the class is full and given back once empty.
+//! Larger allocations get their own run of contiguous frames.
+//!
+//! Frames are reached through the physical memory mapping of the bootloader so the heap never
+//! touches the page tables and can grow while a memory context is borrowed.
+
+use alloc::alloc::{Allocator, GlobalAlloc, Layout};
+use core::ptr::NonNull;
+
 use kalloc::slab::SlabPage;
 use kcore::sync::SpinMutex;
-
 use libx64::{
-    address::VirtualAddr,
-    paging::{
-        page::{Page, PageRangeInclusive},
-        Page4Kb,
-    },
-    units::Kb,
+    address::{PhysicalAddr, VirtualAddr},
+    paging::{page::Page, Page4Kb},
 };
 
-use crate::mem::mmo::MemoryMappedObject;
+use crate::mem::pmm::Frames;
 
-type AllocatorResource = MemoryMappedObject<SpinMutex<SlabPage>, Page4Kb>;
-
-pub const HEAP_OFFSET: VirtualAddr = VirtualAddr::new(0x4444_4444_0000);
+/// Slot sizes of the slab pages, anything larger is page granular
+const SIZE_CLASSES: [usize; 7] = [16, 32, 64, 128, 256, 512, 1024];
 
 #[global_allocator]
-pub static GLOBAL_ALLOC: AllocatorResource = MemoryMappedObject::new(
-    SpinMutex::new(SlabPage::from_page(Page::containing(HEAP_OFFSET))),
-    PageRangeInclusive::with_size(HEAP_OFFSET, 4 * Kb as u64),
-);
+pub static GLOBAL_ALLOC: Heap = Heap::new();
+
+/// Header written at the start of every slab page.
+struct Slab {
+    prev: Option<NonNull<Slab>>,
+    next: Option<NonNull<Slab>>,
+    page: SlabPage,
+}
+
+/// Doubly linked list of the slabs of a size class
+#[derive(Clone, Copy)]
+struct SizeClass {
+    head: Option<NonNull<Slab>>,
+    /// Number of slabs without any allocation, at most one is kept around
+    empty: usize,
+}
+
+struct Inner {
+    /// Physical memory offset, the heap is unusable until it is set
+    offset: Option<VirtualAddr>,
+    classes: [SizeClass; SIZE_CLASSES.len()],
+}
+
+// SAFETY: slabs are only reached through the heap lock
+unsafe impl Send for Inner {}
+
+pub struct Heap {
+    inner: SpinMutex<Inner>,
+}
+
+impl Heap {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            inner: SpinMutex::new(Inner {
+                offset: None,
+                classes: [SizeClass {
+                    head: None,
+                    empty: 0,
+                }; SIZE_CLASSES.len()],
+            }),
+        }
+    }
+
+    /// Start serving allocations, frames are accessed at `offset` + their physical address.
+    pub fn init(&self, offset: VirtualAddr) {
+        libx64::without_interrupts(|| self.inner.lock().offset = Some(offset));
+    }
+
+    fn class_of(layout: Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align());
+        SIZE_CLASSES.iter().position(|&class| size <= class)
+    }
+
+    /// Layout of the frames backing an allocation too large for the slabs.
+    fn pages_layout(layout: Layout) -> Layout {
+        let size = (layout.size() + Page4Kb - 1) & !(Page4Kb - 1);
+        Layout::from_size_align(size, layout.align().max(Page4Kb)).unwrap()
+    }
+}
+
+impl Inner {
+    fn to_virt(&self, phys: NonNull<[u8]>) -> NonNull<u8> {
+        let offset = self.offset.expect("heap used before initialization");
+        let addr = offset + phys.cast::<u8>().as_ptr() as u64;
+        addr.ptr().unwrap()
+    }
+
+    fn to_phys(&self, virt: NonNull<u8>) -> NonNull<u8> {
+        let offset = self.offset.expect("heap used before initialization");
+        let addr = PhysicalAddr::new(virt.as_ptr() as u64 - offset.as_u64());
+        addr.ptr().unwrap()
+    }
+
+    fn allocate_small(&mut self, class: usize, layout: Layout) -> Option<NonNull<u8>> {
+        let mut cursor = self.classes[class].head;
+        while let Some(mut slab) = cursor {
+            let slab = unsafe { slab.as_mut() };
+            let empty = slab.page.is_empty();
+            // a slab with free slots may still not fit the alignment, the next one is tried
+            if let Ok(ptr) = slab.page.allocate(layout) {
+                if empty {
+                    self.classes[class].empty -= 1;
+                }
+                return Some(ptr.cast());
+            }
+            cursor = slab.next;
+        }
+
+        let slab = self.grow(class)?;
+        match unsafe { (*slab.as_ptr()).page.allocate(layout) } {
+            Ok(ptr) => Some(ptr.cast()),
+            Err(_) => {
+                // the new slab stays empty, it is counted so a free can give it back
+                self.classes[class].empty += 1;
+                None
+            }
+        }
+    }
+
+    /// Push a new slab page in front of the class.
+    fn grow(&mut self, class: usize) -> Option<NonNull<Slab>> {
+        let frame = Frames.allocate(Page::<Page4Kb>::alloc_layout()).ok()?;
+        let slab = self.to_virt(frame).cast::<Slab>();
+        let page = Page::containing_ptr(slab.as_ptr());
+
+        let class_list = &mut self.classes[class];
+        unsafe {
+            slab.as_ptr().write(Slab {
+                prev: None,
+                next: class_list.head,
+                page: SlabPage::new(
+                    page,
+                    SIZE_CLASSES[class],
+                    core::mem::size_of::<Slab>(),
+                ),
+            });
+            if let Some(mut head) = class_list.head {
+                head.as_mut().prev = Some(slab);
+            }
+        }
+        class_list.head = Some(slab);
+        Some(slab)
+    }
+
+    unsafe fn deallocate_small(&mut self, class: usize, ptr: NonNull<u8>, layout: Layout) {
+        // slabs are page aligned, the header is at the start of the page
+        let slab = Page::<Page4Kb>::containing_ptr(ptr.as_ptr())
+            .ptr()
+            .ptr::<Slab>()
+            .unwrap();
+        let page = &mut (*slab.as_ptr()).page;
+        page.deallocate(ptr, layout);
+        if !page.is_empty() {
+            return;
+        }
+
+        let class_list = &mut self.classes[class];
+        if class_list.empty == 0 {
+            class_list.empty += 1;
+            return;
+        }
+
+        // give the page back, an empty slab is already kept for the class
+        let Slab { prev, next, .. } = slab.as_ptr().read();
+        match prev {
+            Some(mut prev) => prev.as_mut().next = next,
+            None => class_list.head = next,
+        }
+        if let Some(mut next) = next {
+            next.as_mut().prev = prev;
+        }
+        Frames.deallocate(self.to_phys(slab.cast()), Page::<Page4Kb>::alloc_layout());
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        libx64::without_interrupts(|| {
+            let mut inner = self.inner.lock();
+            let ptr = match Self::class_of(layout) {
+                Some(class) => inner.allocate_small(class, layout),
+                None => Frames
+                    .allocate(Self::pages_layout(layout))
+                    .ok()
+                    .map(|frames| inner.to_virt(frames)),
+            };
+            ptr.map_or(core::ptr::null_mut(), NonNull::as_ptr)
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let ptr = NonNull::new_unchecked(ptr);
+        libx64::without_interrupts(|| {
+            let mut inner = self.inner.lock();
+            match Self::class_of(layout) {
+                Some(class) => inner.deallocate_small(class, ptr, layout),
+                None => Frames.deallocate(inner.to_phys(ptr), Self::pages_layout(layout)),
+            }
+        });
+    }
+}
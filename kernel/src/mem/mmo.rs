@@ -29,10 +29,6 @@ where
         &self.resource
     }
 
-    pub fn into_resource(self) -> T {
-        self.resource
-    }
-
     pub const fn pages(&self) -> &PageRangeInclusive<P> {
         &self.pages
     }
@@ -1,9 +1,12 @@
 use alloc::alloc::Layout;
 
+pub mod cache;
 pub mod context;
+pub mod fault;
 pub mod galloc;
 pub mod mmo;
 pub mod pmm;
+pub mod vma;
 
 #[alloc_error_handler]
 fn alloc_error_handler(error: Layout) -> ! {
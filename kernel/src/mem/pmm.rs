@@ -4,7 +4,7 @@ use kalloc::{buddy::BuddyAllocator, AllocatorBin};
 use libx64::{
     address::PhysicalAddr,
     paging::{
-        frame::{FrameAllocator, FrameError, FrameRange, PhysicalFrame},
+        frame::{FrameAllocator, FrameDeallocator, FrameError, FrameRange, PhysicalFrame},
         Page4Kb,
     },
 };
@@ -156,3 +156,65 @@ where
             .map(PhysicalFrame::containing)
     }
 }
+
+impl<const N: usize> FrameDeallocator<N> for PhysicalMemoryManager
+where
+    libx64::paging::PageCheck<N>: libx64::paging::PageSize,
+{
+    unsafe fn dealloc(&mut self, frame: PhysicalFrame<N>) {
+        let ptr = core::ptr::NonNull::new(frame.ptr().as_u64() as *mut u8).expect("null frame");
+        self.deallocate(ptr, PhysicalFrame::<N>::alloc_layout());
+    }
+}
+
+static GLOBAL: SpinMutex<PhysicalMemoryManager> = SpinMutex::new(PhysicalMemoryManager::new());
+
+/// Handle on the physical memory manager shared by the memory contexts and the heap.
+///
+/// The lock is only held for the duration of a buddy operation with interrupts disabled, the
+/// heap can grow while a memory context is borrowed.
+#[derive(Debug, Clone, Copy)]
+pub struct Frames;
+
+impl Frames {
+    /// Share the physical memory manager, frames are then only allocated through handles.
+    pub fn install(pmm: PhysicalMemoryManager) -> Self {
+        libx64::without_interrupts(|| {
+            let mut global = GLOBAL.lock();
+            assert!(global.buddies.is_none(), "physical memory manager installed twice");
+            *global = pmm;
+        });
+        Self
+    }
+}
+
+unsafe impl Allocator for Frames {
+    fn allocate(
+        &self,
+        layout: Layout,
+    ) -> Result<core::ptr::NonNull<[u8]>, core::alloc::AllocError> {
+        libx64::without_interrupts(|| GLOBAL.lock().allocate(layout))
+    }
+
+    unsafe fn deallocate(&self, ptr: core::ptr::NonNull<u8>, layout: Layout) {
+        libx64::without_interrupts(|| GLOBAL.lock().deallocate(ptr, layout));
+    }
+}
+
+impl<const N: usize> FrameAllocator<N> for Frames
+where
+    libx64::paging::PageCheck<N>: libx64::paging::PageSize,
+{
+    fn alloc(&mut self) -> Result<PhysicalFrame<N>, FrameError> {
+        libx64::without_interrupts(|| GLOBAL.lock().alloc())
+    }
+}
+
+impl<const N: usize> FrameDeallocator<N> for Frames
+where
+    libx64::paging::PageCheck<N>: libx64::paging::PageSize,
+{
+    unsafe fn dealloc(&mut self, frame: PhysicalFrame<N>) {
+        libx64::without_interrupts(|| GLOBAL.lock().dealloc(frame));
+    }
+}
@@ -0,0 +1,365 @@
+//! Kernel virtual address space
+//!
+//! Areas are handed out first fit from a window of the address space, each one is surrounded by
+//! unmapped guard pages so running off the end of an area faults instead of corrupting its
+//! neighbour.
+
+use alloc::collections::BTreeMap;
+
+use libx64::{
+    address::{PhysicalAddr, VirtualAddr},
+    paging::{
+        entry::Flags,
+        frame::{FrameAllocator, FrameDeallocator, FrameError, PhysicalFrame},
+        page::{PageMapper, PageRange, PageRangeInclusive, PageTranslator, TlbFlush},
+        Page4Kb,
+    },
+};
+
+use crate::mem::{
+    context::MemoryContext,
+    fault::{self, RegionKind},
+};
+
+/// Window of the address space managed by the kernel
+pub const KERNEL_WINDOW: (VirtualAddr, VirtualAddr) = (
+    VirtualAddr::new(0x4000_0000_0000),
+    VirtualAddr::new(0x6000_0000_0000),
+);
+
+/// Window of a process address space, the only part that isn't shared with the kernel. It ends
+/// one page before the non canonical hole.
+pub const USER_WINDOW: (VirtualAddr, VirtualAddr) = (
+    VirtualAddr::new(0x6000_0000_0000),
+    VirtualAddr::new(0x7FFF_FFFF_F000),
+);
+
+/// Unmapped pages left on each side of an area
+pub const GUARD_PAGES: usize = 1;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Purpose {
+    Heap,
+    Stack,
+    Mmio,
+    Anonymous,
+    /// Segments of an executable
+    Image,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Backing {
+    /// Frames are allocated when the area is mapped
+    Eager,
+    /// Frames are allocated on first access by the fault handler
+    Lazy,
+    /// The area maps the physical range starting at this address
+    Physical(PhysicalAddr),
+    /// The pages are mapped by the owner of the area
+    Fixed,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Area {
+    pub name: &'static str,
+    pub pages: PageRange<Page4Kb>,
+    pub flags: Flags,
+    pub purpose: Purpose,
+    pub backing: Backing,
+}
+
+impl Area {
+    fn overlaps(&self, pages: &PageRange<Page4Kb>) -> bool {
+        self.pages.start() < pages.end() && pages.start() < self.pages.end()
+    }
+
+    fn inclusive(&self) -> PageRangeInclusive<Page4Kb> {
+        PageRangeInclusive::new_addr(self.pages.start(), self.pages.end() - Page4Kb)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    /// No gap of the window is large enough
+    OutOfSpace,
+    /// The range overlaps the named area
+    Overlap(&'static str),
+    /// No area starts at this address
+    NotFound,
+    Map(FrameError),
+    Fault(fault::Error),
+}
+
+impl From<FrameError> for Error {
+    fn from(err: FrameError) -> Self {
+        Self::Map(err)
+    }
+}
+
+impl From<fault::Error> for Error {
+    fn from(err: fault::Error) -> Self {
+        Self::Fault(err)
+    }
+}
+
+/// Bookkeeping of the areas of an address space, mapping is left to [`MemoryContext`].
+pub struct AddressSpace {
+    window: PageRange<Page4Kb>,
+    /// Areas indexed by their start address
+    areas: BTreeMap<VirtualAddr, Area>,
+}
+
+impl AddressSpace {
+    #[must_use]
+    pub fn new(start: VirtualAddr, end: VirtualAddr) -> Self {
+        Self {
+            window: PageRange::new_addr(start, end),
+            areas: BTreeMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn kernel() -> Self {
+        Self::new(KERNEL_WINDOW.0, KERNEL_WINDOW.1)
+    }
+
+    #[must_use]
+    pub fn user() -> Self {
+        Self::new(USER_WINDOW.0, USER_WINDOW.1)
+    }
+
+    /// Whether the pages are inside the managed window.
+    #[must_use]
+    pub fn covers(&self, pages: &PageRange<Page4Kb>) -> bool {
+        self.window.start() <= pages.start() && pages.end() <= self.window.end()
+    }
+
+    /// First gap that fits `len` pages with a guard on each side.
+    fn find_gap(&self, len: usize) -> Option<PageRange<Page4Kb>> {
+        let size = (len + 2 * GUARD_PAGES) * Page4Kb;
+        let fits = |start: VirtualAddr, end: VirtualAddr| {
+            (end.as_usize() - start.as_usize() >= size).then(|| {
+                let start = start + GUARD_PAGES * Page4Kb;
+                PageRange::new_addr(start, start + len * Page4Kb)
+            })
+        };
+
+        let mut cursor = self.window.start();
+        for area in self.areas.values() {
+            if area.pages.start() > cursor {
+                if let Some(pages) = fits(cursor, area.pages.start()) {
+                    return Some(pages);
+                }
+            }
+            cursor = cursor.max(area.pages.end());
+        }
+        if self.window.end() > cursor {
+            return fits(cursor, self.window.end());
+        }
+        None
+    }
+
+    /// # Errors
+    ///
+    /// Errors if the area overlaps another one
+    pub fn insert(&mut self, area: Area) -> Result<(), Error> {
+        if let Some(other) = self.areas.values().find(|other| other.overlaps(&area.pages)) {
+            return Err(Error::Overlap(other.name));
+        }
+        self.areas.insert(area.pages.start(), area);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, start: VirtualAddr) -> Option<Area> {
+        self.areas.remove(&start)
+    }
+
+    /// Area containing the address.
+    #[must_use]
+    pub fn find(&self, addr: VirtualAddr) -> Option<&Area> {
+        self.areas
+            .range(..=addr)
+            .next_back()
+            .map(|(_, area)| area)
+            .filter(|area| addr < area.pages.end())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Area> {
+        self.areas.values()
+    }
+
+    /// Log every area in address order.
+    pub fn dump(&self) {
+        info!(
+            "address space {:?}..{:?} ({} areas)",
+            self.window.start(),
+            self.window.end(),
+            self.areas.len()
+        );
+        for area in self.areas.values() {
+            info!(
+                "  {:?}..{:?} {:>6} pages {:<10} {:?} {:?} {:?}",
+                area.pages.start(),
+                area.pages.end(),
+                area.pages.len(),
+                area.name,
+                area.purpose,
+                area.backing,
+                area.flags
+            );
+        }
+    }
+}
+
+impl<M, A> MemoryContext<M, A>
+where
+    M: PageMapper<Page4Kb> + PageTranslator,
+    A: FrameAllocator<Page4Kb> + FrameDeallocator<Page4Kb>,
+{
+    /// Allocate `len` pages of address space and back them according to `backing`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if there is no room left or the pages can't be mapped, nothing stays mapped
+    pub fn mmap(
+        &mut self,
+        name: &'static str,
+        len: usize,
+        flags: Flags,
+        purpose: Purpose,
+        backing: Backing,
+    ) -> Result<PageRange<Page4Kb>, Error> {
+        let pages = self.space.find_gap(len).ok_or(Error::OutOfSpace)?;
+        let area = Area {
+            name,
+            pages: pages.clone(),
+            flags: flags | Flags::PRESENT,
+            purpose,
+            backing,
+        };
+
+        self.space.insert(area.clone())?;
+        if let Err(err) = self.back(&area) {
+            self.unback(&area);
+            self.space.remove(area.pages.start());
+            return Err(err);
+        }
+        trace!("mmap {} {:?}", name, pages);
+        Ok(pages)
+    }
+
+    /// Record pages the caller maps itself at a fixed address.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the pages overlap another area
+    pub fn mmap_fixed(
+        &mut self,
+        name: &'static str,
+        pages: PageRange<Page4Kb>,
+        flags: Flags,
+        purpose: Purpose,
+    ) -> Result<(), Error> {
+        self.space.insert(Area {
+            name,
+            pages,
+            flags,
+            purpose,
+            backing: Backing::Fixed,
+        })
+    }
+
+    /// Unmap the area starting at `start` and release its address space.
+    ///
+    /// # Errors
+    ///
+    /// Errors if no area starts at this address
+    pub fn munmap(&mut self, start: VirtualAddr) -> Result<Area, Error> {
+        let area = self.space.remove(start).ok_or(Error::NotFound)?;
+        self.unback(&area);
+        trace!("munmap {} {:?}", area.name, area.pages);
+        Ok(area)
+    }
+
+    /// Change the flags of every page of the area starting at `start`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if no area starts at this address or a mapped page can't be updated
+    pub fn mprotect(&mut self, start: VirtualAddr, flags: Flags) -> Result<(), Error> {
+        let flags = flags | Flags::PRESENT;
+        let area = self
+            .space
+            .areas
+            .get_mut(&start)
+            .ok_or(Error::NotFound)?;
+        area.flags = flags;
+
+        if area.backing == Backing::Lazy {
+            fault::protect(area.pages.start(), flags);
+        }
+        for page in area.pages.clone() {
+            // lazy pages that weren't touched yet aren't mapped
+            if self.mapper.try_translate(page.ptr()).is_ok() {
+                self.mapper.update_flags(page, flags)?.flush();
+            }
+        }
+        Ok(())
+    }
+
+    fn back(&mut self, area: &Area) -> Result<(), Error> {
+        match area.backing {
+            Backing::Eager => area.pages.clone().try_for_each(|page| {
+                let frame = self.alloc.alloc()?;
+                self.mapper
+                    .map(page, frame, area.flags, &mut self.alloc)
+                    .map(TlbFlush::flush)
+            })?,
+            Backing::Physical(phys) => {
+                area.pages.clone().enumerate().try_for_each(|(i, page)| {
+                    let frame = PhysicalFrame::containing(phys + i * Page4Kb);
+                    self.mapper
+                        .map(page, frame, area.flags, &mut self.alloc)
+                        .map(TlbFlush::flush)
+                })?;
+            }
+            Backing::Lazy => {
+                fault::reserve(area.name, area.inclusive(), RegionKind::LazyZero, area.flags)?;
+            }
+            Backing::Fixed => {}
+        }
+        Ok(())
+    }
+
+    /// Unmap whatever part of the area is mapped, the frames allocated for it are freed.
+    fn unback(&mut self, area: &Area) {
+        let owned = match area.backing {
+            Backing::Eager => true,
+            Backing::Lazy => {
+                fault::release(area.pages.start());
+                true
+            }
+            Backing::Physical(_) => false,
+            Backing::Fixed => return,
+        };
+        for page in area.pages.clone() {
+            let Ok(translation) = self.mapper.try_translate(page.ptr()) else {
+                continue;
+            };
+            if let Ok(flush) = self.mapper.unmap(page) {
+                flush.flush();
+                if owned {
+                    let frame = PhysicalFrame::containing(translation.addr);
+                    // SAFETY: the frame was allocated for the area and isn't mapped anymore
+                    unsafe { self.alloc.dealloc(frame) };
+                }
+            }
+        }
+    }
+}
+
+/// Pages of `len` bytes, rounded up.
+#[must_use]
+pub const fn pages_for(len: usize) -> usize {
+    (len + Page4Kb - 1) / Page4Kb
+}
@@ -0,0 +1,150 @@
+use alloc::{vec, vec::Vec};
+
+use kelf::{
+    stack::{self, Args},
+    Image, Loader, Segment,
+};
+use libx64::{
+    address::VirtualAddr,
+    paging::{entry::Flags, page::PageRange},
+    units::Kb,
+};
+use vfs::OpenFlags;
+
+use super::{Error, Process};
+use crate::{
+    fs,
+    mem::vma::{Purpose, USER_WINDOW},
+    thread::ThreadId,
+};
+
+/// Position independent executables are loaded at the start of the user window
+const PIE_BASE: VirtualAddr = USER_WINDOW.0;
+
+/// Stack of the main thread of a process
+pub const USER_STACK_SIZE: usize = 64 * Kb;
+
+/// The main thread stack is placed at the top of the user window, away from the image and the
+/// areas mapped after it
+const USER_STACK_TOP: VirtualAddr = USER_WINDOW.1;
+
+/// Bytes of an executable assembled from `user/` by the build script, aligned for the ELF
+/// parser.
+macro_rules! program {
+    ($name:literal) => {{
+        #[repr(C)]
+        struct Aligned<B: ?Sized> {
+            _align: [u64; 0],
+            bytes: B,
+        }
+        static ALIGNED: &Aligned<[u8]> = &Aligned {
+            _align: [],
+            bytes: *include_bytes!(concat!(env!("OUT_DIR"), "/", $name)),
+        };
+        &ALIGNED.bytes
+    }};
+}
+
+/// Executables embedded in the kernel image, used when the initrd doesn't provide them.
+pub mod programs {
+    pub static HELLO: &[u8] = program!("hello");
+}
+
+impl Process {
+    /// Load the executable `bytes` and write `argv` and `envp` on a new stack, returns the entry
+    /// point and the initial stack pointer.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the executable is invalid, doesn't fit in [`USER_WINDOW`] or can't be mapped
+    pub fn load(
+        &mut self,
+        bytes: &[u8],
+        argv: &[&str],
+        envp: &[&str],
+    ) -> Result<(VirtualAddr, VirtualAddr), Error> {
+        let image = Image::new(bytes, PIE_BASE)?;
+        for area in areas(&image) {
+            if !self.ctx.space.covers(&area.pages) {
+                return Err(Error::OutsideWindow);
+            }
+            self.ctx
+                .mmap_fixed("image", area.pages, area.flags | Flags::US, Purpose::Image)?;
+        }
+
+        // pages mapped before a failure are freed with the process
+        let offset = self.ctx.mapper.offset();
+        let loaded = Loader::new(&image, &mut self.ctx.mapper, &mut self.ctx.alloc, offset)
+            .with_flags(Flags::US)
+            .load()?;
+
+        let stack = PageRange::new_addr(USER_STACK_TOP - USER_STACK_SIZE, USER_STACK_TOP);
+        self.mmap_at("stack", stack.clone(), Flags::RW, Purpose::Stack)?;
+        let args = Args {
+            argv,
+            envp,
+            auxv: &loaded.auxv(),
+        };
+        let sp = stack::init(&mut self.ctx.mapper, offset, stack.start(), stack.end(), &args)?;
+
+        trace!("process {:?} loaded, entry at {:?}", self.id, loaded.entry);
+        Ok((loaded.entry, sp))
+    }
+}
+
+/// Areas covering the segments, segments sharing a page are recorded as one area.
+fn areas(image: &Image) -> Vec<Segment> {
+    let mut areas: Vec<Segment> = Vec::new();
+    for segment in image.segments() {
+        match areas.last_mut() {
+            Some(last) if segment.pages.start() < last.pages.end() => {
+                let end = last.pages.end().max(segment.pages.end());
+                last.pages = PageRange::new_addr(last.pages.start(), end);
+                last.flags |= segment.flags;
+            }
+            _ => areas.push(segment),
+        }
+    }
+    areas
+}
+
+/// Create a process running the executable `bytes`.
+///
+/// # Errors
+///
+/// Errors if the executable can't be loaded or the thread can't be spawned
+pub fn exec(bytes: &[u8], argv: &[&str], envp: &[&str]) -> Result<ThreadId, Error> {
+    let mut process = Process::new()?;
+    let (entry, stack) = process.load(bytes, argv, envp)?;
+    super::spawn(process, entry, stack)
+}
+
+/// Create a process running the executable at `path` in the filesystem.
+///
+/// # Errors
+///
+/// Errors if the file can't be read, the executable can't be loaded or the thread can't be
+/// spawned
+pub fn exec_file(path: &str, argv: &[&str], envp: &[&str]) -> Result<ThreadId, Error> {
+    let (words, len) = fs::with_vfs(|vfs| {
+        let mut file = vfs.open(path, OpenFlags::READ)?;
+        let len = usize::try_from(file.stat().size).map_err(|_| vfs::Error::InvalidOffset)?;
+
+        // read in words so the bytes are aligned for the ELF parser
+        let mut words = vec![0u64; (len + 7) / 8];
+        // SAFETY: the words cover at least `len` bytes
+        let bytes = unsafe { core::slice::from_raw_parts_mut(words.as_mut_ptr().cast(), len) };
+        let mut read = 0;
+        while read < len {
+            match file.read(&mut bytes[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        Ok::<_, vfs::Error>((words, read))
+    })?;
+
+    // SAFETY: the first `len` bytes of the words were read from the file
+    let bytes = unsafe { core::slice::from_raw_parts(words.as_ptr().cast(), len) };
+    exec(bytes, argv, envp)
+}
@@ -0,0 +1,411 @@
+//! User processes
+//!
+//! Every process gets its own level 4 table. The entries outside of [`USER_WINDOW`] are copied
+//! from the kernel table so the kernel stays mapped while a process runs, the kernel window
+//! entries are all allocated by [`init`] so later kernel mappings show up in every process.
+//!
+//! A process runs on a kernel thread whose stack is used as the ring 0 stack, the scheduler
+//! loads the level 4 table and the stack in the TSS when switching to it.
+
+mod exec;
+mod usermode;
+
+use alloc::collections::BTreeMap;
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use kcore::sync::SpinMutex;
+use libx64::{
+    address::{PhysicalAddr, VirtualAddr},
+    paging::{
+        entry::Flags,
+        frame::{FrameAllocator, FrameDeallocator, FrameError, PhysicalFrame},
+        page::{PageMapper, PageRange, PageTranslator},
+        Page4Kb,
+    },
+};
+use page_mapper::OffsetMapper;
+
+use crate::{
+    mem::{
+        context::{self, KernelContext, MemoryContext},
+        pmm::Frames,
+        vma::{self, AddressSpace, Backing, Purpose, KERNEL_WINDOW, USER_WINDOW},
+    },
+    thread::{self, ThreadId},
+};
+
+pub use exec::{exec, exec_file, programs, USER_STACK_SIZE};
+
+pub type ProcessContext = MemoryContext<OffsetMapper, Frames>;
+
+/// Level 4 entries owned by the processes
+const USER_ENTRIES: Range<usize> = level4_index(USER_WINDOW.0)..level4_index(USER_WINDOW.1) + 1;
+
+/// Level 4 entries of the kernel window
+const KERNEL_ENTRIES: Range<usize> = level4_index(KERNEL_WINDOW.0)..level4_index(KERNEL_WINDOW.1);
+
+const ENTRY_PRESENT: u64 = Flags::PRESENT.bits();
+const ENTRY_ADDRESS: u64 = 0x000F_FFFF_FFFF_F000;
+
+static PROCESSES: SpinMutex<BTreeMap<ProcessId, Process>> = SpinMutex::new(BTreeMap::new());
+
+const fn level4_index(addr: VirtualAddr) -> usize {
+    (addr.as_u64() >> 39) as usize % 512
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ProcessId(u64);
+
+impl ProcessId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    #[must_use]
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    Map(vma::Error),
+    /// The address isn't mapped in the process
+    Unmapped(VirtualAddr),
+    /// The range isn't inside of [`USER_WINDOW`]
+    OutsideWindow,
+    Elf(kelf::Error),
+    /// The executable couldn't be read from the filesystem
+    Fs(vfs::Error),
+}
+
+impl From<vma::Error> for Error {
+    fn from(err: vma::Error) -> Self {
+        Self::Map(err)
+    }
+}
+
+impl From<kelf::Error> for Error {
+    fn from(err: kelf::Error) -> Self {
+        Self::Elf(err)
+    }
+}
+
+impl From<vfs::Error> for Error {
+    fn from(err: vfs::Error) -> Self {
+        Self::Fs(err)
+    }
+}
+
+impl From<FrameError> for Error {
+    fn from(err: FrameError) -> Self {
+        Self::Map(vma::Error::Map(err))
+    }
+}
+
+/// Allocate the level 3 tables of the whole kernel window.
+///
+/// # Errors
+///
+/// Errors if a table can't be allocated
+pub fn init(ctx: &mut KernelContext) -> Result<(), FrameError> {
+    let offset = ctx.mapper.offset();
+    let level4 = table_ptr(offset, libx64::control::cr3().frame().ptr());
+
+    for idx in KERNEL_ENTRIES {
+        // SAFETY: the table is the active level 4 table, reached through the physical mapping
+        unsafe {
+            if *level4.add(idx) & ENTRY_PRESENT == 0 {
+                let table = zeroed_frame(&mut ctx.alloc, offset)?;
+                *level4.add(idx) = table.ptr().as_u64() | (Flags::PRESENT | Flags::RW).bits();
+            }
+        }
+    }
+    trace!("kernel window level 4 entries {:?} allocated", KERNEL_ENTRIES);
+    Ok(())
+}
+
+fn table_ptr(offset: VirtualAddr, table: PhysicalAddr) -> *mut u64 {
+    (offset + table.as_u64()).ptr::<u64>().unwrap().as_ptr()
+}
+
+fn zeroed_frame<A>(alloc: &mut A, offset: VirtualAddr) -> Result<PhysicalFrame<Page4Kb>, FrameError>
+where
+    A: FrameAllocator<Page4Kb>,
+{
+    let frame = alloc.alloc()?;
+    unsafe { core::ptr::write_bytes(table_ptr(offset, frame.ptr()), 0, 512) };
+    Ok(frame)
+}
+
+pub struct Process {
+    id: ProcessId,
+    l4: PhysicalFrame<Page4Kb>,
+    pub ctx: ProcessContext,
+}
+
+// SAFETY: the page tables of a process are only reached through its owner
+unsafe impl Send for Process {}
+
+impl Process {
+    /// Create an empty process sharing the kernel half of the current address space.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the level 4 table can't be allocated
+    pub fn new() -> Result<Self, Error> {
+        let (l4, offset, layout) = context::with_kernel(|kernel| {
+            let offset = kernel.mapper.offset();
+            let l4 = zeroed_frame(&mut Frames, offset)?;
+
+            let src = table_ptr(offset, libx64::control::cr3().frame().ptr());
+            let dst = table_ptr(offset, l4.ptr());
+            for idx in (0..512).filter(|idx| !USER_ENTRIES.contains(idx)) {
+                // SAFETY: both tables are reached through the physical mapping
+                unsafe { *dst.add(idx) = *src.add(idx) };
+            }
+            Ok::<_, FrameError>((l4, offset, kernel.layout().clone()))
+        })?;
+
+        let mapper = unsafe { OffsetMapper::from_frame(l4, offset) };
+        let id = ProcessId::next();
+        trace!("process {:?} level 4 table at {:?}", id, l4.ptr());
+        Ok(Self {
+            id,
+            l4,
+            ctx: MemoryContext::with_space(layout, mapper, Frames, AddressSpace::user()),
+        })
+    }
+
+    #[must_use]
+    pub const fn id(&self) -> ProcessId {
+        self.id
+    }
+
+    /// Frame of the level 4 table of the process.
+    #[must_use]
+    pub const fn l4(&self) -> PhysicalFrame<Page4Kb> {
+        self.l4
+    }
+
+    /// Map `len` zeroed pages accessible from ring 3.
+    ///
+    /// # Errors
+    ///
+    /// Errors if there is no room left in the process or the pages can't be mapped
+    pub fn mmap(
+        &mut self,
+        name: &'static str,
+        len: usize,
+        flags: Flags,
+        purpose: Purpose,
+    ) -> Result<PageRange<Page4Kb>, Error> {
+        let pages = self
+            .ctx
+            .mmap(name, len, flags | Flags::US, purpose, Backing::Eager)?;
+        for page in pages.clone() {
+            self.zero(page.ptr())?;
+        }
+        Ok(pages)
+    }
+
+    /// Map `len` zeroed pages at a fixed address of the process.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the pages are outside of [`USER_WINDOW`], overlap another area or can't be
+    /// mapped
+    pub fn mmap_at(
+        &mut self,
+        name: &'static str,
+        pages: PageRange<Page4Kb>,
+        flags: Flags,
+        purpose: Purpose,
+    ) -> Result<(), Error> {
+        if !self.ctx.space.covers(&pages) {
+            return Err(Error::OutsideWindow);
+        }
+        let flags = flags | Flags::US | Flags::PRESENT;
+        self.ctx.mmap_fixed(name, pages.clone(), flags, purpose)?;
+
+        // pages mapped before a failure are freed with the process
+        for page in pages {
+            let frame = self.ctx.alloc.alloc()?;
+            self.ctx
+                .mapper
+                .map(page, frame, flags, &mut self.ctx.alloc)?
+                .flush();
+            self.zero(page.ptr())?;
+        }
+        Ok(())
+    }
+
+    /// Change the flags of the anonymous area starting at `start`, it stays accessible from
+    /// ring 3.
+    ///
+    /// # Errors
+    ///
+    /// Errors if no anonymous area starts at this address or its pages can't be updated
+    pub fn mprotect(&mut self, start: VirtualAddr, flags: Flags) -> Result<(), Error> {
+        match self.ctx.space.find(start) {
+            Some(area) if area.pages.start() == start && area.purpose == Purpose::Anonymous => {}
+            _ => return Err(Error::Unmapped(start)),
+        }
+        self.ctx.mprotect(start, flags | Flags::US)?;
+        Ok(())
+    }
+
+    fn zero(&mut self, page: VirtualAddr) -> Result<(), Error> {
+        let phys = self.translate(page)?;
+        let ptr = table_ptr(self.ctx.mapper.offset(), phys).cast::<u8>();
+        unsafe { core::ptr::write_bytes(ptr, 0, Page4Kb) };
+        Ok(())
+    }
+
+    fn translate(&mut self, addr: VirtualAddr) -> Result<PhysicalAddr, Error> {
+        self.ctx
+            .mapper
+            .try_translate(addr)
+            .map(|translation| translation.addr)
+            .map_err(|_| Error::Unmapped(addr))
+    }
+
+    /// Copy `bytes` to `addr` in the process, the pages don't have to be writable.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a page of the destination isn't mapped
+    pub fn write(&mut self, mut addr: VirtualAddr, mut bytes: &[u8]) -> Result<(), Error> {
+        let offset = self.ctx.mapper.offset();
+        while !bytes.is_empty() {
+            let phys = self.translate(addr)?;
+            let len = bytes.len().min(Page4Kb - (addr.as_usize() % Page4Kb));
+
+            let dst = (offset + phys.as_u64()).ptr::<u8>().unwrap().as_ptr();
+            unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), dst, len) };
+
+            addr = addr + len;
+            bytes = &bytes[len..];
+        }
+        Ok(())
+    }
+
+    /// Copy the bytes at `addr` in the process to `buf`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a page of the source isn't mapped
+    pub fn read(&mut self, mut addr: VirtualAddr, mut buf: &mut [u8]) -> Result<(), Error> {
+        let offset = self.ctx.mapper.offset();
+        while !buf.is_empty() {
+            let phys = self.translate(addr)?;
+            let len = buf.len().min(Page4Kb - (addr.as_usize() % Page4Kb));
+
+            let src = (offset + phys.as_u64()).ptr::<u8>().unwrap().as_ptr();
+            unsafe { core::ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), len) };
+
+            addr = addr + len;
+            buf = &mut buf[len..];
+        }
+        Ok(())
+    }
+
+    /// Check that the `len` bytes at `addr` can be accessed from ring 3 with `flags`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the range isn't inside of [`USER_WINDOW`] or a page isn't mapped with `flags`
+    pub fn check(&mut self, addr: VirtualAddr, len: usize, flags: Flags) -> Result<(), Error> {
+        let end = addr
+            .as_u64()
+            .checked_add(len as u64)
+            .ok_or(Error::OutsideWindow)?;
+        if addr < USER_WINDOW.0 || end > USER_WINDOW.1.as_u64() {
+            return Err(Error::OutsideWindow);
+        }
+
+        let flags = flags | Flags::US | Flags::PRESENT;
+        let mut page = addr.as_u64() & !(Page4Kb as u64 - 1);
+        while page < end {
+            let addr = VirtualAddr::new(page);
+            match self.ctx.mapper.try_translate(addr) {
+                Ok(translation) if translation.flags.contains(flags) => {}
+                _ => return Err(Error::Unmapped(addr)),
+            }
+            page += Page4Kb as u64;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let offset = self.ctx.mapper.offset();
+        let frames = &mut self.ctx.alloc;
+        let level4 = table_ptr(offset, self.l4.ptr());
+        // SAFETY: the user window entries only lead to tables and frames owned by the process
+        unsafe {
+            for idx in USER_ENTRIES {
+                let entry = *level4.add(idx);
+                if entry & ENTRY_PRESENT != 0 {
+                    free_table(frames, offset, PhysicalAddr::new(entry & ENTRY_ADDRESS), 3);
+                }
+            }
+            frames.dealloc(self.l4);
+        }
+        trace!("process {:?} destroyed", self.id);
+    }
+}
+
+/// Free a table of `level` and everything it maps.
+unsafe fn free_table(frames: &mut Frames, offset: VirtualAddr, table: PhysicalAddr, level: u8) {
+    let entries = table_ptr(offset, table);
+    for idx in 0..512 {
+        let entry = *entries.add(idx);
+        if entry & ENTRY_PRESENT == 0 {
+            continue;
+        }
+        let next = PhysicalAddr::new(entry & ENTRY_ADDRESS);
+        if level > 1 {
+            free_table(frames, offset, next, level - 1);
+        } else {
+            frames.dealloc(PhysicalFrame::containing(next));
+        }
+    }
+    frames.dealloc(PhysicalFrame::containing(table));
+}
+
+/// Run the process on a new thread, it enters ring 3 at `entry` with its stack pointer at
+/// `stack`.
+///
+/// # Errors
+///
+/// Errors if the kernel stack of the thread can't be mapped
+pub fn spawn(process: Process, entry: VirtualAddr, stack: VirtualAddr) -> Result<ThreadId, Error> {
+    let (id, l4) = (process.id, process.l4);
+    libx64::without_interrupts(|| PROCESSES.lock().insert(id, process));
+
+    let thread = context::with_kernel(|ctx| {
+        // SAFETY: the thread runs in the address space of the process
+        thread::spawn_user(ctx, id, l4, move || unsafe { usermode::enter(entry, stack) })
+    });
+    if thread.is_err() {
+        libx64::without_interrupts(|| PROCESSES.lock().remove(&id));
+    }
+    thread.map_err(Error::from)
+}
+
+/// Run `f` on the process, `None` if it doesn't exist.
+pub fn with_process<R>(id: ProcessId, f: impl FnOnce(&mut Process) -> R) -> Option<R> {
+    libx64::without_interrupts(|| PROCESSES.lock().get_mut(&id).map(f))
+}
+
+/// Free the address space of a process once its thread finished.
+pub fn destroy(id: ProcessId) {
+    let process = libx64::without_interrupts(|| PROCESSES.lock().remove(&id));
+    drop(process);
+}
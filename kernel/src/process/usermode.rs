@@ -0,0 +1,54 @@
+use core::arch::asm;
+
+use libx64::{address::VirtualAddr, rflags::RFlags};
+
+/// Reserved bit 1 is always set, interrupts are enabled in ring 3
+const USER_RFLAGS: u64 = RFlags::INTERRUPT_FLAG.bits() | 0x2;
+
+/// Drop to ring 3 at `entry` with the stack pointer at `stack`.
+///
+/// The general purpose registers are cleared so nothing leaks from the kernel, the stack the
+/// function is called on is reused as the ring 0 stack of the process.
+///
+/// # Safety
+///
+/// The address space of the process must be loaded, `entry` and `stack` must be mapped with
+/// [`Flags::US`](libx64::paging::entry::Flags::US)
+pub unsafe fn enter(entry: VirtualAddr, stack: VirtualAddr) -> ! {
+    let selectors = crate::init::selectors();
+    let data = u64::from(selectors.user_data.as_u16());
+    let code = u64::from(selectors.user_code.as_u16());
+
+    asm!(
+        "mov ds, {data:x}",
+        "mov es, {data:x}",
+        // iretq frame: ss, rsp, rflags, cs, rip
+        "push {data}",
+        "push {stack}",
+        "push {rflags}",
+        "push {code}",
+        "push {entry}",
+        "xor eax, eax",
+        "xor ebx, ebx",
+        "xor ecx, ecx",
+        "xor edx, edx",
+        "xor esi, esi",
+        "xor edi, edi",
+        "xor ebp, ebp",
+        "xor r8d, r8d",
+        "xor r9d, r9d",
+        "xor r10d, r10d",
+        "xor r11d, r11d",
+        "xor r12d, r12d",
+        "xor r13d, r13d",
+        "xor r14d, r14d",
+        "xor r15d, r15d",
+        "iretq",
+        data = in(reg) data,
+        stack = in(reg) stack.as_u64(),
+        rflags = in(reg) USER_RFLAGS,
+        code = in(reg) code,
+        entry = in(reg) entry.as_u64(),
+        options(noreturn)
+    );
+}
@@ -0,0 +1,83 @@
+use core::arch::global_asm;
+
+use libx64::address::VirtualAddr;
+
+/// Top of the ring 0 stack of the current thread, loaded by the entry stub
+static mut KERNEL_RSP: u64 = 0;
+
+/// User stack pointer, only kept here until it is pushed on the kernel stack
+static mut USER_RSP: u64 = 0;
+
+// `syscall` leaves the user stack loaded and interrupts masked. The user stack pointer, return
+// address and flags are saved on the kernel stack followed by the arguments, which make up the
+// `SyscallFrame` handed to the dispatcher. Interrupts are masked again before the user stack is
+// restored so the scratch slots aren't overwritten by another thread.
+global_asm!(
+    ".global syscall_entry",
+    "syscall_entry:",
+    "mov [rip + {user_rsp}], rsp",
+    "mov rsp, [rip + {kernel_rsp}]",
+    "push qword ptr [rip + {user_rsp}]",
+    "push rcx",
+    "push r11",
+    "push r9",
+    "push r8",
+    "push r10",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push rax",
+    "mov rdi, rsp",
+    "sti",
+    "call {dispatch}",
+    "cli",
+    "pop rax",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop r10",
+    "pop r8",
+    "pop r9",
+    "pop r11",
+    "pop rcx",
+    "pop rsp",
+    "sysretq",
+    user_rsp = sym USER_RSP,
+    kernel_rsp = sym KERNEL_RSP,
+    dispatch = sym super::dispatch,
+);
+
+extern "C" {
+    pub fn syscall_entry();
+}
+
+/// Registers saved by `syscall_entry`, lowest address first.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SyscallFrame {
+    /// Call number, replaced by the encoded result
+    pub rax: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub r10: u64,
+    pub r8: u64,
+    pub r9: u64,
+    /// User `rflags` saved by `syscall`
+    pub r11: u64,
+    /// User return address saved by `syscall`
+    pub rcx: u64,
+    pub rsp: u64,
+}
+
+impl SyscallFrame {
+    pub const fn args(&self) -> [u64; 6] {
+        [self.rdi, self.rsi, self.rdx, self.r10, self.r8, self.r9]
+    }
+}
+
+/// Set the stack the entry stub switches to, `top` must be 16 bytes aligned.
+pub fn set_kernel_stack(top: VirtualAddr) {
+    // SAFETY: the stub only reads the slot with interrupts masked
+    libx64::without_interrupts(|| unsafe { KERNEL_RSP = top.as_u64() });
+}
@@ -0,0 +1,160 @@
+//! System calls
+//!
+//! `syscall` jumps to the entry stub with the user stack still loaded, the stub switches to the
+//! ring 0 stack of the current thread and hands the saved registers to [`dispatch`]. Calls are
+//! looked up in a table indexed by [`Syscall`], every pointer argument is checked against the
+//! address space of the calling process before being used.
+
+mod entry;
+
+use core::time::Duration;
+
+use ksyscall::{Error, MapFlags, Syscall, MAX_LOG_LEN};
+use libx64::{
+    address::VirtualAddr,
+    control::{efer, set_efer, Efer},
+    msr::{set_lstar, set_sfmask, set_star, Star},
+    paging::entry::Flags,
+    rflags::RFlags,
+};
+
+use crate::{
+    mem::vma::{self, Purpose},
+    process::{self, ProcessId},
+    thread,
+};
+
+pub use entry::set_kernel_stack;
+use entry::SyscallFrame;
+
+type Handler = fn(ProcessId, [u64; 6]) -> Result<u64, Error>;
+
+const TABLE: [Handler; Syscall::COUNT] = {
+    let mut table: [Handler; Syscall::COUNT] = [nosys; Syscall::COUNT];
+    table[Syscall::Log as usize] = log;
+    table[Syscall::Exit as usize] = exit;
+    table[Syscall::Yield as usize] = yield_now;
+    table[Syscall::Map as usize] = map;
+    table[Syscall::Sleep as usize] = sleep;
+    table[Syscall::Protect as usize] = protect;
+    table
+};
+
+/// Enable `syscall` and point it to the entry stub.
+///
+/// # Panics
+///
+/// Panics if the user segments don't follow the layout expected by `sysret`
+pub fn init() {
+    let selectors = crate::init::selectors();
+    let kernel = selectors.code_segment.as_u16();
+    // sysret loads `user + 8` in SS and `user + 16` in CS
+    let user = selectors.user_data.as_u16() - 8;
+    assert_eq!(selectors.data_segment.as_u16(), kernel + 8);
+    assert_eq!(selectors.user_code.as_u16(), user + 16);
+
+    // SAFETY: the selectors were checked and the stub switches stack with interrupts masked
+    unsafe {
+        set_star(Star { kernel, user });
+        set_lstar(VirtualAddr::new(entry::syscall_entry as usize as u64));
+        set_sfmask(
+            RFlags::INTERRUPT_FLAG
+                | RFlags::TRAP_FLAG
+                | RFlags::DIRECTION_FLAG
+                | RFlags::ALIGNMENT_CHECK,
+        );
+    }
+    set_efer(efer() | Efer::SCE);
+    trace!("syscall entry at {:?}", libx64::msr::lstar());
+}
+
+extern "C" fn dispatch(frame: &mut SyscallFrame) {
+    let process = thread::current_process().expect("system call from a kernel thread");
+    let result = Syscall::try_from(frame.rax)
+        .and_then(|call| TABLE[call as usize](process, frame.args()));
+    frame.rax = ksyscall::encode(result);
+}
+
+/// Pointer argument, anything outside of the lower half is rejected before building the address
+/// since non canonical values can't be represented.
+fn user_addr(ptr: u64) -> Result<VirtualAddr, Error> {
+    if ptr >> 47 == 0 {
+        Ok(VirtualAddr::new(ptr))
+    } else {
+        Err(Error::Fault)
+    }
+}
+
+fn nosys(_: ProcessId, _: [u64; 6]) -> Result<u64, Error> {
+    Err(Error::NoSys)
+}
+
+fn log(process: ProcessId, [ptr, len, ..]: [u64; 6]) -> Result<u64, Error> {
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|len| *len <= MAX_LOG_LEN)
+        .ok_or(Error::Invalid)?;
+    let addr = user_addr(ptr)?;
+
+    let mut buf = [0; MAX_LOG_LEN];
+    let buf = &mut buf[..len];
+    process::with_process(process, |p| {
+        p.check(addr, len, Flags::empty())?;
+        p.read(addr, buf)
+    })
+    .ok_or(Error::Invalid)?
+    .map_err(|_| Error::Fault)?;
+
+    let msg = core::str::from_utf8(buf).map_err(|_| Error::Invalid)?;
+    info!("{:?}: {}", process, msg);
+    Ok(0)
+}
+
+fn exit(process: ProcessId, [code, ..]: [u64; 6]) -> Result<u64, Error> {
+    info!("{:?} exited with code {}", process, code);
+    thread::exit()
+}
+
+fn yield_now(_: ProcessId, _: [u64; 6]) -> Result<u64, Error> {
+    thread::yield_now();
+    Ok(0)
+}
+
+/// Page flags of the access rights of [`map`] and [`protect`].
+fn map_flags(flags: u64) -> Result<Flags, Error> {
+    let flags = MapFlags::from_bits(flags).ok_or(Error::Invalid)?;
+    Ok(if flags.contains(MapFlags::WRITE) {
+        Flags::RW
+    } else {
+        Flags::empty()
+    })
+}
+
+fn map(process: ProcessId, [len, flags, ..]: [u64; 6]) -> Result<u64, Error> {
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|len| *len != 0)
+        .ok_or(Error::Invalid)?;
+    let flags = map_flags(flags)?;
+
+    let pages = process::with_process(process, |p| {
+        p.mmap("user map", vma::pages_for(len), flags, Purpose::Anonymous)
+    })
+    .ok_or(Error::Invalid)?
+    .map_err(|_| Error::NoMemory)?;
+    Ok(pages.start().as_u64())
+}
+
+fn protect(process: ProcessId, [ptr, flags, ..]: [u64; 6]) -> Result<u64, Error> {
+    let addr = user_addr(ptr)?;
+    let flags = map_flags(flags)?;
+    process::with_process(process, |p| p.mprotect(addr, flags))
+        .ok_or(Error::Invalid)?
+        .map_err(|_| Error::Invalid)?;
+    Ok(0)
+}
+
+fn sleep(_: ProcessId, [millis, ..]: [u64; 6]) -> Result<u64, Error> {
+    thread::sleep(Duration::from_millis(millis));
+    Ok(0)
+}
@@ -0,0 +1,557 @@
+//! Preemptive kernel threads
+//!
+//! Threads are switched round robin, either when their quantum of timer ticks runs out or when
+//! they give up the cpu through [`yield_now`], [`sleep`], [`join`] or [`park`]. The boot code
+//! keeps running as the first thread once [`init`] is called.
+
+mod switch;
+
+use alloc::{alloc::Global, boxed::Box, collections::VecDeque, vec::Vec};
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    task::{RawWaker, RawWakerVTable, Waker},
+    time::Duration,
+};
+
+use kalloc::slab::SlabCache;
+use kcore::sync::SpinMutex;
+use libx64::{
+    control::CR3,
+    paging::{
+        entry::Flags,
+        frame::{FrameAllocator, FrameDeallocator, PhysicalFrame},
+        page::{PageMapper, PageRange, PageTranslator},
+        Page4Kb,
+    },
+    units::Kb,
+};
+
+use crate::{
+    mem::{
+        cache::Cache,
+        context::MemoryContext,
+        vma::{self, Backing, Purpose},
+    },
+    process::ProcessId,
+};
+
+/// Stacks are allocated from the kernel address space, guard pages come with the area
+pub const STACK_SIZE: usize = 64 * Kb;
+
+/// Number of timer ticks a thread runs before being preempted.
+pub const DEFAULT_QUANTUM: u64 = 2;
+
+static QUANTUM: AtomicU64 = AtomicU64::new(DEFAULT_QUANTUM);
+
+/// Id of the running thread, readable without taking the thread list lock
+static CURRENT: AtomicU64 = AtomicU64::new(ThreadId::BOOT.0);
+
+/// Threads are allocated from their own cache, they are created and reaped all the time
+static CACHE: Cache = Cache::new(SlabCache::of::<Thread>("threads", Global));
+
+kcore::klazy! {
+    ref static THREADS: SpinMutex<Option<Threads>> = SpinMutex::new(None);
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    const BOOT: Self = Self(0);
+    const IDLE: Self = Self(1);
+
+    #[must_use]
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(2);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum State {
+    Running,
+    Ready,
+    /// Sleeping until the tick count reaches the deadline
+    Sleeping(u64),
+    /// Waiting on the completion of another thread
+    Blocked,
+    /// Waiting in [`park`] to be woken by [`unpark`]
+    Parked,
+    Finished,
+}
+
+/// Snapshot of a thread returned by [`list`].
+#[derive(Debug, Clone, Copy)]
+pub struct Info {
+    pub id: ThreadId,
+    /// `None` for kernel threads
+    pub process: Option<ProcessId>,
+    pub state: State,
+}
+
+type Stack = PageRange<Page4Kb>;
+type Entry = Box<dyn FnOnce() + Send>;
+/// Process a thread runs for and its level 4 table
+type Space = (ProcessId, PhysicalFrame<Page4Kb>);
+type ThreadBox = Box<Thread, &'static Cache>;
+
+struct Thread {
+    id: ThreadId,
+    state: State,
+    /// Stack pointer saved when the thread is switched out
+    rsp: usize,
+    /// The boot thread runs on the stack setup by the bootloader
+    stack: Option<Stack>,
+    /// Kernel threads run in the kernel address space
+    space: Option<Space>,
+    /// Threads blocked in [`join`] on this one
+    joiners: Vec<ThreadId>,
+    /// [`unpark`] was called while the thread wasn't parked, the next [`park`] returns at once
+    unparked: bool,
+}
+
+impl Thread {
+    const fn new(id: ThreadId, stack: Option<Stack>, space: Option<Space>) -> Self {
+        Self {
+            id,
+            state: State::Ready,
+            rsp: 0,
+            stack,
+            space,
+            joiners: Vec::new(),
+            unparked: false,
+        }
+    }
+}
+
+struct Threads {
+    current: ThreadBox,
+    idle: Option<ThreadBox>,
+    ready: VecDeque<ThreadBox>,
+    waiting: Vec<ThreadBox>,
+    /// Finished threads whose stack can't be unmapped until some other thread runs
+    finished: Vec<ThreadBox>,
+    /// Ticks left to the current thread
+    remaining: u64,
+    /// Level 4 table of the kernel threads
+    kernel_l4: PhysicalFrame<Page4Kb>,
+}
+
+impl Threads {
+    fn alive(&self, id: ThreadId) -> bool {
+        self.current.id == id
+            || self.ready.iter().any(|t| t.id == id)
+            || self.waiting.iter().any(|t| t.id == id)
+    }
+
+    fn wake(&mut self, id: ThreadId) {
+        if let Some(idx) = self.waiting.iter().position(|t| t.id == id) {
+            let mut thread = self.waiting.swap_remove(idx);
+            thread.state = State::Ready;
+            self.ready.push_back(thread);
+        }
+    }
+
+    fn unpark(&mut self, id: ThreadId) {
+        let parked = self
+            .waiting
+            .iter()
+            .position(|t| t.id == id && t.state == State::Parked);
+        if let Some(idx) = parked {
+            let mut thread = self.waiting.swap_remove(idx);
+            thread.state = State::Ready;
+            self.ready.push_back(thread);
+        } else if let Some(thread) = core::iter::once(&mut self.current)
+            .chain(self.ready.iter_mut())
+            .chain(self.waiting.iter_mut())
+            .find(|t| t.id == id)
+        {
+            thread.unparked = true;
+        }
+    }
+
+    fn wake_sleepers(&mut self, now: u64) {
+        let mut idx = 0;
+        while idx < self.waiting.len() {
+            match self.waiting[idx].state {
+                State::Sleeping(deadline) if deadline <= now => {
+                    let mut thread = self.waiting.swap_remove(idx);
+                    thread.state = State::Ready;
+                    self.ready.push_back(thread);
+                }
+                _ => idx += 1,
+            }
+        }
+    }
+
+    /// Swap the current thread with the next runnable one, returns the stack pointers to switch
+    /// between or `None` if the current thread keeps running.
+    fn rotate(&mut self, state: State) -> Option<(*mut usize, usize)> {
+        let next = match self.ready.pop_front() {
+            Some(next) => next,
+            None if state == State::Ready => return None,
+            None => self.idle.take().expect("idle thread is running and blocked"),
+        };
+
+        let mut prev = core::mem::replace(&mut self.current, next);
+        self.current.state = State::Running;
+        self.remaining = QUANTUM.load(Ordering::Relaxed);
+        self.activate();
+
+        prev.state = state;
+        // the box is only moved between lists, the saved stack pointer slot is stable
+        let rsp = core::ptr::addr_of_mut!(prev.rsp);
+        match state {
+            _ if prev.id == ThreadId::IDLE => self.idle = Some(prev),
+            State::Ready => self.ready.push_back(prev),
+            State::Sleeping(_) | State::Blocked | State::Parked => self.waiting.push(prev),
+            State::Finished => {
+                for joiner in core::mem::take(&mut prev.joiners) {
+                    self.wake(joiner);
+                }
+                self.finished.push(prev);
+            }
+            State::Running => unreachable!("switching out to a running state"),
+        }
+
+        Some((rsp, self.current.rsp))
+    }
+
+    /// Load the address space of the current thread and use its stack for interrupts and system
+    /// calls taken in ring 3.
+    fn activate(&self) {
+        CURRENT.store(self.current.id.0, Ordering::Relaxed);
+        if let Some(stack) = &self.current.stack {
+            crate::init::set_kernel_stack(stack.end());
+            crate::syscall::set_kernel_stack(stack.end());
+        }
+        let l4 = self.current.space.map_or(self.kernel_l4, |(_, l4)| l4);
+        if libx64::control::cr3().frame() != l4 {
+            libx64::control::set_cr3(CR3::from_frame(l4));
+        }
+    }
+}
+
+/// Switch out the current thread, it is rescheduled according to `state`.
+fn schedule(state: State) {
+    libx64::without_interrupts(|| {
+        let switch = THREADS.lock().as_mut().and_then(|threads| threads.rotate(state));
+
+        // the lock must be released, the next thread may be one that was preempted
+        if let Some((old, new)) = switch {
+            unsafe { switch::thread_switch(old, new) };
+        }
+    });
+}
+
+extern "C" fn thread_start(entry: *mut Entry) -> ! {
+    let entry = unsafe { Box::from_raw(entry) };
+
+    // threads are first switched to with interrupts disabled
+    libx64::sti();
+    entry();
+
+    exit();
+}
+
+/// Register the running code as the boot thread and create the idle thread.
+///
+/// # Errors
+///
+/// Errors if the idle thread stack could not be mapped
+pub fn init<M, A>(ctx: &mut MemoryContext<M, A>) -> Result<(), vma::Error>
+where
+    M: PageMapper<Page4Kb> + PageTranslator,
+    A: FrameAllocator<Page4Kb> + FrameDeallocator<Page4Kb>,
+{
+    let mut threads = Threads {
+        current: Box::new_in(Thread::new(ThreadId::BOOT, None, None), &CACHE),
+        idle: None,
+        ready: VecDeque::new(),
+        waiting: Vec::new(),
+        finished: Vec::new(),
+        remaining: QUANTUM.load(Ordering::Relaxed),
+        kernel_l4: libx64::control::cr3().frame(),
+    };
+    threads.current.state = State::Running;
+
+    let idle = new_thread(ctx, ThreadId::IDLE, None, Box::new(|| loop {
+        libx64::hlt();
+    }))?;
+    threads.idle = Some(idle);
+
+    libx64::without_interrupts(|| *THREADS.lock() = Some(threads));
+    // spans entered by a thread aren't current in the one preempting it, the callback runs under
+    // the lock of the span registry and must not take the thread list lock
+    qemu_logger::set_task_id(|| CURRENT.load(Ordering::Relaxed));
+    trace!("threads initialized");
+    Ok(())
+}
+
+fn new_thread<M, A>(
+    ctx: &mut MemoryContext<M, A>,
+    id: ThreadId,
+    space: Option<Space>,
+    entry: Entry,
+) -> Result<ThreadBox, vma::Error>
+where
+    M: PageMapper<Page4Kb> + PageTranslator,
+    A: FrameAllocator<Page4Kb> + FrameDeallocator<Page4Kb>,
+{
+    let stack = ctx.mmap(
+        "thread stack",
+        vma::pages_for(STACK_SIZE),
+        Flags::RW,
+        Purpose::Stack,
+        Backing::Eager,
+    )?;
+    let top = stack.end();
+
+    let mut thread = Box::new_in(Thread::new(id, Some(stack), space), &CACHE);
+    let entry = Box::into_raw(Box::new(entry));
+    thread.rsp = unsafe { switch::initial_frame(top.as_usize(), entry as usize) };
+
+    trace!("thread {:?} stack top at {:?}", id, top);
+    Ok(thread)
+}
+
+/// Spawn a kernel thread, it will be run after the threads already waiting for the cpu.
+///
+/// # Errors
+///
+/// Errors if the stack could not be mapped
+///
+/// # Panics
+///
+/// Panics if [`init`] was not called
+pub fn spawn_thread<F, M, A>(
+    ctx: &mut MemoryContext<M, A>,
+    f: F,
+) -> Result<ThreadId, vma::Error>
+where
+    F: FnOnce() + Send + 'static,
+    M: PageMapper<Page4Kb> + PageTranslator,
+    A: FrameAllocator<Page4Kb> + FrameDeallocator<Page4Kb>,
+{
+    spawn(ctx, None, Box::new(f))
+}
+
+/// Spawn a thread running in the address space of a process, its stack is the ring 0 stack of
+/// the process.
+///
+/// # Errors
+///
+/// Errors if the stack could not be mapped
+///
+/// # Panics
+///
+/// Panics if [`init`] was not called
+pub fn spawn_user<F, M, A>(
+    ctx: &mut MemoryContext<M, A>,
+    process: ProcessId,
+    l4: PhysicalFrame<Page4Kb>,
+    f: F,
+) -> Result<ThreadId, vma::Error>
+where
+    F: FnOnce() + Send + 'static,
+    M: PageMapper<Page4Kb> + PageTranslator,
+    A: FrameAllocator<Page4Kb> + FrameDeallocator<Page4Kb>,
+{
+    spawn(ctx, Some((process, l4)), Box::new(f))
+}
+
+fn spawn<M, A>(
+    ctx: &mut MemoryContext<M, A>,
+    space: Option<Space>,
+    entry: Entry,
+) -> Result<ThreadId, vma::Error>
+where
+    M: PageMapper<Page4Kb> + PageTranslator,
+    A: FrameAllocator<Page4Kb> + FrameDeallocator<Page4Kb>,
+{
+    let id = ThreadId::next();
+    libx64::without_interrupts(|| {
+        let mut threads = THREADS.lock();
+        let threads = threads.as_mut().expect("threads are not initialized");
+
+        reap(threads, ctx);
+        let thread = new_thread(ctx, id, space, entry)?;
+        threads.ready.push_back(thread);
+        Ok(id)
+    })
+}
+
+/// Unmap the stacks of the finished threads and free the processes they ran.
+fn reap<M, A>(threads: &mut Threads, ctx: &mut MemoryContext<M, A>)
+where
+    M: PageMapper<Page4Kb> + PageTranslator,
+    A: FrameAllocator<Page4Kb> + FrameDeallocator<Page4Kb>,
+{
+    for thread in threads.finished.drain(..) {
+        if let Some(stack) = thread.stack {
+            if let Err(err) = ctx.munmap(stack.start()) {
+                error!("unable to unmap the stack of {:?}: {:?}", thread.id, err);
+            }
+        }
+        if let Some((process, _)) = thread.space {
+            crate::process::destroy(process);
+        }
+    }
+}
+
+/// Process the current thread runs for, `None` for kernel threads.
+pub fn current_process() -> Option<ProcessId> {
+    libx64::without_interrupts(|| {
+        let threads = THREADS.lock();
+        threads.as_ref()?.current.space.map(|(process, _)| process)
+    })
+}
+
+/// Threads that didn't finish yet, the idle thread excluded.
+pub fn list() -> Vec<Info> {
+    libx64::without_interrupts(|| {
+        let threads = THREADS.lock();
+        let Some(threads) = threads.as_ref() else {
+            return Vec::new();
+        };
+        core::iter::once(&threads.current)
+            .chain(&threads.ready)
+            .chain(&threads.waiting)
+            .filter(|thread| thread.id != ThreadId::IDLE)
+            .map(|thread| Info {
+                id: thread.id,
+                process: thread.space.map(|(process, _)| process),
+                state: thread.state,
+            })
+            .collect()
+    })
+}
+
+/// Give the cpu to the next ready thread.
+pub fn yield_now() {
+    schedule(State::Ready);
+}
+
+/// Terminate the current thread, the threads joining it are woken up.
+pub fn exit() -> ! {
+    schedule(State::Finished);
+    unreachable!("finished thread was rescheduled");
+}
+
+/// Block the current thread for at least `duration`.
+pub fn sleep(duration: Duration) {
+    let ticks = pit::clock::ticks_for(duration).max(1);
+    schedule(State::Sleeping(pit::clock::ticks() + ticks));
+}
+
+/// Block until the thread exits, returns immediately if it already did.
+pub fn join(id: ThreadId) {
+    libx64::without_interrupts(|| {
+        // the joiner must be blocked before the target can run again, otherwise an exit in
+        // between would wake a thread that is not waiting yet
+        let switch = {
+            let mut threads = THREADS.lock();
+            let threads = threads.as_mut().expect("threads are not initialized");
+            assert_ne!(threads.current.id, id, "a thread can't join itself");
+
+            if !threads.alive(id) {
+                return;
+            }
+            let current = threads.current.id;
+            threads
+                .ready
+                .iter_mut()
+                .chain(threads.waiting.iter_mut())
+                .find(|t| t.id == id)
+                .expect("thread is alive")
+                .joiners
+                .push(current);
+            threads.rotate(State::Blocked)
+        };
+
+        if let Some((old, new)) = switch {
+            unsafe { switch::thread_switch(old, new) };
+        }
+    });
+}
+
+/// Id of the running thread.
+pub fn current() -> ThreadId {
+    ThreadId(CURRENT.load(Ordering::Relaxed))
+}
+
+/// Block until [`unpark`] is called for the current thread, returns immediately if it was
+/// called since the last park.
+pub fn park() {
+    libx64::without_interrupts(|| {
+        // the token is checked under the lock, an unpark from an interrupt handler can't be lost
+        let switch = {
+            let mut threads = THREADS.lock();
+            let threads = threads.as_mut().expect("threads are not initialized");
+            if core::mem::take(&mut threads.current.unparked) {
+                return;
+            }
+            threads.rotate(State::Parked)
+        };
+
+        if let Some((old, new)) = switch {
+            unsafe { switch::thread_switch(old, new) };
+        }
+    });
+}
+
+/// Wake a thread blocked in [`park`], can be called from interrupt handlers.
+pub fn unpark(id: ThreadId) {
+    libx64::without_interrupts(|| {
+        if let Some(threads) = THREADS.lock().as_mut() {
+            threads.unpark(id);
+        }
+    });
+}
+
+/// Waker unparking the thread `id`, lets a thread wait on a future driven by an interrupt.
+#[must_use]
+pub fn waker(id: ThreadId) -> Waker {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+
+    unsafe fn clone(ptr: *const ()) -> RawWaker {
+        RawWaker::new(ptr, &VTABLE)
+    }
+    unsafe fn wake(ptr: *const ()) {
+        unpark(ThreadId(ptr as u64));
+    }
+    unsafe fn drop(_: *const ()) {}
+
+    // SAFETY: the id is stored as the data pointer, the vtable functions never dereference it
+    unsafe { Waker::from_raw(RawWaker::new(id.0 as *const (), &VTABLE)) }
+}
+
+/// Set the number of ticks a thread runs before being preempted.
+pub fn set_quantum(ticks: u64) {
+    QUANTUM.store(ticks.max(1), Ordering::Relaxed);
+}
+
+/// Called by the timer interrupt handler once the interrupt is acknowledged and the clock
+/// advanced.
+pub fn tick() {
+    let now = pit::clock::ticks();
+
+    let preempt = {
+        let mut threads = THREADS.lock();
+        let Some(threads) = threads.as_mut() else {
+            return;
+        };
+
+        threads.wake_sleepers(now);
+        threads.remaining = threads.remaining.saturating_sub(1);
+        threads.remaining == 0 || threads.current.id == ThreadId::IDLE
+    };
+
+    if preempt {
+        schedule(State::Ready);
+    }
+}
@@ -0,0 +1,82 @@
+use core::arch::global_asm;
+
+// Callee saved registers are pushed on the current stack which is then saved in `old`, the
+// registers of the next thread are popped from the `new` stack. Caller saved registers are
+// already on the stack, either spilled by the caller or by the interrupt handler prologue.
+global_asm!(
+    ".global thread_switch",
+    "thread_switch:",
+    "push rbp",
+    "push rbx",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "pushfq",
+    "mov [rdi], rsp",
+    "mov rsp, rsi",
+    "popfq",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbx",
+    "pop rbp",
+    "ret",
+);
+
+// First code run by a new thread, `thread_switch` returns here with the entry point in r12.
+global_asm!(
+    ".global thread_trampoline",
+    "thread_trampoline:",
+    "mov rdi, r12",
+    "call {start}",
+    "ud2",
+    start = sym super::thread_start,
+);
+
+extern "C" {
+    /// # Safety
+    ///
+    /// `new` must be a stack pointer saved by a previous switch or prepared by
+    /// [`initial_frame`], interrupts must be disabled.
+    pub fn thread_switch(old: *mut usize, new: usize);
+
+    fn thread_trampoline();
+}
+
+/// Registers popped by `thread_switch`, lowest address first.
+#[repr(C)]
+struct InitialFrame {
+    rflags: u64,
+    r15: u64,
+    r14: u64,
+    r13: u64,
+    r12: u64,
+    rbx: u64,
+    rbp: u64,
+    ret: u64,
+}
+
+/// Reserved flag bit 1 is always set, interrupts stay disabled until the thread is started.
+const INITIAL_RFLAGS: u64 = 0x2;
+
+/// Write the first frame of a thread below `top` and return the stack pointer to switch to.
+///
+/// # Safety
+///
+/// `top` must be the 16 bytes aligned end of a mapped and writable stack
+pub unsafe fn initial_frame(top: usize, entry: usize) -> usize {
+    let frame = (top as *mut InitialFrame).sub(1);
+    frame.write(InitialFrame {
+        rflags: INITIAL_RFLAGS,
+        r15: 0,
+        r14: 0,
+        r13: 0,
+        r12: entry as u64,
+        rbx: 0,
+        rbp: 0,
+        ret: thread_trampoline as usize as u64,
+    });
+    frame as usize
+}
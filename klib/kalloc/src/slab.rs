@@ -1,13 +1,20 @@
-use alloc::alloc::{AllocError, Layout};
+use alloc::alloc::{AllocError, Allocator, Layout};
 use core::ptr::NonNull;
 
 use crate::kalloc::AllocatorMutImpl;
 
 use libx64::paging::{page::Page, Page4Kb};
 
+/// # Slab page
+///
+/// A 4Kb page split in slots of a power of two size, the head of the page can be reserved for
+/// bookkeeping by the owner. Slots are naturally aligned since the page is.
 pub struct SlabPage {
     base: NonNull<u8>,
-    mask: u32,
+    slot: u32,
+    /// First slot past the reserved head
+    first: u32,
+    mask: [u64; 4],
     len: u32,
 }
 
@@ -19,12 +26,37 @@ unsafe impl Sync for SlabPage {}
 impl SlabPage {
     const SLOT_BYTES: usize = (N as usize) / 8;
 
+    /// Most slots a page can be split in
+    pub const MAX_SLOTS: usize = 256;
+    /// Smallest slot size
+    pub const MIN_SLOT_BYTES: usize = N / Self::MAX_SLOTS;
+
     #[inline]
     #[must_use]
     pub const fn from_page(page: Page<Page4Kb>) -> Self {
+        Self::new(page, Self::SLOT_BYTES, 0)
+    }
+
+    /// Split the page in `slot` bytes slots, the first `reserved` bytes are never handed out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slot` isn't a power of two between [`SlabPage::MIN_SLOT_BYTES`] and the page
+    /// size or if the reserved head covers the whole page
+    #[inline]
+    #[must_use]
+    pub const fn new(page: Page<Page4Kb>, slot: usize, reserved: usize) -> Self {
+        assert!(slot.is_power_of_two(), "slot size must be a power of two");
+        assert!(slot >= Self::MIN_SLOT_BYTES && slot <= N, "slot size out of range");
+
+        let first = (reserved + slot - 1) / slot;
+        assert!(first < N / slot, "reserved head covers the whole page");
+
         Self {
             base: unsafe { NonNull::new_unchecked(page.ptr().as_u64() as *mut u8) },
-            mask: 0,
+            slot: slot as u32,
+            first: first as u32,
+            mask: [0; 4],
             len: 0,
         }
     }
@@ -43,56 +75,77 @@ impl SlabPage {
 
     #[inline]
     #[must_use]
-    pub const fn capacity() -> usize {
-        Page4Kb as usize / N
+    pub const fn is_full(&self) -> bool {
+        self.len() == self.capacity()
     }
 
-    fn allocate(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
-        if layout.size() > Self::SLOT_BYTES {
+    /// Number of slots that can be handed out.
+    #[inline]
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        N / self.slot_size() - self.first as usize
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn slot_size(&self) -> usize {
+        self.slot as usize
+    }
+
+    /// Whether the pointer is inside the page.
+    #[inline]
+    #[must_use]
+    pub fn contains(&self, ptr: NonNull<u8>) -> bool {
+        let base = self.base.as_ptr() as usize;
+        (base..base + N).contains(&(ptr.as_ptr() as usize))
+    }
+
+    const fn is_used(&self, at: usize) -> bool {
+        self.mask[at / 64] & (1 << (at % 64)) != 0
+    }
+
+    /// # Errors
+    ///
+    /// Errors if the layout doesn't fit in a slot or the page is full
+    pub fn allocate(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() > self.slot_size() || layout.align() > self.slot_size() {
             return Err(AllocError);
         }
 
-        let result = if self.len() % 2 == 0 {
-            let mut range = 0..=(Self::capacity() / 2);
-            range.find_map(|i| self.try_alloc_at(i))
-        } else {
-            let range = (Self::capacity() / 2 + 1)..Self::capacity();
-            range.rev().find_map(|i| self.try_alloc_at(i))
-        };
+        let mut slots = self.first as usize..N / self.slot_size();
+        let at = slots.find(|&at| !self.is_used(at)).ok_or(AllocError)?;
 
-        result.ok_or(AllocError)
-    }
-
-    fn try_alloc_at(&mut self, at: usize) -> Option<NonNull<[u8]>> {
-        let mask_entry = 1 << at;
-        if mask_entry & self.mask == 0 {
-            self.mask |= mask_entry;
-            self.len += 1;
-            let s = unsafe {
-                core::slice::from_raw_parts_mut(
-                    self.base.as_ptr().add(at * Self::SLOT_BYTES),
-                    Self::SLOT_BYTES,
-                )
-            };
-            Some(NonNull::from(s))
-        } else {
-            None
-        }
+        self.mask[at / 64] |= 1 << (at % 64);
+        self.len += 1;
+        let s = unsafe {
+            core::slice::from_raw_parts_mut(
+                self.base.as_ptr().add(at * self.slot_size()),
+                self.slot_size(),
+            )
+        };
+        Ok(NonNull::from(s))
     }
 
-    fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
-        assert!(layout.size() <= Self::SLOT_BYTES, "{:?}={:?}", ptr, layout);
+    /// # Panics
+    ///
+    /// Panics if the pointer isn't in the page
+    pub fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        assert!(
+            layout.size() <= self.slot_size() && self.contains(ptr),
+            "{:?}={:?}",
+            ptr,
+            layout
+        );
 
         let ptr = ptr.as_ptr() as u64;
         let this = self.base.as_ptr() as u64;
 
         #[allow(clippy::cast_possible_truncation)]
-        let offset = (ptr - this) as usize / Self::SLOT_BYTES;
-        let mask = 1 << offset;
+        let at = (ptr - this) as usize / self.slot_size();
 
-        if mask & self.mask != 0 {
+        if self.is_used(at) {
             self.len -= 1;
-            self.mask ^= mask;
+            self.mask[at / 64] ^= 1 << (at % 64);
         }
     }
 }
@@ -101,9 +154,9 @@ impl core::fmt::Debug for SlabPage {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("SlabPage")
             .field("base", &format_args!("{:#x}", self.base.as_ptr() as u64))
-            .field("mask", &format_args!("{:#034b}", self.mask))
+            .field("slot", &self.slot)
             .field("len", &self.len)
-            .field("cap", &Self::capacity())
+            .field("cap", &self.capacity())
             .finish()
     }
 }
@@ -113,17 +166,566 @@ unsafe impl AllocatorMutImpl for SlabPage {
         &mut self,
         layout: core::alloc::Layout,
     ) -> Result<core::ptr::NonNull<[u8]>, core::alloc::AllocError> {
-        assert!(layout.size() <= SlabPage::SLOT_BYTES);
-
         // FIXME: this is wrong on so many levels
         libx64::without_interrupts(|| SlabPage::allocate(self, layout))
     }
 
     unsafe fn deallocate_mut(&mut self, ptr: core::ptr::NonNull<u8>, layout: core::alloc::Layout) {
-        assert!(layout.size() <= SlabPage::SLOT_BYTES);
-
         // FIXME: this is wrong on so many levels
         #[allow(unsafe_op_in_unsafe_fn, unused_unsafe)]
         libx64::without_interrupts(|| SlabPage::deallocate(self, ptr, layout));
     }
 }
+
+/// # Slab cache
+///
+/// Dedicated allocator for objects of a single layout. Pages are requested from the backing
+/// allocator on demand and kept on one of three lists: `partial` pages have free slots, `full`
+/// pages don't and `empty` pages have no live object. Every page starts with a [`SlabHeader`]
+/// followed by as many slots of the object layout as fit, slots are tracked with a bitmap so
+/// freed objects keep their content.
+///
+/// The optional constructor runs once on every slot of a new page, objects handed back to the
+/// cache are expected to be returned in their constructed state.
+///
+/// Wrap the cache in a [`SpinMutex`](kcore::sync::mutex::SpinMutex) to get an
+/// [`Allocator`](alloc::alloc::Allocator).
+pub struct SlabCache<A: Allocator> {
+    name: &'static str,
+    layout: Layout,
+    /// Distance between two slots
+    stride: usize,
+    /// Offset of the first slot in the page
+    offset: usize,
+    /// Bytes of a page, a power of two of at least 4Kb
+    slab: usize,
+    capacity: usize,
+    ctor: Option<fn(NonNull<u8>)>,
+    partial: SlabList,
+    full: SlabList,
+    empty: SlabList,
+    /// Empty pages kept around before being given back
+    keep_empty: usize,
+    backing: A,
+}
+
+/// Bookkeeping at the start of every page of a [`SlabCache`].
+pub struct SlabHeader {
+    prev: Option<NonNull<SlabHeader>>,
+    next: Option<NonNull<SlabHeader>>,
+    used: usize,
+    bitmap: [u64; SlabHeader::BITMAP_LEN],
+}
+
+impl SlabHeader {
+    const BITMAP_LEN: usize = 8;
+    /// Most slots of a cache page
+    pub const MAX_SLOTS: usize = Self::BITMAP_LEN * 64;
+
+    const fn is_used(&self, at: usize) -> bool {
+        self.bitmap[at / 64] & (1 << (at % 64)) != 0
+    }
+}
+
+/// Intrusive doubly linked list of cache pages.
+#[derive(Debug, Default)]
+struct SlabList {
+    head: Option<NonNull<SlabHeader>>,
+    len: usize,
+}
+
+impl SlabList {
+    const fn new() -> Self {
+        Self { head: None, len: 0 }
+    }
+
+    unsafe fn push(&mut self, mut slab: NonNull<SlabHeader>) {
+        let header = slab.as_mut();
+        header.prev = None;
+        header.next = self.head;
+        if let Some(mut head) = self.head {
+            head.as_mut().prev = Some(slab);
+        }
+        self.head = Some(slab);
+        self.len += 1;
+    }
+
+    unsafe fn remove(&mut self, mut slab: NonNull<SlabHeader>) {
+        let header = slab.as_mut();
+        match header.prev {
+            Some(mut prev) => prev.as_mut().next = header.next,
+            None => self.head = header.next,
+        }
+        if let Some(mut next) = header.next {
+            next.as_mut().prev = header.prev;
+        }
+        header.prev = None;
+        header.next = None;
+        self.len -= 1;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = NonNull<SlabHeader>> + '_ {
+        // SAFETY: the pages of the list are owned by the cache
+        core::iter::successors(self.head, |slab| unsafe { slab.as_ref().next })
+    }
+
+    unsafe fn pop(&mut self) -> Option<NonNull<SlabHeader>> {
+        let head = self.head?;
+        self.remove(head);
+        Some(head)
+    }
+}
+
+unsafe impl<A: Allocator + Send> Send for SlabCache<A> {}
+
+impl<A: Allocator> SlabCache<A> {
+    /// Smallest distance between two slots
+    const MIN_STRIDE: usize = core::mem::size_of::<usize>();
+
+    /// Cache of objects of `layout`, pages are allocated from `backing`. Pages are 4Kb unless a
+    /// single object doesn't fit after the header, they are then the smallest power of two that
+    /// fits one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object alignment is larger than 4Kb
+    #[must_use]
+    pub const fn new(name: &'static str, layout: Layout, backing: A) -> Self {
+        let align = layout.align();
+        let size = (layout.size() + align - 1) & !(align - 1);
+        let stride = if size < Self::MIN_STRIDE {
+            Self::MIN_STRIDE
+        } else {
+            size
+        };
+        let offset = (core::mem::size_of::<SlabHeader>() + align - 1) & !(align - 1);
+        assert!(align <= N, "object alignment larger than a page");
+        let mut slab = N;
+        while offset + stride > slab {
+            slab *= 2;
+        }
+
+        Self {
+            name,
+            layout,
+            stride,
+            offset,
+            slab,
+            capacity: Self::capacity_of(slab, offset, stride),
+            ctor: None,
+            partial: SlabList::new(),
+            full: SlabList::new(),
+            empty: SlabList::new(),
+            keep_empty: 1,
+            backing,
+        }
+    }
+
+    /// Cache of `T` objects.
+    #[must_use]
+    pub const fn of<T>(name: &'static str, backing: A) -> Self {
+        Self::new(name, Layout::new::<T>(), backing)
+    }
+
+    /// Use pages of `size` bytes, objects close to the page size waste less space to the header
+    /// on larger pages. Pages larger than 4Kb are only asked to be 4Kb aligned, the page of a
+    /// freed object is then looked up in the lists.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size isn't a power of two or a single object doesn't fit
+    #[must_use]
+    pub const fn slab_size(mut self, size: usize) -> Self {
+        assert!(size.is_power_of_two() && size >= self.slab, "slab page too small");
+        self.slab = size;
+        self.capacity = Self::capacity_of(size, self.offset, self.stride);
+        self
+    }
+
+    const fn capacity_of(slab: usize, offset: usize, stride: usize) -> usize {
+        let capacity = (slab - offset) / stride;
+        if capacity > SlabHeader::MAX_SLOTS {
+            SlabHeader::MAX_SLOTS
+        } else {
+            capacity
+        }
+    }
+
+    /// Run `ctor` on every slot of the new pages.
+    #[must_use]
+    pub const fn with_ctor(mut self, ctor: fn(NonNull<u8>)) -> Self {
+        self.ctor = Some(ctor);
+        self
+    }
+
+    /// Number of empty pages kept around instead of being given back, defaults to one.
+    #[must_use]
+    pub const fn keep_empty(mut self, pages: usize) -> Self {
+        self.keep_empty = pages;
+        self
+    }
+
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Layout of the cached objects.
+    #[must_use]
+    pub const fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Objects per page.
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Bytes of a page of the cache.
+    #[must_use]
+    pub const fn slab(&self) -> usize {
+        self.slab
+    }
+
+    /// Number of pages owned by the cache.
+    #[must_use]
+    pub const fn pages(&self) -> usize {
+        self.partial.len + self.full.len + self.empty.len
+    }
+
+    /// Number of partial, full and empty pages.
+    #[must_use]
+    pub const fn lists(&self) -> (usize, usize, usize) {
+        (self.partial.len, self.full.len, self.empty.len)
+    }
+
+    const fn page_layout(&self) -> Layout {
+        // SAFETY: the page size is a non zero power of two
+        unsafe { Layout::from_size_align_unchecked(self.slab, N) }
+    }
+
+    fn slot(&self, slab: NonNull<SlabHeader>, at: usize) -> NonNull<u8> {
+        let addr = slab.as_ptr() as usize + self.offset + at * self.stride;
+        // SAFETY: the slot is inside the page
+        unsafe { NonNull::new_unchecked(addr as *mut u8) }
+    }
+
+    /// Take a page from the backing allocator and construct its slots.
+    fn grow(&mut self) -> Result<NonNull<SlabHeader>, AllocError> {
+        let page = self.backing.allocate(self.page_layout())?;
+        let slab = page.as_non_null_ptr().cast::<SlabHeader>();
+        unsafe {
+            slab.as_ptr().write(SlabHeader {
+                prev: None,
+                next: None,
+                used: 0,
+                bitmap: [0; SlabHeader::BITMAP_LEN],
+            });
+        }
+        if let Some(ctor) = self.ctor {
+            for at in 0..self.capacity {
+                ctor(self.slot(slab, at));
+            }
+        }
+        Ok(slab)
+    }
+
+    /// Hand out an object, in its constructed state if the cache has a constructor.
+    ///
+    /// # Errors
+    ///
+    /// Errors if every page is full and the backing allocator is exhausted
+    pub fn alloc(&mut self) -> Result<NonNull<u8>, AllocError> {
+        let slab = unsafe {
+            match self.partial.head {
+                Some(slab) => slab,
+                None => {
+                    let slab = match self.empty.pop() {
+                        Some(slab) => slab,
+                        None => self.grow()?,
+                    };
+                    self.partial.push(slab);
+                    slab
+                }
+            }
+        };
+
+        let header = unsafe { &mut *slab.as_ptr() };
+        let at = (0..self.capacity)
+            .find(|&at| !header.is_used(at))
+            .ok_or(AllocError)?;
+        header.bitmap[at / 64] |= 1 << (at % 64);
+        header.used += 1;
+
+        if header.used == self.capacity {
+            unsafe {
+                self.partial.remove(slab);
+                self.full.push(slab);
+            }
+        }
+        Ok(self.slot(slab, at))
+    }
+
+    /// Give an object back to the cache.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must come from [`SlabCache::alloc`] on this cache
+    pub unsafe fn free(&mut self, ptr: NonNull<u8>) {
+        let slab = self.slab_of(ptr);
+        debug_assert!(slab.is_some(), "{:p} isn't from the {} cache", ptr, self.name);
+        let Some(slab) = slab else {
+            return;
+        };
+        let at = (ptr.as_ptr() as usize - slab.as_ptr() as usize - self.offset) / self.stride;
+
+        let header = &mut *slab.as_ptr();
+        debug_assert!(header.is_used(at), "double free in the {} cache", self.name);
+        if !header.is_used(at) {
+            return;
+        }
+        header.bitmap[at / 64] ^= 1 << (at % 64);
+        header.used -= 1;
+
+        if header.used + 1 == self.capacity {
+            self.full.remove(slab);
+            self.partial.push(slab);
+        }
+        if header.used == 0 {
+            self.partial.remove(slab);
+            if self.empty.len < self.keep_empty {
+                self.empty.push(slab);
+            } else {
+                self.backing.deallocate(slab.cast(), self.page_layout());
+            }
+        }
+    }
+
+    /// Give every empty page back to the backing allocator.
+    pub fn shrink(&mut self) {
+        while let Some(slab) = unsafe { self.empty.pop() } {
+            unsafe { self.backing.deallocate(slab.cast(), self.page_layout()) };
+        }
+    }
+
+    /// Page holding `ptr`.
+    fn slab_of(&self, ptr: NonNull<u8>) -> Option<NonNull<SlabHeader>> {
+        let addr = ptr.as_ptr() as usize;
+        if self.slab == N {
+            return NonNull::new((addr & !(N - 1)) as *mut SlabHeader);
+        }
+        // only pages with live objects can hold a freed one
+        self.partial.iter().chain(self.full.iter()).find(|slab| {
+            let start = slab.as_ptr() as usize;
+            (start..start + self.slab).contains(&addr)
+        })
+    }
+
+    fn fits(&self, layout: Layout) -> bool {
+        layout.size() <= self.layout.size() && layout.align() <= self.layout.align()
+    }
+}
+
+impl<A: Allocator> Drop for SlabCache<A> {
+    fn drop(&mut self) {
+        let layout = self.page_layout();
+        for list in [&mut self.partial, &mut self.full, &mut self.empty] {
+            while let Some(slab) = unsafe { list.pop() } {
+                unsafe { self.backing.deallocate(slab.cast(), layout) };
+            }
+        }
+    }
+}
+
+impl<A: Allocator> core::fmt::Debug for SlabCache<A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SlabCache")
+            .field("name", &self.name)
+            .field("layout", &self.layout)
+            .field("capacity", &self.capacity)
+            .field("partial", &self.partial.len)
+            .field("full", &self.full.len)
+            .field("empty", &self.empty.len)
+            .finish()
+    }
+}
+
+unsafe impl<A: Allocator> AllocatorMutImpl for SlabCache<A> {
+    fn allocate_mut(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if !self.fits(layout) {
+            return Err(AllocError);
+        }
+        let ptr = self.alloc()?;
+        Ok(NonNull::slice_from_raw_parts(ptr, self.layout.size()))
+    }
+
+    unsafe fn deallocate_mut(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        debug_assert!(self.fits(layout), "{:?} isn't from the {} cache", layout, self.name);
+        self.free(ptr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::boxed::Box;
+
+    #[repr(C, align(4096))]
+    struct Frame([u8; N]);
+
+    fn page() -> (Box<Frame>, Page<Page4Kb>) {
+        let frame = Box::new(Frame([0; N]));
+        let page = Page::containing_ptr(frame.0.as_ptr());
+        (frame, page)
+    }
+
+    #[test]
+    fn fills_every_slot() {
+        let (_frame, page) = page();
+        let mut slab = SlabPage::new(page, 16, 0);
+        assert_eq!(slab.capacity(), SlabPage::MAX_SLOTS);
+
+        for _ in 0..slab.capacity() {
+            slab.allocate(Layout::new::<u128>()).unwrap();
+        }
+        assert!(slab.is_full());
+        assert!(slab.allocate(Layout::new::<u8>()).is_err());
+    }
+
+    #[test]
+    fn reserved_head_is_skipped() {
+        let (_frame, page) = page();
+        let mut slab = SlabPage::new(page, 64, 48);
+        assert_eq!(slab.capacity(), N / 64 - 1);
+
+        let ptr = slab.allocate(Layout::new::<u8>()).unwrap();
+        assert_eq!(ptr.as_mut_ptr() as u64, page.ptr().as_u64() + 64);
+    }
+
+    #[test]
+    fn rejects_oversized_layouts() {
+        let (_frame, page) = page();
+        let mut slab = SlabPage::new(page, 32, 0);
+
+        assert!(slab.allocate(Layout::new::<[u8; 33]>()).is_err());
+        assert!(slab.allocate(Layout::from_size_align(8, 64).unwrap()).is_err());
+    }
+
+    #[test]
+    fn slots_are_reused() {
+        let (_frame, page) = page();
+        let mut slab = SlabPage::new(page, 1024, 0);
+        let layout = Layout::new::<[u8; 1024]>();
+
+        let ptrs = [(); 4].map(|_| slab.allocate(layout).unwrap());
+        assert!(slab.is_full());
+
+        slab.deallocate(ptrs[2].as_non_null_ptr(), layout);
+        assert_eq!(slab.len(), 3);
+        assert_eq!(slab.allocate(layout).unwrap(), ptrs[2]);
+        for ptr in ptrs {
+            slab.deallocate(ptr.as_non_null_ptr(), layout);
+        }
+        assert!(slab.is_empty());
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Task {
+        id: u64,
+        state: [u8; 40],
+    }
+
+    #[test]
+    fn cache_fills_pages() {
+        let mut cache = SlabCache::of::<Task>("tasks", alloc::alloc::Global);
+        let layout = Layout::new::<Task>();
+        assert_eq!(cache.capacity(), (N - cache.offset) / layout.size());
+
+        let objects = (0..cache.capacity() + 1)
+            .map(|_| cache.alloc().unwrap())
+            .collect::<std::vec::Vec<_>>();
+        assert_eq!(cache.lists(), (1, 1, 0));
+
+        for (i, a) in objects.iter().enumerate() {
+            assert_eq!(a.as_ptr() as usize % layout.align(), 0);
+            for b in &objects[i + 1..] {
+                let distance = (a.as_ptr() as usize).abs_diff(b.as_ptr() as usize);
+                assert!(distance >= layout.size());
+            }
+        }
+
+        for ptr in objects {
+            unsafe { cache.free(ptr) };
+        }
+        assert_eq!(cache.lists(), (0, 0, 1));
+    }
+
+    #[test]
+    fn cache_moves_pages_between_lists() {
+        let mut cache = SlabCache::new(
+            "blocks",
+            Layout::from_size_align(1000, 8).unwrap(),
+            alloc::alloc::Global,
+        );
+        assert_eq!(cache.capacity(), 4);
+
+        let ptrs = [(); 4].map(|_| cache.alloc().unwrap());
+        assert_eq!(cache.lists(), (0, 1, 0));
+
+        unsafe { cache.free(ptrs[1]) };
+        assert_eq!(cache.lists(), (1, 0, 0));
+        assert_eq!(cache.alloc().unwrap(), ptrs[1]);
+
+        for ptr in ptrs {
+            unsafe { cache.free(ptr) };
+        }
+        assert_eq!(cache.lists(), (0, 0, 1));
+        cache.shrink();
+        assert_eq!(cache.pages(), 0);
+    }
+
+    #[test]
+    fn cache_keeps_constructed_objects() {
+        fn ctor(ptr: NonNull<u8>) {
+            unsafe { ptr.cast::<u64>().as_ptr().write(0xCAFE) };
+        }
+
+        let mut cache = SlabCache::of::<u64>("words", alloc::alloc::Global).with_ctor(ctor);
+        let ptr = cache.alloc().unwrap();
+        assert_eq!(unsafe { *ptr.cast::<u64>().as_ptr() }, 0xCAFE);
+
+        // freed objects aren't overwritten by the cache
+        unsafe {
+            cache.free(ptr);
+            assert_eq!(*cache.alloc().unwrap().cast::<u64>().as_ptr(), 0xCAFE);
+        }
+    }
+
+    #[test]
+    fn cache_fits_page_sized_objects() {
+        let layout = Layout::from_size_align(N, N).unwrap();
+        let cache = SlabCache::new("tables", layout, alloc::alloc::Global);
+        assert_eq!((cache.slab(), cache.capacity()), (2 * N, 1));
+
+        let mut cache = cache.slab_size(16 * N);
+        assert_eq!(cache.capacity(), 15);
+
+        let ptrs = [(); 16].map(|_| cache.alloc().unwrap());
+        assert_eq!(cache.lists(), (1, 1, 0));
+        for ptr in ptrs {
+            assert_eq!(ptr.as_ptr() as usize % N, 0);
+            unsafe { cache.free(ptr) };
+        }
+        assert_eq!(cache.lists(), (0, 0, 1));
+    }
+
+    #[test]
+    fn cache_rejects_foreign_layouts() {
+        let mut cache = SlabCache::of::<u32>("small", alloc::alloc::Global);
+
+        assert!(cache.allocate_mut(Layout::new::<u64>()).is_err());
+        assert!(cache.allocate_mut(Layout::new::<u16>()).is_ok());
+    }
+}
@@ -25,6 +25,10 @@ pub mod futures {
     pub mod stream {
         pub use futures_util::stream::*;
     }
+
+    pub mod sink {
+        pub use futures_util::sink::*;
+    }
 }
 
 pub mod sync;
@@ -23,9 +23,15 @@ pub mod idt {
 pub mod gdt {
     use libx64::segments::SegmentSelector;
 
+    /// Segments of the GDT, the user selectors have a requested privilege level of 3.
+    ///
+    /// The user data segment comes right before the user code segment as `sysret` expects.
     #[derive(Debug)]
     pub struct Selectors {
         pub code_segment: SegmentSelector,
+        pub data_segment: SegmentSelector,
+        pub user_data: SegmentSelector,
+        pub user_code: SegmentSelector,
         pub task_state: SegmentSelector,
     }
 }
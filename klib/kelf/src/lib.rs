@@ -0,0 +1,168 @@
+//! ELF loader shared by the bootloader and the kernel
+//!
+//! An [`Image`] is a parsed static or position independent x86_64 executable. The [`Loader`]
+//! maps its `PT_LOAD` segments with any [`PageMapper`](libx64::paging::page::PageMapper),
+//! zeroes the bss, applies the relative relocations of PIE binaries and records the TLS
+//! template. Physical memory is reached through a fixed offset, which is zero for identity
+//! mapped memory.
+#![no_std]
+#![allow(clippy::module_name_repetitions)]
+
+#[cfg(test)]
+#[macro_use]
+extern crate std;
+
+mod load;
+pub mod stack;
+
+use libx64::{
+    address::VirtualAddr,
+    paging::{entry::Flags, frame::FrameError, page::PageRange, Page4Kb},
+};
+use xmas_elf::{
+    header::{self, Machine, Type},
+    program::{self, Type as SegmentType},
+    ElfFile,
+};
+
+pub use load::{Loaded, Loader, Source};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    /// The file isn't a valid ELF file
+    Malformed(&'static str),
+    /// The file isn't an x86_64 executable or position independent executable
+    Unsupported,
+    /// The loader only applies `R_X86_64_RELATIVE` relocations
+    Relocation(u32),
+    /// A relocation points outside of the loaded segments
+    OutOfBounds,
+    /// The stack is too small for the arguments or isn't mapped
+    Stack,
+    Map(FrameError),
+}
+
+impl From<&'static str> for Error {
+    fn from(err: &'static str) -> Self {
+        Self::Malformed(err)
+    }
+}
+
+impl From<FrameError> for Error {
+    fn from(err: FrameError) -> Self {
+        Self::Map(err)
+    }
+}
+
+/// Thread local storage template of an image.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Tls {
+    /// Virtual address of the `.tdata` section
+    pub start_addr: VirtualAddr,
+    /// Size of the `.tdata` and `.tbss` sections
+    pub mem_size: u64,
+    /// Size of the `.tdata` section
+    pub file_size: u64,
+}
+
+/// Pages spanned by a loadable segment once relocated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub pages: PageRange<Page4Kb>,
+    pub flags: Flags,
+}
+
+/// A parsed ELF executable and the address it is loaded at.
+pub struct Image<'a> {
+    elf: ElfFile<'a>,
+    offset: VirtualAddr,
+}
+
+impl<'a> Image<'a> {
+    /// Parse `bytes`, position independent executables are relocated at `base`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the file is malformed or isn't an x86_64 executable
+    pub fn new(bytes: &'a [u8], base: VirtualAddr) -> Result<Self, Error> {
+        let elf = ElfFile::new(bytes)?;
+        header::sanity_check(&elf)?;
+        for segment in elf.program_iter() {
+            program::sanity_check(segment, &elf)?;
+        }
+
+        if elf.header.pt2.machine().as_machine() != Machine::X86_64 {
+            return Err(Error::Unsupported);
+        }
+        let offset = match elf.header.pt2.type_().as_type() {
+            Type::Executable => VirtualAddr::null(),
+            Type::SharedObject => base,
+            Type::None | Type::Relocatable | Type::Core | Type::ProcessorSpecific(_) => {
+                return Err(Error::Unsupported)
+            }
+        };
+        Ok(Self { elf, offset })
+    }
+
+    #[must_use]
+    pub const fn elf(&self) -> &ElfFile<'a> {
+        &self.elf
+    }
+
+    /// Offset added to every address of the file, zero for static executables.
+    #[must_use]
+    pub const fn offset(&self) -> VirtualAddr {
+        self.offset
+    }
+
+    #[must_use]
+    pub fn is_pie(&self) -> bool {
+        self.elf.header.pt2.type_().as_type() == Type::SharedObject
+    }
+
+    #[must_use]
+    pub fn entry(&self) -> VirtualAddr {
+        self.offset + self.elf.header.pt2.entry_point()
+    }
+
+    /// Pages and flags of the loadable segments, the pages of two segments may overlap.
+    pub fn segments(&self) -> impl Iterator<Item = Segment> + '_ {
+        self.elf
+            .program_iter()
+            .filter(|segment| segment.get_type() == Ok(SegmentType::Load))
+            .map(|segment| {
+                let start = self.offset + segment.virtual_addr();
+                let end = (start + segment.mem_size()).align_up(Page4Kb as u64);
+                Segment {
+                    pages: PageRange::new_addr(start.align_down(Page4Kb as u64), end),
+                    flags: segment_flags(segment.flags()),
+                }
+            })
+    }
+}
+
+/// Page flags of a segment, execution isn't restricted as `EFER.NXE` may be unsupported.
+fn segment_flags(flags: program::Flags) -> Flags {
+    if flags.is_write() {
+        Flags::PRESENT | Flags::RW
+    } else {
+        Flags::PRESENT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(matches!(
+            Image::new(&[0; 64], VirtualAddr::null()),
+            Err(Error::Malformed(_))
+        ));
+        assert!(matches!(
+            Image::new(b"\x7fELF", VirtualAddr::null()),
+            Err(Error::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,706 @@
+use core::mem::size_of;
+
+use libx64::{
+    address::{PhysicalAddr, VirtualAddr},
+    paging::{
+        entry::Flags,
+        frame::{FrameAllocator, FrameRange, PhysicalFrame},
+        page::{Page, PageMapper, PageRange, PageTranslator, TlbFlush, TlbMethod},
+        table::Translation,
+        Page4Kb,
+    },
+};
+use xmas_elf::{
+    dynamic::Tag,
+    program::{ProgramHeader, SegmentData, Type},
+    ElfFile,
+};
+
+use crate::{segment_flags, Error, Image, Tls};
+
+/// Set by [`Loader::make_mut`] on the pages that no longer alias the file, removed once the
+/// image is loaded.
+const COPIED: Flags = Flags::AVL1;
+
+/// `R_X86_64_RELATIVE`
+const RELATIVE: u32 = 8;
+
+/// Size of an `Elf64_Rela` entry
+const RELA_SIZE: usize = 3 * size_of::<u64>();
+
+/// Where the bytes of the segments come from.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Source {
+    /// The file is in physical memory at this page aligned address, its frames are mapped
+    /// directly and a page is only copied before being written to
+    Frames(PhysicalAddr),
+    /// Every page gets a new frame the file is copied to
+    Copy,
+}
+
+/// Addresses of a loaded image.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Loaded {
+    pub entry: VirtualAddr,
+    pub tls: Option<Tls>,
+    /// Program headers in the loaded image, if a segment contains them
+    pub phdr: Option<VirtualAddr>,
+    pub phent: u16,
+    pub phnum: u16,
+    /// First page after the highest segment
+    pub end: VirtualAddr,
+}
+
+pub struct Loader<'a, M, F> {
+    image: &'a Image<'a>,
+    mapper: &'a mut M,
+    frames: &'a mut F,
+    /// Virtual address of the physical memory
+    phys_offset: VirtualAddr,
+    source: Source,
+    /// Added to the flags of every segment
+    flags: Flags,
+}
+
+impl<'a, M, F> Loader<'a, M, F>
+where
+    M: PageMapper<Page4Kb> + PageTranslator,
+    F: FrameAllocator<Page4Kb>,
+{
+    /// Load `image` with `mapper`, frames are written to through the physical memory mapped at
+    /// `phys_offset`.
+    pub fn new(
+        image: &'a Image<'a>,
+        mapper: &'a mut M,
+        frames: &'a mut F,
+        phys_offset: VirtualAddr,
+    ) -> Self {
+        Self {
+            image,
+            mapper,
+            frames,
+            phys_offset,
+            source: Source::Copy,
+            flags: Flags::empty(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_source(mut self, source: Source) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Add `flags` to every page, [`Flags::US`] makes the image accessible from ring 3.
+    #[must_use]
+    pub fn with_flags(mut self, flags: Flags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Map the segments and apply the relocations.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a page can't be mapped, the file has an unsupported relocation or more than
+    /// one TLS segment
+    pub fn load(mut self) -> Result<Loaded, Error> {
+        let image = self.image;
+        let elf = image.elf();
+
+        let mut tls = None;
+        for segment in elf.program_iter() {
+            match segment.get_type()? {
+                Type::Load => match self.source {
+                    Source::Frames(file) => self.map_segment(segment, file)?,
+                    Source::Copy => self.copy_segment(segment)?,
+                },
+                Type::Tls if tls.is_some() => {
+                    return Err(Error::Malformed("multiple TLS segments"));
+                }
+                Type::Tls => {
+                    tls = Some(Tls {
+                        start_addr: image.offset() + segment.virtual_addr(),
+                        mem_size: segment.mem_size(),
+                        file_size: segment.file_size(),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        for segment in elf.program_iter() {
+            if segment.get_type()? == Type::Dynamic {
+                self.relocate(segment)?;
+            }
+        }
+
+        if let Source::Frames(_) = self.source {
+            self.remove_copied_flags()?;
+        }
+
+        Ok(Loaded {
+            entry: image.entry(),
+            tls,
+            phdr: phdr(elf).map(|addr| image.offset() + addr),
+            phent: elf.header.pt2.ph_entry_size(),
+            phnum: elf.header.pt2.ph_count(),
+            end: image
+                .segments()
+                .map(|segment| segment.pages.end())
+                .max()
+                .unwrap_or_else(|| image.offset()),
+        })
+    }
+
+    fn frame_ptr(&self, addr: PhysicalAddr) -> *mut u8 {
+        (self.phys_offset + addr.as_u64()).as_u64() as *mut u8
+    }
+
+    fn zeroed_frame(&mut self) -> Result<PhysicalFrame<Page4Kb>, Error> {
+        let frame = self.frames.alloc()?;
+        // SAFETY: the frame was just allocated
+        unsafe { core::ptr::write_bytes(self.frame_ptr(frame.ptr()), 0, Page4Kb) };
+        Ok(frame)
+    }
+
+    /// Copy the segment to new frames, a page shared with a previous segment is reused.
+    fn copy_segment(&mut self, segment: ProgramHeader) -> Result<(), Error> {
+        let image = self.image;
+        let flags = segment_flags(segment.flags()) | self.flags;
+        let start = (image.offset() + segment.virtual_addr()).as_u64();
+        let file_end = start + segment.file_size();
+        let data = file_range(image.elf(), segment.offset(), segment.file_size())?;
+
+        let pages = PageRange::<Page4Kb>::new_addr(
+            VirtualAddr::new(start).align_down(Page4Kb as u64),
+            VirtualAddr::new(start + segment.mem_size()).align_up(Page4Kb as u64),
+        );
+        for page in pages {
+            let frame = self.owned_frame(page, flags)?;
+
+            let page_start = page.ptr().as_u64();
+            let from = page_start.max(start);
+            let to = (page_start + Page4Kb as u64).min(file_end);
+            if from < to {
+                let src = &data[(from - start) as usize..(to - start) as usize];
+                let dst = self.frame_ptr(frame.ptr() + (from - page_start));
+                // SAFETY: the frame is owned by the image and the copy stays inside of it
+                unsafe { core::ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len()) };
+            }
+        }
+        Ok(())
+    }
+
+    /// Frame of `page`, a zeroed frame is mapped if the page isn't mapped yet.
+    fn owned_frame(
+        &mut self,
+        page: Page<Page4Kb>,
+        flags: Flags,
+    ) -> Result<PhysicalFrame<Page4Kb>, Error> {
+        match self.mapper.try_translate(page.ptr()) {
+            Ok(Translation {
+                addr,
+                flags: current,
+                ..
+            }) => {
+                if !current.contains(flags) {
+                    self.mapper
+                        .update_flags(page, current | flags)
+                        .map(TlbFlush::ignore)?;
+                }
+                Ok(PhysicalFrame::containing(addr))
+            }
+            Err(_) => {
+                let frame = self.zeroed_frame()?;
+                self.mapper
+                    .map(page, frame, flags, self.frames)
+                    .map(TlbFlush::ignore)?;
+                Ok(frame)
+            }
+        }
+    }
+
+    /// Map the frames of the file holding the segment.
+    fn map_segment(&mut self, segment: ProgramHeader, file: PhysicalAddr) -> Result<(), Error> {
+        let flags = segment_flags(segment.flags()) | self.flags;
+
+        let phys_start_addr = file + segment.offset();
+        let start_frame = PhysicalFrame::<Page4Kb>::containing(phys_start_addr);
+        let end_frame = PhysicalFrame::<Page4Kb>::containing(
+            (phys_start_addr + segment.file_size()).align_up(Page4Kb as u64),
+        );
+
+        let virt_start_addr = self.image.offset() + segment.virtual_addr();
+        let start_page = Page::<Page4Kb>::containing(virt_start_addr);
+        let end_page = Page::<Page4Kb>::containing(
+            (virt_start_addr + segment.file_size()).align_up(Page4Kb as u64),
+        );
+
+        self.mapper.map_range(
+            PageRange::new(start_page, end_page),
+            FrameRange::new(start_frame, end_frame),
+            flags,
+            self.frames,
+            TlbMethod::Ignore,
+        )?;
+
+        // Handle .bss section (mem_size > file_size)
+        if segment.mem_size() > segment.file_size() {
+            self.map_bss(&segment, flags)?;
+        }
+        Ok(())
+    }
+
+    fn map_bss(&mut self, segment: &ProgramHeader, flags: Flags) -> Result<(), Error> {
+        let virt_start_addr = self.image.offset() + segment.virtual_addr();
+        let mem_size = segment.mem_size();
+        let file_size = segment.file_size();
+
+        // calculate virual memory region that must be zeroed
+        let zero_start = virt_start_addr + file_size;
+        let zero_end = virt_start_addr + mem_size;
+
+        // In some cases, `zero_start` might not be page-aligned. This requires some
+        // special treatment because we can't safely zero a frame of the original file.
+        let data_bytes_before_zero = zero_start.as_usize() & 0xfff;
+        if data_bytes_before_zero != 0 {
+            // The last non-bss frame of the segment consists partly of data and partly of bss
+            // memory, which must be zeroed. Unfortunately, the file representation might have
+            // reused the part of the frame that should be zeroed to store the next segment. This
+            // means that we can't simply overwrite that part with zeroes, as we might overwrite
+            // other data this way.
+            //
+            // Example:
+            //
+            //   XXXXXXXXXXXXXXX000000YYYYYYY000ZZZZZZZZZZZ     virtual memory (XYZ are data)
+            //   |·············|     /·····/   /·········/
+            //   |·············| ___/·····/   /·········/
+            //   |·············|/·····/‾‾‾   /·········/
+            //   |·············||·····|/·̅·̅·̅·̅·̅·····/‾‾‾‾
+            //   XXXXXXXXXXXXXXXYYYYYYYZZZZZZZZZZZ              file memory (zeros are not saved)
+            //   '       '       '       '        '
+            //   The areas filled with dots (`·`) indicate a mapping between virtual and file
+            //   memory. We see that the data regions `X`, `Y`, `Z` have a valid mapping, while
+            //   the regions that are initialized with 0 have not.
+            //
+            //   The ticks (`'`) below the file memory line indicate the start of a new frame. We
+            //   see that the last frames of the `X` and `Y` regions in the file are followed
+            //   by the bytes of the next region. So we can't zero these parts of the frame
+            //   because they are needed by other memory regions.
+            //
+            // To solve this problem, we need to allocate a new frame for the last segment page
+            // and copy all data content of the original frame over. Afterwards, we can zero
+            // the remaining part of the frame since the frame is no longer shared with other
+            // segments now.
+
+            let last_page = Page::<Page4Kb>::containing(virt_start_addr + file_size - 1u64);
+            let new_frame = self.make_mut(last_page)?;
+            let new_bytes_ptr = self.frame_ptr(new_frame.ptr());
+            unsafe {
+                core::ptr::write_bytes(
+                    new_bytes_ptr.add(data_bytes_before_zero),
+                    0,
+                    Page4Kb - data_bytes_before_zero,
+                );
+            }
+        }
+
+        // map additional frames for `.bss` memory that is not present in source file
+        let start_page = Page::<Page4Kb>::containing(zero_start.align_up(Page4Kb as u64));
+        let end_page = Page::<Page4Kb>::containing(zero_end.align_up(Page4Kb as u64));
+        for page in PageRange::new(start_page, end_page) {
+            let frame = self.zeroed_frame()?;
+            self.mapper
+                .map(page, frame, flags, self.frames)
+                .map(TlbFlush::ignore)?;
+        }
+        Ok(())
+    }
+
+    /// Make a page mapped from the file writable without modifying the file.
+    ///
+    /// All memory from a Load segment starts out by mapped to the same frames that
+    /// contain the elf file. Thus writing to memory in that state will cause aliasing issues.
+    /// To avoid that, we allocate a new frame, copy all bytes from the old frame to the new frame,
+    /// and remap the page to the new frame. At this point the page no longer aliases the elf file
+    /// and we can write to it.
+    ///
+    /// When we map the new frame we also set [`COPIED`] flag in the page table flags, so that
+    /// we can detect if the frame has already been copied when we try to modify the page again.
+    fn make_mut(&mut self, page: Page<Page4Kb>) -> Result<PhysicalFrame<Page4Kb>, Error> {
+        let Translation { addr, flags, .. } = self.mapper.try_translate(page.ptr())?;
+        let frame = PhysicalFrame::<Page4Kb>::containing(addr);
+
+        if flags.contains(COPIED) {
+            // The frame was already copied, we are free to modify it.
+            return Ok(frame);
+        }
+
+        let new_frame = self.frames.alloc()?;
+        // SAFETY: the new frame was just allocated, both are reached through the offset
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                self.frame_ptr(frame.ptr()),
+                self.frame_ptr(new_frame.ptr()),
+                Page4Kb,
+            );
+        }
+
+        // Replace the underlying frame and update the flags.
+        self.mapper.unmap(page).map(TlbFlush::ignore)?;
+        self.mapper
+            .map(page, new_frame, flags | COPIED, self.frames)
+            .map(TlbFlush::ignore)?;
+
+        Ok(new_frame)
+    }
+
+    /// Cleans up the custom flags set by [`Loader::make_mut`].
+    fn remove_copied_flags(&mut self) -> Result<(), Error> {
+        let image = self.image;
+        for segment in image.segments() {
+            for page in segment.pages {
+                // Translate the page and get the flags.
+                let Translation { flags, .. } = self.mapper.try_translate(page.ptr())?;
+
+                if flags.contains(COPIED) {
+                    self.mapper
+                        .update_flags(page, flags & !COPIED)
+                        .map(TlbFlush::ignore)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the relocations listed in the dynamic segment.
+    fn relocate(&mut self, segment: ProgramHeader) -> Result<(), Error> {
+        let image = self.image;
+        let elf = image.elf();
+        let SegmentData::Dynamic64(data) = segment.get_data(elf)? else {
+            return Err(Error::Malformed("expected a Dynamic64 segment"));
+        };
+
+        // Find the `Rela`, `RelaSize` and `RelaEnt` entries.
+        let mut rela = None;
+        let mut rela_size = None;
+        let mut rela_ent = None;
+        for entry in data {
+            let (slot, value) = match entry.get_tag()? {
+                Tag::Rela => (&mut rela, entry.get_ptr()?),
+                Tag::RelaSize => (&mut rela_size, entry.get_val()?),
+                Tag::RelaEnt => (&mut rela_ent, entry.get_val()?),
+                _ => continue,
+            };
+            if slot.replace(value).is_some() {
+                return Err(Error::Malformed("duplicated relocation entry"));
+            }
+        }
+
+        let Some(rela) = rela else {
+            // The section doesn't contain any relocations.
+            return match (rela_size, rela_ent) {
+                (None, None) => Ok(()),
+                _ => Err(Error::Malformed("relocation table is missing")),
+            };
+        };
+        let total_size = rela_size.ok_or(Error::Malformed("RelaSize entry is missing"))?;
+        let entry_size = rela_ent.ok_or(Error::Malformed("RelaEnt entry is missing"))?;
+        if entry_size != RELA_SIZE as u64 {
+            return Err(Error::Malformed("unexpected relocation entry size"));
+        }
+
+        // the table is read from the file, it may not be aligned
+        let offset = file_offset(elf, rela).ok_or(Error::OutOfBounds)?;
+        let table = file_range(elf, offset, total_size)?;
+        for entry in table.chunks_exact(RELA_SIZE) {
+            let field = |idx: usize| {
+                let mut bytes = [0; size_of::<u64>()];
+                bytes.copy_from_slice(&entry[idx * 8..(idx + 1) * 8]);
+                u64::from_le_bytes(bytes)
+            };
+            let (offset, info, addend) = (field(0), field(1), field(2));
+
+            if info >> 32 != 0 {
+                // relocations using the symbol table are not supported
+                return Err(Error::Relocation(info as u32));
+            }
+            match info as u32 {
+                RELATIVE => {
+                    file_offset(elf, offset).ok_or(Error::OutOfBounds)?;
+                    let addr = image.offset() + offset;
+                    let value = image.offset() + addend;
+                    self.write_u64(addr, value.as_u64())?;
+                }
+                ty => return Err(Error::Relocation(ty)),
+            }
+        }
+        Ok(())
+    }
+
+    fn write_u64(&mut self, addr: VirtualAddr, value: u64) -> Result<(), Error> {
+        if addr.as_usize() % size_of::<u64>() != 0 {
+            return Err(Error::Malformed("destination of relocation is not aligned"));
+        }
+
+        let page = Page::<Page4Kb>::containing(addr);
+        let frame = match self.source {
+            Source::Frames(_) => self.make_mut(page)?,
+            Source::Copy => PhysicalFrame::containing(self.mapper.try_translate(addr)?.addr),
+        };
+        let ptr = self.frame_ptr(frame.ptr() + u64::from(addr.page_offset()));
+        // SAFETY: the frame is owned by the image and the address is aligned
+        unsafe { ptr.cast::<u64>().write(value) };
+        Ok(())
+    }
+}
+
+/// Bytes of the file in `offset..offset + len`.
+fn file_range<'a>(elf: &ElfFile<'a>, offset: u64, len: u64) -> Result<&'a [u8], Error> {
+    let end = offset.checked_add(len).ok_or(Error::OutOfBounds)?;
+    elf.input
+        .get(usize::try_from(offset).map_err(|_| Error::OutOfBounds)?..)
+        .and_then(|bytes| bytes.get(..usize::try_from(end - offset).ok()?))
+        .ok_or(Error::OutOfBounds)
+}
+
+/// File offset of the virtual address `addr` of a load segment.
+fn file_offset(elf: &ElfFile, addr: u64) -> Option<u64> {
+    elf.program_iter()
+        .filter(|segment| segment.get_type() == Ok(Type::Load))
+        .find(|segment| {
+            (segment.virtual_addr()..segment.virtual_addr() + segment.file_size()).contains(&addr)
+        })
+        .map(|segment| segment.offset() + (addr - segment.virtual_addr()))
+}
+
+/// Virtual address of the program headers before relocation.
+fn phdr(elf: &ElfFile) -> Option<u64> {
+    if let Some(segment) = elf
+        .program_iter()
+        .find(|segment| segment.get_type() == Ok(Type::Phdr))
+    {
+        return Some(segment.virtual_addr());
+    }
+    let offset = elf.header.pt2.ph_offset();
+    elf.program_iter()
+        .filter(|segment| segment.get_type() == Ok(Type::Load))
+        .find(|segment| {
+            (segment.offset()..segment.offset() + segment.file_size()).contains(&offset)
+        })
+        .map(|segment| segment.virtual_addr() + (offset - segment.offset()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{boxed::Box, collections::BTreeMap, vec::Vec};
+
+    use libx64::paging::{frame::FrameError, table::Level4, PinTableMut};
+
+    use super::*;
+    use crate::Segment;
+
+    const TEXT: u64 = 0x40_0000;
+    const DATA: u64 = 0x40_1100;
+    const ENTRY: u64 = TEXT + 0xB0;
+    /// `hlt` in a loop
+    const CODE: [u8; 3] = [0xF4, 0xEB, 0xFD];
+    const WORD: u64 = 0x1122_3344_5566_7788;
+
+    /// Static executable with a read only text segment holding the headers and the code, and a
+    /// writable data segment whose bss spills over the next page
+    fn fixture() -> Vec<u64> {
+        let mut bytes = vec![0u8; 0x110];
+        let mut put = |offset: usize, field: &[u8]| {
+            bytes[offset..offset + field.len()].copy_from_slice(field);
+        };
+
+        put(0, b"\x7fELF\x02\x01\x01");
+        put(16, &2u16.to_le_bytes()); // ET_EXEC
+        put(18, &0x3Eu16.to_le_bytes()); // EM_X86_64
+        put(20, &1u32.to_le_bytes());
+        put(24, &ENTRY.to_le_bytes());
+        put(32, &64u64.to_le_bytes());
+        put(52, &64u16.to_le_bytes());
+        put(54, &56u16.to_le_bytes());
+        put(56, &2u16.to_le_bytes());
+        put(58, &64u16.to_le_bytes());
+
+        // (flags, offset, address, file size, memory size)
+        let segments: [(u32, u64, u64, u64, u64); 2] = [
+            (0b101, 0, TEXT, 0x100, 0x100),
+            (0b110, 0x100, DATA, 8, 0x1000),
+        ];
+        for (idx, (flags, offset, addr, file_size, mem_size)) in segments.into_iter().enumerate() {
+            let phdr = 64 + idx * 56;
+            put(phdr, &1u32.to_le_bytes()); // PT_LOAD
+            put(phdr + 4, &flags.to_le_bytes());
+            put(phdr + 8, &offset.to_le_bytes());
+            put(phdr + 16, &addr.to_le_bytes());
+            put(phdr + 24, &addr.to_le_bytes());
+            put(phdr + 32, &file_size.to_le_bytes());
+            put(phdr + 40, &mem_size.to_le_bytes());
+            put(phdr + 48, &0x1000u64.to_le_bytes());
+        }
+        put((ENTRY - TEXT) as usize, &CODE);
+        put(0x100, &WORD.to_le_bytes());
+
+        // the headers are read in place and must be aligned
+        bytes
+            .chunks_exact(8)
+            .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+            .collect()
+    }
+
+    #[repr(C, align(4096))]
+    struct Frame([u8; Page4Kb]);
+
+    /// Frames are host pages, the physical memory offset is zero. They are boxed so they don't
+    /// move while mapped.
+    #[allow(clippy::vec_box)]
+    #[derive(Default)]
+    struct Frames(Vec<Box<Frame>>);
+
+    impl FrameAllocator<Page4Kb> for Frames {
+        fn alloc(&mut self) -> Result<PhysicalFrame<Page4Kb>, FrameError> {
+            // poisoned, the loader must zero what it doesn't copy
+            let frame = Box::new(Frame([0xAA; Page4Kb]));
+            let addr = PhysicalAddr::from_ptr(frame.0.as_ptr());
+            self.0.push(frame);
+            Ok(PhysicalFrame::containing(addr))
+        }
+    }
+
+    /// Page table as a map from virtual pages to frames
+    #[derive(Default)]
+    struct Tables(BTreeMap<u64, (PhysicalAddr, Flags)>);
+
+    impl PageMapper<Page4Kb> for Tables {
+        unsafe fn from_level4(_: PinTableMut<'_, Level4>) -> Self {
+            unimplemented!()
+        }
+
+        fn level4(&mut self) -> PinTableMut<'_, Level4> {
+            unimplemented!()
+        }
+
+        fn map<A>(
+            &mut self,
+            page: Page<Page4Kb>,
+            frame: PhysicalFrame<Page4Kb>,
+            flags: Flags,
+            _: &mut A,
+        ) -> Result<TlbFlush<Page4Kb>, FrameError>
+        where
+            A: FrameAllocator<Page4Kb>,
+        {
+            let previous = self.0.insert(page.ptr().as_u64(), (frame.ptr(), flags));
+            assert!(previous.is_none(), "{:?} is mapped twice", page.ptr());
+            Ok(TlbFlush::new(page))
+        }
+
+        fn update_flags(
+            &mut self,
+            page: Page<Page4Kb>,
+            flags: Flags,
+        ) -> Result<TlbFlush<Page4Kb>, FrameError> {
+            let entry = self
+                .0
+                .get_mut(&page.ptr().as_u64())
+                .ok_or(FrameError::EntryMissing)?;
+            entry.1 = flags;
+            Ok(TlbFlush::new(page))
+        }
+
+        fn unmap(&mut self, page: Page<Page4Kb>) -> Result<TlbFlush<Page4Kb>, FrameError> {
+            self.0
+                .remove(&page.ptr().as_u64())
+                .ok_or(FrameError::EntryMissing)?;
+            Ok(TlbFlush::new(page))
+        }
+    }
+
+    impl PageTranslator for Tables {
+        fn try_translate(&mut self, addr: VirtualAddr) -> Result<Translation, FrameError> {
+            let page = addr.align_down(Page4Kb as u64).as_u64();
+            let (frame, flags) = *self.0.get(&page).ok_or(FrameError::EntryMissing)?;
+            Ok(Translation {
+                flags,
+                addr: frame + u64::from(addr.page_offset()),
+                offset: addr.page_offset(),
+            })
+        }
+    }
+
+    impl Tables {
+        fn flags(&self, page: u64) -> Flags {
+            self.0[&page].1
+        }
+
+        fn read(&mut self, addr: u64, len: usize) -> &[u8] {
+            let phys = self.try_translate(VirtualAddr::new(addr)).unwrap().addr;
+            // SAFETY: the frame is a live host page and the read stays inside of it
+            unsafe { core::slice::from_raw_parts(phys.as_u64() as *const u8, len) }
+        }
+    }
+
+    #[test]
+    fn loads_static_executable() {
+        let file = fixture();
+        // SAFETY: the words are reinterpreted as the bytes of the file
+        let bytes = unsafe { core::slice::from_raw_parts(file.as_ptr().cast(), file.len() * 8) };
+        let image = Image::new(bytes, VirtualAddr::new(0x1000_0000)).unwrap();
+        assert!(!image.is_pie());
+        assert_eq!(image.offset(), VirtualAddr::null());
+
+        let segments: Vec<Segment> = image.segments().collect();
+        let pages = |start: u64, end: u64| {
+            PageRange::<Page4Kb>::new_addr(VirtualAddr::new(start), VirtualAddr::new(end))
+        };
+        assert_eq!(
+            segments,
+            [
+                Segment {
+                    pages: pages(TEXT, TEXT + 0x1000),
+                    flags: Flags::PRESENT,
+                },
+                Segment {
+                    pages: pages(TEXT + 0x1000, TEXT + 0x3000),
+                    flags: Flags::PRESENT | Flags::RW,
+                },
+            ]
+        );
+
+        let (mut tables, mut frames) = (Tables::default(), Frames::default());
+        let loaded = Loader::new(&image, &mut tables, &mut frames, VirtualAddr::null())
+            .with_flags(Flags::US)
+            .load()
+            .unwrap();
+        assert_eq!(
+            loaded,
+            Loaded {
+                entry: VirtualAddr::new(ENTRY),
+                tls: None,
+                phdr: Some(VirtualAddr::new(TEXT + 64)),
+                phent: 56,
+                phnum: 2,
+                end: VirtualAddr::new(TEXT + 0x3000),
+            }
+        );
+
+        // text isn't writable, the data and its bss are
+        assert_eq!(tables.0.len(), 3);
+        assert_eq!(tables.flags(TEXT), Flags::PRESENT | Flags::US);
+        let data = Flags::PRESENT | Flags::RW | Flags::US;
+        assert_eq!(tables.flags(TEXT + 0x1000), data);
+        assert_eq!(tables.flags(TEXT + 0x2000), data);
+
+        assert_eq!(tables.read(ENTRY, CODE.len()), CODE);
+        assert_eq!(tables.read(TEXT, 4), *b"\x7fELF");
+        assert_eq!(tables.read(DATA, 8), WORD.to_le_bytes());
+        assert!(tables.read(TEXT + 0x1000, 0x100).iter().all(|b| *b == 0));
+        assert!(tables.read(DATA + 8, 0xEF8).iter().all(|b| *b == 0));
+        assert!(tables.read(TEXT + 0x2000, Page4Kb).iter().all(|b| *b == 0));
+    }
+}
@@ -0,0 +1,243 @@
+//! Initial stack of a process following the System V x86_64 ABI
+//!
+//! From the stack pointer up: `argc`, the `argv` pointers and a null, the `envp` pointers and a
+//! null, the auxiliary vector ended by [`AT_NULL`], then the strings they point to.
+
+use core::mem::size_of;
+
+use libx64::{
+    address::VirtualAddr,
+    paging::{page::PageTranslator, Page4Kb},
+};
+
+use crate::{Error, Loaded};
+
+pub const AT_NULL: u64 = 0;
+pub const AT_IGNORE: u64 = 1;
+pub const AT_PHDR: u64 = 3;
+pub const AT_PHENT: u64 = 4;
+pub const AT_PHNUM: u64 = 5;
+pub const AT_PAGESZ: u64 = 6;
+pub const AT_BASE: u64 = 7;
+pub const AT_ENTRY: u64 = 9;
+
+/// Alignment of the stack pointer at the entry point
+const STACK_ALIGN: u64 = 16;
+
+impl Loaded {
+    /// Auxiliary vector describing the image, there is no interpreter so `AT_BASE` is zero.
+    #[must_use]
+    pub fn auxv(&self) -> [(u64, u64); 6] {
+        [
+            self.phdr
+                .map_or((AT_IGNORE, 0), |phdr| (AT_PHDR, phdr.as_u64())),
+            (AT_PHENT, u64::from(self.phent)),
+            (AT_PHNUM, u64::from(self.phnum)),
+            (AT_PAGESZ, Page4Kb as u64),
+            (AT_BASE, 0),
+            (AT_ENTRY, self.entry.as_u64()),
+        ]
+    }
+}
+
+/// What a process finds on its stack.
+#[derive(Debug, Clone, Copy)]
+pub struct Args<'a> {
+    pub argv: &'a [&'a str],
+    pub envp: &'a [&'a str],
+    /// Entries without the final [`AT_NULL`]
+    pub auxv: &'a [(u64, u64)],
+}
+
+/// Write `args` at the top of the stack mapped at `bottom..top` and return the stack pointer the
+/// process starts with.
+///
+/// # Errors
+///
+/// Errors if the arguments don't fit or a page of the stack isn't mapped
+pub fn init<M: PageTranslator>(
+    mapper: &mut M,
+    phys_offset: VirtualAddr,
+    bottom: VirtualAddr,
+    top: VirtualAddr,
+    args: &Args,
+) -> Result<VirtualAddr, Error> {
+    let mut stack = Stack {
+        mapper,
+        phys_offset,
+        bottom: bottom.as_u64(),
+        top: top.as_u64(),
+    };
+    let strings = || args.argv.iter().chain(args.envp);
+
+    let strings_len: u64 = strings().map(|s| s.len() as u64 + 1).sum();
+    let strings_start = stack.top.checked_sub(strings_len).ok_or(Error::Stack)?;
+
+    let words = 1 + (args.argv.len() + 1) + (args.envp.len() + 1) + 2 * (args.auxv.len() + 1);
+    let sp = strings_start
+        .checked_sub((words * size_of::<u64>()) as u64)
+        .ok_or(Error::Stack)?
+        & !(STACK_ALIGN - 1);
+
+    let mut cursor = sp;
+    let mut push = |stack: &mut Stack<M>, word: u64| {
+        stack.write(cursor, &word.to_le_bytes())?;
+        cursor += size_of::<u64>() as u64;
+        Ok::<_, Error>(())
+    };
+
+    push(&mut stack, args.argv.len() as u64)?;
+    if args.argv.is_empty() {
+        push(&mut stack, 0)?;
+    }
+    let mut string = strings_start;
+    for (idx, s) in strings().enumerate() {
+        stack.write(string, s.as_bytes())?;
+        stack.write(string + s.len() as u64, &[0])?;
+        push(&mut stack, string)?;
+        if idx + 1 == args.argv.len() {
+            push(&mut stack, 0)?;
+        }
+        string += s.len() as u64 + 1;
+    }
+    push(&mut stack, 0)?;
+
+    for &(key, value) in args.auxv.iter().chain(&[(AT_NULL, 0)]) {
+        push(&mut stack, key)?;
+        push(&mut stack, value)?;
+    }
+
+    Ok(VirtualAddr::new(sp))
+}
+
+struct Stack<'a, M> {
+    mapper: &'a mut M,
+    phys_offset: VirtualAddr,
+    bottom: u64,
+    top: u64,
+}
+
+impl<M: PageTranslator> Stack<'_, M> {
+    fn write(&mut self, mut addr: u64, mut bytes: &[u8]) -> Result<(), Error> {
+        if addr < self.bottom || addr + bytes.len() as u64 > self.top {
+            return Err(Error::Stack);
+        }
+        while !bytes.is_empty() {
+            let phys = self
+                .mapper
+                .try_translate(VirtualAddr::new(addr))
+                .map_err(|_| Error::Stack)?
+                .addr;
+            let len = bytes.len().min(Page4Kb - (addr as usize % Page4Kb));
+
+            let dst = (self.phys_offset + phys.as_u64()).as_u64() as *mut u8;
+            // SAFETY: the page is mapped in the stack of the process
+            unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), dst, len) };
+
+            addr += len as u64;
+            bytes = &bytes[len..];
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use libx64::{
+        address::PhysicalAddr,
+        paging::{entry::Flags, frame::FrameError, table::Translation},
+    };
+
+    use super::*;
+
+    const BOTTOM: u64 = 0x6000_0000_0000;
+    const PAGES: usize = 2;
+
+    /// Stack pages backed by a buffer, the physical memory offset is zero
+    struct Buffer(Vec<u8>);
+
+    impl PageTranslator for Buffer {
+        fn try_translate(&mut self, addr: VirtualAddr) -> Result<Translation, FrameError> {
+            let offset = addr
+                .as_u64()
+                .checked_sub(BOTTOM)
+                .filter(|offset| *offset < self.0.len() as u64)
+                .ok_or(FrameError::EntryMissing)?;
+            Ok(Translation {
+                flags: Flags::PRESENT | Flags::RW | Flags::US,
+                addr: PhysicalAddr::from_ptr(self.0.as_ptr()) + offset,
+                offset: addr.page_offset(),
+            })
+        }
+    }
+
+    impl Buffer {
+        fn word(&self, addr: u64) -> u64 {
+            let idx = (addr - BOTTOM) as usize;
+            u64::from_le_bytes(self.0[idx..idx + 8].try_into().unwrap())
+        }
+
+        fn string(&self, addr: u64) -> &str {
+            let idx = (addr - BOTTOM) as usize;
+            let len = self.0[idx..].iter().position(|b| *b == 0).unwrap();
+            core::str::from_utf8(&self.0[idx..idx + len]).unwrap()
+        }
+    }
+
+    fn setup(buffer: &mut Buffer, args: &Args) -> Result<u64, Error> {
+        let top = VirtualAddr::new(BOTTOM + (PAGES * Page4Kb) as u64);
+        init(buffer, VirtualAddr::null(), VirtualAddr::new(BOTTOM), top, args)
+            .map(VirtualAddr::as_u64)
+    }
+
+    #[test]
+    fn stack_layout() {
+        let mut buffer = Buffer(vec![0xAA; PAGES * Page4Kb]);
+        let args = Args {
+            argv: &["init", "-v"],
+            envp: &["TERM=dumb"],
+            auxv: &[(AT_PAGESZ, 4096), (AT_ENTRY, 0x40_1000)],
+        };
+        let sp = setup(&mut buffer, &args).unwrap();
+        assert_eq!(sp % STACK_ALIGN, 0);
+
+        let words: Vec<u64> = (0..10).map(|idx| buffer.word(sp + idx * 8)).collect();
+        assert_eq!(words[0], 2);
+        assert_eq!(buffer.string(words[1]), "init");
+        assert_eq!(buffer.string(words[2]), "-v");
+        assert_eq!(words[3], 0);
+        assert_eq!(buffer.string(words[4]), "TERM=dumb");
+        assert_eq!(words[5], 0);
+        assert_eq!(&words[6..10], &[AT_PAGESZ, 4096, AT_ENTRY, 0x40_1000]);
+        assert_eq!(buffer.word(sp + 80), AT_NULL);
+        assert_eq!(buffer.word(sp + 88), 0);
+    }
+
+    #[test]
+    fn empty_arguments() {
+        let mut buffer = Buffer(vec![0xAA; PAGES * Page4Kb]);
+        let args = Args {
+            argv: &[],
+            envp: &[],
+            auxv: &[],
+        };
+        let sp = setup(&mut buffer, &args).unwrap();
+        let words: Vec<u64> = (0..5).map(|idx| buffer.word(sp + idx * 8)).collect();
+        assert_eq!(words, [0, 0, 0, AT_NULL, 0]);
+    }
+
+    #[test]
+    fn arguments_must_fit() {
+        let mut buffer = Buffer(vec![0; PAGES * Page4Kb]);
+        let long = "x".repeat(PAGES * Page4Kb);
+        let argv = [long.as_str()];
+        let args = Args {
+            argv: &argv,
+            envp: &[],
+            auxv: &[],
+        };
+        assert_eq!(setup(&mut buffer, &args), Err(Error::Stack));
+    }
+}
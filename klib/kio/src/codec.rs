@@ -22,10 +22,13 @@ pub trait Decoder {
     type Item;
     type Error: From<crate::Error>;
 
+    /// Decode an item from the start of `src`, returns the number of bytes it used or `None` if
+    /// `src` doesn't hold a whole item yet.
+    ///
     /// # Errors
     ///
     /// Deserialization errors
-    fn decode(&mut self, src: &[u8]) -> Result<Option<Self::Item>, Self::Error>;
+    fn decode(&mut self, src: &[u8]) -> Result<Option<(usize, Self::Item)>, Self::Error>;
 }
 
 pub struct Chained<B, First, Second> {
@@ -19,9 +19,13 @@ pub struct Error {
 #[derive(Debug, Clone, Copy)]
 #[non_exhaustive]
 pub enum ErrorKind {
+    NotFound,
+    PermissionDenied,
     StorageFull,
     OutOfMemory,
     InvalidData,
+    Unsupported,
+    UnexpectedEof,
 }
 
 impl Error {
@@ -1,4 +1,282 @@
-pub trait Read {}
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
 
+use kcore::futures::stream::Stream;
+
+use crate::codec::Decoder;
+
+pub trait Read {
+    /// Read some bytes into `buffer`, zero means the end of the stream.
+    ///
+    /// # Errors
+    ///
+    /// This should error if a byte can't be read
+    fn read(&mut self, buffer: &mut [u8]) -> crate::Result<usize>;
+
+    /// # Errors
+    ///
+    /// Return the first error of [`Read::read`](Read::read) or
+    /// [`UnexpectedEof`](crate::ErrorKind::UnexpectedEof) if the stream ends first
+    fn read_exact(&mut self, mut buffer: &mut [u8]) -> crate::Result<()> {
+        while !buffer.is_empty() {
+            match self.read(buffer) {
+                Ok(0) => return Err(crate::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => buffer = &mut buffer[n..],
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for &[u8] {
+    fn read(&mut self, buffer: &mut [u8]) -> crate::Result<usize> {
+        let n = buffer.len().min(self.len());
+        let (read, rest) = self.split_at(n);
+        buffer[..n].copy_from_slice(read);
+        *self = rest;
+        Ok(n)
+    }
+}
+
+/// Read half of an asynchronous byte source.
+pub trait AsyncRead {
+    /// Read some bytes into `buffer` or register the waker of `cx` until bytes are available,
+    /// zero means the end of the stream.
+    ///
+    /// # Errors
+    ///
+    /// This should error if a byte can't be read
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut [u8],
+    ) -> Poll<crate::Result<usize>>;
+}
+
+/// Bytes of a reader split into items by a decoder.
+///
+/// A blocking [`Read`] reader yields the items as an [`Iterator`] and an [`AsyncRead`] reader
+/// as a [`Stream`], both end with the reader. The buffer must fit the largest encoded item.
 #[allow(clippy::module_name_repetitions)]
-pub struct FramedRead {}
+pub struct FramedRead<B, R, D>
+where
+    B: AsMut<[u8]>,
+{
+    buffer: B,
+    reader: R,
+    decoder: D,
+    /// Start of the bytes not read yet
+    filled: usize,
+}
+
+impl<B, R, D> FramedRead<B, R, D>
+where
+    B: AsMut<[u8]>,
+{
+    #[must_use]
+    pub fn new(buffer: B, reader: R, decoder: D) -> Self {
+        Self {
+            buffer,
+            reader,
+            decoder,
+            filled: 0,
+        }
+    }
+
+    pub fn reader(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Bytes read but not decoded yet.
+    pub fn buffered(&mut self) -> &[u8] {
+        &self.buffer.as_mut()[..self.filled]
+    }
+}
+
+impl<B, R, D> FramedRead<B, R, D>
+where
+    B: AsMut<[u8]>,
+    D: Decoder,
+{
+    /// Decode the next item of the buffer and drop its bytes, the buffer is dropped on errors.
+    fn decode(&mut self) -> Result<Option<D::Item>, D::Error> {
+        let buffer = self.buffer.as_mut();
+        match self.decoder.decode(&buffer[..self.filled]) {
+            Ok(Some((n, item))) => {
+                buffer.copy_within(n..self.filled, 0);
+                self.filled -= n;
+                Ok(Some(item))
+            }
+            Ok(None) if self.filled == buffer.len() => {
+                self.filled = 0;
+                Err(crate::Error::new(crate::ErrorKind::StorageFull).into())
+            }
+            Ok(None) => Ok(None),
+            Err(err) => {
+                self.filled = 0;
+                Err(err)
+            }
+        }
+    }
+
+    /// Free part of the buffer and the reader filling it.
+    fn spare(&mut self) -> (&mut [u8], &mut R) {
+        (&mut self.buffer.as_mut()[self.filled..], &mut self.reader)
+    }
+}
+
+impl<B, R, D> Iterator for FramedRead<B, R, D>
+where
+    B: AsMut<[u8]>,
+    R: Read,
+    D: Decoder,
+{
+    type Item = Result<D::Item, D::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.decode() {
+                Ok(Some(item)) => return Some(Ok(item)),
+                Ok(None) => {}
+                Err(err) => return Some(Err(err)),
+            }
+            let (spare, reader) = self.spare();
+            match reader.read(spare) {
+                // the bytes of an unfinished item are lost
+                Ok(0) => return None,
+                Ok(n) => self.filled += n,
+                Err(err) => return Some(Err(err.into())),
+            }
+        }
+    }
+}
+
+impl<B, R, D> Stream for FramedRead<B, R, D>
+where
+    B: AsMut<[u8]> + Unpin,
+    R: AsyncRead + Unpin,
+    D: Decoder + Unpin,
+{
+    type Item = Result<D::Item, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match this.decode() {
+                Ok(Some(item)) => return Poll::Ready(Some(Ok(item))),
+                Ok(None) => {}
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+            let (spare, reader) = this.spare();
+            match Pin::new(reader).poll_read(cx, spare) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(None),
+                Poll::Ready(Ok(n)) => this.filled += n,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::task::{RawWaker, RawWakerVTable, Waker};
+
+    use super::*;
+    use crate::ErrorKind;
+
+    /// Lines ended by `\n`, decoded as their length
+    struct Lines;
+
+    impl Decoder for Lines {
+        type Item = usize;
+        type Error = crate::Error;
+
+        fn decode(&mut self, src: &[u8]) -> Result<Option<(usize, usize)>, Self::Error> {
+            Ok(src.iter().position(|b| *b == b'\n').map(|n| (n + 1, n)))
+        }
+    }
+
+    /// Reader giving one byte per read, and nothing every other poll
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        ready: bool,
+    }
+
+    impl AsyncRead for Trickle<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buffer: &mut [u8],
+        ) -> Poll<crate::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = self.bytes.len().min(buffer.len()).min(1);
+            self.bytes.read(&mut buffer[..n]).into()
+        }
+    }
+
+    fn noop_waker() -> Waker {
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(
+            |_| RawWaker::new(core::ptr::null(), &VTABLE),
+            |_| {},
+            |_| {},
+            |_| {},
+        );
+        // SAFETY: the vtable does nothing
+        unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+    }
+
+    #[test]
+    fn read_exact() {
+        let mut reader = &b"hello"[..];
+        let mut buffer = [0; 3];
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"hel");
+        let err = reader.read_exact(&mut buffer).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn iterate_items() {
+        let framed = FramedRead::new([0; 8], &b"ab\n\ncdef\nunf"[..], Lines);
+        let items: std::vec::Vec<_> = framed.map(Result::unwrap).collect();
+        assert_eq!(items, [2, 0, 4]);
+    }
+
+    #[test]
+    fn item_too_large() {
+        let mut framed = FramedRead::new([0; 4], &b"abcdefg\nhi\n"[..], Lines);
+        let err = framed.next().unwrap().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::StorageFull));
+    }
+
+    #[test]
+    fn stream_items() {
+        let reader = Trickle {
+            bytes: b"abc\nd\n",
+            ready: false,
+        };
+        let mut framed = FramedRead::new([0; 8], reader, Lines);
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut items = std::vec::Vec::new();
+        let mut pending = 0;
+        loop {
+            match Pin::new(&mut framed).poll_next(&mut cx) {
+                Poll::Ready(Some(item)) => items.push(item.unwrap()),
+                Poll::Ready(None) => break,
+                Poll::Pending => pending += 1,
+            }
+        }
+        assert_eq!(items, [3, 1]);
+        assert_eq!(pending, 6);
+    }
+}
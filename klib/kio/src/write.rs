@@ -52,6 +52,10 @@ where
             encoder,
         }
     }
+
+    pub fn writer(&mut self) -> &mut W {
+        &mut self.writer
+    }
 }
 
 impl<B, W, E, Item> Sink<Item> for FramedWrite<B, W, E>
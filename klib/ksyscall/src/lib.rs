@@ -0,0 +1,200 @@
+//! System call interface shared by the kernel and user programs
+//!
+//! The call number goes in `rax` and the arguments in `rdi`, `rsi`, `rdx`, `r10`, `r8` and `r9`.
+//! The result comes back in `rax`, values from `-4095` to `-1` are [`Error`] codes. `syscall`
+//! overwrites `rcx` and `r11`, every other register is preserved.
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+pub mod raw;
+
+use core::{ptr::NonNull, time::Duration};
+
+use bitflags::bitflags;
+
+/// Longest message accepted by [`Syscall::Log`]
+pub const MAX_LOG_LEN: usize = 1024;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(u64)]
+pub enum Syscall {
+    /// `log(ptr, len)`, write an UTF-8 message to the kernel log
+    Log = 0,
+    /// `exit(code)`, terminate the calling process
+    Exit = 1,
+    /// `yield()`, give the cpu to the next ready thread
+    Yield = 2,
+    /// `map(len, flags)`, map zeroed memory and return its address
+    Map = 3,
+    /// `sleep(millis)`, block the caller for at least this many milliseconds
+    Sleep = 4,
+    /// `protect(addr, flags)`, change the access rights of the memory mapped at `addr`
+    Protect = 5,
+}
+
+impl Syscall {
+    /// Number of system calls
+    pub const COUNT: usize = 6;
+}
+
+impl TryFrom<u64> for Syscall {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Log,
+            1 => Self::Exit,
+            2 => Self::Yield,
+            3 => Self::Map,
+            4 => Self::Sleep,
+            5 => Self::Protect,
+            _ => return Err(Error::NoSys),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(i64)]
+pub enum Error {
+    /// Unknown system call number
+    NoSys = -1,
+    /// A buffer isn't mapped in the address space of the caller
+    Fault = -2,
+    /// An argument is out of range
+    Invalid = -3,
+    /// The kernel ran out of memory
+    NoMemory = -4,
+}
+
+impl Error {
+    const MAX_CODE: u64 = 4095;
+
+    #[must_use]
+    pub const fn from_code(code: i64) -> Option<Self> {
+        Some(match code {
+            -1 => Self::NoSys,
+            -2 => Self::Fault,
+            -3 => Self::Invalid,
+            -4 => Self::NoMemory,
+            _ => return None,
+        })
+    }
+}
+
+/// Value returned in `rax` for `result`.
+#[must_use]
+pub const fn encode(result: Result<u64, Error>) -> u64 {
+    match result {
+        Ok(value) => value,
+        Err(err) => err as i64 as u64,
+    }
+}
+
+/// Split the value returned in `rax` in a result.
+///
+/// # Errors
+///
+/// Errors if the value is an error code, unknown codes are reported as [`Error::Invalid`]
+pub const fn decode(value: u64) -> Result<u64, Error> {
+    if value.wrapping_neg() > Error::MAX_CODE || value == 0 {
+        return Ok(value);
+    }
+    match Error::from_code(value as i64) {
+        Some(err) => Err(err),
+        None => Err(Error::Invalid),
+    }
+}
+
+bitflags! {
+    /// Access rights of the memory returned by [`map`] and changed by [`protect`]
+    pub struct MapFlags: u64 {
+        const WRITE = 1;
+    }
+}
+
+/// Write `msg` to the kernel log.
+///
+/// # Errors
+///
+/// Errors if the message is longer than [`MAX_LOG_LEN`]
+pub fn log(msg: &str) -> Result<(), Error> {
+    let ret = unsafe { raw::syscall2(Syscall::Log, msg.as_ptr() as u64, msg.len() as u64) };
+    decode(ret).map(drop)
+}
+
+/// Terminate the calling process.
+pub fn exit(code: u64) -> ! {
+    unsafe { raw::syscall1(Syscall::Exit, code) };
+    unreachable!("the process exited")
+}
+
+/// Give the cpu to the next ready thread.
+pub fn yield_now() {
+    unsafe { raw::syscall0(Syscall::Yield) };
+}
+
+/// Map `len` bytes of zeroed memory, rounded up to whole pages.
+///
+/// # Errors
+///
+/// Errors if `len` is zero or the kernel is out of memory
+pub fn map(len: usize, flags: MapFlags) -> Result<NonNull<u8>, Error> {
+    let ret = unsafe { raw::syscall2(Syscall::Map, len as u64, flags.bits()) };
+    decode(ret).and_then(|addr| NonNull::new(addr as *mut u8).ok_or(Error::Invalid))
+}
+
+/// Change the access rights of the memory returned by [`map`] at `addr`.
+///
+/// # Errors
+///
+/// Errors if `addr` wasn't returned by [`map`]
+pub fn protect(addr: NonNull<u8>, flags: MapFlags) -> Result<(), Error> {
+    let ret = unsafe { raw::syscall2(Syscall::Protect, addr.as_ptr() as u64, flags.bits()) };
+    decode(ret).map(drop)
+}
+
+/// Block the caller for at least `duration`.
+///
+/// # Errors
+///
+/// Errors if the duration doesn't fit in 64 bits of milliseconds
+pub fn sleep(duration: Duration) -> Result<(), Error> {
+    let millis = u64::try_from(duration.as_millis()).map_err(|_| Error::Invalid)?;
+    decode(unsafe { raw::syscall1(Syscall::Sleep, millis) }).map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_round_trip() {
+        for result in [
+            Ok(0),
+            Ok(42),
+            Ok(0x6000_0000_1000),
+            Ok(u64::MAX - Error::MAX_CODE),
+            Err(Error::NoSys),
+            Err(Error::Fault),
+            Err(Error::Invalid),
+            Err(Error::NoMemory),
+        ] {
+            assert_eq!(decode(encode(result)), result);
+        }
+    }
+
+    #[test]
+    fn unknown_codes_are_invalid() {
+        assert_eq!(decode(-100_i64 as u64), Err(Error::Invalid));
+    }
+
+    #[test]
+    fn numbers_match_calls() {
+        for number in 0..Syscall::COUNT as u64 {
+            assert_eq!(Syscall::try_from(number).map(|call| call as u64), Ok(number));
+        }
+        assert_eq!(Syscall::try_from(Syscall::COUNT as u64), Err(Error::NoSys));
+    }
+}
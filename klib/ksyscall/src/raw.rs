@@ -0,0 +1,68 @@
+//! `syscall` instruction wrappers returning the raw value of `rax`.
+
+use core::arch::asm;
+
+use crate::Syscall;
+
+/// # Safety
+///
+/// The call must be valid without arguments
+#[inline]
+pub unsafe fn syscall0(call: Syscall) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        inlateout("rax") call as u64 => ret,
+        lateout("rcx") _, lateout("r11") _,
+        options(nostack),
+    );
+    ret
+}
+
+/// # Safety
+///
+/// Pointer arguments must be valid for the call
+#[inline]
+pub unsafe fn syscall1(call: Syscall, a0: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        inlateout("rax") call as u64 => ret,
+        in("rdi") a0,
+        lateout("rcx") _, lateout("r11") _,
+        options(nostack),
+    );
+    ret
+}
+
+/// # Safety
+///
+/// Pointer arguments must be valid for the call
+#[inline]
+pub unsafe fn syscall2(call: Syscall, a0: u64, a1: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        inlateout("rax") call as u64 => ret,
+        in("rdi") a0, in("rsi") a1,
+        lateout("rcx") _, lateout("r11") _,
+        options(nostack),
+    );
+    ret
+}
+
+/// # Safety
+///
+/// Pointer arguments must be valid for the call
+#[inline]
+pub unsafe fn syscall3(call: Syscall, a0: u64, a1: u64, a2: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        inlateout("rax") call as u64 => ret,
+        in("rdi") a0, in("rsi") a1, in("rdx") a2,
+        lateout("rcx") _, lateout("r11") _,
+        options(nostack),
+    );
+    ret
+}
@@ -0,0 +1,403 @@
+//! Read-only parser for ustar archives
+//!
+//! An [`Archive`] borrows the bytes of an archive, its [`entries`](Archive::entries) are read in
+//! place: each one is a 512 bytes header followed by the file contents padded to 512 bytes, and
+//! the archive ends with zeroed blocks. Both POSIX and GNU ustar headers are accepted, GNU long
+//! names and pax extended headers are returned as [`Kind::Other`] entries.
+#![no_std]
+#![allow(clippy::module_name_repetitions)]
+
+#[cfg(test)]
+#[macro_use]
+extern crate std;
+
+use core::{fmt, str};
+
+/// Size of a header and granularity of the file contents
+pub const BLOCK_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    /// A header doesn't have the ustar magic
+    Magic,
+    /// The checksum of a header doesn't match its bytes
+    Checksum,
+    /// A numeric field isn't an octal number
+    Number,
+    /// A path isn't valid UTF-8
+    Path,
+    /// The contents of an entry go past the end of the archive
+    Truncated,
+    /// No entry has the requested path
+    NotFound,
+}
+
+/// Type of an entry, from the `typeflag` field of its header.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Kind {
+    File,
+    HardLink,
+    Symlink,
+    Directory,
+    Other(u8),
+}
+
+impl From<u8> for Kind {
+    fn from(flag: u8) -> Self {
+        match flag {
+            b'0' | b'\0' | b'7' => Self::File,
+            b'1' => Self::HardLink,
+            b'2' => Self::Symlink,
+            b'5' => Self::Directory,
+            other => Self::Other(other),
+        }
+    }
+}
+
+/// Path of an entry, stored as the `prefix` and `name` fields of its header.
+///
+/// Paths are compared by components, so the leading `./` added by archivers and the trailing
+/// `/` of directories don't matter. The root directory has no components.
+#[derive(Debug, Clone, Copy)]
+pub struct Path<'a> {
+    prefix: &'a str,
+    name: &'a str,
+}
+
+impl<'a> Path<'a> {
+    pub fn components(&self) -> impl DoubleEndedIterator<Item = &'a str> + Clone {
+        components(self.prefix).chain(components(self.name))
+    }
+
+    /// Whether this is the path `other`.
+    #[must_use]
+    pub fn is(&self, other: &str) -> bool {
+        self.components().eq(components(other))
+    }
+
+    /// Whether this path is directly inside the directory `dir`.
+    #[must_use]
+    pub fn is_in(&self, dir: &str) -> bool {
+        let mut path = self.components();
+        path.next_back().is_some() && path.eq(components(dir))
+    }
+
+    /// Last component of the path, empty for the root directory.
+    #[must_use]
+    pub fn file_name(&self) -> &'a str {
+        self.components().next_back().unwrap_or("")
+    }
+}
+
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, component) in self.components().enumerate() {
+            if idx != 0 {
+                f.write_str("/")?;
+            }
+            f.write_str(component)?;
+        }
+        Ok(())
+    }
+}
+
+fn components(path: &str) -> impl DoubleEndedIterator<Item = &str> + Clone {
+    path.split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+}
+
+/// A file, directory or link of the archive.
+#[derive(Debug, Clone, Copy)]
+pub struct Entry<'a> {
+    pub path: Path<'a>,
+    pub kind: Kind,
+    /// Permission bits
+    pub mode: u32,
+    /// Modification time in seconds since the epoch
+    pub mtime: u64,
+    /// Target of a link
+    pub link: &'a str,
+    data: &'a [u8],
+}
+
+impl<'a> Entry<'a> {
+    /// Contents of the entry, empty for directories and links.
+    #[must_use]
+    pub const fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    #[must_use]
+    pub const fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    #[must_use]
+    pub fn is_dir(&self) -> bool {
+        self.kind == Kind::Directory
+    }
+}
+
+/// A ustar archive held in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Archive<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Archive<'a> {
+    #[must_use]
+    pub const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    #[must_use]
+    pub const fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Entries in the order of the archive, iteration stops after the first error.
+    #[must_use]
+    pub const fn entries(&self) -> Entries<'a> {
+        Entries {
+            bytes: self.bytes,
+            failed: false,
+        }
+    }
+
+    /// Find the entry at `path`, the last one wins if an archive has the path twice.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a header is invalid or no entry has this path
+    pub fn open(&self, path: &str) -> Result<Entry<'a>, Error> {
+        let mut found = Err(Error::NotFound);
+        for entry in self.entries() {
+            let entry = entry?;
+            if entry.path.is(path) {
+                found = Ok(entry);
+            }
+        }
+        found
+    }
+
+    /// Entries directly inside the directory `path`, the root directory is the empty path.
+    pub fn read_dir<'p>(&self, path: &'p str) -> impl Iterator<Item = Entry<'a>> + 'p
+    where
+        'a: 'p,
+    {
+        self.entries()
+            .filter_map(Result::ok)
+            .filter(move |entry| entry.path.is_in(path))
+    }
+}
+
+/// Iterator over the entries of an [`Archive`].
+#[derive(Debug, Clone)]
+pub struct Entries<'a> {
+    bytes: &'a [u8],
+    failed: bool,
+}
+
+impl<'a> Entries<'a> {
+    fn next_entry(&mut self) -> Result<Option<Entry<'a>>, Error> {
+        if self.bytes.len() < BLOCK_SIZE {
+            return Ok(None);
+        }
+        let (header, rest) = self.bytes.split_at(BLOCK_SIZE);
+        if header.iter().all(|b| *b == 0) {
+            return Ok(None);
+        }
+
+        if &header[257..262] != b"ustar" {
+            return Err(Error::Magic);
+        }
+        let checksum = header
+            .iter()
+            .enumerate()
+            .map(|(idx, b)| if (148..156).contains(&idx) { b' ' } else { *b })
+            .map(u64::from)
+            .sum::<u64>();
+        if checksum != octal(&header[148..156])? {
+            return Err(Error::Checksum);
+        }
+
+        let kind = Kind::from(header[156]);
+        let size = usize::try_from(octal(&header[124..136])?).map_err(|_| Error::Truncated)?;
+        if size > rest.len() {
+            return Err(Error::Truncated);
+        }
+        // the last block of the contents may be missing its padding
+        let padded = (size + padding(size)).min(rest.len());
+
+        let entry = Entry {
+            path: Path {
+                prefix: string(&header[345..500])?,
+                name: string(&header[0..100])?,
+            },
+            kind,
+            mode: u32::try_from(octal(&header[100..108])?).map_err(|_| Error::Number)?,
+            mtime: octal(&header[136..148])?,
+            link: string(&header[157..257])?,
+            data: &rest[..size],
+        };
+        self.bytes = &rest[padded..];
+        Ok(Some(entry))
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<Entry<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let next = self.next_entry().transpose();
+        self.failed = matches!(next, Some(Err(_)));
+        next
+    }
+}
+
+/// Bytes between the end of the contents and the next block.
+const fn padding(size: usize) -> usize {
+    (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE
+}
+
+/// Octal number padded with spaces and terminated by a space or a null byte.
+fn octal(field: &[u8]) -> Result<u64, Error> {
+    let digits = field
+        .iter()
+        .skip_while(|b| **b == b' ')
+        .take_while(|b| **b != b' ' && **b != 0);
+    let mut number: u64 = 0;
+    for digit in digits {
+        if !(b'0'..=b'7').contains(digit) {
+            return Err(Error::Number);
+        }
+        number = number.checked_mul(8).ok_or(Error::Number)? + u64::from(digit - b'0');
+    }
+    Ok(number)
+}
+
+/// String field terminated by a null byte unless it fills the field.
+fn string(field: &[u8]) -> Result<&str, Error> {
+    let len = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    str::from_utf8(&field[..len]).map_err(|_| Error::Path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        string::{String, ToString},
+        vec::Vec,
+    };
+
+    use super::*;
+
+    fn header(archive: &mut Vec<u8>, prefix: &str, name: &str, flag: u8, data: &[u8]) {
+        let mut header = [0; BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+        header[136..148].copy_from_slice(b"14000000000\0");
+        header[156] = flag;
+        header[257..265].copy_from_slice(b"ustar\x0000");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|b| u32::from(*b)).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+        archive.extend_from_slice(&header);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len() + padding(archive.len()), 0);
+    }
+
+    fn archive() -> Vec<u8> {
+        let mut archive = Vec::new();
+        header(&mut archive, "", "./", b'5', &[]);
+        header(&mut archive, "", "./bin/", b'5', &[]);
+        header(&mut archive, "", "./bin/hello", b'0', b"\x7fELF");
+        header(&mut archive, "", "./motd", b'0', &[b'x'; 600]);
+        header(
+            &mut archive,
+            "./etc",
+            "init.conf",
+            b'0',
+            b"console=serial\n",
+        );
+        archive.resize(archive.len() + 2 * BLOCK_SIZE, 0);
+        archive
+    }
+
+    #[test]
+    fn list_entries() {
+        let bytes = archive();
+        let entries: Vec<_> = Archive::new(&bytes)
+            .entries()
+            .map(Result::unwrap)
+            .map(|entry| (entry.path.to_string(), entry.kind, entry.size()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (String::new(), Kind::Directory, 0),
+                ("bin".to_string(), Kind::Directory, 0),
+                ("bin/hello".to_string(), Kind::File, 4),
+                ("motd".to_string(), Kind::File, 600),
+                ("etc/init.conf".to_string(), Kind::File, 15),
+            ]
+        );
+    }
+
+    #[test]
+    fn open_files() {
+        let bytes = archive();
+        let archive = Archive::new(&bytes);
+        assert_eq!(archive.open("/bin/hello").unwrap().data(), b"\x7fELF");
+        assert_eq!(archive.open("motd").unwrap().data(), &[b'x'; 600]);
+        let conf = archive.open("/etc/init.conf").unwrap();
+        assert_eq!(conf.data(), b"console=serial\n");
+        assert_eq!(conf.mode, 0o644);
+        assert_eq!(conf.path.file_name(), "init.conf");
+        assert!(archive.open("/bin").unwrap().is_dir());
+        assert_eq!(archive.open("/hello").unwrap_err(), Error::NotFound);
+    }
+
+    #[test]
+    fn read_directories() {
+        let bytes = archive();
+        let archive = Archive::new(&bytes);
+        let names = |dir| {
+            archive
+                .read_dir(dir)
+                .map(|entry| entry.path.file_name())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("/"), ["bin", "motd"]);
+        assert_eq!(names("/bin"), ["hello"]);
+        assert_eq!(names("etc/"), ["init.conf"]);
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let mut bytes = archive();
+        bytes[BLOCK_SIZE + 1] ^= 1;
+        let entries: Vec<_> = Archive::new(&bytes).entries().collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].unwrap_err(), Error::Checksum);
+
+        let mut bytes = archive();
+        bytes[257] = b'x';
+        assert_eq!(Archive::new(&bytes).open("motd").unwrap_err(), Error::Magic);
+
+        let bytes = archive();
+        let truncated = Archive::new(&bytes[..5 * BLOCK_SIZE + 100]);
+        assert_eq!(truncated.open("motd").unwrap_err(), Error::Truncated);
+    }
+
+    #[test]
+    fn empty_archive() {
+        assert_eq!(Archive::new(&[]).entries().count(), 0);
+        assert_eq!(Archive::new(&[0; 2 * BLOCK_SIZE]).entries().count(), 0);
+    }
+}
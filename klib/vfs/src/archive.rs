@@ -0,0 +1,248 @@
+//! Read-only filesystem backed by a ustar archive
+//!
+//! Files are read in place from the archive. Directories without an entry of their own, like
+//! the parents of paths stored with a prefix, exist as long as one of their files does.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use ktar::{Archive, Entry};
+
+use crate::{DirEntry, Error, Filesystem, Kind, Result, Stat, Vnode};
+
+/// Permission bits of the implicit directories
+const DIR_MODE: u32 = 0o555;
+
+pub struct ArchiveFs {
+    archive: Archive<'static>,
+}
+
+impl ArchiveFs {
+    /// Check the headers of `archive` so reading its entries can't fail later.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a header of the archive is invalid
+    pub fn new(archive: Archive<'static>) -> Result<Self> {
+        for entry in archive.entries() {
+            entry?;
+        }
+        Ok(Self { archive })
+    }
+}
+
+impl Filesystem for ArchiveFs {
+    fn root(&self) -> Arc<dyn Vnode> {
+        Arc::new(Node {
+            archive: self.archive,
+            path: Vec::new(),
+            entry: None,
+        })
+    }
+}
+
+fn kind(kind: ktar::Kind) -> Option<Kind> {
+    match kind {
+        ktar::Kind::File | ktar::Kind::HardLink => Some(Kind::File),
+        ktar::Kind::Directory => Some(Kind::Directory),
+        ktar::Kind::Symlink => Some(Kind::Symlink),
+        ktar::Kind::Other(_) => None,
+    }
+}
+
+/// An entry of the archive, or an implicit directory without one.
+struct Node {
+    archive: Archive<'static>,
+    path: Vec<String>,
+    entry: Option<Entry<'static>>,
+}
+
+impl Node {
+    fn entries(&self) -> impl Iterator<Item = Entry<'static>> {
+        // the headers were checked by `ArchiveFs::new`
+        self.archive.entries().filter_map(core::result::Result::ok)
+    }
+
+    /// Name of the child of this directory on the way to `entry`, and whether the child is
+    /// the entry itself.
+    fn child<'e>(&self, entry: &Entry<'e>) -> Option<(&'e str, bool)> {
+        let mut components = entry.path.components();
+        for component in &self.path {
+            if components.next()? != component {
+                return None;
+            }
+        }
+        let name = components.next()?;
+        Some((name, components.next().is_none()))
+    }
+
+    fn is_dir(&self) -> bool {
+        !matches!(self.entry, Some(entry) if entry.kind != ktar::Kind::Directory)
+    }
+}
+
+impl Vnode for Node {
+    fn stat(&self) -> Stat {
+        match self.entry {
+            Some(entry) => Stat {
+                kind: kind(entry.kind).unwrap_or(Kind::File),
+                size: entry.size() as u64,
+                mode: entry.mode,
+            },
+            None => Stat {
+                kind: Kind::Directory,
+                size: 0,
+                mode: DIR_MODE,
+            },
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Vnode>> {
+        if !self.is_dir() {
+            return Err(Error::NotDirectory);
+        }
+        let mut found = None;
+        for entry in self.entries() {
+            match self.child(&entry) {
+                // the last entry with a path wins
+                Some((child, true)) if child == name && kind(entry.kind).is_some() => {
+                    found = Some(Some(entry));
+                }
+                Some((child, false)) if child == name && found.is_none() => found = Some(None),
+                _ => {}
+            }
+        }
+
+        let mut entry = found.ok_or(Error::NotFound)?;
+        if let Some(link) = entry.filter(|entry| entry.kind == ktar::Kind::HardLink) {
+            entry = Some(self.archive.open(link.link).map_err(|_| Error::NotFound)?);
+        }
+        let mut path = self.path.clone();
+        path.push(name.into());
+        Ok(Arc::new(Node {
+            archive: self.archive,
+            path,
+            entry,
+        }))
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let data = match self.entry {
+            Some(entry) if !self.is_dir() => entry.data(),
+            _ => return Err(Error::IsDirectory),
+        };
+        let start = usize::try_from(offset).map_or(data.len(), |o| o.min(data.len()));
+        let n = buffer.len().min(data.len() - start);
+        buffer[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn readdir(&self) -> Result<Vec<DirEntry>> {
+        if !self.is_dir() {
+            return Err(Error::NotDirectory);
+        }
+        let mut entries: Vec<DirEntry> = Vec::new();
+        for entry in self.entries() {
+            let (name, kind) = match self.child(&entry) {
+                Some((name, true)) => match kind(entry.kind) {
+                    Some(kind) => (name, kind),
+                    None => continue,
+                },
+                Some((name, false)) => (name, Kind::Directory),
+                None => continue,
+            };
+            match entries.iter_mut().find(|entry| entry.name == name) {
+                Some(existing) => existing.kind = kind,
+                None => entries.push(DirEntry {
+                    name: name.into(),
+                    kind,
+                }),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{boxed::Box, vec::Vec};
+
+    use super::*;
+
+    fn header(archive: &mut Vec<u8>, prefix: &str, name: &str, flag: u8, data: &[u8]) {
+        let mut header = [0; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+        header[156] = flag;
+        header[257..265].copy_from_slice(b"ustar\x0000");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|b| u32::from(*b)).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+        archive.extend_from_slice(&header);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len() + (512 - data.len() % 512) % 512, 0);
+    }
+
+    fn fs() -> ArchiveFs {
+        let mut archive = Vec::new();
+        header(&mut archive, "", "./", b'5', &[]);
+        header(&mut archive, "", "./bin/", b'5', &[]);
+        header(&mut archive, "", "./bin/hello", b'0', b"\x7fELF");
+        header(&mut archive, "./etc", "motd", b'0', b"welcome\n");
+        archive.resize(archive.len() + 1024, 0);
+        ArchiveFs::new(Archive::new(Box::leak(archive.into_boxed_slice()))).unwrap()
+    }
+
+    #[test]
+    fn read_files() {
+        let root = fs().root();
+        let motd = root.lookup("etc").unwrap().lookup("motd").unwrap();
+        assert_eq!(motd.stat().size, 8);
+        assert_eq!(motd.stat().mode, 0o644);
+
+        let mut buffer = [0; 16];
+        assert_eq!(motd.read_at(3, &mut buffer).unwrap(), 5);
+        assert_eq!(&buffer[..5], b"come\n");
+        assert!(matches!(motd.write_at(0, b"x"), Err(Error::ReadOnly)));
+        assert!(matches!(root.lookup("motd"), Err(Error::NotFound)));
+    }
+
+    #[test]
+    fn implicit_directories() {
+        let root = fs().root();
+        let names = |node: Arc<dyn Vnode>| {
+            node.readdir()
+                .unwrap()
+                .into_iter()
+                .map(|entry| (entry.name, entry.kind))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(root.clone()),
+            [
+                ("bin".into(), Kind::Directory),
+                ("etc".into(), Kind::Directory)
+            ]
+        );
+        let etc = root.lookup("etc").unwrap();
+        assert_eq!(etc.stat().kind, Kind::Directory);
+        assert_eq!(names(etc), [("motd".into(), Kind::File)]);
+        assert_eq!(
+            names(root.lookup("bin").unwrap()),
+            [("hello".into(), Kind::File)]
+        );
+    }
+
+    #[test]
+    fn invalid_archive() {
+        let mut archive = vec![0; 1024];
+        archive[0] = b'x';
+        let archive = Archive::new(Box::leak(archive.into_boxed_slice()));
+        assert!(matches!(
+            ArchiveFs::new(archive),
+            Err(Error::Archive(ktar::Error::Magic))
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+//! Open files and descriptor tables
+
+use alloc::{sync::Arc, vec::Vec};
+
+use bitflags::bitflags;
+
+use crate::{DirEntry, Error, Kind, Result, Stat, Vnode};
+
+bitflags! {
+    /// How a file is opened by [`Vfs::open`](crate::Vfs::open)
+    pub struct OpenFlags: u32 {
+        const READ = 1;
+        const WRITE = 1 << 1;
+        /// Create the file if it doesn't exist
+        const CREATE = 1 << 2;
+        /// Empty the file when it is opened for writing
+        const TRUNCATE = 1 << 3;
+        /// Every write goes to the end of the file, implies [`OpenFlags::WRITE`]
+        const APPEND = 1 << 4;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+/// A vnode opened with an offset.
+pub struct File {
+    node: Arc<dyn Vnode>,
+    offset: u64,
+    flags: OpenFlags,
+}
+
+impl File {
+    #[must_use]
+    pub fn new(node: Arc<dyn Vnode>, flags: OpenFlags) -> Self {
+        Self {
+            node,
+            offset: 0,
+            flags,
+        }
+    }
+
+    #[must_use]
+    pub const fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    #[must_use]
+    pub const fn flags(&self) -> OpenFlags {
+        self.flags
+    }
+
+    #[must_use]
+    pub fn stat(&self) -> Stat {
+        self.node.stat()
+    }
+
+    /// Read from the offset and move it past the bytes read.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the file isn't opened for reading or is a directory
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        if !self.flags.contains(OpenFlags::READ) {
+            return Err(Error::Access);
+        }
+        let n = self.node.read_at(self.offset, buffer)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    /// Write at the offset, or at the end of the file in append mode, and move the offset
+    /// past the bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the file isn't opened for writing or can't be written
+    pub fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        if !self.flags.contains(OpenFlags::WRITE) {
+            return Err(Error::Access);
+        }
+        if self.flags.contains(OpenFlags::APPEND) {
+            self.offset = self.node.stat().size;
+        }
+        let n = self.node.write_at(self.offset, buffer)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    /// Move the offset, it can go past the end of the file.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the offset would be negative
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::Current(delta) => (self.offset, delta),
+            SeekFrom::End(delta) => (self.node.stat().size, delta),
+        };
+        let offset = if delta < 0 {
+            base.checked_sub(delta.unsigned_abs())
+        } else {
+            base.checked_add(delta.unsigned_abs())
+        };
+        self.offset = offset.ok_or(Error::InvalidOffset)?;
+        Ok(self.offset)
+    }
+
+    /// Entries of an opened directory.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the file isn't a directory
+    pub fn readdir(&self) -> Result<Vec<DirEntry>> {
+        if self.node.stat().kind != Kind::Directory {
+            return Err(Error::NotDirectory);
+        }
+        self.node.readdir()
+    }
+}
+
+impl kio::write::Write for File {
+    fn write(&mut self, buffer: &[u8]) -> kio::Result<usize> {
+        Ok(File::write(self, buffer)?)
+    }
+}
+
+/// Number of an open file in a [`Descriptors`] table
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Fd(pub usize);
+
+/// Open files of a process, a descriptor is the lowest free slot.
+#[derive(Default)]
+pub struct Descriptors {
+    files: Vec<Option<File>>,
+}
+
+impl Descriptors {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { files: Vec::new() }
+    }
+
+    pub fn insert(&mut self, file: File) -> Fd {
+        if let Some(idx) = self.files.iter().position(Option::is_none) {
+            self.files[idx] = Some(file);
+            Fd(idx)
+        } else {
+            self.files.push(Some(file));
+            Fd(self.files.len() - 1)
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Errors if no file is opened with `fd`
+    pub fn get(&mut self, fd: Fd) -> Result<&mut File> {
+        self.files
+            .get_mut(fd.0)
+            .and_then(Option::as_mut)
+            .ok_or(Error::BadDescriptor)
+    }
+
+    /// Remove the file opened with `fd`, the descriptor can then be reused.
+    ///
+    /// # Errors
+    ///
+    /// Errors if no file is opened with `fd`
+    pub fn close(&mut self, fd: Fd) -> Result<File> {
+        let file = self
+            .files
+            .get_mut(fd.0)
+            .and_then(Option::take)
+            .ok_or(Error::BadDescriptor)?;
+        while let Some(None) = self.files.last() {
+            self.files.pop();
+        }
+        Ok(file)
+    }
+
+    /// Open files and their descriptors.
+    pub fn iter(&self) -> impl Iterator<Item = (Fd, &File)> {
+        self.files
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, file)| Some((Fd(idx), file.as_ref()?)))
+    }
+}
@@ -0,0 +1,172 @@
+//! Virtual filesystem
+//!
+//! Filesystems expose their files as [`Vnode`]s, the [`Vfs`] mounts them on directories and
+//! resolves absolute paths across the mount points. An opened [`File`] keeps its offset and
+//! is referred to by a [`Fd`] in a [`Descriptors`] table.
+//!
+//! Two backends are provided: [`TmpFs`], a writable filesystem kept in memory, and
+//! [`ArchiveFs`], a read-only view of a ustar archive such as the initrd.
+#![no_std]
+#![allow(clippy::module_name_repetitions)]
+
+#[cfg(test)]
+#[macro_use]
+extern crate std;
+
+extern crate alloc;
+
+pub mod archive;
+pub mod file;
+mod mount;
+pub mod path;
+pub mod tmpfs;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+pub use archive::ArchiveFs;
+pub use file::{Descriptors, Fd, File, OpenFlags, SeekFrom};
+pub use mount::Vfs;
+pub use tmpfs::TmpFs;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Error {
+    /// No file has this path
+    NotFound,
+    /// A file already has this path
+    Exists,
+    /// A component of the path is a file
+    NotDirectory,
+    /// The operation only applies to files
+    IsDirectory,
+    /// The filesystem can't be modified
+    ReadOnly,
+    /// The filesystem doesn't have this kind of file
+    Unsupported,
+    /// The file wasn't opened for this operation
+    Access,
+    /// Paths must be absolute and components can't contain null bytes
+    InvalidPath,
+    /// The offset is before the start of the file
+    InvalidOffset,
+    /// No file is opened with this descriptor
+    BadDescriptor,
+    /// The directory is a mount point
+    Busy,
+    /// The archive of an [`ArchiveFs`] is invalid
+    Archive(ktar::Error),
+}
+
+impl From<ktar::Error> for Error {
+    fn from(err: ktar::Error) -> Self {
+        Self::Archive(err)
+    }
+}
+
+impl From<Error> for kio::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::NotFound => kio::ErrorKind::NotFound.into(),
+            Error::Access | Error::ReadOnly => kio::ErrorKind::PermissionDenied.into(),
+            Error::Archive(_) => kio::ErrorKind::InvalidData.into(),
+            _ => kio::ErrorKind::Unsupported.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Kind {
+    File,
+    Directory,
+    Symlink,
+}
+
+/// Attributes of a vnode.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Stat {
+    pub kind: Kind,
+    /// Size in bytes, zero for directories
+    pub size: u64,
+    /// Permission bits
+    pub mode: u32,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: Kind,
+}
+
+/// A file or directory of a filesystem.
+///
+/// Operations that don't apply to the vnode keep the default implementation, which reports
+/// why they failed. Vnodes are shared between the open files so they are modified through
+/// `&self`.
+pub trait Vnode: Send + Sync {
+    fn stat(&self) -> Stat;
+
+    /// Find the entry `name` of a directory.
+    ///
+    /// # Errors
+    ///
+    /// Errors if this isn't a directory or it has no entry `name`
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Vnode>> {
+        let _ = name;
+        Err(Error::NotDirectory)
+    }
+
+    /// Read the bytes at `offset` into `buffer`, returns the number of bytes read which is zero
+    /// at the end of the file.
+    ///
+    /// # Errors
+    ///
+    /// Errors if this isn't a file
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let _ = (offset, buffer);
+        Err(Error::IsDirectory)
+    }
+
+    /// Write `buffer` at `offset`, the file grows if needed.
+    ///
+    /// # Errors
+    ///
+    /// Errors if this isn't a file or the filesystem is read-only
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> Result<usize> {
+        let _ = (offset, buffer);
+        Err(Error::ReadOnly)
+    }
+
+    /// Resize a file, new bytes are zeroes.
+    ///
+    /// # Errors
+    ///
+    /// Errors if this isn't a file or the filesystem is read-only
+    fn truncate(&self, size: u64) -> Result<()> {
+        let _ = size;
+        Err(Error::ReadOnly)
+    }
+
+    /// Entries of a directory, without `.` and `..`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if this isn't a directory
+    fn readdir(&self) -> Result<Vec<DirEntry>> {
+        Err(Error::NotDirectory)
+    }
+
+    /// Add the entry `name` to a directory.
+    ///
+    /// # Errors
+    ///
+    /// Errors if this isn't a directory, the entry exists or the filesystem is read-only
+    fn create(&self, name: &str, kind: Kind) -> Result<Arc<dyn Vnode>> {
+        let _ = (name, kind);
+        Err(Error::ReadOnly)
+    }
+}
+
+pub trait Filesystem: Send + Sync {
+    fn root(&self) -> Arc<dyn Vnode>;
+}
@@ -0,0 +1,293 @@
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use crate::{path, DirEntry, Error, File, Filesystem, Kind, OpenFlags, Result, Stat, Vnode};
+
+struct Mount {
+    components: Vec<String>,
+    fs: Arc<dyn Filesystem>,
+}
+
+/// Mount table and path resolver.
+///
+/// A path is resolved from the root of the filesystem mounted on its longest prefix, the
+/// directories hidden by a mount point can't be reached.
+#[derive(Default)]
+pub struct Vfs {
+    mounts: Vec<Mount>,
+}
+
+impl Vfs {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { mounts: Vec::new() }
+    }
+
+    /// Mount `fs` on the directory `path`, the first filesystem must be mounted on `/`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `path` isn't a directory or is already a mount point
+    pub fn mount(&mut self, path: &str, fs: Arc<dyn Filesystem>) -> Result<()> {
+        let components = path::components(path)?;
+        if self
+            .mounts
+            .iter()
+            .any(|mount| mount.components == components)
+        {
+            return Err(Error::Busy);
+        }
+        if !components.is_empty() && self.resolve(&components)?.stat().kind != Kind::Directory {
+            return Err(Error::NotDirectory);
+        }
+        self.mounts.push(Mount {
+            components: components.iter().map(ToString::to_string).collect(),
+            fs,
+        });
+        Ok(())
+    }
+
+    /// Remove the filesystem mounted on `path`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `path` isn't a mount point or other filesystems are mounted under it
+    pub fn unmount(&mut self, path: &str) -> Result<Arc<dyn Filesystem>> {
+        let components = path::components(path)?;
+        let idx = self
+            .mounts
+            .iter()
+            .position(|mount| mount.components == components)
+            .ok_or(Error::NotFound)?;
+        let nested = self.mounts.iter().any(|mount| {
+            mount.components.len() > components.len()
+                && mount.components.starts_with(&self.mounts[idx].components)
+        });
+        if nested {
+            return Err(Error::Busy);
+        }
+        Ok(self.mounts.remove(idx).fs)
+    }
+
+    fn resolve(&self, components: &[&str]) -> Result<Arc<dyn Vnode>> {
+        let mount = self
+            .mounts
+            .iter()
+            .filter(|mount| {
+                mount.components.len() <= components.len()
+                    && mount.components.iter().zip(components).all(|(a, b)| a == b)
+            })
+            .max_by_key(|mount| mount.components.len())
+            .ok_or(Error::NotFound)?;
+
+        let mut node = mount.fs.root();
+        for component in &components[mount.components.len()..] {
+            node = node.lookup(component)?;
+        }
+        Ok(node)
+    }
+
+    /// Find the vnode at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the path is invalid or doesn't exist
+    pub fn lookup(&self, path: &str) -> Result<Arc<dyn Vnode>> {
+        self.resolve(&path::components(path)?)
+    }
+
+    /// # Errors
+    ///
+    /// Errors if the path is invalid or doesn't exist
+    pub fn stat(&self, path: &str) -> Result<Stat> {
+        Ok(self.lookup(path)?.stat())
+    }
+
+    /// # Errors
+    ///
+    /// Errors if the path is invalid or isn't a directory
+    pub fn readdir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        self.lookup(path)?.readdir()
+    }
+
+    /// Create a file or directory at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the parent directory doesn't exist, the path exists or its filesystem is
+    /// read-only
+    pub fn create(&self, path: &str, kind: Kind) -> Result<Arc<dyn Vnode>> {
+        let (parent, name) = path::split_last(path)?;
+        if self.is_mount_point(&parent, name) {
+            return Err(Error::Exists);
+        }
+        self.resolve(&parent)?.create(name, kind)
+    }
+
+    fn is_mount_point(&self, parent: &[&str], name: &str) -> bool {
+        self.mounts.iter().any(|mount| {
+            mount.components.len() == parent.len() + 1
+                && mount.components.iter().zip(parent).all(|(a, b)| a == b)
+                && mount.components[parent.len()] == name
+        })
+    }
+
+    /// Open the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the path doesn't exist and isn't created, or a directory is opened for
+    /// writing
+    pub fn open(&self, path: &str, mut flags: OpenFlags) -> Result<File> {
+        if flags.contains(OpenFlags::APPEND) {
+            flags |= OpenFlags::WRITE;
+        }
+        let node = match self.lookup(path) {
+            Err(Error::NotFound) if flags.contains(OpenFlags::CREATE) => {
+                self.create(path, Kind::File)?
+            }
+            node => node?,
+        };
+        let writable = flags.contains(OpenFlags::WRITE);
+        if writable && node.stat().kind == Kind::Directory {
+            return Err(Error::IsDirectory);
+        }
+        if writable && flags.contains(OpenFlags::TRUNCATE) {
+            node.truncate(0)?;
+        }
+        Ok(File::new(node, flags))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::boxed::Box;
+
+    use kio::write::Write;
+    use ktar::Archive;
+
+    use super::*;
+    use crate::{ArchiveFs, Descriptors, Fd, SeekFrom, TmpFs};
+
+    /// Archive with the single file `hello`
+    fn archive() -> ArchiveFs {
+        let mut bytes = vec![0u8; 3 * 512];
+        bytes[..5].copy_from_slice(b"hello");
+        bytes[100..108].copy_from_slice(b"0000444\0");
+        bytes[124..136].copy_from_slice(b"00000000003\0");
+        bytes[156] = b'0';
+        bytes[257..265].copy_from_slice(b"ustar\x0000");
+        bytes[148..156].fill(b' ');
+        let checksum: u32 = bytes[..512].iter().map(|b| u32::from(*b)).sum();
+        bytes[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+        bytes[512..515].copy_from_slice(b"hi\n");
+        ArchiveFs::new(Archive::new(Box::leak(bytes.into_boxed_slice()))).unwrap()
+    }
+
+    fn vfs() -> Vfs {
+        let mut vfs = Vfs::new();
+        vfs.mount("/", Arc::new(TmpFs::new())).unwrap();
+        vfs.create("/initrd", Kind::Directory).unwrap();
+        vfs.mount("/initrd", Arc::new(archive())).unwrap();
+        vfs
+    }
+
+    #[test]
+    fn resolve_across_mounts() {
+        let vfs = vfs();
+        assert_eq!(vfs.stat("/initrd/hello").unwrap().size, 3);
+        assert_eq!(vfs.stat("/tmp/../initrd/./hello").unwrap().mode, 0o444);
+        assert_eq!(vfs.lookup("/initrd/nope").err(), Some(Error::NotFound));
+        assert_eq!(vfs.lookup("initrd").err(), Some(Error::InvalidPath));
+        assert_eq!(
+            vfs.create("/initrd/new", Kind::File).err(),
+            Some(Error::ReadOnly)
+        );
+        assert_eq!(vfs.create("/initrd", Kind::File).err(), Some(Error::Exists));
+    }
+
+    #[test]
+    fn mount_points() {
+        let mut vfs = vfs();
+        assert_eq!(
+            vfs.mount("/initrd", Arc::new(TmpFs::new())).err(),
+            Some(Error::Busy)
+        );
+        assert_eq!(
+            vfs.mount("/missing", Arc::new(TmpFs::new())).err(),
+            Some(Error::NotFound)
+        );
+        vfs.create("/file", Kind::File).unwrap();
+        assert_eq!(
+            vfs.mount("/file", Arc::new(TmpFs::new())).err(),
+            Some(Error::NotDirectory)
+        );
+        assert_eq!(vfs.unmount("/").err(), Some(Error::Busy));
+        vfs.unmount("/initrd").unwrap();
+        assert!(vfs.readdir("/initrd").unwrap().is_empty());
+    }
+
+    #[test]
+    fn open_files() {
+        let vfs = vfs();
+        let mut fds = Descriptors::new();
+
+        let log = fds.insert(
+            vfs.open("/log", OpenFlags::WRITE | OpenFlags::CREATE)
+                .unwrap(),
+        );
+        let file = fds.get(log).unwrap();
+        file.write_all(b"first ").unwrap();
+        file.write_all(b"line").unwrap();
+        assert_eq!(file.offset(), 10);
+        assert!(matches!(file.read(&mut [0; 4]), Err(Error::Access)));
+
+        let mut file = vfs.open("/log", OpenFlags::READ).unwrap();
+        assert_eq!(file.seek(SeekFrom::End(-4)).unwrap(), 6);
+        let mut buffer = [0; 8];
+        assert_eq!(file.read(&mut buffer).unwrap(), 4);
+        assert_eq!(&buffer[..4], b"line");
+        assert_eq!(file.seek(SeekFrom::Current(-20)), Err(Error::InvalidOffset));
+
+        let mut append = vfs
+            .open("/log", OpenFlags::WRITE | OpenFlags::APPEND)
+            .unwrap();
+        append.write(b"!").unwrap();
+        assert_eq!(vfs.stat("/log").unwrap().size, 11);
+
+        // append alone is enough to write
+        let mut append = vfs.open("/log", OpenFlags::APPEND).unwrap();
+        assert!(append.flags().contains(OpenFlags::WRITE));
+        append.write(b"?").unwrap();
+        assert_eq!(vfs.stat("/log").unwrap().size, 12);
+
+        vfs.open("/log", OpenFlags::WRITE | OpenFlags::TRUNCATE)
+            .unwrap();
+        assert_eq!(vfs.stat("/log").unwrap().size, 0);
+        assert_eq!(
+            vfs.open("/initrd", OpenFlags::WRITE).err(),
+            Some(Error::IsDirectory)
+        );
+
+        let hello = fds.insert(vfs.open("/initrd/hello", OpenFlags::READ).unwrap());
+        assert_eq!(hello, Fd(1));
+        fds.close(log).unwrap();
+        assert_eq!(fds.get(log).err(), Some(Error::BadDescriptor));
+        assert_eq!(fds.insert(file), log);
+        assert_eq!(fds.iter().count(), 2);
+    }
+
+    #[test]
+    fn io_errors() {
+        let vfs = vfs();
+        let err = kio::Error::from(vfs.open("/missing", OpenFlags::READ).err().unwrap());
+        assert!(matches!(err.kind(), kio::ErrorKind::NotFound));
+
+        let mut hello = vfs.open("/initrd/hello", OpenFlags::READ).unwrap();
+        let err = hello.write_all(b"x").unwrap_err();
+        assert!(matches!(err.kind(), kio::ErrorKind::PermissionDenied));
+    }
+}
@@ -0,0 +1,63 @@
+//! Absolute paths
+//!
+//! Paths are split on `/` and resolved lexically: empty components and `.` are skipped and
+//! `..` removes the previous component, it stays at the root.
+
+use alloc::vec::Vec;
+
+use crate::{Error, Result};
+
+/// Components of the absolute path `path`.
+///
+/// # Errors
+///
+/// Errors if the path is relative or contains a null byte
+pub fn components(path: &str) -> Result<Vec<&str>> {
+    if !path.starts_with('/') || path.contains('\0') {
+        return Err(Error::InvalidPath);
+    }
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    Ok(components)
+}
+
+/// Components of the parent directory and name of the last component of `path`.
+///
+/// # Errors
+///
+/// Errors if the path is invalid or is the root directory
+pub fn split_last(path: &str) -> Result<(Vec<&str>, &str)> {
+    let mut components = components(path)?;
+    let name = components.pop().ok_or(Error::Exists)?;
+    Ok((components, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_components() {
+        assert_eq!(components("/").unwrap(), [] as [&str; 0]);
+        assert_eq!(components("/bin//hello/").unwrap(), ["bin", "hello"]);
+        assert_eq!(components("/bin/./../etc/init").unwrap(), ["etc", "init"]);
+        assert_eq!(components("/../..").unwrap(), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn invalid_paths() {
+        assert_eq!(components("bin/hello"), Err(Error::InvalidPath));
+        assert_eq!(components(""), Err(Error::InvalidPath));
+        assert_eq!(components("/bin\0"), Err(Error::InvalidPath));
+        assert_eq!(split_last("/.."), Err(Error::Exists));
+        assert_eq!(split_last("/etc/motd").unwrap(), (vec!["etc"], "motd"));
+    }
+}
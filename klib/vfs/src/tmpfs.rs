@@ -0,0 +1,208 @@
+//! Filesystem kept in memory
+//!
+//! Files are byte vectors and directories are ordered maps, everything is lost when the
+//! filesystem is dropped.
+
+use alloc::{borrow::ToOwned, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+use kcore::sync::SpinMutex;
+
+use crate::{DirEntry, Error, Filesystem, Kind, Result, Stat, Vnode};
+
+/// Permission bits of new files and directories
+const FILE_MODE: u32 = 0o644;
+const DIR_MODE: u32 = 0o755;
+
+pub struct TmpFs {
+    root: Arc<Node>,
+}
+
+impl TmpFs {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            root: Arc::new(Node {
+                content: SpinMutex::new(Content::Directory(BTreeMap::new())),
+            }),
+        }
+    }
+}
+
+impl Default for TmpFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Filesystem for TmpFs {
+    fn root(&self) -> Arc<dyn Vnode> {
+        self.root.clone()
+    }
+}
+
+enum Content {
+    File(Vec<u8>),
+    Directory(BTreeMap<String, Arc<Node>>),
+}
+
+struct Node {
+    content: SpinMutex<Content>,
+}
+
+impl Node {
+    fn new(kind: Kind) -> Result<Arc<Self>> {
+        let content = match kind {
+            Kind::Directory => Content::Directory(BTreeMap::new()),
+            Kind::File => Content::File(Vec::new()),
+            Kind::Symlink => return Err(Error::Unsupported),
+        };
+        Ok(Arc::new(Self {
+            content: SpinMutex::new(content),
+        }))
+    }
+}
+
+impl Vnode for Node {
+    fn stat(&self) -> Stat {
+        match &*self.content.lock() {
+            Content::File(bytes) => Stat {
+                kind: Kind::File,
+                size: bytes.len() as u64,
+                mode: FILE_MODE,
+            },
+            Content::Directory(_) => Stat {
+                kind: Kind::Directory,
+                size: 0,
+                mode: DIR_MODE,
+            },
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Vnode>> {
+        match &*self.content.lock() {
+            Content::Directory(entries) => {
+                let node = entries.get(name).ok_or(Error::NotFound)?;
+                Ok(node.clone())
+            }
+            Content::File(_) => Err(Error::NotDirectory),
+        }
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        match &*self.content.lock() {
+            Content::File(bytes) => {
+                let start = usize::try_from(offset).map_or(bytes.len(), |o| o.min(bytes.len()));
+                let n = buffer.len().min(bytes.len() - start);
+                buffer[..n].copy_from_slice(&bytes[start..start + n]);
+                Ok(n)
+            }
+            Content::Directory(_) => Err(Error::IsDirectory),
+        }
+    }
+
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> Result<usize> {
+        match &mut *self.content.lock() {
+            Content::File(bytes) => {
+                let start = usize::try_from(offset).map_err(|_| Error::InvalidOffset)?;
+                let end = start
+                    .checked_add(buffer.len())
+                    .ok_or(Error::InvalidOffset)?;
+                if bytes.len() < end {
+                    bytes.resize(end, 0);
+                }
+                bytes[start..end].copy_from_slice(buffer);
+                Ok(buffer.len())
+            }
+            Content::Directory(_) => Err(Error::IsDirectory),
+        }
+    }
+
+    fn truncate(&self, size: u64) -> Result<()> {
+        match &mut *self.content.lock() {
+            Content::File(bytes) => {
+                bytes.resize(usize::try_from(size).map_err(|_| Error::InvalidOffset)?, 0);
+                Ok(())
+            }
+            Content::Directory(_) => Err(Error::IsDirectory),
+        }
+    }
+
+    fn readdir(&self) -> Result<Vec<DirEntry>> {
+        match &*self.content.lock() {
+            Content::Directory(entries) => Ok(entries
+                .iter()
+                .map(|(name, node)| DirEntry {
+                    name: name.clone(),
+                    kind: node.stat().kind,
+                })
+                .collect()),
+            Content::File(_) => Err(Error::NotDirectory),
+        }
+    }
+
+    fn create(&self, name: &str, kind: Kind) -> Result<Arc<dyn Vnode>> {
+        match &mut *self.content.lock() {
+            Content::Directory(entries) => {
+                if entries.contains_key(name) {
+                    return Err(Error::Exists);
+                }
+                let node = Node::new(kind)?;
+                entries.insert(name.to_owned(), node.clone());
+                Ok(node)
+            }
+            Content::File(_) => Err(Error::NotDirectory),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read_back() {
+        let fs = TmpFs::new();
+        let file = fs.root().create("motd", Kind::File).unwrap();
+        assert_eq!(file.write_at(0, b"hello").unwrap(), 5);
+        assert_eq!(file.write_at(8, b"world").unwrap(), 5);
+        assert_eq!(file.stat().size, 13);
+
+        let mut buffer = [0xff; 16];
+        assert_eq!(file.read_at(0, &mut buffer).unwrap(), 13);
+        assert_eq!(&buffer[..13], b"hello\0\0\0world");
+        assert_eq!(file.read_at(10, &mut buffer).unwrap(), 3);
+        assert_eq!(file.read_at(20, &mut buffer).unwrap(), 0);
+
+        file.truncate(2).unwrap();
+        assert_eq!(file.read_at(0, &mut buffer).unwrap(), 2);
+    }
+
+    #[test]
+    fn directories() {
+        let fs = TmpFs::new();
+        let root = fs.root();
+        let etc = root.create("etc", Kind::Directory).unwrap();
+        etc.create("passwd", Kind::File).unwrap();
+        root.create("bin", Kind::Directory).unwrap();
+
+        assert!(matches!(root.create("etc", Kind::File), Err(Error::Exists)));
+        assert_eq!(
+            root.readdir().unwrap(),
+            [
+                DirEntry {
+                    name: "bin".into(),
+                    kind: Kind::Directory
+                },
+                DirEntry {
+                    name: "etc".into(),
+                    kind: Kind::Directory
+                },
+            ]
+        );
+
+        let passwd = root.lookup("etc").unwrap().lookup("passwd").unwrap();
+        assert_eq!(passwd.stat().kind, Kind::File);
+        assert!(matches!(passwd.lookup("x"), Err(Error::NotDirectory)));
+        assert!(matches!(etc.read_at(0, &mut []), Err(Error::IsDirectory)));
+    }
+}
@@ -0,0 +1,148 @@
+//! Captures of the raw COBS stream of the serial line
+//!
+//! A capture starts with [`MAGIC`], its version and the time the recording started in
+//! microseconds since the unix epoch, followed by the chunks read from the serial line. Each chunk
+//! is the microseconds between the start and its arrival, its length and its bytes, the integers
+//! are little endian.
+use std::{
+    io::{self, Write},
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+pub const MAGIC: [u8; 4] = *b"kcap";
+
+const VERSION: u8 = 1;
+
+const HEADER_SIZE: usize = 16;
+
+const CHUNK_HEADER_SIZE: usize = 12;
+
+/// Bytes read from the serial line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// Since the start of the recording
+    pub arrival: Duration,
+    pub bytes: Vec<u8>,
+}
+
+/// Appends the chunks to a capture.
+pub struct Writer<W> {
+    inner: W,
+    start: Instant,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        let started = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut header = [0u8; HEADER_SIZE];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4] = VERSION;
+        header[8..].copy_from_slice(&micros(started).to_le_bytes());
+        inner.write_all(&header)?;
+        Ok(Self {
+            inner,
+            start: Instant::now(),
+        })
+    }
+
+    /// Write the chunk at once, the capture stays readable if the recording is interrupted.
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let len = u32::try_from(bytes.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "chunk too large"))?;
+        let mut chunk = Vec::with_capacity(CHUNK_HEADER_SIZE + bytes.len());
+        chunk.extend_from_slice(&micros(self.start.elapsed()).to_le_bytes());
+        chunk.extend_from_slice(&len.to_le_bytes());
+        chunk.extend_from_slice(bytes);
+        self.inner.write_all(&chunk)
+    }
+}
+
+/// Chunks of a capture, a chunk cut by the end of the recording is dropped.
+pub fn parse(mut bytes: &[u8]) -> io::Result<Vec<Chunk>> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+    if bytes.len() < HEADER_SIZE || bytes[..4] != MAGIC {
+        return Err(invalid("not a konsole capture"));
+    }
+    if bytes[4] != VERSION {
+        return Err(invalid("unsupported capture version"));
+    }
+    bytes = &bytes[HEADER_SIZE..];
+
+    let mut chunks = vec![];
+    while bytes.len() >= CHUNK_HEADER_SIZE {
+        let arrival = Duration::from_micros(le_u64(&bytes[..8]));
+        let len = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+        let Some(chunk) = bytes[CHUNK_HEADER_SIZE..].get(..len) else {
+            break;
+        };
+        chunks.push(Chunk {
+            arrival,
+            bytes: chunk.to_vec(),
+        });
+        bytes = &bytes[CHUNK_HEADER_SIZE + len..];
+    }
+    Ok(chunks)
+}
+
+/// Reader copying the bytes it reads to a capture.
+pub struct Recorder<R, W> {
+    inner: R,
+    capture: Writer<W>,
+}
+
+impl<R, W: Write> Recorder<R, W> {
+    pub fn new(inner: R, capture: Writer<W>) -> Self {
+        Self { inner, capture }
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: Write + Unpin> AsyncRead for Recorder<R, W> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) if buf.filled().len() > filled => {
+                Poll::Ready(this.capture.write(&buf.filled()[filled..]))
+            }
+            poll => poll,
+        }
+    }
+}
+
+fn micros(duration: Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
+
+fn le_u64(bytes: &[u8]) -> u64 {
+    let mut le = [0u8; 8];
+    le.copy_from_slice(bytes);
+    u64::from_le_bytes(le)
+}
+
+#[test]
+fn capture_chunks() {
+    let mut bytes = vec![];
+    let mut writer = Writer::new(&mut bytes).unwrap();
+    writer.write(b"\x02\x01\x01\x00").unwrap();
+    writer.write(b"\x03log").unwrap();
+    // recording interrupted in the middle of a chunk
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 9, 0, 0, 0, 1]);
+
+    let chunks = parse(&bytes).unwrap();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].bytes, b"\x02\x01\x01\x00");
+    assert_eq!(chunks[1].bytes, b"\x03log");
+    assert!(chunks[0].arrival <= chunks[1].arrival);
+
+    assert!(parse(b"\xfflog").is_err());
+}
@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+/// Converts the time stamp counter of the packets once the kernel sent its frequency.
+#[derive(Debug, Default)]
+pub struct Clock {
+    /// Hz, the counts are printed as cycles until it is known
+    frequency: Option<u64>,
+    /// Timestamp of the last line printed
+    previous: Option<u64>,
+}
+
+impl Clock {
+    pub fn set_frequency(&mut self, hz: u64) {
+        self.frequency = Some(hz).filter(|&hz| hz != 0);
+    }
+
+    pub fn duration(&self, cycles: u64) -> Option<Duration> {
+        let hz = self.frequency?;
+        let nanos = u128::from(cycles) * 1_000_000_000 / u128::from(hz);
+        Some(Duration::from_nanos(u64::try_from(nanos).ok()?))
+    }
+
+    /// `[absolute +relative]` time of a line printed at `cycles`, relative to the previous line.
+    ///
+    /// Empty for the packets without timestamp.
+    pub fn stamp(&mut self, cycles: Option<u64>) -> String {
+        let Some(cycles) = cycles else {
+            return String::new();
+        };
+        let relative = cycles.saturating_sub(self.previous.unwrap_or(cycles));
+        self.previous = Some(cycles);
+        match (self.duration(cycles), self.duration(relative)) {
+            (Some(absolute), Some(relative)) => format!(
+                "[{:>4}.{:06}s +{}] ",
+                absolute.as_secs(),
+                absolute.subsec_micros(),
+                human(relative)
+            ),
+            _ => format!("[{} +{} cycles] ", cycles, relative),
+        }
+    }
+
+    /// Time elapsed in a span.
+    pub fn elapsed(&self, cycles: u64) -> String {
+        self.duration(cycles)
+            .map_or_else(|| format!("{} cycles", cycles), human)
+    }
+}
+
+/// Duration with the largest unit keeping it above one.
+fn human(duration: Duration) -> String {
+    if duration < Duration::from_micros(1) {
+        format!("{}ns", duration.as_nanos())
+    } else if duration < Duration::from_millis(1) {
+        format!("{:.3}µs", duration.as_nanos() as f64 / 1e3)
+    } else if duration < Duration::from_secs(1) {
+        format!("{:.3}ms", duration.as_secs_f64() * 1e3)
+    } else {
+        format!("{:.3}s", duration.as_secs_f64())
+    }
+}
+
+#[test]
+fn stamps() {
+    let mut clock = Clock::default();
+    assert_eq!(clock.stamp(None), "");
+    assert_eq!(clock.stamp(Some(1000)), "[1000 +0 cycles] ");
+    assert_eq!(clock.elapsed(20), "20 cycles");
+
+    clock.set_frequency(1_000_000_000);
+    assert_eq!(clock.stamp(Some(2_500_001_500)), "[   2.500001s +2.500s] ");
+    assert_eq!(
+        clock.stamp(Some(2_500_014_000)),
+        "[   2.500014s +12.500µs] "
+    );
+    assert_eq!(clock.elapsed(3_200_000), "3.200ms");
+    assert_eq!(clock.elapsed(999), "999ns");
+}
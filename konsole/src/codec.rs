@@ -1,97 +1,131 @@
 use std::io;
 
 use bytes::BytesMut;
-use rkyv::{util, AlignedVec, ArchiveUnsized};
 
-use protocols::log::LogPacket;
+use crate::packet::Stamped;
 
-pub struct LogRef(pub AlignedVec);
-impl AsRef<<LogPacket<'static> as ArchiveUnsized>::Archived> for LogRef {
-    fn as_ref(&self) -> &<LogPacket<'static> as ArchiveUnsized>::Archived {
-        unsafe { util::archived_unsized_root::<LogPacket<'static>>(&self.0[..]) }
-    }
+/// Decode the frames of the logger, frames that aren't valid packets are skipped.
+pub struct LogDecoder {
+    skipped: usize,
 }
 
-pub struct LogDecoder;
-
 impl LogDecoder {
     #[rustfmt::skip]
-    pub const fn new() -> Self { Self }
+    pub const fn new() -> Self { Self { skipped: 0 } }
+
+    /// Frames skipped so far, text written before the logger started or corrupted frames.
+    pub const fn skipped(&self) -> usize {
+        self.skipped
+    }
 }
 
 impl tokio_util::codec::Decoder for LogDecoder {
-    type Item = LogRef;
+    type Item = Stamped;
 
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let buf = match src
-            .iter()
-            .enumerate()
-            .find_map(|(i, &b)| (b == 0).then_some(i + 1))
-        {
-            Some(n) => {
-                let buf = src.split_to(n);
-                src.reserve(1024);
-                buf.freeze()
+        while let Some(n) = src.iter().position(|&b| b == 0) {
+            let buf = src.split_to(n + 1);
+            src.reserve(1024);
+
+            let mut decode = rkyv::AlignedVec::with_capacity(buf.len());
+            decode.resize(buf.len(), 0);
+            let n = mais::decode(&buf[..], &mut decode[..]);
+            decode.resize(n, 0);
+
+            match Stamped::decode(&decode[..]) {
+                Ok(item) => return Ok(Some(item)),
+                Err(_) => self.skipped += 1,
             }
-            None => return Ok(None),
-        };
-
-        let mut decode = rkyv::AlignedVec::with_capacity(buf.len());
-        unsafe { decode.set_len(buf.len()) };
-        decode.fill(0);
-        let n = mais::decode(&buf[..], &mut decode[..]);
+        }
+        Ok(None)
+    }
 
-        unsafe { decode.set_len(n) };
-        Ok(Some(LogRef(decode)))
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let item = self.decode(src)?;
+        if item.is_none() {
+            // frame cut by the end of a capture
+            src.clear();
+        }
+        Ok(item)
     }
 }
 
+/// Frames of the first version as sent by its encoder
 #[test]
-fn deser() {
-    use bytes::BufMut;
-    use protocols::log::{ArchivedLogPacket, LogPacket, Span};
-    use rkyv::ser::Serializer;
+fn baseline_frames() {
+    use protocols::log::Level;
     use tokio_util::codec::Decoder;
 
-    let p = LogPacket::NewSpan(Span {
-        id: 8,
-        target: "map_range",
-        fields: "pages=PageRange<2Mb>(0x1000000000..0x1100000000),frames=FrameRange<2Mb>(0x0..0x100000000),flags=3,",
-    });
-    // let a = rkyv::util::to_bytes::<_, 512>(&p).unwrap();
-    let mut s = rkyv::ser::serializers::AllocSerializer::<512>::default();
-    s.serialize_unsized_value(&p).unwrap();
-    let n = s.pos();
-    dbg!(n);
-    let (s, _, _) = s.into_components();
-
-    let mut buff = Vec::with_capacity(1024);
-    unsafe { buff.set_len(1024) };
-    buff.fill(0);
+    use crate::{
+        fields::{Field, Value},
+        packet::{Message, Packet},
+    };
 
-    let encode_n = mais::encode(&s.into_inner()[0..n], &mut buff).unwrap();
+    let debug = |name: &str, value: &str| Field {
+        name: name.to_string(),
+        value: Value::Debug(value.to_string()),
+    };
 
-    // --------------
-    let buf = b"lmap_rangepages=PageRange<2Mb>(0x1000000000..0x1100000000),frames=FrameRange<2Mb>(0x0..0x100000000),flags=3,\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x02\x08\x08\x01\x01\x01\x01\x01\x01\x06\x80\xff\xff\xff\t\x01\x01\x06\x81\xff\xff\xffb\x01\x01\x05\xe0\xff\xff\xff\x00\x02\x02\x01\x01\x01\x01\x01\x01\x02\x08\x08\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01";
-    // assert_eq!(&buff[..encode_n], buf);
+    let mut bytes = BytesMut::new();
+    bytes.extend_from_slice(b"\x17kernelversion=\"0.1.0\",\x01\x01\x01\x01\x01\x01\x01\x01\x01\x02\x01\x01\x01\x01\x01\x01\x01\x06\xd8\xff\xff\xff\x06\x01\x01\x06\xd6\xff\xff\xff\x10\x01\x01\x05\xe0\xff\xff\xff\0");
+    bytes.extend_from_slice(b"\x02\x02\x01\x01\x01\x01\x01\x01\x02\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x05\xe0\xff\xff\xff\0");
+    bytes.extend_from_slice(b"\x1dkernelmessage=kernel loaded,\x01\x01\x01\x02\x01\x01\x01\x02\x02\x01\x01\x02\x3b\x01\x01\x06\xd4\xff\xff\xff\x06\x01\x01\x06\xd2\xff\xff\xff\x16\x01\x01\x01\x01\x01\x01\x05\xe0\xff\xff\xff\0");
+    bytes.extend_from_slice(b"\x02\x03\x01\x01\x01\x01\x01\x01\x02\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x05\xe0\xff\xff\xff\0");
+
+    let mut decoder = LogDecoder::new();
+    let mut packets = Vec::new();
+    while let Some(item) = decoder.decode(&mut bytes).unwrap() {
+        assert_eq!(item.cycles, None);
+        packets.push(item.packet);
+    }
+    assert!(bytes.is_empty());
+
+    assert_eq!(
+        packets,
+        [
+            Packet::NewSpan {
+                id: 1,
+                name: String::new(),
+                target: "kernel".to_string(),
+                fields: vec![debug("", "version=\"0.1.0\",")],
+            },
+            Packet::EnterSpan(1),
+            Packet::Message(Message {
+                level: Level::Info,
+                line: 59,
+                module: "kernel".to_string(),
+                fields: vec![debug("message", "message=kernel loaded,")],
+            }),
+            Packet::ExitSpan {
+                id: 1,
+                elapsed: None,
+            },
+        ]
+    );
+}
 
-    // return;
+#[test]
+fn skips_invalid_frames() {
+    use tokio_util::codec::Decoder;
 
-    let buf = b"lmap_rangepages=PageRange<2Mb>(0x1000000000..0x1100000000),frames=FrameRange<2Mb>(0x0..0x100000000),flags=3,\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x02\x08\x01\x01\x01\x01\x01\x01\x06\x80\xff\xff\xff\t\x01\x01\x06\x81\xff\xff\xffb\x01\x01\x05\xe0\xff\xff\xff\x00\x02\x02\x01\x01\x01\x01\x01\x01\x02\x08\x08\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01";
+    use crate::packet::Packet;
 
     let mut bytes = BytesMut::new();
-    bytes.extend_from_slice(buf);
-    bytes.truncate(bytes.len() - 0);
-    bytes.put_u8(0);
-
-    let item: LogRef = LogDecoder.decode(&mut bytes).unwrap().unwrap();
-
-    match item.as_ref() {
-        ArchivedLogPacket::NewSpan(span) => {
-            dbg!(&*span.fields);
-        }
-        _ => unreachable!(),
-    }
+    // short frame, text written before the logger and garbage
+    bytes.extend_from_slice(b"\x01\0");
+    bytes.extend_from_slice(b"Booting (second stage)...\n\0");
+    bytes.extend_from_slice(b"\xde\xad\xbe\xef\xff\xff\0");
+    bytes.extend_from_slice(b"\x02\x02\x01\x01\x01\x01\x01\x01\x02\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x05\xe0\xff\xff\xff\0");
+    bytes.extend_from_slice(b"\x01\0");
+
+    let mut decoder = LogDecoder::new();
+    let item = decoder.decode(&mut bytes).unwrap().unwrap();
+    assert_eq!(item.packet, Packet::EnterSpan(1));
+    assert_eq!(decoder.skipped(), 3);
+
+    assert_eq!(decoder.decode_eof(&mut bytes).unwrap(), None);
+    assert_eq!(decoder.skipped(), 4);
+    assert!(bytes.is_empty());
 }
@@ -0,0 +1,188 @@
+use std::fmt::Write;
+
+use rkyv::{ser::Serializer, AlignedVec};
+
+use protocols::{
+    command::{
+        ArchivedError, ArchivedRegionKind, ArchivedResponse, ArchivedTaskState, Request, Response,
+        MAX_READ,
+    },
+    log::Level,
+};
+
+pub const HELP: &str = "\
+commands:
+  memmap               dump the memory map
+  read <addr> <len>    read physical memory
+  tasks                list the kernel threads
+  level <level>        set the log level (error, warn, info, debug, trace)
+  reboot               reboot the machine
+  help                 print this message
+";
+
+/// Parse a line of the prompt, `None` for an empty line.
+pub fn parse(line: &str) -> Result<Option<Request>, String> {
+    let mut words = line.split_whitespace();
+    let Some(command) = words.next() else {
+        return Ok(None);
+    };
+    let request = match command {
+        "memmap" => Request::MemoryMap,
+        "read" => {
+            let address = number(words.next().ok_or("missing address")?)?;
+            let len = number(words.next().unwrap_or("16"))?;
+            let len = u32::try_from(len)
+                .ok()
+                .filter(|len| *len <= MAX_READ)
+                .ok_or_else(|| format!("at most {} bytes can be read", MAX_READ))?;
+            Request::ReadPhysical { address, len }
+        }
+        "tasks" => Request::Tasks,
+        "level" => Request::SetLogLevel(level(words.next().ok_or("missing level")?)?),
+        "reboot" => Request::Reboot,
+        _ => return Err(format!("unknown command `{}`", command)),
+    };
+    match words.next() {
+        Some(word) => Err(format!("unexpected argument `{}`", word)),
+        None => Ok(Some(request)),
+    }
+}
+
+fn number(word: &str) -> Result<u64, String> {
+    let number = match word.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => word.parse(),
+    };
+    number.map_err(|err| format!("invalid number `{}`: {}", word, err))
+}
+
+pub fn level(word: &str) -> Result<Level, String> {
+    Ok(match word {
+        "error" => Level::Error,
+        "warn" => Level::Warn,
+        "info" => Level::Info,
+        "debug" => Level::Debug,
+        "trace" => Level::Trace,
+        _ => return Err(format!("unknown level `{}`", word)),
+    })
+}
+
+/// COBS frame of the request.
+pub fn encode(request: &Request) -> Vec<u8> {
+    let mut s = rkyv::ser::serializers::AllocSerializer::<64>::default();
+    s.serialize_value(request).unwrap();
+    let bytes = s.into_serializer().into_inner();
+
+    let mut frame = vec![0; bytes.len() + bytes.len() / 254 + 2];
+    let n = mais::encode(&bytes[..], &mut frame).unwrap();
+    frame.truncate(n + 1);
+    frame
+}
+
+/// Format the response carried by a `LogPacket::Response`.
+pub fn reply(bytes: &[u8]) -> String {
+    let mut aligned = AlignedVec::with_capacity(bytes.len());
+    aligned.extend_from_slice(bytes);
+    let response = unsafe { rkyv::archived_root::<Response<'static>>(&aligned[..]) };
+
+    let mut out = String::new();
+    match response {
+        ArchivedResponse::MemoryMap(regions) => {
+            for region in regions.iter() {
+                let kind = match region.kind {
+                    ArchivedRegionKind::Usable => "usable",
+                    ArchivedRegionKind::Bootloader => "bootloader",
+                    ArchivedRegionKind::Initrd => "initrd",
+                    ArchivedRegionKind::Reserved => "reserved",
+                };
+                let _ = writeln!(
+                    out,
+                    "{:#014x}..{:#014x} {:>10} KiB {}",
+                    region.start,
+                    region.end,
+                    (region.end - region.start) / 1024,
+                    kind
+                );
+            }
+        }
+        ArchivedResponse::Memory { address, bytes } => {
+            for (i, line) in bytes.chunks(16).enumerate() {
+                let _ = write!(out, "{:#014x}:", address + 16 * i as u64);
+                for byte in line {
+                    let _ = write!(out, " {:02x}", byte);
+                }
+                let ascii: String = line
+                    .iter()
+                    .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                    .collect();
+                let _ = writeln!(out, "{:>pad$} {}", "", ascii, pad = 3 * (16 - line.len()));
+            }
+        }
+        ArchivedResponse::Tasks(tasks) => {
+            for task in tasks.iter() {
+                let state = match task.state {
+                    ArchivedTaskState::Running => "running",
+                    ArchivedTaskState::Ready => "ready",
+                    ArchivedTaskState::Sleeping => "sleeping",
+                    ArchivedTaskState::Blocked => "blocked",
+                    ArchivedTaskState::Finished => "finished",
+                };
+                let _ = match task.process {
+                    0 => writeln!(out, "thread {:>4} {:<8} kernel", task.id, state),
+                    process => writeln!(
+                        out,
+                        "thread {:>4} {:<8} process {}",
+                        task.id, state, process
+                    ),
+                };
+            }
+        }
+        ArchivedResponse::LogLevel(level) => {
+            let _ = writeln!(out, "log level set to {:?}", Level::from(*level));
+        }
+        ArchivedResponse::Rebooting => out.push_str("rebooting\n"),
+        ArchivedResponse::Error(err) => {
+            let err = match err {
+                ArchivedError::InvalidRequest => "invalid request",
+                ArchivedError::InvalidRange => "invalid physical range",
+            };
+            let _ = writeln!(out, "\u{001b}[31;1merror\u{001b}[0m: {}", err);
+        }
+    }
+    out
+}
+
+#[test]
+fn parse_commands() {
+    assert_eq!(parse("  ").unwrap(), None);
+    assert_eq!(parse("tasks").unwrap(), Some(Request::Tasks));
+    assert_eq!(
+        parse("read 0xb8000 32").unwrap(),
+        Some(Request::ReadPhysical {
+            address: 0xb8000,
+            len: 32
+        })
+    );
+    assert_eq!(
+        parse("level debug").unwrap(),
+        Some(Request::SetLogLevel(Level::Debug))
+    );
+    assert!(parse("read 0x1000 4096").is_err());
+    assert!(parse("reboot now").is_err());
+    assert!(parse("level loud").is_err());
+}
+
+#[test]
+fn encode_request() {
+    let frame = encode(&Request::ReadPhysical {
+        address: 0x1000,
+        len: 16,
+    });
+    assert_eq!(frame.last(), Some(&0));
+    assert!(frame[..frame.len() - 1].iter().all(|&b| b != 0));
+
+    let mut decoded = AlignedVec::with_capacity(frame.len());
+    decoded.extend_from_slice(&frame);
+    let n = mais::decode(&frame, &mut decoded[..]);
+    assert!(protocols::command::archived_request(&decoded[..n]).is_some());
+}
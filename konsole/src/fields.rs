@@ -0,0 +1,215 @@
+use std::fmt;
+
+use protocols::log::{ArchivedField, ArchivedValue};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    /// Empty for the flattened fields of the first version of the packets
+    pub name: String,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    I64(i64),
+    U64(u64),
+    Bool(bool),
+    Str(String),
+    Debug(String),
+}
+
+impl From<&ArchivedField<'_>> for Field {
+    fn from(field: &ArchivedField<'_>) -> Self {
+        let value = match &field.value {
+            ArchivedValue::I64(value) => Value::I64(*value),
+            ArchivedValue::U64(value) => Value::U64(*value),
+            ArchivedValue::Bool(value) => Value::Bool(*value),
+            ArchivedValue::Str(value) => Value::Str(value.to_string()),
+            ArchivedValue::Debug(value) => Value::Debug(value.to_string()),
+        };
+        Self {
+            name: field.name.to_string(),
+            value,
+        }
+    }
+}
+
+impl Field {
+    /// Fields flattened into a string by the first version.
+    pub fn flattened(fields: &str) -> Vec<Self> {
+        if fields.is_empty() {
+            return vec![];
+        }
+        vec![Self {
+            name: String::new(),
+            value: Value::Debug(fields.to_string()),
+        }]
+    }
+
+    /// Text of a message of the first version, under the name of the `message` field.
+    pub fn text(text: &str) -> Vec<Self> {
+        vec![Self {
+            name: "message".to_string(),
+            value: Value::Debug(text.to_string()),
+        }]
+    }
+
+    pub fn to_json(&self) -> String {
+        format!("{}:{}", json_string(&self.name), self.value.to_json())
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "{}", self.value)
+        } else {
+            write!(f, "{}={}", self.name, self.value)
+        }
+    }
+}
+
+impl Value {
+    pub fn to_json(&self) -> String {
+        match self {
+            Self::I64(value) => value.to_string(),
+            Self::U64(value) => value.to_string(),
+            Self::Bool(value) => value.to_string(),
+            Self::Str(value) | Self::Debug(value) => json_string(value),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::I64(value) => write!(f, "{}", value),
+            Self::U64(value) => write!(f, "{}", value),
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Str(value) => write!(f, "{:?}", value),
+            Self::Debug(value) => f.write_str(value),
+        }
+    }
+}
+
+/// Text of an event, its `message` field.
+pub fn message(fields: &[Field]) -> Option<&str> {
+    fields.iter().find_map(|field| match &field.value {
+        Value::Debug(text) | Value::Str(text) if field.name == "message" => Some(text.as_str()),
+        _ => None,
+    })
+}
+
+/// Fields to render after the text of an event, separated by spaces.
+pub fn render(fields: &[Field]) -> String {
+    fields
+        .iter()
+        .filter(|field| field.name != "message")
+        .map(|field| format!(" \u{001b}[2m{}\u{001b}[0m", field))
+        .collect()
+}
+
+/// `name` or `name=value`, matches the packets carrying the field with this value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldFilter {
+    name: String,
+    value: Option<String>,
+}
+
+impl FieldFilter {
+    pub fn parse(filter: &str) -> Self {
+        let (name, value) = match filter.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (filter, None),
+        };
+        Self {
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    pub fn matches(&self, fields: &[Field]) -> bool {
+        fields.iter().any(|field| {
+            field.name == self.name
+                && self
+                    .value
+                    .as_ref()
+                    .map_or(true, |value| match &field.value {
+                        // strings are compared without their quotes
+                        Value::Str(text) => text == value,
+                        other => other.to_string() == *value,
+                    })
+        })
+    }
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// JSON object of the fields, the flattened fields are under an empty name.
+pub fn to_json(fields: &[Field]) -> String {
+    let fields: Vec<String> = fields.iter().map(Field::to_json).collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+#[test]
+fn filter_fields() {
+    let fields = [
+        Field {
+            name: "message".to_string(),
+            value: Value::Debug("mapped".to_string()),
+        },
+        Field {
+            name: "frames".to_string(),
+            value: Value::U64(512),
+        },
+        Field {
+            name: "owner".to_string(),
+            value: Value::Str("kernel".to_string()),
+        },
+    ];
+    assert_eq!(message(&fields), Some("mapped"));
+    assert!(FieldFilter::parse("frames").matches(&fields));
+    assert!(FieldFilter::parse("frames=512").matches(&fields));
+    assert!(FieldFilter::parse("owner=kernel").matches(&fields));
+    assert!(!FieldFilter::parse("frames=4").matches(&fields));
+    assert!(!FieldFilter::parse("flags").matches(&fields));
+}
+
+#[test]
+fn export_fields() {
+    let fields = [
+        Field {
+            name: "message".to_string(),
+            value: Value::Debug("a \"quoted\"\nline".to_string()),
+        },
+        Field {
+            name: "huge".to_string(),
+            value: Value::Bool(true),
+        },
+        Field {
+            name: "offset".to_string(),
+            value: Value::I64(-8),
+        },
+    ];
+    assert_eq!(
+        to_json(&fields),
+        r#"{"message":"a \"quoted\"\nline","huge":true,"offset":-8}"#
+    );
+    assert_eq!(render(&fields[1..2]), " \u{001b}[2mhuge=true\u{001b}[0m");
+}
@@ -0,0 +1,154 @@
+//! Messages and spans selected on the command line
+use std::{cell::RefCell, rc::Rc};
+
+use protocols::log::Level;
+use regex::Regex;
+
+use crate::{
+    fields::{self, FieldFilter},
+    packet::Message,
+    Span,
+};
+
+/// Pattern where `*` matches any text and `?` a single character, e.g. `kernel::mem::*`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glob(Vec<char>);
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        Self(pattern.chars().collect())
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        // position of the last `*` and of the text it matched up to
+        let mut star = None;
+        let (mut p, mut t) = (0, 0);
+        while t < text.len() {
+            match self.0.get(p) {
+                Some('*') => {
+                    star = Some((p, t));
+                    p += 1;
+                }
+                Some(&c) if c == '?' || c == text[t] => {
+                    p += 1;
+                    t += 1;
+                }
+                _ => match star {
+                    // let the last `*` match one more character
+                    Some((star_p, star_t)) => {
+                        star = Some((star_p, star_t + 1));
+                        p = star_p + 1;
+                        t = star_t + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+        self.0[p..].iter().all(|&c| c == '*')
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Most verbose level printed
+    pub level: Option<Level>,
+    /// Modules printed, every module if empty
+    pub modules: Vec<Glob>,
+    pub excluded: Vec<Glob>,
+    /// Only inside the spans with a matching target, spans included
+    pub spans: Vec<Glob>,
+    /// Matching the text of the messages
+    pub text: Option<Regex>,
+    /// Fields of the message or of a span entered
+    pub fields: Vec<FieldFilter>,
+}
+
+impl Filter {
+    /// Whether the message sent inside the `stack` of spans is printed.
+    pub fn message(&self, message: &Message, stack: &[Rc<RefCell<Span>>]) -> bool {
+        self.level.map_or(true, |level| message.level <= level)
+            && self.module(&message.module)
+            && self.inside(stack)
+            && self.text.as_ref().map_or(true, |text| {
+                text.is_match(fields::message(&message.fields).unwrap_or_default())
+            })
+            && self.fields.iter().all(|filter| {
+                filter.matches(&message.fields)
+                    || stack
+                        .iter()
+                        .any(|span| filter.matches(&span.borrow().fields))
+            })
+    }
+
+    /// Whether the events of the span entered inside the `stack` are printed.
+    pub fn span(&self, span: &Span, stack: &[Rc<RefCell<Span>>]) -> bool {
+        self.module(&span.target)
+            && (self.spans.is_empty()
+                || self.spans.iter().any(|glob| glob.matches(&span.target))
+                || self.inside(stack))
+    }
+
+    fn module(&self, module: &str) -> bool {
+        (self.modules.is_empty() || self.modules.iter().any(|glob| glob.matches(module)))
+            && !self.excluded.iter().any(|glob| glob.matches(module))
+    }
+
+    fn inside(&self, stack: &[Rc<RefCell<Span>>]) -> bool {
+        self.spans.is_empty()
+            || stack.iter().any(|span| {
+                let target = &span.borrow().target;
+                self.spans.iter().any(|glob| glob.matches(target))
+            })
+    }
+}
+
+#[test]
+fn globs() {
+    assert!(Glob::new("kernel::*").matches("kernel::mem::context"));
+    assert!(Glob::new("*::mem::*").matches("kernel::mem::context"));
+    assert!(Glob::new("map_range").matches("map_range"));
+    assert!(Glob::new("map_?ange").matches("map_range"));
+    assert!(Glob::new("*").matches(""));
+    assert!(!Glob::new("kernel::*").matches("bootloader::kernel"));
+    assert!(!Glob::new("map").matches("map_range"));
+    assert!(!Glob::new("*a*b").matches("aaba_"));
+}
+
+#[test]
+fn filter_messages() {
+    let message = |level, module: &str| Message {
+        level,
+        line: 0,
+        module: module.to_string(),
+        fields: vec![],
+    };
+    let filter = Filter {
+        level: Some(Level::Info),
+        modules: vec![Glob::new("kernel::*")],
+        excluded: vec![Glob::new("kernel::sched*")],
+        ..Filter::default()
+    };
+    assert!(filter.message(&message(Level::Warn, "kernel::mem"), &[]));
+    assert!(!filter.message(&message(Level::Debug, "kernel::mem"), &[]));
+    assert!(!filter.message(&message(Level::Warn, "kernel::scheduler"), &[]));
+    assert!(!filter.message(&message(Level::Error, "page_mapper"), &[]));
+
+    let filter = Filter {
+        spans: vec![Glob::new("map_*")],
+        ..Filter::default()
+    };
+    let span = Span::new(1, None, "map_range".to_string(), vec![]);
+    assert!(filter.message(&message(Level::Trace, "page_mapper"), &[span]));
+    assert!(!filter.message(&message(Level::Trace, "page_mapper"), &[]));
+
+    // the text of the first version of the packets is matched too
+    let filter = Filter {
+        text: Some(Regex::new("out of frames").unwrap()),
+        ..Filter::default()
+    };
+    let mut message = message(Level::Error, "page_mapper");
+    assert!(!filter.message(&message, &[]));
+    message.fields = fields::Field::text("out of frames: 0 left");
+    assert!(filter.message(&message, &[]));
+}
@@ -1,36 +1,69 @@
+mod capture;
+mod clock;
 mod codec;
+mod command;
+mod fields;
+mod filter;
+mod output;
+mod packet;
+mod tui;
 
-use std::{cell::RefCell, collections::HashMap, io, rc::Rc};
+use std::{
+    cell::RefCell, collections::HashMap, fs::File, io, io::Write, process::ExitCode, rc::Rc,
+};
 
-use protocols::log::{ArchivedLevel, ArchivedLogPacket, Level};
+use protocols::log::Level;
 
-use tokio::{io::AsyncWriteExt, net::TcpListener};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    net::{tcp::OwnedWriteHalf, TcpListener, TcpStream},
+};
 
-use tokio_util::codec::Decoder;
+use tokio_util::codec::FramedRead;
 
 use kcore::futures::stream::StreamExt;
 
+use fields::{Field, FieldFilter};
+use filter::{Filter, Glob};
+use output::{Format, SpanEvent};
+use packet::{Message, Packet, Stamped};
+
+const USAGE: &str = "usage: konsole <addr> [<options>]
+       konsole record <addr> <file> [<options>]
+       konsole replay <file> [--realtime] [<options>]
+       konsole decode [<options>]
+options:
+  --level <level>            most verbose level printed (error, warn, info, debug, trace)
+  --module <glob>            print the messages of the matching modules, e.g. `kernel::mem::*`
+  --exclude <glob>           hide the messages of the matching modules
+  --span <glob>              print what happens inside the spans with a matching target
+  --grep <regex>             print the messages with a matching text
+  --field <name>[=<value>]   print the messages with the field, or inside a span with it
+  --format <json|text|plain> format of the output, coloured text by default
+  --export <file>            write the messages printed as JSON lines in the file
+  --tui                      browse the tree of spans in a terminal interface
+the options taking a pattern can be repeated, the exit status is 1 if an error was logged and 2
+if the kernel panicked";
+
+/// Bytes in flight between the replayed capture and the decoder
+const REPLAY_BUFFER: usize = 4096;
+
 #[derive(Debug)]
 struct Span {
     id: u64,
+    /// Span entered when this one was created
+    parent: Option<u64>,
     target: String,
-    fields: String,
+    fields: Vec<Field>,
     messages: Vec<Message>,
 }
 
-#[derive(Debug)]
-pub struct Message {
-    pub level: Level,
-    pub line: usize,
-    pub module: String,
-    pub message: String,
-}
-
 impl Span {
     #[must_use]
-    fn new(id: u64, target: String, fields: String) -> Rc<RefCell<Self>> {
+    fn new(id: u64, parent: Option<u64>, target: String, fields: Vec<Field>) -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Self {
             id,
+            parent,
             target,
             fields,
             messages: vec![],
@@ -38,127 +71,377 @@ impl Span {
     }
 }
 
+/// Source of the log stream.
+enum Mode {
+    /// Wait for the serial line of QEMU on the address
+    Listen(String),
+    /// Listen and store the stream in a capture
+    Record { addr: String, file: String },
+    /// Decode a capture, at the pace it was recorded if `realtime`
+    Replay { file: String, realtime: bool },
+    /// Decode the stream written on stdin, e.g. by `-serial stdio` or a `-serial file:`
+    Decode,
+}
+
+struct Options {
+    filter: Filter,
+    format: Format,
+    /// Browse the spans in a terminal interface instead of printing the logs
+    tui: bool,
+    /// Messages written as JSON lines
+    export: Option<File>,
+}
+
+/// Worst message observed, the exit status of konsole.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Status {
+    #[default]
+    Clean = 0,
+    Error = 1,
+    Panic = 2,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> io::Result<(Mode, Self)> {
+        let usage = || io::Error::new(io::ErrorKind::Other, USAGE);
+        let invalid = |err: String| io::Error::new(io::ErrorKind::InvalidInput, err);
+        let mut options = Self {
+            filter: Filter::default(),
+            format: Format::Text,
+            tui: false,
+            export: None,
+        };
+        let mut positionals = vec![];
+        let mut realtime = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--level" => {
+                    let level = command::level(&args.next().ok_or_else(usage)?).map_err(invalid)?;
+                    options.filter.level = Some(level);
+                }
+                "--module" => options
+                    .filter
+                    .modules
+                    .push(Glob::new(&args.next().ok_or_else(usage)?)),
+                "--exclude" => options
+                    .filter
+                    .excluded
+                    .push(Glob::new(&args.next().ok_or_else(usage)?)),
+                "--span" => options
+                    .filter
+                    .spans
+                    .push(Glob::new(&args.next().ok_or_else(usage)?)),
+                "--grep" => {
+                    let regex = regex::Regex::new(&args.next().ok_or_else(usage)?)
+                        .map_err(|err| invalid(err.to_string()))?;
+                    options.filter.text = Some(regex);
+                }
+                "--field" => options
+                    .filter
+                    .fields
+                    .push(FieldFilter::parse(&args.next().ok_or_else(usage)?)),
+                "--format" => {
+                    options.format =
+                        Format::parse(&args.next().ok_or_else(usage)?).map_err(invalid)?;
+                }
+                "--export" => options.export = Some(File::create(args.next().ok_or_else(usage)?)?),
+                "--realtime" => realtime = true,
+                "--tui" => options.tui = true,
+                _ if !arg.starts_with("--") => positionals.push(arg),
+                _ => return Err(usage()),
+            }
+        }
+
+        let mode = match positionals.as_slice() {
+            [] => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "missing server address",
+                ))
+            }
+            [command, addr, file] if command == "record" => Mode::Record {
+                addr: addr.clone(),
+                file: file.clone(),
+            },
+            [command, file] if command == "replay" => Mode::Replay {
+                file: file.clone(),
+                realtime,
+            },
+            [command] if command == "decode" => Mode::Decode,
+            [addr] => Mode::Listen(addr.clone()),
+            _ => return Err(usage()),
+        };
+        if realtime && !matches!(mode, Mode::Replay { .. }) {
+            return Err(usage());
+        }
+        Ok((mode, options))
+    }
+}
+
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> io::Result<()> {
-    let mut addr = std::env::args().skip(1);
-    let addr = addr
-        .next()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "missing server address"))?;
+async fn main() -> io::Result<ExitCode> {
+    let (mode, mut options) = match Options::parse(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
 
-    let listener = TcpListener::bind(addr).await?;
+    let status = match mode {
+        Mode::Listen(addr) => {
+            let (reader, writer) = accept(&addr).await?.into_split();
+            view(reader, Some(writer), &mut options).await
+        }
+        Mode::Record { addr, file } => {
+            let capture = capture::Writer::new(File::create(file)?)?;
+            let (reader, writer) = accept(&addr).await?.into_split();
+            let reader = capture::Recorder::new(reader, capture);
+            view(reader, Some(writer), &mut options).await
+        }
+        Mode::Replay { file, realtime } => {
+            let chunks = capture::parse(&std::fs::read(file)?)?;
+            let (mut tx, rx) = tokio::io::duplex(REPLAY_BUFFER);
+            tokio::spawn(async move {
+                let start = tokio::time::Instant::now();
+                for chunk in chunks {
+                    if realtime {
+                        tokio::time::sleep_until(start + chunk.arrival).await;
+                    }
+                    // the decoder stopped on an invalid frame
+                    if tx.write_all(&chunk.bytes).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            view(rx, None, &mut options).await
+        }
+        Mode::Decode => view(tokio::io::stdin(), None, &mut options).await,
+    }?;
+    Ok(ExitCode::from(status as u8))
+}
 
+async fn accept(addr: &str) -> io::Result<TcpStream> {
+    let listener = TcpListener::bind(addr).await?;
     let (stream, _) = listener.accept().await?;
+    Ok(stream)
+}
 
+/// Show the logs read from `reader` as asked by the options.
+async fn view<R>(
+    reader: R,
+    commands: Option<OwnedWriteHalf>,
+    options: &mut Options,
+) -> io::Result<Status>
+where
+    R: AsyncRead + Unpin,
+{
+    if options.tui {
+        // the commands are typed in the prompt of the printed logs
+        tui::run(reader, options).await
+    } else {
+        monitor(reader, commands, options).await
+    }
+}
+
+/// Print the logs read from `reader`, the commands typed on stdin are sent to `commands`.
+///
+/// Returns once the stream ends or the kernel panicked.
+async fn monitor<R>(
+    reader: R,
+    mut commands: Option<OwnedWriteHalf>,
+    options: &mut Options,
+) -> io::Result<Status>
+where
+    R: AsyncRead + Unpin,
+{
     let mut stdout = tokio::io::stdout();
 
+    let mut status = Status::Clean;
+    let mut clock = clock::Clock::default();
     let mut spans = HashMap::<u64, Rc<RefCell<Span>>>::new();
     let mut span_stack = Vec::<Rc<RefCell<Span>>>::new();
 
-    let mut framed = codec::LogDecoder::new().framed(stream);
-
-    while let Some(message) = framed.next().await.transpose()? {
-        let message = match message.as_ref() {
-            ArchivedLogPacket::Message(message) => message,
-            ArchivedLogPacket::NewSpan(span) => {
-                spans.insert(
-                    span.id,
-                    Span::new(
-                        span.id,
-                        (&*span.target).to_string(),
-                        (&*span.fields).to_string(),
-                    ),
-                );
+    let mut framed = FramedRead::new(reader, codec::LogDecoder::new());
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    // stdin carries the logs when decoding
+    let mut interactive = commands.is_some();
+
+    loop {
+        let Stamped { cycles, packet } = tokio::select! {
+            packet = framed.next() => match packet.transpose()? {
+                Some(packet) => packet,
+                None => break,
+            },
+            line = lines.next_line(), if interactive => {
+                match line? {
+                    Some(line) if line.trim() == "help" => {
+                        stdout.write_all(command::HELP.as_bytes()).await?;
+                    }
+                    Some(line) => match (command::parse(&line), commands.as_mut()) {
+                        (Ok(Some(request)), Some(writer)) => {
+                            writer.write_all(&command::encode(&request)).await?;
+                        }
+                        (Ok(_), _) => {}
+                        (Err(err), _) => {
+                            stdout
+                                .write_all(format!("{}, try `help`\n", err).as_bytes())
+                                .await?;
+                        }
+                    },
+                    // stdin is closed when running in the background
+                    None => interactive = false,
+                }
+                continue;
+            }
+        };
+
+        let message = match packet {
+            Packet::Message(message) => message,
+            Packet::NewSpan {
+                id, target, fields, ..
+            } => {
+                let parent = span_stack.last().map(|parent| parent.borrow().id);
+                spans.insert(id, Span::new(id, parent, target, fields));
                 continue;
             }
-            ArchivedLogPacket::EnterSpan(span) => {
-                if let Some(span) = spans.get(span) {
+            Packet::EnterSpan(span) => {
+                if let Some(span) = spans.get(&span) {
                     span_stack.push(Rc::clone(span));
-                    let s = RefCell::borrow(&span);
-                    stdout
-                        .write_all(
-                            format!("OPEN: {} - {} - fields: {}\n", s.id, s.target, s.fields)
-                                .as_bytes(),
-                        )
-                        .await?;
+                    let s = RefCell::borrow(span);
+                    if options.filter.span(&s, &span_stack) {
+                        let event = SpanEvent::Open { fields: &s.fields };
+                        let line = output::span_line(
+                            options.format,
+                            &mut clock,
+                            cycles,
+                            depth(&spans, s.id),
+                            (s.id, &s.target),
+                            &event,
+                        );
+                        stdout.write_all(line.as_bytes()).await?;
+                    }
                 }
                 continue;
             }
-            ArchivedLogPacket::ExitSpan(span) => {
-                if let Some(span) = spans.get(span) {
-                    let s = RefCell::borrow(&span);
-                    assert_eq!(span_stack.pop().map(|sp| sp.borrow().id), Some(s.id));
-                    stdout
-                        .write_all(format!("CLOSE: {} - {}\n", s.id, s.target).as_bytes())
-                        .await?;
+            Packet::Response(bytes) => {
+                let reply = output::reply(options.format, command::reply(&bytes));
+                stdout.write_all(reply.as_bytes()).await?;
+                continue;
+            }
+            Packet::ExitSpan { id, elapsed } => {
+                // a preempted task may exit its spans out of order
+                if let Some(i) = span_stack.iter().rposition(|s| s.borrow().id == id) {
+                    let s = span_stack.remove(i);
+                    let s = s.borrow();
+                    if options.filter.span(&s, &span_stack) {
+                        let line = output::span_line(
+                            options.format,
+                            &mut clock,
+                            cycles,
+                            depth(&spans, s.id),
+                            (s.id, &s.target),
+                            &SpanEvent::Close { elapsed },
+                        );
+                        stdout.write_all(line.as_bytes()).await?;
+                    }
+                }
+                continue;
+            }
+            Packet::Record { id, fields } => {
+                if let Some(span) = spans.get(&id) {
+                    let depth = depth(&spans, id);
+                    // the stack may hold the span, it can't be mutably borrowed while filtering
+                    let visible = options.filter.span(&span.borrow(), &span_stack);
+                    let mut s = span.borrow_mut();
+                    if visible {
+                        let line = output::span_line(
+                            options.format,
+                            &mut clock,
+                            cycles,
+                            depth,
+                            (s.id, &s.target),
+                            &SpanEvent::Record { fields: &fields },
+                        );
+                        stdout.write_all(line.as_bytes()).await?;
+                    }
+                    s.fields.extend(fields);
                 }
                 continue;
             }
+            Packet::FollowsFrom { id, follows } => {
+                if let (Some(span), Some(follows)) = (spans.get(&id), spans.get(&follows)) {
+                    let (s, f) = (span.borrow(), follows.borrow());
+                    if options.filter.span(&s, &span_stack) {
+                        let event = SpanEvent::Follows {
+                            id: f.id,
+                            target: &f.target,
+                        };
+                        let line = output::span_line(
+                            options.format,
+                            &mut clock,
+                            cycles,
+                            depth(&spans, s.id),
+                            (s.id, &s.target),
+                            &event,
+                        );
+                        stdout.write_all(line.as_bytes()).await?;
+                    }
+                }
+                continue;
+            }
+            Packet::CloseSpan(span) => {
+                spans.remove(&span);
+                continue;
+            }
+            Packet::TscFrequency(hz) => {
+                clock.set_frequency(hz);
+                continue;
+            }
         };
 
-        let level = archive_to_level(message.level);
-
-        if let Some(last) = span_stack.last_mut() {
-            last.borrow_mut().messages.push(Message {
-                level,
-                line: message.line as usize,
-                module: String::from(&*message.path),
-                message: String::from(&*message.message),
-            });
+        // observed even when the message isn't printed
+        if message.level == Level::Error {
+            status = status.max(Status::Error);
         }
+        let panicked = message.panicked();
 
-        let fmt_log = match message.level {
-            ArchivedLevel::Error => {
-                format!(
-                    "[\u{001b}[31;1mERROR\u{001b}[0m][{}:{}] > {}",
-                    &*message.path, message.line, &*message.message
-                )
-            }
-            ArchivedLevel::Warn => {
-                format!(
-                    "[\u{001b}[33;1mWARN\u{001b}[0m][{}:{}] > {}",
-                    &*message.path, message.line, &*message.message
-                )
+        if options.filter.message(&message, &span_stack) {
+            if let Some(export) = options.export.as_mut() {
+                let span = span_stack.last().map(|span| span.borrow().id);
+                writeln!(export, "{}", output::message_json(&message, span, cycles))?;
             }
 
-            ArchivedLevel::Info => {
-                format!(
-                    "[\u{001b}[34;1mINFO\u{001b}[0m][{}:{}] > {}",
-                    &*message.path, message.line, &*message.message
-                )
-            }
-            ArchivedLevel::Debug => {
-                format!(
-                    "\u{001b}[4;1m[DEBUG][{}:{}]\u{001b}[0m > {}",
-                    &*message.path, message.line, &*message.message
-                )
-            }
-            ArchivedLevel::Trace => {
-                format!(
-                    "\u{001b}[38;2;128;128;128;2m[TRACE][{}:{}] > {}\u{001b}[0m",
-                    &*message.path, message.line, &*message.message
-                )
-            }
-        };
+            let span = span_stack.last().map(|span| {
+                let id = span.borrow().id;
+                (id, depth(&spans, id))
+            });
+            let line = output::message_line(options.format, &mut clock, cycles, span, &message);
+            stdout.write_all(line.as_bytes()).await?;
+        }
 
-        for _ in 0..span_stack.len() {
-            stdout.write_all(b" ").await?;
+        if panicked {
+            // the kernel halts after a panic, nothing else will be sent
+            status = Status::Panic;
+            break;
         }
-        if !span_stack.is_empty() {
-            stdout.write_all("↳".as_bytes()).await?;
+
+        if let Some(last) = span_stack.last_mut() {
+            last.borrow_mut().messages.push(message);
         }
-        stdout.write_all(fmt_log.as_bytes()).await?;
-        let _ = stdout.write(b"\n").await?;
     }
 
-    Ok(())
+    stdout.flush().await?;
+    let skipped = framed.decoder().skipped();
+    if skipped > 0 {
+        eprintln!("skipped {} invalid frames", skipped);
+    }
+    Ok(status)
 }
 
-const fn archive_to_level(archive: ArchivedLevel) -> Level {
-    match archive {
-        ArchivedLevel::Error => Level::Error,
-        ArchivedLevel::Warn => Level::Warn,
-        ArchivedLevel::Info => Level::Info,
-        ArchivedLevel::Debug => Level::Debug,
-        ArchivedLevel::Trace => Level::Trace,
-    }
+/// Ancestors of the span still open.
+fn depth(spans: &HashMap<u64, Rc<RefCell<Span>>>, id: u64) -> usize {
+    let parent = |id: u64| spans.get(&id).and_then(|span| span.borrow().parent);
+    std::iter::successors(parent(id), |&id| parent(id)).count()
 }
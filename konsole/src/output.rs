@@ -0,0 +1,280 @@
+//! Lines printed for the packets
+use protocols::log::Level;
+
+use crate::{
+    clock::Clock,
+    fields::{self, Field},
+    packet::Message,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Coloured with ANSI escape codes
+    Text,
+    /// Text without escape codes
+    Plain,
+    /// JSON lines
+    Json,
+}
+
+impl Format {
+    pub fn parse(word: &str) -> Result<Self, String> {
+        Ok(match word {
+            "text" => Self::Text,
+            "plain" => Self::Plain,
+            "json" => Self::Json,
+            _ => return Err(format!("unknown format `{}`", word)),
+        })
+    }
+}
+
+/// Event of a span printed on its own line.
+pub enum SpanEvent<'a> {
+    Open {
+        fields: &'a [Field],
+    },
+    /// `elapsed` cycles since the span was entered
+    Close {
+        elapsed: Option<u64>,
+    },
+    Record {
+        fields: &'a [Field],
+    },
+    Follows {
+        id: u64,
+        target: &'a str,
+    },
+}
+
+/// Line of an event of the span `id`, `depth` is the number of its ancestors.
+pub fn span_line(
+    format: Format,
+    clock: &mut Clock,
+    cycles: Option<u64>,
+    depth: usize,
+    (id, target): (u64, &str),
+    event: &SpanEvent<'_>,
+) -> String {
+    if format == Format::Json {
+        let (name, details) = match event {
+            SpanEvent::Open { fields } => {
+                ("open", format!(",\"fields\":{}", fields::to_json(fields)))
+            }
+            SpanEvent::Close { elapsed } => {
+                ("close", format!(",\"elapsed\":{}", or_null(*elapsed)))
+            }
+            SpanEvent::Record { fields } => {
+                ("record", format!(",\"fields\":{}", fields::to_json(fields)))
+            }
+            SpanEvent::Follows { id: follows, .. } => {
+                ("follows", format!(",\"follows\":{}", follows))
+            }
+        };
+        return format!(
+            "{{\"event\":\"{}\",\"span\":{},\"target\":{},\"cycles\":{}{}}}\n",
+            name,
+            id,
+            fields::json_string(target),
+            or_null(cycles),
+            details
+        );
+    }
+
+    let text = match event {
+        SpanEvent::Open { fields } => {
+            format!(
+                "OPEN: {} - {} - fields:{}",
+                id,
+                target,
+                fields::render(fields)
+            )
+        }
+        SpanEvent::Close { elapsed } => {
+            let elapsed = elapsed
+                .map(|elapsed| format!(" ({})", clock.elapsed(elapsed)))
+                .unwrap_or_default();
+            format!("CLOSE: {} - {}{}", id, target, elapsed)
+        }
+        SpanEvent::Record { fields } => {
+            format!(
+                "RECORD: {} - {} - fields:{}",
+                id,
+                target,
+                fields::render(fields)
+            )
+        }
+        SpanEvent::Follows {
+            id: follows,
+            target: follows_target,
+        } => format!(
+            "FOLLOWS: {} - {} follows {} - {}",
+            id, target, follows, follows_target
+        ),
+    };
+    text_line(
+        format,
+        format!("{}{}{}", clock.stamp(cycles), " ".repeat(depth), text),
+    )
+}
+
+/// Line of a message, `depth` is the number of ancestors of its innermost `span`.
+pub fn message_line(
+    format: Format,
+    clock: &mut Clock,
+    cycles: Option<u64>,
+    span: Option<(u64, usize)>,
+    message: &Message,
+) -> String {
+    if format == Format::Json {
+        return format!(
+            "{}\n",
+            message_json(message, span.map(|(id, _)| id), cycles)
+        );
+    }
+
+    let text = format!(
+        "{}{}",
+        fields::message(&message.fields).unwrap_or_default(),
+        fields::render(&message.fields)
+    );
+    let fmt_log = match message.level {
+        Level::Error => {
+            format!(
+                "[\u{001b}[31;1mERROR\u{001b}[0m][{}:{}] > {}",
+                message.module, message.line, text
+            )
+        }
+        Level::Warn => {
+            format!(
+                "[\u{001b}[33;1mWARN\u{001b}[0m][{}:{}] > {}",
+                message.module, message.line, text
+            )
+        }
+
+        Level::Info => {
+            format!(
+                "[\u{001b}[34;1mINFO\u{001b}[0m][{}:{}] > {}",
+                message.module, message.line, text
+            )
+        }
+        Level::Debug => {
+            format!(
+                "\u{001b}[4;1m[DEBUG][{}:{}]\u{001b}[0m > {}",
+                message.module, message.line, text
+            )
+        }
+        Level::Trace => {
+            format!(
+                "\u{001b}[38;2;128;128;128;2m[TRACE][{}:{}] > {}\u{001b}[0m",
+                message.module, message.line, text
+            )
+        }
+    };
+
+    let indent = span
+        .map(|(_, depth)| format!("{}↳", " ".repeat(depth + 1)))
+        .unwrap_or_default();
+    text_line(
+        format,
+        format!("{}{}{}", clock.stamp(cycles), indent, fmt_log),
+    )
+}
+
+/// JSON object of a message sent at `cycles` and its innermost span.
+pub fn message_json(message: &Message, span: Option<u64>, cycles: Option<u64>) -> String {
+    format!(
+        "{{\"event\":\"message\",\"level\":\"{:?}\",\"module\":{},\"line\":{},\"span\":{},\"cycles\":{},\"fields\":{}}}",
+        message.level,
+        fields::json_string(&message.module),
+        message.line,
+        or_null(span),
+        or_null(cycles),
+        fields::to_json(&message.fields)
+    )
+}
+
+fn text_line(format: Format, line: String) -> String {
+    let line = match format {
+        Format::Plain => strip_escapes(&line),
+        Format::Text | Format::Json => line,
+    };
+    line + "\n"
+}
+
+/// Reply to a command, the escape codes are only kept in the coloured format.
+pub fn reply(format: Format, reply: String) -> String {
+    match format {
+        Format::Text => reply,
+        Format::Plain | Format::Json => strip_escapes(&reply),
+    }
+}
+
+fn or_null(value: Option<u64>) -> String {
+    value.map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+/// Remove the ANSI escape sequences of the text.
+pub fn strip_escapes(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{001b}' {
+            // CSI sequences end with a letter
+            let _ = chars.by_ref().find(char::is_ascii_alphabetic);
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+#[test]
+fn output_formats() {
+    use crate::fields::Value;
+
+    let message = Message {
+        level: Level::Warn,
+        line: 3,
+        module: "kernel::mem".to_string(),
+        fields: vec![Field {
+            name: "message".to_string(),
+            value: Value::Debug("low memory".to_string()),
+        }],
+    };
+    let mut clock = Clock::default();
+    assert_eq!(
+        message_line(Format::Plain, &mut clock, None, Some((7, 0)), &message),
+        " ↳[WARN][kernel::mem:3] > low memory\n"
+    );
+    assert_eq!(
+        message_line(Format::Json, &mut clock, Some(12), None, &message),
+        "{\"event\":\"message\",\"level\":\"Warn\",\"module\":\"kernel::mem\",\"line\":3,\
+         \"span\":null,\"cycles\":12,\"fields\":{\"message\":\"low memory\"}}\n"
+    );
+    assert_eq!(
+        span_line(
+            Format::Json,
+            &mut clock,
+            None,
+            1,
+            (7, "map_range"),
+            &SpanEvent::Close { elapsed: Some(40) }
+        ),
+        "{\"event\":\"close\",\"span\":7,\"target\":\"map_range\",\"cycles\":null,\"elapsed\":40}\n"
+    );
+    assert_eq!(
+        span_line(
+            Format::Plain,
+            &mut clock,
+            None,
+            1,
+            (7, "map_range"),
+            &SpanEvent::Close { elapsed: None }
+        ),
+        " CLOSE: 7 - map_range\n"
+    );
+
+    let error = "\u{001b}[31;1merror\u{001b}[0m: busy\n".to_string();
+    assert_eq!(reply(Format::Json, error.clone()), "error: busy\n");
+    assert_eq!(reply(Format::Text, error.clone()), error);
+}
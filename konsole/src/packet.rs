@@ -0,0 +1,148 @@
+use std::io;
+
+use protocols::log::{self, v0, Frame, FrameError, Level};
+
+use crate::fields::{Field, Value};
+
+/// Log packet of any version.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Packet {
+    NewSpan {
+        id: u64,
+        /// Empty for the first version
+        name: String,
+        target: String,
+        fields: Vec<Field>,
+    },
+    Message(Message),
+    EnterSpan(u64),
+    ExitSpan {
+        id: u64,
+        /// Cycles since the span was entered, unknown before the third version
+        elapsed: Option<u64>,
+    },
+    Response(Vec<u8>),
+    Record {
+        id: u64,
+        fields: Vec<Field>,
+    },
+    FollowsFrom {
+        id: u64,
+        follows: u64,
+    },
+    CloseSpan(u64),
+    /// Frequency of the time stamp counter in Hz
+    TscFrequency(u64),
+}
+
+/// Packet and the time stamp counter when it was sent, unknown before the third version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stamped {
+    pub cycles: Option<u64>,
+    pub packet: Packet,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub level: Level,
+    pub line: usize,
+    pub module: String,
+    pub fields: Vec<Field>,
+}
+
+impl Message {
+    /// Sent by the panic handlers, with a `panic = true` field.
+    pub fn panicked(&self) -> bool {
+        self.fields
+            .iter()
+            .any(|field| field.name == "panic" && field.value == Value::Bool(true))
+    }
+}
+
+impl Stamped {
+    /// Decode the bytes of a frame.
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        match log::archived_frame(bytes) {
+            Ok(Frame::V0(packet)) => Ok(Self {
+                cycles: None,
+                packet: Packet::from_v0(packet),
+            }),
+            Ok(Frame::Typed { cycles, packet }) => Ok(Self {
+                cycles,
+                packet: Packet::from_typed(packet),
+            }),
+            Err(FrameError::Version(version)) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported log packet version {}", version),
+            )),
+            Err(FrameError::Invalid) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid log frame",
+            )),
+        }
+    }
+}
+
+impl Packet {
+    fn from_v0(packet: &v0::ArchivedLogPacket<'_>) -> Self {
+        match packet {
+            v0::ArchivedLogPacket::NewSpan(span) => Self::NewSpan {
+                id: span.id,
+                name: String::new(),
+                target: span.target.to_string(),
+                fields: Field::flattened(&span.fields),
+            },
+            v0::ArchivedLogPacket::Message(message) => Self::Message(Message {
+                level: message.level.into(),
+                line: message.line as usize,
+                module: message.path.to_string(),
+                fields: Field::text(&message.message),
+            }),
+            v0::ArchivedLogPacket::EnterSpan(id) => Self::EnterSpan(*id),
+            v0::ArchivedLogPacket::ExitSpan(id) => Self::ExitSpan {
+                id: *id,
+                elapsed: None,
+            },
+        }
+    }
+
+    fn from_typed(packet: &log::ArchivedLogPacket<'_>) -> Self {
+        let fields = |fields: &[log::ArchivedField<'_>]| -> Vec<Field> {
+            fields.iter().map(Field::from).collect()
+        };
+        match packet {
+            log::ArchivedLogPacket::NewSpan(span) => Self::NewSpan {
+                id: span.id,
+                name: span.name.to_string(),
+                target: span.target.to_string(),
+                fields: fields(&span.fields),
+            },
+            log::ArchivedLogPacket::Message(message) => Self::Message(Message {
+                level: message.level.into(),
+                line: message.line as usize,
+                module: message.path.to_string(),
+                fields: fields(&message.fields),
+            }),
+            log::ArchivedLogPacket::EnterSpan(id) => Self::EnterSpan(*id),
+            log::ArchivedLogPacket::ExitSpan(id) => Self::ExitSpan {
+                id: *id,
+                elapsed: None,
+            },
+            log::ArchivedLogPacket::Response(bytes) => Self::Response(bytes.to_vec()),
+            log::ArchivedLogPacket::Record { id, fields: values } => Self::Record {
+                id: *id,
+                fields: fields(values),
+            },
+            log::ArchivedLogPacket::FollowsFrom { id, follows } => Self::FollowsFrom {
+                id: *id,
+                follows: *follows,
+            },
+            log::ArchivedLogPacket::CloseSpan(id) => Self::CloseSpan(*id),
+            log::ArchivedLogPacket::Exited { id, elapsed } => Self::ExitSpan {
+                id: *id,
+                elapsed: Some(*elapsed),
+            },
+            log::ArchivedLogPacket::TscFrequency(hz) => Self::TscFrequency(*hz),
+        }
+    }
+}
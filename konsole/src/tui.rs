@@ -0,0 +1,798 @@
+//! Terminal interface browsing the tree of spans
+//!
+//! The spans are kept once closed so a boot can be inspected after the fact, the messages are
+//! listed under the span they were sent in.
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    io,
+    rc::Rc,
+    time::Duration,
+};
+
+use crossterm::{
+    event::{Event, EventStream, KeyCode, KeyEvent},
+    execute,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use protocols::log::Level;
+use tokio::io::AsyncRead;
+use tokio_util::codec::FramedRead;
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span as Styled, Spans},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame, Terminal,
+};
+
+use kcore::futures::stream::StreamExt;
+
+use crate::{
+    clock::Clock,
+    codec::LogDecoder,
+    fields, output,
+    packet::{Message, Packet, Stamped},
+    Options, Span, Status,
+};
+
+/// Delay between two redraws while packets are received
+const REDRAW: Duration = Duration::from_millis(50);
+
+const LEVELS: [Level; 5] = [
+    Level::Error,
+    Level::Warn,
+    Level::Info,
+    Level::Debug,
+    Level::Trace,
+];
+
+/// Index of a span in the session, the kernel reuses the ids of the closed spans.
+type Node = usize;
+
+/// Child of a span or of the top level, in the order they were sent.
+#[derive(Debug, Clone, Copy)]
+enum Entry {
+    Span(Node),
+    /// Index in the messages of the span or in the messages outside spans
+    Message(usize),
+}
+
+/// Line of the tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Row {
+    Span {
+        node: Node,
+        depth: usize,
+    },
+    Message {
+        span: Option<Node>,
+        index: usize,
+        depth: usize,
+    },
+}
+
+/// Spans and messages received.
+#[derive(Default)]
+struct Session {
+    clock: Clock,
+    /// Spans in the order they were created
+    nodes: Vec<Rc<RefCell<Span>>>,
+    /// Node of the open span holding each id
+    ids: HashMap<u64, Node>,
+    stack: Vec<Rc<RefCell<Span>>>,
+    /// Children of the spans, `None` for the top level
+    children: HashMap<Option<Node>, Vec<Entry>>,
+    /// Messages sent outside spans
+    top: Vec<Message>,
+    /// Cycles between the last enter and exit of the spans
+    elapsed: HashMap<Node, u64>,
+    /// Spans every handle was dropped
+    closed: HashSet<Node>,
+    /// Messages received for each level, including the filtered ones
+    levels: [usize; LEVELS.len()],
+    modules: BTreeMap<String, usize>,
+    status: Status,
+}
+
+impl Session {
+    fn apply(&mut self, Stamped { packet, .. }: Stamped, options: &Options) {
+        match packet {
+            Packet::NewSpan {
+                id, target, fields, ..
+            } => {
+                let parent = self.stack.last().map(|parent| parent.borrow().id);
+                let node = self.nodes.len();
+                self.nodes.push(Span::new(id, parent, target, fields));
+                self.ids.insert(id, node);
+                self.children
+                    .entry(self.current())
+                    .or_default()
+                    .push(Entry::Span(node));
+            }
+            Packet::EnterSpan(id) => {
+                if let Some(span) = self.span(id) {
+                    self.stack.push(Rc::clone(span));
+                }
+            }
+            Packet::ExitSpan { id, elapsed } => {
+                if let Some(i) = self.stack.iter().rposition(|s| s.borrow().id == id) {
+                    self.stack.remove(i);
+                }
+                if let (Some(&node), Some(elapsed)) = (self.ids.get(&id), elapsed) {
+                    self.elapsed.insert(node, elapsed);
+                }
+            }
+            Packet::Record { id, fields } => {
+                if let Some(span) = self.span(id) {
+                    span.borrow_mut().fields.extend(fields);
+                }
+            }
+            Packet::CloseSpan(id) => {
+                if let Some(node) = self.ids.remove(&id) {
+                    self.closed.insert(node);
+                }
+            }
+            Packet::TscFrequency(hz) => self.clock.set_frequency(hz),
+            Packet::Message(message) => self.message(message, options),
+            Packet::FollowsFrom { .. } | Packet::Response(_) => {}
+        }
+    }
+
+    /// Open span holding `id`.
+    fn span(&self, id: u64) -> Option<&Rc<RefCell<Span>>> {
+        self.ids.get(&id).map(|&node| &self.nodes[node])
+    }
+
+    /// Node of the innermost entered span, its id may have been reused since it was entered.
+    fn current(&self) -> Option<Node> {
+        let span = self.stack.last()?;
+        let id = span.borrow().id;
+        match self.ids.get(&id) {
+            Some(&node) if Rc::ptr_eq(&self.nodes[node], span) => Some(node),
+            _ => self.nodes.iter().rposition(|node| Rc::ptr_eq(node, span)),
+        }
+    }
+
+    fn message(&mut self, message: Message, options: &Options) {
+        self.levels[message.level as usize] += 1;
+        *self.modules.entry(message.module.clone()).or_default() += 1;
+        if message.level == Level::Error {
+            self.status = self.status.max(Status::Error);
+        }
+        if message.panicked() {
+            self.status = Status::Panic;
+        }
+
+        if !options.filter.message(&message, &self.stack) {
+            return;
+        }
+        let parent = self.current();
+        let index = match parent {
+            Some(node) => {
+                let mut span = self.nodes[node].borrow_mut();
+                span.messages.push(message);
+                span.messages.len() - 1
+            }
+            None => {
+                self.top.push(message);
+                self.top.len() - 1
+            }
+        };
+        self.children
+            .entry(parent)
+            .or_default()
+            .push(Entry::Message(index));
+    }
+
+    /// Lines of the tree, the children of the collapsed spans are hidden.
+    fn rows(&self, collapsed: &HashSet<Node>) -> Vec<Row> {
+        let mut rows = vec![];
+        self.push_rows(None, 0, collapsed, &mut rows);
+        rows
+    }
+
+    fn push_rows(
+        &self,
+        parent: Option<Node>,
+        depth: usize,
+        collapsed: &HashSet<Node>,
+        rows: &mut Vec<Row>,
+    ) {
+        for entry in self.children.get(&parent).into_iter().flatten() {
+            match *entry {
+                Entry::Span(node) => {
+                    rows.push(Row::Span { node, depth });
+                    if !collapsed.contains(&node) {
+                        self.push_rows(Some(node), depth + 1, collapsed, rows);
+                    }
+                }
+                Entry::Message(index) => rows.push(Row::Message {
+                    span: parent,
+                    index,
+                    depth,
+                }),
+            }
+        }
+    }
+
+    /// Calls `f` with the message of the row.
+    fn with_message<T>(
+        &self,
+        span: Option<Node>,
+        index: usize,
+        f: impl FnOnce(&Message) -> T,
+    ) -> T {
+        match span {
+            Some(node) => f(&self.nodes[node].borrow().messages[index]),
+            None => f(&self.top[index]),
+        }
+    }
+
+    /// Text of the row without style, searched by the user.
+    fn text(&self, row: Row) -> String {
+        match row {
+            Row::Span { node, .. } => {
+                let span = self.nodes[node].borrow();
+                let elapsed = self
+                    .elapsed
+                    .get(&node)
+                    .map(|&elapsed| format!(" ({})", self.clock.elapsed(elapsed)))
+                    .unwrap_or_default();
+                format!(
+                    "{}{}{}",
+                    span.target,
+                    output::strip_escapes(&fields::render(&span.fields)),
+                    elapsed
+                )
+            }
+            Row::Message { span, index, .. } => self.with_message(span, index, |message| {
+                format!(
+                    "[{:?}][{}:{}] > {}{}",
+                    message.level,
+                    message.module,
+                    message.line,
+                    fields::message(&message.fields).unwrap_or_default(),
+                    output::strip_escapes(&fields::render(&message.fields))
+                )
+            }),
+        }
+    }
+
+    fn level(&self, row: Row) -> Option<Level> {
+        match row {
+            Row::Span { .. } => None,
+            Row::Message { span, index, .. } => {
+                Some(self.with_message(span, index, |message| message.level))
+            }
+        }
+    }
+}
+
+fn level_style(level: Level) -> Style {
+    let style = Style::default();
+    match level {
+        Level::Error => style.fg(Color::Red).add_modifier(Modifier::BOLD),
+        Level::Warn => style.fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        Level::Info => style.fg(Color::Blue),
+        Level::Debug => style.add_modifier(Modifier::UNDERLINED),
+        Level::Trace => style.fg(Color::DarkGray),
+    }
+}
+
+/// State of the interface.
+#[derive(Default)]
+struct App {
+    session: Session,
+    collapsed: HashSet<Node>,
+    rows: Vec<Row>,
+    selected: usize,
+    /// First row displayed
+    offset: usize,
+    /// Keep the last row selected as packets arrive
+    follow: bool,
+    /// Text searched, typed after `/`
+    search: String,
+    searching: bool,
+    /// The stream ended or failed
+    disconnected: Option<String>,
+}
+
+impl App {
+    fn refresh(&mut self) {
+        let selected = self.rows.get(self.selected).copied();
+        self.rows = self.session.rows(&self.collapsed);
+        if self.follow {
+            self.selected = self.rows.len().saturating_sub(1);
+        } else if let Some(selected) = selected {
+            // the rows above may have been collapsed
+            self.selected = self
+                .rows
+                .iter()
+                .position(|row| *row == selected)
+                .unwrap_or(self.selected);
+        }
+        self.selected = self.selected.min(self.rows.len().saturating_sub(1));
+    }
+
+    fn select(&mut self, row: usize) {
+        self.selected = row.min(self.rows.len().saturating_sub(1));
+        self.follow = self.selected + 1 >= self.rows.len();
+    }
+
+    /// Collapse or expand the span of the selected row.
+    fn toggle(&mut self, collapse: Option<bool>) {
+        let node = match self.rows.get(self.selected) {
+            Some(Row::Span { node, .. }) => *node,
+            // collapse the span of a message
+            Some(Row::Message {
+                span: Some(node), ..
+            }) if collapse != Some(false) => *node,
+            _ => return,
+        };
+        let collapse = collapse.unwrap_or(!self.collapsed.contains(&node));
+        if collapse {
+            self.collapsed.insert(node);
+        } else {
+            self.collapsed.remove(&node);
+        }
+        self.refresh();
+        if let Some(row) = self
+            .rows
+            .iter()
+            .position(|row| matches!(row, Row::Span { node: span, .. } if *span == node))
+        {
+            self.selected = row;
+        }
+    }
+
+    /// Select the next row matching the search, backwards if `!forward`.
+    fn find(&mut self, forward: bool, from: usize) {
+        if self.search.is_empty() || self.rows.is_empty() {
+            return;
+        }
+        let search = self.search.to_lowercase();
+        let len = self.rows.len();
+        let found = (0..len)
+            .map(|i| {
+                if forward {
+                    (from + i) % len
+                } else {
+                    (from + len - i) % len
+                }
+            })
+            .find(|&i| self.matches(self.rows[i], &search));
+        if let Some(row) = found {
+            self.select(row);
+        }
+    }
+
+    fn matches(&self, row: Row, search: &str) -> bool {
+        !search.is_empty() && self.session.text(row).to_lowercase().contains(search)
+    }
+
+    /// Handle a key, returns `false` to quit.
+    fn key(&mut self, key: KeyEvent, page: usize) -> bool {
+        if self.searching {
+            match key.code {
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Esc => {
+                    self.searching = false;
+                    self.search.clear();
+                }
+                KeyCode::Backspace => {
+                    self.search.pop();
+                }
+                KeyCode::Char(c) => {
+                    self.search.push(c);
+                    self.find(true, self.selected);
+                }
+                _ => {}
+            }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.select(self.selected.saturating_sub(1)),
+            KeyCode::Down | KeyCode::Char('j') => self.select(self.selected + 1),
+            KeyCode::PageUp => self.select(self.selected.saturating_sub(page)),
+            KeyCode::PageDown => self.select(self.selected + page),
+            KeyCode::Home | KeyCode::Char('g') => self.select(0),
+            KeyCode::End | KeyCode::Char('G') => self.select(self.rows.len()),
+            KeyCode::Enter | KeyCode::Char(' ') => self.toggle(None),
+            KeyCode::Left | KeyCode::Char('h') => self.toggle(Some(true)),
+            KeyCode::Right | KeyCode::Char('l') => self.toggle(Some(false)),
+            KeyCode::Char('f') => {
+                self.follow = !self.follow;
+                self.refresh();
+            }
+            KeyCode::Char('/') => {
+                self.searching = true;
+                self.search.clear();
+            }
+            KeyCode::Char('n') => self.find(true, self.selected + 1),
+            KeyCode::Char('N') => {
+                self.find(false, (self.selected + self.rows.len()).saturating_sub(1))
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<'_, B>) {
+        let [main, status] = split(
+            Direction::Vertical,
+            f.size(),
+            [Constraint::Min(3), Constraint::Length(1)],
+        );
+        let [tree, sidebar] = split(
+            Direction::Horizontal,
+            main,
+            [Constraint::Min(40), Constraint::Length(32)],
+        );
+        let [tree, details] = split(
+            Direction::Vertical,
+            tree,
+            [Constraint::Min(3), Constraint::Length(6)],
+        );
+
+        self.draw_tree(f, tree);
+        self.draw_details(f, details);
+        self.draw_sidebar(f, sidebar);
+
+        let status_line = if self.searching {
+            format!("/{}", self.search)
+        } else {
+            let state = match (&self.disconnected, self.session.status) {
+                (_, Status::Panic) => "kernel panicked".to_string(),
+                (Some(reason), _) => reason.clone(),
+                (None, _) if self.follow => "following".to_string(),
+                (None, _) => "paused".to_string(),
+            };
+            format!(
+                "{} - {} rows - q quit, enter fold, / search, n/N next, f follow",
+                state,
+                self.rows.len()
+            )
+        };
+        f.render_widget(
+            Paragraph::new(status_line).style(Style::default().add_modifier(Modifier::REVERSED)),
+            status,
+        );
+    }
+
+    fn draw_tree<B: Backend>(&mut self, f: &mut Frame<'_, B>, area: Rect) {
+        let height = usize::from(area.height.saturating_sub(2)).max(1);
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+
+        let search = self.search.to_lowercase();
+        let items: Vec<ListItem<'_>> = self.rows[self.offset.min(self.rows.len())..]
+            .iter()
+            .take(height)
+            .map(|&row| {
+                let (depth, marker) = match row {
+                    Row::Span { node, depth } if self.collapsed.contains(&node) => (depth, "▸ "),
+                    Row::Span { depth, .. } => (depth, "▾ "),
+                    Row::Message { depth, .. } => (depth, "  "),
+                };
+                let mut style = match (row, self.session.level(row)) {
+                    (_, Some(level)) => level_style(level),
+                    (Row::Span { node, .. }, None) if self.session.closed.contains(&node) => {
+                        Style::default().add_modifier(Modifier::DIM)
+                    }
+                    _ => Style::default().add_modifier(Modifier::BOLD),
+                };
+                if self.matches(row, &search) {
+                    style = style.bg(Color::Magenta);
+                }
+                ListItem::new(Spans::from(vec![
+                    Styled::raw(format!("{}{}", "  ".repeat(depth), marker)),
+                    Styled::styled(self.session.text(row), style),
+                ]))
+            })
+            .collect();
+
+        let mut state = ListState::default();
+        state.select(
+            Some(self.selected.saturating_sub(self.offset)).filter(|_| !self.rows.is_empty()),
+        );
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("spans"))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, area, &mut state);
+    }
+
+    /// Fields of the selected row.
+    fn draw_details<B: Backend>(&self, f: &mut Frame<'_, B>, area: Rect) {
+        let lines: Vec<Spans<'_>> = match self.rows.get(self.selected) {
+            Some(Row::Span { node, .. }) => {
+                let span = self.session.nodes[*node].borrow();
+                let mut lines = vec![Spans::from(format!(
+                    "span {} - {} - {} messages",
+                    span.id,
+                    span.target,
+                    span.messages.len()
+                ))];
+                lines.extend(
+                    span.fields
+                        .iter()
+                        .map(|field| Spans::from(field.to_string())),
+                );
+                lines
+            }
+            Some(&Row::Message { span, index, .. }) => {
+                self.session.with_message(span, index, |message| {
+                    message
+                        .fields
+                        .iter()
+                        .map(|field| Spans::from(field.to_string()))
+                        .collect()
+                })
+            }
+            None => vec![],
+        };
+        let block = Block::default().borders(Borders::ALL).title("fields");
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    /// Messages received per level and per module.
+    fn draw_sidebar<B: Backend>(&self, f: &mut Frame<'_, B>, area: Rect) {
+        let [levels, modules] = split(
+            Direction::Vertical,
+            area,
+            [
+                Constraint::Length(LEVELS.len() as u16 + 2),
+                Constraint::Min(3),
+            ],
+        );
+
+        let items: Vec<ListItem<'_>> = LEVELS
+            .iter()
+            .map(|&level| {
+                ListItem::new(Spans::from(vec![
+                    Styled::styled(format!("{:<6}", format!("{:?}", level)), level_style(level)),
+                    Styled::raw(format!("{:>8}", self.session.levels[level as usize])),
+                ]))
+            })
+            .collect();
+        let block = Block::default().borders(Borders::ALL).title("levels");
+        f.render_widget(List::new(items).block(block), levels);
+
+        let mut counts: Vec<_> = self.session.modules.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1));
+        let width = usize::from(area.width.saturating_sub(2 + 8));
+        let items: Vec<ListItem<'_>> = counts
+            .into_iter()
+            .map(|(module, count)| {
+                // the end of the path is the most specific part
+                let skip = module.chars().count().saturating_sub(width);
+                let module: String = module.chars().skip(skip).collect();
+                ListItem::new(format!("{:<width$}{:>8}", module, count, width = width))
+            })
+            .collect();
+        let block = Block::default().borders(Borders::ALL).title("modules");
+        f.render_widget(List::new(items).block(block), modules);
+    }
+}
+
+fn split<const N: usize>(
+    direction: Direction,
+    area: Rect,
+    constraints: [Constraint; N],
+) -> [Rect; N] {
+    let rects = Layout::default()
+        .direction(direction)
+        .constraints(constraints.as_ref())
+        .split(area);
+    let mut split = [Rect::default(); N];
+    split.copy_from_slice(&rects);
+    split
+}
+
+/// Restores the terminal when the interface stops, even on errors.
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        if let Err(err) = execute!(io::stdout(), EnterAlternateScreen) {
+            let _ = terminal::disable_raw_mode();
+            return Err(err);
+        }
+        Ok(Self)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Browse the logs read from `reader` until the user quits.
+pub async fn run<R>(reader: R, options: &Options) -> io::Result<Status>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut framed = FramedRead::new(reader, LogDecoder::new());
+    let mut events = EventStream::new();
+    let mut redraw = tokio::time::interval(REDRAW);
+
+    let _screen = Screen::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    terminal.hide_cursor()?;
+
+    let mut app = App {
+        follow: true,
+        ..App::default()
+    };
+    let mut dirty = true;
+
+    loop {
+        tokio::select! {
+            packet = framed.next(), if app.disconnected.is_none() => {
+                match packet {
+                    Some(Ok(packet)) => app.session.apply(packet, options),
+                    Some(Err(err)) => app.disconnected = Some(format!("invalid stream: {}", err)),
+                    None => {
+                        let skipped = framed.decoder().skipped();
+                        app.disconnected = Some(match skipped {
+                            0 => "disconnected".to_string(),
+                            n => format!("disconnected, skipped {} invalid frames", n),
+                        });
+                    }
+                }
+                dirty = true;
+            }
+            event = events.next() => match event.transpose()? {
+                Some(Event::Key(key)) => {
+                    let page = usize::from(terminal.size()?.height.saturating_sub(10)).max(1);
+                    if !app.key(key, page) {
+                        break;
+                    }
+                    terminal.draw(|f| app.draw(f))?;
+                }
+                Some(Event::Resize(_, _)) => dirty = true,
+                Some(_) => {}
+                None => break,
+            },
+            _ = redraw.tick(), if dirty => {
+                app.refresh();
+                terminal.draw(|f| app.draw(f))?;
+                dirty = false;
+            }
+        }
+    }
+
+    terminal.show_cursor()?;
+    Ok(app.session.status)
+}
+
+#[test]
+fn span_tree() {
+    use crate::{fields::Field, filter::Filter, output::Format};
+
+    let options = Options {
+        filter: Filter::default(),
+        format: Format::Text,
+        tui: true,
+        export: None,
+    };
+    let message = |level, text: &str| {
+        Packet::Message(Message {
+            level,
+            line: 0,
+            module: "kernel::mem".to_string(),
+            fields: vec![Field {
+                name: "message".to_string(),
+                value: fields::Value::Debug(text.to_string()),
+            }],
+        })
+    };
+    let mut app = App {
+        follow: true,
+        ..App::default()
+    };
+    for packet in [
+        message(Level::Info, "boot"),
+        Packet::NewSpan {
+            id: 1,
+            name: String::new(),
+            target: "map_range".to_string(),
+            fields: vec![],
+        },
+        Packet::EnterSpan(1),
+        message(Level::Warn, "huge page split"),
+        Packet::ExitSpan {
+            id: 1,
+            elapsed: None,
+        },
+        message(Level::Error, "out of frames"),
+    ] {
+        app.session.apply(
+            Stamped {
+                cycles: None,
+                packet,
+            },
+            &options,
+        );
+    }
+    app.refresh();
+
+    assert_eq!(app.rows.len(), 4);
+    assert_eq!(app.rows[1], Row::Span { node: 0, depth: 0 });
+    assert_eq!(
+        app.rows[2],
+        Row::Message {
+            span: Some(0),
+            index: 0,
+            depth: 1
+        }
+    );
+    assert_eq!(app.selected, 3);
+    assert_eq!(app.session.levels, [1, 1, 1, 0, 0]);
+    assert_eq!(app.session.status, Status::Error);
+
+    app.search = "huge".to_string();
+    app.find(true, 0);
+    assert_eq!(app.selected, 2);
+    assert!(!app.follow);
+
+    app.toggle(Some(true));
+    assert_eq!(app.rows.len(), 3);
+    assert_eq!(app.selected, 1);
+    app.toggle(Some(false));
+
+    // v0 kernels don't send CloseSpan and reuse the id right away
+    for packet in [
+        Packet::NewSpan {
+            id: 1,
+            name: String::new(),
+            target: "unmap_range".to_string(),
+            fields: vec![],
+        },
+        Packet::EnterSpan(1),
+        message(Level::Info, "unmapped"),
+        Packet::ExitSpan {
+            id: 1,
+            elapsed: None,
+        },
+    ] {
+        app.session.apply(
+            Stamped {
+                cycles: None,
+                packet,
+            },
+            &options,
+        );
+    }
+    app.refresh();
+
+    assert_eq!(app.rows.len(), 6);
+    assert_eq!(
+        app.rows[2],
+        Row::Message {
+            span: Some(0),
+            index: 0,
+            depth: 1
+        }
+    );
+    assert_eq!(app.rows[4], Row::Span { node: 1, depth: 0 });
+    assert_eq!(
+        app.rows[5],
+        Row::Message {
+            span: Some(1),
+            index: 0,
+            depth: 1
+        }
+    );
+    assert!(app.session.text(app.rows[2]).contains("huge page split"));
+    assert!(app.session.text(app.rows[5]).contains("unmapped"));
+}
@@ -30,6 +30,13 @@ bitfield! {
 }
 
 impl CR3 {
+    /// Point to the level 4 table in `frame`, caching bits are cleared.
+    #[inline]
+    #[must_use]
+    pub const fn from_frame(frame: PhysicalFrame<Page4Kb>) -> Self {
+        Self(frame.ptr().as_u64())
+    }
+
     #[inline]
     #[must_use]
     pub const fn frame(&self) -> PhysicalFrame<Page4Kb> {
@@ -47,6 +47,15 @@ impl CodeSegmentDescriptor {
 
         this
     }
+
+    /// Long mode code segment accessible from ring 3.
+    #[inline]
+    #[must_use]
+    pub const fn user_x64() -> Self {
+        let mut this = Self::kernel_x64();
+        this.flags = this.flags.set_dpl(crate::Privilege::Ring3 as u8);
+        this
+    }
 }
 
 impl core::fmt::Debug for CodeSegmentDescriptor {
@@ -54,6 +63,8 @@ impl core::fmt::Debug for CodeSegmentDescriptor {
         if *self == Self::kernel_x64() {
             write!(f, "CodeSegmentDescriptor::kernel_x64()")?;
             Ok(())
+        } else if *self == Self::user_x64() {
+            write!(f, "CodeSegmentDescriptor::user_x64()")
         } else {
             // TODO: find a better impl that's not too long
             f.debug_struct("CodeSegmentDescriptor")
@@ -139,4 +150,10 @@ mod test {
         let a = unsafe { core::mem::transmute::<_, u64>(CodeSegmentDescriptor::kernel_x64()) };
         assert_eq!(a, 0x00af9b000000ffff);
     }
+
+    #[test]
+    fn user_segment() {
+        let a = unsafe { core::mem::transmute::<_, u64>(CodeSegmentDescriptor::user_x64()) };
+        assert_eq!(a, 0x00affb000000ffff);
+    }
 }
@@ -46,6 +46,15 @@ impl DataSegmentDescriptor {
 
         this
     }
+
+    /// Data segment accessible from ring 3.
+    #[inline]
+    #[must_use]
+    pub const fn user_x64() -> Self {
+        let mut this = Self::kernel_x64();
+        this.flags = this.flags.set_dpl(crate::Privilege::Ring3 as u8);
+        this
+    }
 }
 
 bitfield! {
@@ -123,3 +132,9 @@ fn kernel_segment() {
     let a = unsafe { core::mem::transmute::<_, u64>(DataSegmentDescriptor::kernel_x64()) };
     assert_eq!(a, 0x00cf93000000ffff);
 }
+
+#[test]
+fn user_segment() {
+    let a = unsafe { core::mem::transmute::<_, u64>(DataSegmentDescriptor::user_x64()) };
+    assert_eq!(a, 0x00cff3000000ffff);
+}
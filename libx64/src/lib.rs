@@ -16,10 +16,12 @@ pub mod control;
 pub mod descriptors;
 pub mod gdt;
 pub mod idt;
+pub mod msr;
 pub mod paging;
 pub mod port;
 pub mod rflags;
 pub mod segments;
+pub mod tsc;
 pub mod units;
 
 #[repr(u8)]
@@ -66,6 +68,17 @@ pub fn sti() {
     }
 }
 
+/// Enable interrupts and halt until the next one arrives.
+///
+/// `sti` only takes effect after the following instruction, so an interrupt that becomes pending
+/// between the two cannot be serviced before the `hlt` and leave the cpu sleeping.
+#[inline]
+pub fn sti_hlt() {
+    unsafe {
+        asm!("sti; hlt", options(nostack, nomem));
+    }
+}
+
 pub fn without_interrupts<F, R>(f: F) -> R
 where
     F: FnOnce() -> R,
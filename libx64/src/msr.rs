@@ -0,0 +1,121 @@
+use core::arch::asm;
+
+use crate::{address::VirtualAddr, rflags::RFlags};
+
+/// Model specific register
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Msr(u32);
+
+impl Msr {
+    #[inline]
+    #[must_use]
+    pub const fn new(reg: u32) -> Self {
+        Self(reg)
+    }
+
+    /// # Safety
+    ///
+    /// The register must exist on the cpu, reading an unknown msr raises a #GP
+    #[inline]
+    #[must_use]
+    pub unsafe fn read(&self) -> u64 {
+        let (high, low): (u32, u32);
+        asm!(
+            "rdmsr",
+            in("ecx") self.0,
+            out("eax") low, out("edx") high,
+            options(nomem, nostack, preserves_flags),
+        );
+        ((high as u64) << 32) | (low as u64)
+    }
+
+    /// # Safety
+    ///
+    /// Writing a msr can break memory safety (ie. moving the APIC MMIO window)
+    #[inline]
+    pub unsafe fn write(&mut self, value: u64) {
+        let low = value as u32;
+        let high = (value >> 32) as u32;
+        asm!(
+            "wrmsr",
+            in("ecx") self.0,
+            in("eax") low, in("edx") high,
+            options(nostack, preserves_flags),
+        );
+    }
+}
+
+/// Local APIC base address and enable flags
+pub const IA32_APIC_BASE: Msr = Msr::new(0x1B);
+
+/// Segments loaded by `syscall` and `sysret`
+pub const IA32_STAR: Msr = Msr::new(0xC000_0081);
+
+/// Entry point of `syscall` in 64 bits mode
+pub const IA32_LSTAR: Msr = Msr::new(0xC000_0082);
+
+/// `rflags` bits cleared by `syscall`
+pub const IA32_FMASK: Msr = Msr::new(0xC000_0084);
+
+/// Segment selectors of the `STAR` register.
+///
+/// `syscall` loads `kernel` in CS and `kernel + 8` in SS. `sysret` to 64 bits code loads
+/// `user + 16` in CS and `user + 8` in SS, both with a requested privilege level of 3.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Star {
+    pub kernel: u16,
+    pub user: u16,
+}
+
+#[inline]
+#[must_use]
+pub fn star() -> Star {
+    // SAFETY: the register exists on every cpu supporting long mode
+    let value = unsafe { IA32_STAR.read() };
+    Star {
+        kernel: (value >> 32) as u16,
+        user: (value >> 48) as u16,
+    }
+}
+
+/// # Safety
+///
+/// The selectors must follow the layout expected by `syscall` and `sysret`
+#[inline]
+pub unsafe fn set_star(star: Star) {
+    let value = (u64::from(star.user) << 48) | (u64::from(star.kernel) << 32);
+    let mut msr = IA32_STAR;
+    msr.write(value);
+}
+
+#[inline]
+#[must_use]
+pub fn lstar() -> VirtualAddr {
+    // SAFETY: the register exists on every cpu supporting long mode
+    VirtualAddr::new(unsafe { IA32_LSTAR.read() })
+}
+
+/// # Safety
+///
+/// `entry` must be a valid `syscall` entry point, it runs in ring 0 on the user stack
+#[inline]
+pub unsafe fn set_lstar(entry: VirtualAddr) {
+    let mut msr = IA32_LSTAR;
+    msr.write(entry.as_u64());
+}
+
+#[inline]
+#[must_use]
+pub fn sfmask() -> RFlags {
+    // SAFETY: the register exists on every cpu supporting long mode
+    RFlags::from_bits_truncate(unsafe { IA32_FMASK.read() })
+}
+
+/// # Safety
+///
+/// Interrupts must be masked unless the entry point is safe to interrupt on the user stack
+#[inline]
+pub unsafe fn set_sfmask(mask: RFlags) {
+    let mut msr = IA32_FMASK;
+    msr.write(mask.bits());
+}
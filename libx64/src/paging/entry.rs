@@ -134,6 +134,12 @@ impl RawPageEntry {
     pub const fn set_flags(self, flags: Flags) -> Self {
         Self(self.as_u64() | flags.bits())
     }
+    /// Overwrite the flags, unlike [`RawPageEntry::set_flags`] missing flags are cleared.
+    #[inline]
+    #[must_use]
+    pub const fn replace_flags(self, flags: Flags) -> Self {
+        Self((self.as_u64() & !Flags::all().bits()) | flags.bits())
+    }
     #[inline]
     #[must_use]
     pub const fn get_flags(self) -> Flags {
@@ -229,6 +235,15 @@ impl<L> PageEntry<L> {
         this.raw = this.raw.set_flags(flags);
     }
 
+    /// # Safety
+    ///
+    /// The flags must be valid for the current entry
+    #[inline]
+    pub unsafe fn replace_flags(self: Pin<&mut Self>, flags: Flags) {
+        let this = self.get_unchecked_mut();
+        this.raw = this.raw.replace_flags(flags);
+    }
+
     #[inline]
     pub fn set_user_bits(self: Pin<&mut Self>, val: u8) {
         unsafe {
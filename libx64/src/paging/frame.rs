@@ -19,6 +19,16 @@ where
     fn alloc(&mut self) -> Result<PhysicalFrame<N>, FrameError>;
 }
 
+pub trait FrameDeallocator<const N: usize>
+where
+    PageCheck<N>: PageSize,
+{
+    /// # Safety
+    ///
+    /// The frame must have been returned by the allocator and must not be mapped anymore
+    unsafe fn dealloc(&mut self, frame: PhysicalFrame<N>);
+}
+
 pub trait FrameTranslator<L, const N: usize>
 where
     PageCheck<N>: PageSize,
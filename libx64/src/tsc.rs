@@ -0,0 +1,60 @@
+//! Time stamp counter
+//!
+//! The counter is incremented at a constant rate since the reset of the cpu on processors with an
+//! invariant TSC, its frequency has to be measured against another timer.
+
+use core::{arch::asm, time::Duration};
+
+/// Cycles since the reset of the cpu.
+///
+/// The read may be reordered with the instructions around it, see [`rdtscp`].
+#[inline]
+#[must_use]
+pub fn rdtsc() -> u64 {
+    let (high, low): (u32, u32);
+    unsafe {
+        asm!(
+            "rdtsc",
+            out("eax") low, out("edx") high,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    ((high as u64) << 32) | (low as u64)
+}
+
+/// Cycles since the reset of the cpu and the `IA32_TSC_AUX` register identifying it.
+///
+/// The counter is read once the previous instructions are executed.
+///
+/// # Safety
+///
+/// The cpu must support `rdtscp` (`CPUID.80000001H:EDX[27]`), it raises a #UD otherwise
+#[inline]
+#[must_use]
+pub unsafe fn rdtscp() -> (u64, u32) {
+    let (high, low, aux): (u32, u32, u32);
+    asm!(
+        "rdtscp",
+        out("eax") low, out("edx") high, out("ecx") aux,
+        options(nomem, nostack, preserves_flags),
+    );
+    (((high as u64) << 32) | (low as u64), aux)
+}
+
+/// Measure the frequency of the counter in Hz while `wait` spins for `window`.
+///
+/// Interrupts are disabled during the measure.
+///
+/// # Errors
+///
+/// Forwards the error of `wait`
+pub fn calibrate<E>(
+    window: Duration,
+    wait: impl FnOnce(Duration) -> Result<(), E>,
+) -> Result<u64, E> {
+    let cycles = crate::without_interrupts(|| {
+        let start = rdtsc();
+        wait(window).map(|()| rdtsc() - start)
+    })?;
+    Ok((u128::from(cycles) * 1_000_000_000 / window.as_nanos().max(1)) as u64)
+}
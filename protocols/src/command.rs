@@ -0,0 +1,185 @@
+//! Commands sent by the host to the kernel over the serial line
+//!
+//! Requests are COBS framed like the logs, the kernel answers each of them with one or more
+//! [`Response`] carried by [`LogPacket::Response`](crate::log::LogPacket::Response).
+#![allow(clippy::module_name_repetitions)]
+use rkyv::with::RefAsBox;
+
+use crate::log::Level;
+
+/// Bytes read by a single [`Request::ReadPhysical`]
+pub const MAX_READ: u32 = 256;
+
+/// Items carried by a single list response, longer lists are split across responses
+pub const MAX_ITEMS: usize = 8;
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "validation", archive(check_bytes))]
+#[archive_attr(derive(Debug))]
+pub enum Request {
+    MemoryMap,
+    ReadPhysical { address: u64, len: u32 },
+    Tasks,
+    SetLogLevel(Level),
+    Reboot,
+}
+
+/// Interpret the bytes of a request sent by the host.
+///
+/// The request is validated before it is read, returns `None` if `bytes` isn't an aligned
+/// request.
+#[cfg(feature = "validation")]
+#[must_use]
+pub fn archived_request(bytes: &[u8]) -> Option<&ArchivedRequest> {
+    rkyv::check_archived_root::<Request>(bytes).ok()
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug)]
+pub enum Response<'a> {
+    MemoryMap(#[with(RefAsBox)] &'a [Region]),
+    Memory {
+        address: u64,
+
+        #[with(RefAsBox)]
+        bytes: &'a [u8],
+    },
+    Tasks(#[with(RefAsBox)] &'a [Task]),
+    LogLevel(Level),
+    Rebooting,
+    Error(Error),
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[archive_attr(derive(Debug, Clone, Copy))]
+pub enum Error {
+    /// The frame isn't a request
+    InvalidRequest,
+    /// The physical range is too large or outside of the memory map
+    InvalidRange,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[archive_attr(derive(Debug, Clone, Copy))]
+pub struct Region {
+    pub start: u64,
+    /// Exclusive
+    pub end: u64,
+    pub kind: RegionKind,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[archive_attr(derive(Debug, Clone, Copy))]
+pub enum RegionKind {
+    Usable,
+    Bootloader,
+    Initrd,
+    Reserved,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[archive_attr(derive(Debug, Clone, Copy))]
+pub struct Task {
+    pub id: u64,
+    /// Zero for kernel threads
+    pub process: u64,
+    pub state: TaskState,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[archive_attr(derive(Debug, Clone, Copy))]
+pub enum TaskState {
+    Running,
+    Ready,
+    Sleeping,
+    Blocked,
+    Finished,
+}
+
+#[cfg(test)]
+mod test {
+    use rkyv::{ser::Serializer, AlignedVec, Deserialize};
+
+    use super::*;
+
+    fn to_bytes<T>(value: &T) -> AlignedVec
+    where
+        T: rkyv::Serialize<rkyv::ser::serializers::AllocSerializer<256>>,
+    {
+        let mut s = rkyv::ser::serializers::AllocSerializer::<256>::default();
+        s.serialize_value(value).unwrap();
+        s.into_serializer().into_inner()
+    }
+
+    #[test]
+    #[cfg(feature = "validation")]
+    fn requests() {
+        let requests = [
+            Request::MemoryMap,
+            Request::ReadPhysical {
+                address: 0xb8000,
+                len: 16,
+            },
+            Request::Tasks,
+            Request::SetLogLevel(Level::Debug),
+            Request::Reboot,
+        ];
+        for request in requests {
+            let bytes = to_bytes(&request);
+            let archived = archived_request(&bytes).unwrap();
+            let deserialized: Request = archived.deserialize(&mut rkyv::Infallible).unwrap();
+            assert_eq!(deserialized, request);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "validation")]
+    fn invalid_requests() {
+        let mut bytes = to_bytes(&Request::SetLogLevel(Level::Trace));
+        assert!(archived_request(&[]).is_none());
+        assert!(archived_request(&bytes[1..]).is_none());
+
+        bytes[1] = 5;
+        assert!(archived_request(&bytes).is_none());
+        bytes[0] = 0xff;
+        assert!(archived_request(&bytes).is_none());
+    }
+
+    #[test]
+    fn responses() {
+        let regions = [
+            Region {
+                start: 0,
+                end: 0x1000,
+                kind: RegionKind::Reserved,
+            },
+            Region {
+                start: 0x1000,
+                end: 0x9f000,
+                kind: RegionKind::Usable,
+            },
+        ];
+        let bytes = to_bytes(&Response::MemoryMap(&regions));
+        let response = unsafe { rkyv::archived_root::<Response>(&bytes[..]) };
+        match response {
+            ArchivedResponse::MemoryMap(archived) => {
+                assert_eq!(archived.len(), 2);
+                assert_eq!(archived[1].end, 0x9f000);
+                assert!(matches!(archived[1].kind, ArchivedRegionKind::Usable));
+            }
+            _ => panic!(),
+        }
+
+        let bytes = to_bytes(&Response::Memory {
+            address: 0x10,
+            bytes: b"kernel",
+        });
+        let response = unsafe { rkyv::archived_root::<Response>(&bytes[..]) };
+        match response {
+            ArchivedResponse::Memory { address, bytes } => {
+                assert_eq!(*address, 0x10);
+                assert_eq!(&bytes[..], b"kernel");
+            }
+            _ => panic!(),
+        }
+    }
+}
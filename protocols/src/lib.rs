@@ -10,6 +10,9 @@ extern crate alloc;
 #[cfg(feature = "log")]
 pub mod log;
 
+#[cfg(feature = "command")]
+pub mod command;
+
 #[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
 pub enum Noop {
     Noop = 0,
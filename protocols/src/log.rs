@@ -1,5 +1,36 @@
+//! Packets of the logs sent by the kernel over the serial line
+//!
+//! Each frame starts with a [`header`] holding the cycle count of the packet followed by an
+//! archived [`LogPacket`]. Frames of the first version have no header, see [`v0`], and the header
+//! of the second version has no timestamp.
 #![allow(clippy::module_name_repetitions)]
-use rkyv::with::RefAsBox;
+use rkyv::{with::RefAsBox, Archived};
+
+pub mod v0;
+
+/// Version of the packets written after the [`header`]
+pub const VERSION: u8 = 2;
+
+/// Bytes of the [`header`], the packet following it stays aligned
+pub const HEADER_SIZE: usize = 16;
+
+/// Bytes of the header of the second version, without the timestamp
+#[cfg(feature = "validation")]
+const V1_HEADER_SIZE: usize = 8;
+
+/// Start of the versioned frames, the text and tags written first by the first version never
+/// start with `0xff`
+const MAGIC: [u8; 4] = [0xff, b'l', b'o', b'g'];
+
+/// Header of a frame sent after `cycles` of the time stamp counter.
+#[must_use]
+pub const fn header(cycles: u64) -> [u8; HEADER_SIZE] {
+    let c = cycles.to_le_bytes();
+    [
+        MAGIC[0], MAGIC[1], MAGIC[2], MAGIC[3], VERSION, 0, 0, 0, c[0], c[1], c[2], c[3], c[4],
+        c[5], c[6], c[7],
+    ]
+}
 
 #[derive(
     rkyv::Archive,
@@ -15,7 +46,7 @@ use rkyv::with::RefAsBox;
     Hash,
 )]
 #[archive_attr(derive(Debug, Clone, Copy))]
-// #[cfg_attr(test, archive_attr(derive(bytecheck::CheckBytes)))]
+#[cfg_attr(feature = "validation", archive(check_bytes))]
 #[repr(u8)]
 pub enum Level {
     Error = 0,
@@ -25,29 +56,69 @@ pub enum Level {
     Trace = 4,
 }
 
+impl From<ArchivedLevel> for Level {
+    fn from(level: ArchivedLevel) -> Self {
+        match level {
+            ArchivedLevel::Error => Self::Error,
+            ArchivedLevel::Warn => Self::Warn,
+            ArchivedLevel::Info => Self::Info,
+            ArchivedLevel::Debug => Self::Debug,
+            ArchivedLevel::Trace => Self::Trace,
+        }
+    }
+}
+
 #[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug)]
-// #[cfg_attr(test, archive_attr(derive(bytecheck::CheckBytes)))]
+#[cfg_attr(feature = "validation", archive(check_bytes))]
 pub enum LogPacket<'a> {
     NewSpan(Span<'a>),
     Message(Message<'a>),
     EnterSpan(u64),
+    /// Replaced by [`LogPacket::Exited`] since the third version
     ExitSpan(u64),
+    /// Archived `command::Response` to a command of the host
+    Response(#[with(RefAsBox)] &'a [u8]),
+    /// Fields recorded on a span after its creation
+    Record {
+        id: u64,
+
+        #[with(RefAsBox)]
+        fields: &'a [Field<'a>],
+    },
+    /// The span `id` is caused by the span `follows`
+    FollowsFrom {
+        id: u64,
+        follows: u64,
+    },
+    /// Every handle of the span was dropped, its id won't be used again
+    CloseSpan(u64),
+    /// The span `id` was exited `elapsed` cycles after being entered
+    Exited {
+        id: u64,
+        elapsed: u64,
+    },
+    /// Frequency of the time stamp counter in Hz, sent once it is measured
+    TscFrequency(u64),
 }
 
 #[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug)]
-// #[cfg_attr(test, archive_attr(derive(bytecheck::CheckBytes)))]
+#[cfg_attr(feature = "validation", archive(check_bytes))]
 pub struct Span<'a> {
     pub id: u64,
 
+    #[with(RefAsBox)]
+    pub name: &'a str,
+
     #[with(RefAsBox)]
     pub target: &'a str,
 
     #[with(RefAsBox)]
-    pub fields: &'a str,
+    pub fields: &'a [Field<'a>],
 }
 
+/// Event, its text is the `message` field.
 #[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug)]
-// #[cfg_attr(test, archive_attr(derive(bytecheck::CheckBytes)))]
+#[cfg_attr(feature = "validation", archive(check_bytes))]
 pub struct Message<'a> {
     pub level: Level,
     pub line: usize,
@@ -56,54 +127,217 @@ pub struct Message<'a> {
     pub path: &'a str,
 
     #[with(RefAsBox)]
-    pub message: &'a str,
+    pub fields: &'a [Field<'a>],
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "validation", archive(check_bytes))]
+pub struct Field<'a> {
+    #[with(RefAsBox)]
+    pub name: &'a str,
+    pub value: Value<'a>,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "validation", archive(check_bytes))]
+pub enum Value<'a> {
+    I64(i64),
+    U64(u64),
+    Bool(bool),
+    Str(#[with(RefAsBox)] &'a str),
+    /// `Debug` representation of the values of other types
+    Debug(#[with(RefAsBox)] &'a str),
+}
+
+/// Archived packet of any version.
+pub enum Frame<'a> {
+    V0(&'a Archived<v0::LogPacket<'static>>),
+    Typed {
+        /// Time stamp counter when the packet was sent, `None` for the second version
+        cycles: Option<u64>,
+        packet: &'a Archived<LogPacket<'static>>,
+    },
+}
+
+/// Why a frame can't be read.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FrameError {
+    /// Written by a version newer than [`VERSION`]
+    Version(u8),
+    /// The frame is truncated, misaligned or its packet is malformed
+    Invalid,
 }
 
-#[cfg(test)]
+/// Interpret a decoded frame, its packet is validated before it is read.
+///
+/// # Errors
+///
+/// Errors if the frame was written by a newer version or isn't a valid aligned frame
+#[cfg(feature = "validation")]
+pub fn archived_frame(bytes: &[u8]) -> Result<Frame<'_>, FrameError> {
+    match bytes {
+        [0xff, b'l', b'o', b'g', 1, ..] => Ok(Frame::Typed {
+            cycles: None,
+            packet: packet::<LogPacket>(bytes.get(V1_HEADER_SIZE..).unwrap_or_default())?,
+        }),
+        [0xff, b'l', b'o', b'g', VERSION, _, _, _, c0, c1, c2, c3, c4, c5, c6, c7, ..] => {
+            Ok(Frame::Typed {
+                cycles: Some(u64::from_le_bytes([*c0, *c1, *c2, *c3, *c4, *c5, *c6, *c7])),
+                packet: packet::<LogPacket>(&bytes[HEADER_SIZE..])?,
+            })
+        }
+        [0xff, b'l', b'o', b'g', version, ..] if *version > VERSION => {
+            Err(FrameError::Version(*version))
+        }
+        [0xff, ..] => Err(FrameError::Invalid),
+        _ => Ok(Frame::V0(packet::<v0::LogPacket>(bytes)?)),
+    }
+}
+
+/// Validate the packet of a frame, it is written as an unsized value whose root is a relative
+/// pointer at the end of the frame, archived like a `Box`.
+#[cfg(feature = "validation")]
+fn packet<'a, T>(bytes: &'a [u8]) -> Result<&'a Archived<T>, FrameError>
+where
+    T: rkyv::Archive,
+    Archived<alloc::boxed::Box<T>>:
+        rkyv::CheckBytes<rkyv::validation::validators::DefaultValidator<'a>>,
+{
+    rkyv::check_archived_root::<alloc::boxed::Box<T>>(bytes)
+        .map(|packet| packet.get())
+        .map_err(|_| FrameError::Invalid)
+}
+
+#[cfg(all(test, feature = "validation"))]
 mod test {
     use rkyv::{ser::Serializer, AlignedVec};
 
     use super::*;
 
-    #[test]
-    fn span_message() {
-        let p = LogPacket::NewSpan(Span {
-            id: 1,
-            target: "bios",
-            fields: "",
-        });
-        // let a = rkyv::util::to_bytes::<_, 512>(&p).unwrap();
+    fn frame(packet: &LogPacket<'_>) -> AlignedVec {
         let mut s = rkyv::ser::serializers::AllocSerializer::<512>::default();
-        s.serialize_unsized_value(&p).unwrap();
-        let (s, _, _) = s.into_components();
-        let a = s.into_inner();
-
-        let offset = &a[..a.len() - 0];
-        unsafe {
-            let packet = rkyv::archived_unsized_root::<LogPacket>(offset);
-            match packet {
-                ArchivedLogPacket::NewSpan(ref span) => {
-                    std::dbg!(&*span.target);
+        s.write(&header(0x1234_5678_9abc)).unwrap();
+        s.serialize_unsized_value(packet).unwrap();
+        s.into_serializer().into_inner()
+    }
+
+    #[test]
+    fn typed_fields() {
+        let fields = [
+            Field {
+                name: "message",
+                value: Value::Debug("mapped"),
+            },
+            Field {
+                name: "frames",
+                value: Value::U64(512),
+            },
+            Field {
+                name: "offset",
+                value: Value::I64(-8),
+            },
+            Field {
+                name: "huge",
+                value: Value::Bool(true),
+            },
+            Field {
+                name: "owner",
+                value: Value::Str("kernel"),
+            },
+        ];
+        let bytes = frame(&LogPacket::Message(Message {
+            level: Level::Debug,
+            line: 12,
+            path: "page_mapper",
+            fields: &fields,
+        }));
+
+        match archived_frame(&bytes) {
+            Ok(Frame::Typed {
+                cycles: Some(0x1234_5678_9abc),
+                packet: ArchivedLogPacket::Message(message),
+            }) => {
+                assert_eq!(&*message.path, "page_mapper");
+                assert_eq!(message.fields.len(), 5);
+                assert_eq!(&*message.fields[1].name, "frames");
+                assert!(matches!(message.fields[1].value, ArchivedValue::U64(512)));
+                assert!(matches!(message.fields[2].value, ArchivedValue::I64(-8)));
+                assert!(matches!(message.fields[3].value, ArchivedValue::Bool(true)));
+                match &message.fields[4].value {
+                    ArchivedValue::Str(owner) => assert_eq!(&**owner, "kernel"),
+                    _ => panic!(),
                 }
-                _ => panic!(),
             }
+            _ => panic!(),
         }
-
-        std::dbg!(p, offset);
     }
 
     #[test]
-    fn deser() {
-        let mut input = AlignedVec::new();
-        input.extend_from_slice(
-     b"map_rangepages=PageRange<2Mb>(0x1000000000..0x1100000000),frames=FrameRange<2Mb>(0x0..0x100000000),flags=3,\0\0\0\0\0\0\0\0\0\0\0\0\0\x08\0\x08\x01\x01\x01\x01\x01\x01\x06\x80\xff\xff\xff\t\x01\x01\x06\x81\xff\xff\xffb\x01\x01\x05\xe0\xff\xff\xff"
+    fn versions() {
+        let mut s = rkyv::ser::serializers::AllocSerializer::<512>::default();
+        s.serialize_unsized_value(&v0::LogPacket::EnterSpan(4))
+            .unwrap();
+        let bytes = s.into_serializer().into_inner();
+        assert!(matches!(
+            archived_frame(&bytes),
+            Ok(Frame::V0(v0::ArchivedLogPacket::EnterSpan(4)))
+        ));
+
+        let mut s = rkyv::ser::serializers::AllocSerializer::<512>::default();
+        s.write(&[0xff, b'l', b'o', b'g', 1, 0, 0, 0]).unwrap();
+        s.serialize_unsized_value(&LogPacket::CloseSpan(4)).unwrap();
+        let bytes = s.into_serializer().into_inner();
+        assert!(matches!(
+            archived_frame(&bytes),
+            Ok(Frame::Typed {
+                cycles: None,
+                packet: ArchivedLogPacket::CloseSpan(4)
+            })
+        ));
+
+        let mut bytes = frame(&LogPacket::Exited { id: 4, elapsed: 9 });
+        assert!(matches!(
+            archived_frame(&bytes),
+            Ok(Frame::Typed {
+                cycles: Some(0x1234_5678_9abc),
+                packet: ArchivedLogPacket::Exited { id: 4, elapsed: 9 }
+            })
+        ));
+
+        bytes[4] = VERSION + 1;
+        assert_eq!(
+            archived_frame(&bytes).err(),
+            Some(FrameError::Version(VERSION + 1))
         );
+    }
 
-        let val = unsafe { rkyv::archived_unsized_root::<LogPacket>(&input[..]) };
+    #[test]
+    fn invalid_frames() {
+        let invalid = |bytes: &[u8]| {
+            let mut frame = AlignedVec::new();
+            frame.extend_from_slice(bytes);
+            matches!(archived_frame(&frame), Err(FrameError::Invalid))
+        };
+        assert!(invalid(b""));
+        assert!(invalid(b"\x01"));
+        assert!(invalid(b"Booting (second stage)...\n"));
+        assert!(invalid(&[0xff, b'l', b'o', b'g', 1]));
+        assert!(invalid(&header(0)));
 
-        match val {
-            ArchivedLogPacket::NewSpan(msg) => {}
-            _ => unreachable!(),
-        }
+        // truncated and misaligned packets
+        let bytes = frame(&LogPacket::EnterSpan(4));
+        assert!(invalid(&bytes[..bytes.len() - 1]));
+        let mut misaligned = AlignedVec::new();
+        misaligned.push(0);
+        misaligned.extend_from_slice(&bytes);
+        assert!(matches!(
+            archived_frame(&misaligned[1..]),
+            Err(FrameError::Invalid)
+        ));
+
+        // tag of a variant that doesn't exist
+        let mut bytes = frame(&LogPacket::EnterSpan(4));
+        bytes[HEADER_SIZE] = 0xf0;
+        assert!(invalid(&bytes));
     }
 }
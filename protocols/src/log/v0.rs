@@ -0,0 +1,113 @@
+//! Packets of the first version, their fields are flattened into strings
+//!
+//! Frames of this version have no header, they are only decoded to read older captures.
+use rkyv::with::RefAsBox;
+
+use super::Level;
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug)]
+#[cfg_attr(feature = "validation", archive(check_bytes))]
+pub enum LogPacket<'a> {
+    NewSpan(Span<'a>),
+    Message(Message<'a>),
+    EnterSpan(u64),
+    ExitSpan(u64),
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug)]
+#[cfg_attr(feature = "validation", archive(check_bytes))]
+pub struct Span<'a> {
+    pub id: u64,
+
+    #[with(RefAsBox)]
+    pub target: &'a str,
+
+    #[with(RefAsBox)]
+    pub fields: &'a str,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug)]
+#[cfg_attr(feature = "validation", archive(check_bytes))]
+pub struct Message<'a> {
+    pub level: Level,
+    pub line: usize,
+
+    #[with(RefAsBox)]
+    pub path: &'a str,
+
+    #[with(RefAsBox)]
+    pub message: &'a str,
+}
+
+#[cfg(test)]
+mod test {
+    use rkyv::{ser::Serializer, AlignedVec};
+
+    use super::*;
+    use crate::log::ArchivedLevel;
+
+    fn archived(bytes: &[u8]) -> &ArchivedLogPacket<'static> {
+        assert_eq!(bytes.as_ptr().align_offset(16), 0);
+        unsafe { rkyv::archived_unsized_root::<LogPacket>(bytes) }
+    }
+
+    #[test]
+    fn span_message() {
+        let p = LogPacket::NewSpan(Span {
+            id: 1,
+            target: "bios",
+            fields: "stage=2,",
+        });
+        let mut s = rkyv::ser::serializers::AllocSerializer::<512>::default();
+        s.serialize_unsized_value(&p).unwrap();
+        let a = s.into_serializer().into_inner();
+
+        match archived(&a) {
+            ArchivedLogPacket::NewSpan(span) => {
+                assert_eq!(span.id, 1);
+                assert_eq!(&*span.target, "bios");
+                assert_eq!(&*span.fields, "stage=2,");
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Frames written by the encoder of the first version, before COBS
+    #[test]
+    fn baseline_frames() {
+        let aligned = |bytes: &[u8]| {
+            let mut frame = AlignedVec::new();
+            frame.extend_from_slice(bytes);
+            frame
+        };
+
+        let span = aligned(b"kernelversion=\"0.1.0\",\0\0\0\0\0\0\0\0\0\0\x01\0\0\0\0\0\0\0\xd8\xff\xff\xff\x06\0\0\0\xd6\xff\xff\xff\x10\0\0\0\xe0\xff\xff\xff");
+        match archived(&span) {
+            ArchivedLogPacket::NewSpan(span) => {
+                assert_eq!(span.id, 1);
+                assert_eq!(&*span.target, "kernel");
+                assert_eq!(&*span.fields, "version=\"0.1.0\",");
+            }
+            _ => panic!(),
+        }
+
+        let message = aligned(b"kernelmessage=kernel loaded,\0\0\0\0\x01\0\0\0\x02\0\0\0\x3b\0\0\0\xd4\xff\xff\xff\x06\0\0\0\xd2\xff\xff\xff\x16\0\0\0\0\0\0\0\xe0\xff\xff\xff");
+        match archived(&message) {
+            ArchivedLogPacket::Message(message) => {
+                assert!(matches!(message.level, ArchivedLevel::Info));
+                assert_eq!(message.line, 59);
+                assert_eq!(&*message.path, "kernel");
+                assert_eq!(&*message.message, "message=kernel loaded,");
+            }
+            _ => panic!(),
+        }
+
+        let mut enter = aligned(&[0; 36]);
+        enter[0] = 2;
+        enter[8] = 1;
+        enter[32..].copy_from_slice(b"\xe0\xff\xff\xff");
+        assert!(matches!(archived(&enter), ArchivedLogPacket::EnterSpan(1)));
+        enter[0] = 3;
+        assert!(matches!(archived(&enter), ArchivedLogPacket::ExitSpan(1)));
+    }
+}
@@ -18,6 +18,45 @@ impl kio::codec::Encoder<&[u8]> for CobsCodec {
     }
 }
 
+/// Decoder of zero delimited frames of at most `N` bytes once decoded.
+pub struct CobsDecoder<const N: usize>;
+
+impl<const N: usize> kio::codec::Decoder for CobsDecoder<N> {
+    type Item = Frame<N>;
+    type Error = kio::Error;
+
+    fn decode(&mut self, src: &[u8]) -> Result<Option<(usize, Self::Item)>, Self::Error> {
+        let Some(end) = src.iter().position(|&b| b == 0) else {
+            return Ok(None);
+        };
+        // a frame decodes to one byte less than its encoding at most
+        if end > N + 1 {
+            return Err(kio::Error::new(kio::ErrorKind::StorageFull));
+        }
+        let mut frame = Frame {
+            bytes: [0; N],
+            len: 0,
+        };
+        frame.len = decode(&src[..=end], &mut frame.bytes);
+        Ok(Some((end + 1, frame)))
+    }
+}
+
+/// A decoded frame.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Frame<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> core::ops::Deref for Frame<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.bytes[..self.len]
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 struct EncoderState {
     cursor_buffer: usize,
@@ -248,6 +287,33 @@ mod tests {
         }
     }
 
+    mod decoder {
+        use crate::{CobsDecoder, Frame};
+        use kio::{codec::Decoder, read::FramedRead};
+        use std::vec::Vec;
+
+        #[test]
+        fn split_frames() {
+            let input = &b"\x0612345\x056789\x00\x01\x00\x021\x00\x03"[..];
+            let frames: Vec<Frame<16>> = FramedRead::new([0; 32], input, CobsDecoder)
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(frames.len(), 3);
+            assert_eq!(&*frames[0], b"12345\x006789");
+            assert_eq!(&*frames[1], b"");
+            assert_eq!(&*frames[2], b"1");
+        }
+
+        #[test]
+        fn frame_too_large() {
+            let mut decoder = CobsDecoder::<4>;
+            assert!(decoder.decode(b"\x0612345").unwrap().is_none());
+            assert!(decoder.decode(b"\x0612345\x00").is_err());
+            let (n, frame) = decoder.decode(b"\x051234\x00").unwrap().unwrap();
+            assert_eq!((n, &*frame), (6, &b"1234"[..]));
+        }
+    }
+
     mod encode {
         use super::*;
         use crate::encode;
@@ -0,0 +1,86 @@
+//! Typed fields recorded on the spans and events
+
+use core::fmt::Write;
+
+use kio::cursor::Cursor;
+use protocols::log::{Field, Value};
+use tracing_core::field::Visit;
+
+/// Fields of a single span or event, the others are dropped
+pub const MAX_FIELDS: usize = 16;
+
+/// Value of the fields whose text doesn't fit in the buffer
+const TRUNCATED: Value<'static> = Value::Debug("...");
+
+/// Visitor keeping the values with their types, the text of the values is copied in a buffer.
+pub struct Fields<'a> {
+    fields: [Field<'a>; MAX_FIELDS],
+    len: usize,
+    /// Unused part of the buffer
+    text: &'a mut [u8],
+}
+
+impl<'a> Fields<'a> {
+    pub fn new(text: &'a mut [u8]) -> Self {
+        const EMPTY: Field<'static> = Field {
+            name: "",
+            value: Value::Bool(false),
+        };
+        Self {
+            fields: [EMPTY; MAX_FIELDS],
+            len: 0,
+            text,
+        }
+    }
+
+    pub fn as_slice(&self) -> &[Field<'a>] {
+        &self.fields[..self.len]
+    }
+
+    fn push(&mut self, name: &'static str, value: Value<'a>) {
+        if let Some(field) = self.fields.get_mut(self.len) {
+            *field = Field { name, value };
+            self.len += 1;
+        }
+    }
+
+    /// Format the arguments at the start of the buffer and remove them from it.
+    fn text(&mut self, args: core::fmt::Arguments<'_>) -> Option<&'a str> {
+        let mut cursor = Cursor::new(&mut *self.text);
+        cursor.write_fmt(args).ok()?;
+        let len = cursor.buffer().len();
+
+        let (text, rest) = core::mem::take(&mut self.text).split_at_mut(len);
+        self.text = rest;
+        // SAFETY: only `str` were written
+        Some(unsafe { core::str::from_utf8_unchecked(text) })
+    }
+}
+
+impl Visit for Fields<'_> {
+    fn record_i64(&mut self, field: &tracing_core::Field, value: i64) {
+        self.push(field.name(), Value::I64(value));
+    }
+
+    fn record_u64(&mut self, field: &tracing_core::Field, value: u64) {
+        self.push(field.name(), Value::U64(value));
+    }
+
+    fn record_bool(&mut self, field: &tracing_core::Field, value: bool) {
+        self.push(field.name(), Value::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &tracing_core::Field, value: &str) {
+        let value = self
+            .text(format_args!("{}", value))
+            .map_or(TRUNCATED, Value::Str);
+        self.push(field.name(), value);
+    }
+
+    fn record_debug(&mut self, field: &tracing_core::Field, value: &dyn core::fmt::Debug) {
+        let value = self
+            .text(format_args!("{:?}", value))
+            .map_or(TRUNCATED, Value::Debug);
+        self.push(field.name(), value);
+    }
+}
@@ -0,0 +1,198 @@
+//! Per-target filtering of the spans and events
+//!
+//! Directives follow the syntax of `RUST_LOG`: a comma separated list of `target=level`, a bare
+//! `level` applies to the targets matched by no other directive. The directive with the longest
+//! target matching the target of a callsite decides whether it is enabled, a target matches the
+//! modules nested in it, `kernel` matches `kernel::mem`.
+//!
+//! ```text
+//! warn,kernel=info,page_mapper=trace
+//! ```
+
+use protocols::log::Level;
+
+/// Directives of a single filter
+pub const MAX_DIRECTIVES: usize = 8;
+
+/// Bytes of the target of a directive
+pub const MAX_TARGET: usize = 32;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParseError {
+    TooManyDirectives,
+    TargetTooLong,
+    InvalidLevel,
+}
+
+/// Most verbose level enabled, `None` is lower than every level and disables everything.
+pub type LevelFilter = Option<Level>;
+
+#[derive(Debug, Clone, Copy)]
+struct Directive {
+    target: [u8; MAX_TARGET],
+    len: usize,
+    level: LevelFilter,
+}
+
+impl Directive {
+    fn target(&self) -> &[u8] {
+        &self.target[..self.len]
+    }
+
+    fn matches(&self, target: &str) -> bool {
+        let prefix = self.target();
+        target.as_bytes().starts_with(prefix)
+            && matches!(&target.as_bytes()[prefix.len()..], [] | [b':', b':', ..])
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Filter {
+    directives: [Option<Directive>; MAX_DIRECTIVES],
+    default: LevelFilter,
+}
+
+impl Filter {
+    /// Filter applying `default` to every target.
+    #[must_use]
+    pub const fn new(default: LevelFilter) -> Self {
+        Self {
+            directives: [None; MAX_DIRECTIVES],
+            default,
+        }
+    }
+
+    /// Parse a list of directives, targets without a level enable every level.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a directive is invalid or if there are too many of them
+    pub fn parse(directives: &str) -> Result<Self, ParseError> {
+        let mut filter = Self::new(None);
+        let mut slots = filter.directives.iter_mut();
+        for directive in directives.split(',').map(str::trim) {
+            if directive.is_empty() {
+                continue;
+            }
+            let (target, level) = match directive.split_once('=') {
+                Some((target, level)) => (target.trim(), parse_level(level.trim())?),
+                None => match parse_level(directive) {
+                    Ok(level) => {
+                        filter.default = level;
+                        continue;
+                    }
+                    Err(_) => (directive, Some(Level::Trace)),
+                },
+            };
+            if target.len() > MAX_TARGET {
+                return Err(ParseError::TargetTooLong);
+            }
+            let mut bytes = [0; MAX_TARGET];
+            bytes[..target.len()].copy_from_slice(target.as_bytes());
+
+            let slot = slots.next().ok_or(ParseError::TooManyDirectives)?;
+            *slot = Some(Directive {
+                target: bytes,
+                len: target.len(),
+                level,
+            });
+        }
+        Ok(filter)
+    }
+
+    /// Change the level of the targets matched by no directive.
+    pub fn set_default(&mut self, default: LevelFilter) {
+        self.default = default;
+    }
+
+    /// Level enabled for `target`.
+    #[must_use]
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .flatten()
+            .filter(|directive| directive.matches(target))
+            .max_by_key(|directive| directive.len)
+            .map_or(self.default, |directive| directive.level)
+    }
+
+    #[must_use]
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        Some(level) <= self.level(target)
+    }
+
+    /// Most verbose level enabled for any target.
+    #[must_use]
+    pub fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .flatten()
+            .map(|directive| directive.level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, ParseError> {
+    Ok(Some(match level {
+        "off" => return Ok(None),
+        "error" => Level::Error,
+        "warn" => Level::Warn,
+        "info" => Level::Info,
+        "debug" => Level::Debug,
+        "trace" => Level::Trace,
+        _ => return Err(ParseError::InvalidLevel),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn longest_target() {
+        let filter = Filter::parse("warn, kernel=info,kernel::mem=trace,page_mapper=off").unwrap();
+        assert_eq!(filter.level("bootloader"), Some(Level::Warn));
+        assert_eq!(filter.level("kernel"), Some(Level::Info));
+        assert_eq!(filter.level("kernel::mem::pmm"), Some(Level::Trace));
+        assert_eq!(filter.level("kernel::memory"), Some(Level::Info));
+        assert_eq!(filter.level("kernelx"), Some(Level::Warn));
+        assert_eq!(filter.level("page_mapper::instrumented"), None);
+
+        assert!(filter.enabled("kernel::thread", Level::Info));
+        assert!(!filter.enabled("kernel::thread", Level::Debug));
+        assert!(!filter.enabled("page_mapper", Level::Error));
+        assert_eq!(filter.max_level(), Some(Level::Trace));
+    }
+
+    #[test]
+    fn default_level() {
+        let mut filter = Filter::parse("warn,kernel::mem=trace").unwrap();
+        filter.set_default(Some(Level::Debug));
+        assert_eq!(filter.level("kernel"), Some(Level::Debug));
+        assert_eq!(filter.level("kernel::mem"), Some(Level::Trace));
+    }
+
+    #[test]
+    fn bare_target() {
+        let filter = Filter::parse("kcore").unwrap();
+        assert_eq!(filter.level("kcore::sync"), Some(Level::Trace));
+        assert_eq!(filter.level("kernel"), None);
+        assert_eq!(Filter::parse("").unwrap().max_level(), None);
+    }
+
+    #[test]
+    fn invalid_directives() {
+        assert_eq!(
+            Filter::parse("kernel=loud").unwrap_err(),
+            ParseError::InvalidLevel
+        );
+        assert_eq!(
+            Filter::parse("a,b,c,d,e,f,g,h,i").unwrap_err(),
+            ParseError::TooManyDirectives
+        );
+        assert_eq!(
+            Filter::parse("a_target_longer_than_thirty_two_bytes=info").unwrap_err(),
+            ParseError::TargetTooLong
+        );
+    }
+}
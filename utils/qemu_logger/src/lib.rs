@@ -1,20 +1,19 @@
 #![no_std]
 
-use core::{
-    fmt::Write,
-    sync::atomic::{AtomicU64, Ordering},
-};
-
 use kcore::{klazy, sync::SpinMutex};
+use libx64::tsc;
+
 use kio::{
     codec::{Chained, Encoder},
-    cursor::Cursor,
     write::{FramedWrite, Sink},
 };
 
 use mais::CobsCodec;
-use protocols::log::{Level, LogPacket, Message, Span};
-use serialuart16550::SerialPort;
+use protocols::{
+    command::Response,
+    log::{self, Level, LogPacket, Message, Span, HEADER_SIZE},
+};
+use serialuart16550::{Com, Config, SerialPort, Uart, Writer};
 
 use rkyv::{
     ser::{
@@ -24,10 +23,22 @@ use rkyv::{
     AlignedBytes,
 };
 use tracing_core::{
+    callsite,
+    collect::Interest,
     span::{Attributes, Current, Id, Record},
     Event, Metadata,
 };
 
+use crate::{
+    fields::Fields,
+    filter::{Filter, LevelFilter, ParseError},
+    registry::Registry,
+};
+
+mod fields;
+pub mod filter;
+mod registry;
+
 #[macro_export]
 macro_rules! dbg {
     ($arg:expr) => {{
@@ -38,26 +49,52 @@ macro_rules! dbg {
 
 const BUFFER_SIZE: usize = 512;
 
+/// Filter applied until [`set_filter`] is called, overridden by `KERNEL_LOG` at build time
+const DEFAULT_FILTER: &str = match option_env!("KERNEL_LOG") {
+    Some(directives) => directives,
+    None => "info,page_mapper=warn",
+};
+
+/// Bytes buffered in each direction of the serial port
+const RING_SIZE: usize = 4096;
+
+/// First serial port, its IRQ must be routed to [`Uart::handle_interrupt`] for the logs to be
+/// sent without polling.
+// SAFETY: COM1 is only driven through this static
+#[link_section = ".logger"]
+pub static SERIAL: Uart<RING_SIZE> = Uart::new(unsafe { SerialPort::com(Com::Com1) });
+
 klazy! {
-    // SAFETY: we are the only one accessing this port on initialization
     #[link_section = ".logger"]
-    pub ref static DRIVER: SpinMutex<FramedWrite<AlignedBytes<BUFFER_SIZE>, SerialPort, Chained<AlignedBytes<BUFFER_SIZE>, LogEncoder,CobsCodec>>> = unsafe {
-        let mut port = SerialPort::new(0x3f8);
-        port.init();
+    pub ref static DRIVER: SpinMutex<FramedWrite<AlignedBytes<BUFFER_SIZE>, Writer<'static, RING_SIZE>, Chained<AlignedBytes<BUFFER_SIZE>, LogEncoder,CobsCodec>>> = {
+        SERIAL.init(Config::DEFAULT).expect("default configuration is valid");
         SpinMutex::new(
-            FramedWrite::new(AlignedBytes([0; BUFFER_SIZE]), port, LogEncoder::new().chain(AlignedBytes([0u8; BUFFER_SIZE]), CobsCodec)))
+            FramedWrite::new(AlignedBytes([0; BUFFER_SIZE]), SERIAL.writer(), LogEncoder::new().chain(AlignedBytes([0u8; BUFFER_SIZE]), CobsCodec)))
 
     };
 }
 
+klazy! {
+    ref static FILTER: SpinMutex<Filter> = {
+        // panicking here would recurse through the logging of the panic
+        SpinMutex::new(Filter::parse(DEFAULT_FILTER).unwrap_or(Filter::new(Some(Level::Info))))
+    };
+}
+
 struct Logger;
 static LOGGER: Logger = Logger;
 
-static SPANS: AtomicU64 = AtomicU64::new(1);
-static CURRENT_SPAN: AtomicU64 = AtomicU64::new(0);
+static REGISTRY: SpinMutex<Registry> = SpinMutex::new(Registry::new());
+
+/// Id of the running task, the entered spans are tracked per task
+static TASK_ID: SpinMutex<fn() -> u64> = SpinMutex::new(single_task);
+
+const fn single_task() -> u64 {
+    0
+}
 
-// TODO: this probably doesn't work very well and should be a stack of metadata which is poped on span exit
-static mut CURRENT_METADATA: *const Metadata = core::ptr::null();
+/// Id of the spans created while the registry is full, they are never reported
+const DROPPED_SPAN: u64 = u64::MAX;
 
 /// # Errors
 ///
@@ -67,6 +104,75 @@ pub fn init() -> Result<(), tracing_core::dispatch::SetGlobalDefaultError> {
     Ok(())
 }
 
+/// Track the entered spans of each task, `task_id` returns the id of the running task. It is
+/// called with interrupts disabled and must not log nor block.
+pub fn set_task_id(task_id: fn() -> u64) {
+    libx64::without_interrupts(|| *TASK_ID.lock() = task_id);
+}
+
+fn task_id() -> u64 {
+    (*TASK_ID.lock())()
+}
+
+/// Replace the filter of the spans and events, see [`filter`] for the syntax of the directives.
+///
+/// # Errors
+///
+/// Errors if the directives are invalid, the filter is then left untouched
+pub fn set_filter(directives: &str) -> Result<(), ParseError> {
+    let filter = Filter::parse(directives)?;
+    replace_filter(filter);
+    Ok(())
+}
+
+/// Change the level of the targets matched by no directive, the directives are kept.
+pub fn set_max_level(level: Level) {
+    update_filter(|filter| filter.set_default(Some(level)));
+}
+
+fn replace_filter(filter: Filter) {
+    update_filter(|current| *current = filter);
+}
+
+fn update_filter(f: impl FnOnce(&mut Filter)) {
+    libx64::without_interrupts(|| f(&mut FILTER.lock()));
+    // the interest of the callsites already registered was computed with the previous filter
+    callsite::rebuild_interest_cache();
+}
+
+fn enabled(metadata: &Metadata<'_>) -> bool {
+    let level = level_from_tracing(*metadata.level());
+    libx64::without_interrupts(|| FILTER.lock().enabled(metadata.target(), level))
+}
+
+/// Send the frequency of the time stamp counter, the host converts the timestamps of the packets
+/// with it.
+pub fn set_tsc_frequency(hz: u64) {
+    libx64::without_interrupts(|| _qprint_encode(LogPacket::TscFrequency(hz)));
+}
+
+/// Send the response to a command of the host.
+///
+/// # Errors
+///
+/// Errors if the response doesn't fit in a packet
+pub fn respond(response: &Response<'_>) -> Result<(), kio::Error> {
+    let mut scratch = AlignedBytes([0u8; BUFFER_SIZE]);
+    let mut buffer = CompositeSerializer::new(
+        BufferSerializer::new(AlignedBytes([0u8; BUFFER_SIZE])),
+        BufferScratch::new(&mut scratch[..]),
+        rkyv::Infallible,
+    );
+    buffer
+        .serialize_value(response)
+        .map_err(|_| kio::Error::new(kio::ErrorKind::StorageFull))?;
+    let n = buffer.pos();
+    let (bytes, _, _) = buffer.into_components();
+    let bytes = bytes.into_inner();
+
+    libx64::without_interrupts(|| DRIVER.lock().send(LogPacket::Response(&bytes[..n])))
+}
+
 pub struct LogEncoder {
     scratch_buffer: AlignedBytes<BUFFER_SIZE>,
 }
@@ -85,10 +191,13 @@ pub static MAX_SCRATCH: core::sync::atomic::AtomicUsize = core::sync::atomic::At
 impl kio::codec::Encoder<LogPacket<'_>> for LogEncoder {
     type Error = kio::Error;
 
-    fn encode<T>(&mut self, item: LogPacket<'_>, dst: T) -> Result<usize, Self::Error>
+    fn encode<T>(&mut self, item: LogPacket<'_>, mut dst: T) -> Result<usize, Self::Error>
     where
         T: AsMut<[u8]>,
     {
+        let (header, dst) = dst.as_mut().split_at_mut(HEADER_SIZE);
+        header.copy_from_slice(&log::header(tsc::rdtsc()));
+
         let mut buffer = CompositeSerializer::new(
             BufferSerializer::new(dst),
             ScratchTracker::new(BufferScratch::new(&mut self.scratch_buffer[..])),
@@ -98,7 +207,7 @@ impl kio::codec::Encoder<LogPacket<'_>> for LogEncoder {
         buffer
             .serialize_unsized_value(&item)
             .map_err(|_| kio::Error::new(kio::ErrorKind::InvalidData))?;
-        let n = buffer.pos();
+        let n = HEADER_SIZE + buffer.pos();
         let (_, scratch, _) = buffer.into_components();
 
         MAX_SCRATCH.store(
@@ -114,93 +223,130 @@ fn _qprint_encode(message: LogPacket<'_>) {
     DRIVER.lock().send(message).unwrap();
 }
 
-struct DebugArgs<'a>(Cursor<'a>);
-
-impl<'a> From<Cursor<'a>> for DebugArgs<'a> {
-    fn from(c: Cursor<'a>) -> Self {
-        Self(c)
+impl tracing_core::Collect for Logger {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        // cached until the filter is replaced
+        if enabled(metadata) {
+            Interest::always()
+        } else {
+            Interest::never()
+        }
     }
-}
 
-impl tracing_core::field::Visit for DebugArgs<'_> {
-    fn record_debug(&mut self, field: &tracing_core::Field, value: &dyn core::fmt::Debug) {
-        self.0
-            .write_fmt(format_args!("{}={:?},", field.name(), value))
-            .unwrap();
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        enabled(metadata)
     }
-}
 
-impl tracing_core::Collect for Logger {
-    fn enabled(&self, _metadata: &Metadata) -> bool {
-        true
+    fn max_level_hint(&self) -> Option<tracing_core::LevelFilter> {
+        let max = libx64::without_interrupts(|| FILTER.lock().max_level());
+        Some(level_filter(max))
     }
 
     fn new_span(&self, attr: &Attributes<'_>) -> Id {
-        // NOTE: this is note thread safe, metadata is allways static so the pointer is valid
-        unsafe {
-            CURRENT_METADATA = attr.metadata();
-        }
-        let id = Id::from_u64(SPANS.fetch_add(1, Ordering::Relaxed));
+        let Some(id) = libx64::without_interrupts(|| REGISTRY.lock().insert(attr.metadata()))
+        else {
+            return Id::from_u64(DROPPED_SPAN);
+        };
 
         let mut buffer = [0u8; BUFFER_SIZE];
-        let mut args = DebugArgs::from(Cursor::new(&mut buffer));
-        attr.record(&mut args);
-        let fields = unsafe { core::str::from_utf8_unchecked(args.0.buffer()) };
+        let mut fields = Fields::new(&mut buffer);
+        attr.record(&mut fields);
 
         let span = Span {
             id: id.into_u64(),
+            name: attr.metadata().name(),
             target: attr.metadata().target(),
-            fields,
+            fields: fields.as_slice(),
         };
 
         libx64::without_interrupts(|| _qprint_encode(LogPacket::NewSpan(span)));
         id
     }
 
-    #[inline]
-    fn record(&self, _span: &Id, _values: &Record<'_>) {
-        // TODO
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if !libx64::without_interrupts(|| REGISTRY.lock().contains(span)) {
+            return;
+        }
+
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let mut fields = Fields::new(&mut buffer);
+        values.record(&mut fields);
+
+        let record = LogPacket::Record {
+            id: span.into_u64(),
+            fields: fields.as_slice(),
+        };
+        libx64::without_interrupts(|| _qprint_encode(record));
     }
 
-    #[inline]
-    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+    fn record_follows_from(&self, span: &Id, follows: &Id) {
+        let packet = LogPacket::FollowsFrom {
+            id: span.into_u64(),
+            follows: follows.into_u64(),
+        };
+        let known = libx64::without_interrupts(|| {
+            let mut registry = REGISTRY.lock();
+            registry.contains(span) && registry.contains(follows)
+        });
+        if known {
+            libx64::without_interrupts(|| _qprint_encode(packet));
+        }
+    }
 
     fn event(&self, event: &Event<'_>) {
         let mut buffer = [0u8; BUFFER_SIZE];
-        let mut args = DebugArgs::from(Cursor::new(&mut buffer));
-        event.record(&mut args);
-        let message = unsafe { core::str::from_utf8_unchecked(args.0.buffer()) };
+        let mut fields = Fields::new(&mut buffer);
+        event.record(&mut fields);
 
         let metadata = event.metadata();
         let log = Message {
             level: level_from_tracing(*metadata.level()),
             line: metadata.line().unwrap_or(0) as usize,
             path: metadata.module_path().unwrap_or("notfound"),
-            message,
+            fields: fields.as_slice(),
         };
 
         libx64::without_interrupts(|| _qprint_encode(LogPacket::Message(log)));
     }
 
-    #[inline]
     fn enter(&self, span: &Id) {
-        CURRENT_SPAN.store(span.into_u64(), Ordering::Relaxed);
-        libx64::without_interrupts(|| _qprint_encode(LogPacket::EnterSpan(span.into_u64())));
+        libx64::without_interrupts(|| {
+            if REGISTRY.lock().enter(task_id(), span, tsc::rdtsc()) {
+                _qprint_encode(LogPacket::EnterSpan(span.into_u64()));
+            }
+        });
     }
 
-    #[inline]
     fn exit(&self, span: &Id) {
-        CURRENT_SPAN.store(0, Ordering::Relaxed);
-        libx64::without_interrupts(|| _qprint_encode(LogPacket::ExitSpan(span.into_u64())));
+        libx64::without_interrupts(|| {
+            let elapsed = REGISTRY.lock().exit(task_id(), span, tsc::rdtsc());
+            if let Some(elapsed) = elapsed {
+                _qprint_encode(LogPacket::Exited {
+                    id: span.into_u64(),
+                    elapsed,
+                });
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        libx64::without_interrupts(|| REGISTRY.lock().clone_span(span));
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        libx64::without_interrupts(|| {
+            let closed = REGISTRY.lock().try_close(&span);
+            if closed {
+                _qprint_encode(LogPacket::CloseSpan(span.into_u64()));
+            }
+            closed
+        })
     }
 
     fn current_span(&self) -> Current {
-        unsafe {
-            Current::new(
-                Id::from_u64(CURRENT_SPAN.load(Ordering::Relaxed)),
-                &*CURRENT_METADATA,
-            )
-        }
+        libx64::without_interrupts(|| REGISTRY.lock().current(task_id()))
+            .map_or_else(Current::none, |(id, metadata)| Current::new(id, metadata))
     }
 }
 
@@ -213,3 +359,14 @@ const fn level_from_tracing(level: tracing_core::Level) -> Level {
         tracing_core::Level::TRACE => Level::Trace,
     }
 }
+
+const fn level_filter(level: LevelFilter) -> tracing_core::LevelFilter {
+    match level {
+        None => tracing_core::LevelFilter::OFF,
+        Some(Level::Error) => tracing_core::LevelFilter::ERROR,
+        Some(Level::Warn) => tracing_core::LevelFilter::WARN,
+        Some(Level::Info) => tracing_core::LevelFilter::INFO,
+        Some(Level::Debug) => tracing_core::LevelFilter::DEBUG,
+        Some(Level::Trace) => tracing_core::LevelFilter::TRACE,
+    }
+}
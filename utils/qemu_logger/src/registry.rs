@@ -0,0 +1,277 @@
+//! Spans alive in the kernel
+//!
+//! Every task gets its own stack of entered spans so a task switched in doesn't see the spans of
+//! the one it preempted as current. A task may still exit its spans out of order so they are
+//! removed from its stack wherever they are.
+
+use tracing_core::{span::Id, Metadata};
+
+/// Spans alive at once, new spans are dropped when they are all in use
+pub const MAX_SPANS: usize = 128;
+
+/// Spans entered at once by a task
+pub const MAX_DEPTH: usize = 32;
+
+/// Tasks with spans entered at once
+pub const MAX_TASKS: usize = 16;
+
+#[derive(Clone, Copy)]
+struct Slot {
+    /// Incremented when the slot is reused so the ids stay unique
+    generation: u32,
+    refs: usize,
+    metadata: Option<&'static Metadata<'static>>,
+}
+
+#[derive(Clone, Copy)]
+struct Entered {
+    id: u64,
+    /// Time stamp counter when the span was entered
+    cycles: u64,
+}
+
+#[derive(Clone, Copy)]
+struct Stack {
+    /// Task that entered the spans, `None` once they are all exited
+    task: Option<u64>,
+    entered: [Entered; MAX_DEPTH],
+    depth: usize,
+}
+
+pub struct Registry {
+    slots: [Slot; MAX_SPANS],
+    stacks: [Stack; MAX_TASKS],
+}
+
+impl Registry {
+    #[must_use]
+    pub const fn new() -> Self {
+        const FREE: Slot = Slot {
+            generation: 0,
+            refs: 0,
+            metadata: None,
+        };
+        const EMPTY: Stack = Stack {
+            task: None,
+            entered: [Entered { id: 0, cycles: 0 }; MAX_DEPTH],
+            depth: 0,
+        };
+        Self {
+            slots: [FREE; MAX_SPANS],
+            stacks: [EMPTY; MAX_TASKS],
+        }
+    }
+
+    /// Id of the span in the slot, never zero.
+    fn id(index: usize, generation: u32) -> u64 {
+        u64::from(generation) << 32 | (index as u64 + 1)
+    }
+
+    fn slot(&mut self, id: &Id) -> Option<&mut Slot> {
+        let id = id.into_u64();
+        let index = usize::try_from(id & u64::from(u32::MAX))
+            .ok()?
+            .checked_sub(1)?;
+        let slot = self.slots.get_mut(index)?;
+        (slot.refs > 0 && u64::from(slot.generation) == id >> 32).then_some(slot)
+    }
+
+    fn stack(&mut self, task: u64) -> Option<&mut Stack> {
+        self.stacks
+            .iter_mut()
+            .find(|stack| stack.task == Some(task))
+    }
+
+    /// Register a span with a single reference, `None` if every slot is used.
+    pub fn insert(&mut self, metadata: &'static Metadata<'static>) -> Option<Id> {
+        let (index, slot) = self
+            .slots
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.refs == 0)?;
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.refs = 1;
+        slot.metadata = Some(metadata);
+        Some(Id::from_u64(Self::id(index, slot.generation)))
+    }
+
+    pub fn contains(&mut self, id: &Id) -> bool {
+        self.slot(id).is_some()
+    }
+
+    /// Add a reference to the span, returns `false` if it isn't registered.
+    pub fn clone_span(&mut self, id: &Id) -> bool {
+        self.slot(id).map(|slot| slot.refs += 1).is_some()
+    }
+
+    /// Drop a reference to the span, returns `true` if it was the last one.
+    pub fn try_close(&mut self, id: &Id) -> bool {
+        let Some(slot) = self.slot(id) else {
+            return false;
+        };
+        slot.refs -= 1;
+        if slot.refs > 0 {
+            return false;
+        }
+        slot.metadata = None;
+        true
+    }
+
+    /// Push the span entered at `cycles` on the stack of `task`, returns `false` if it isn't
+    /// registered.
+    pub fn enter(&mut self, task: u64, id: &Id, cycles: u64) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        let index = self
+            .stacks
+            .iter()
+            .position(|stack| stack.task == Some(task))
+            .or_else(|| self.stacks.iter().position(|stack| stack.task.is_none()));
+        // the spans of too many tasks or deeper than the stack are still reported but aren't
+        // current
+        if let Some(stack) = index.map(|index| &mut self.stacks[index]) {
+            if let Some(top) = stack.entered.get_mut(stack.depth) {
+                *top = Entered {
+                    id: id.into_u64(),
+                    cycles,
+                };
+                stack.depth += 1;
+                stack.task = Some(task);
+            }
+        }
+        true
+    }
+
+    /// Remove the last entry of the span from the stack of `task`, returns the cycles elapsed
+    /// since it was entered or `None` if it isn't registered.
+    ///
+    /// The elapsed cycles of the spans that weren't pushed are unknown and reported as zero.
+    pub fn exit(&mut self, task: u64, id: &Id, cycles: u64) -> Option<u64> {
+        let mut elapsed = 0;
+        if let Some(stack) = self.stack(task) {
+            if let Some(position) = stack.entered[..stack.depth]
+                .iter()
+                .rposition(|entered| entered.id == id.into_u64())
+            {
+                elapsed = cycles.saturating_sub(stack.entered[position].cycles);
+                stack
+                    .entered
+                    .copy_within(position + 1..stack.depth, position);
+                stack.depth -= 1;
+                if stack.depth == 0 {
+                    stack.task = None;
+                }
+            }
+        }
+        self.contains(id).then_some(elapsed)
+    }
+
+    /// Innermost span entered by `task`.
+    pub fn current(&mut self, task: u64) -> Option<(Id, &'static Metadata<'static>)> {
+        let stack = self.stack(task)?;
+        let id = Id::from_u64(stack.entered[..stack.depth].last()?.id);
+        let metadata = self.slot(&id)?.metadata?;
+        Some((id, metadata))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tracing_core::{callsite::Callsite, collect::Interest, metadata, Kind, Level};
+
+    use super::*;
+
+    struct TestCallsite;
+
+    impl Callsite for TestCallsite {
+        fn set_interest(&self, _: Interest) {}
+
+        fn metadata(&self) -> &Metadata<'_> {
+            &METADATA
+        }
+    }
+
+    /// Id of a span that was never registered
+    const DROPPED: u64 = u64::MAX;
+
+    static CALLSITE: TestCallsite = TestCallsite;
+    static METADATA: Metadata<'static> = metadata! {
+        name: "span",
+        target: "registry",
+        level: Level::TRACE,
+        fields: &[],
+        callsite: &CALLSITE,
+        kind: Kind::SPAN,
+    };
+
+    #[test]
+    fn insert_until_full() {
+        let mut registry = Registry::new();
+        let ids = [(); MAX_SPANS].map(|_| registry.insert(&METADATA).unwrap());
+        assert!(registry.insert(&METADATA).is_none());
+        assert!(ids.iter().all(|id| registry.contains(id)));
+
+        // the freed slot is reused under a new id
+        assert!(registry.try_close(&ids[3]));
+        let id = registry.insert(&METADATA).unwrap();
+        assert_ne!(id, ids[3]);
+        assert!(!registry.contains(&ids[3]));
+        assert!(registry.contains(&id));
+    }
+
+    #[test]
+    fn clone_and_close() {
+        let mut registry = Registry::new();
+        let id = registry.insert(&METADATA).unwrap();
+
+        assert!(registry.clone_span(&id));
+        assert!(!registry.try_close(&id));
+        assert!(registry.try_close(&id));
+        assert!(!registry.contains(&id));
+
+        assert!(!registry.clone_span(&id));
+        assert!(!registry.try_close(&id));
+        assert!(!registry.contains(&Id::from_u64(DROPPED)));
+    }
+
+    #[test]
+    fn enter_and_exit() {
+        let mut registry = Registry::new();
+        let outer = registry.insert(&METADATA).unwrap();
+        let inner = registry.insert(&METADATA).unwrap();
+
+        assert!(registry.enter(1, &outer, 10));
+        assert!(registry.enter(1, &inner, 15));
+        assert_eq!(registry.current(1).unwrap().0, inner);
+
+        // exited out of order
+        assert_eq!(registry.exit(1, &outer, 30), Some(20));
+        assert_eq!(registry.current(1).unwrap().0, inner);
+        assert_eq!(registry.exit(1, &inner, 40), Some(25));
+        assert!(registry.current(1).is_none());
+
+        assert!(!registry.enter(1, &Id::from_u64(DROPPED), 0));
+        assert_eq!(registry.exit(1, &Id::from_u64(DROPPED), 0), None);
+    }
+
+    #[test]
+    fn stacks_per_task() {
+        let mut registry = Registry::new();
+        let first = registry.insert(&METADATA).unwrap();
+        let second = registry.insert(&METADATA).unwrap();
+
+        assert!(registry.enter(1, &first, 0));
+        assert!(registry.enter(2, &second, 0));
+        assert_eq!(registry.current(1).unwrap().0, first);
+        assert_eq!(registry.current(2).unwrap().0, second);
+        assert!(registry.current(3).is_none());
+
+        // a task can't exit the spans entered by another one
+        assert_eq!(registry.exit(2, &first, 5), Some(0));
+        assert_eq!(registry.current(1).unwrap().0, first);
+        assert_eq!(registry.exit(1, &first, 5), Some(5));
+        assert!(registry.current(1).is_none());
+        assert_eq!(registry.current(2).unwrap().0, second);
+    }
+}